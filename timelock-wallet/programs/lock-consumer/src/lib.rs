@@ -0,0 +1,128 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::program::get_return_data;
+use timelock_wallet::program::TimelockWallet;
+use timelock_wallet::{
+    AssetKind, LockConfig, UnlockAttestation, LOCK_CONFIG_VERSION, UNLOCK_ATTESTATION_LEN,
+};
+
+declare_id!("CcYAEViPRqLnDbSRLgc36oHjJkF8CYcT7vb5uzm5e3gm");
+
+// A minimal composing program: it asks timelock-wallet whether a lock has matured through
+// attest_unlock and branches on the answer, without any unlock logic of its own.
+#[program]
+pub mod lock_consumer {
+    use super::*;
+
+    // Succeed only if `lock_account` has matured, as attested by timelock-wallet in this same
+    // transaction.
+    pub fn require_matured(ctx: Context<RequireMatured>) -> Result<()> {
+        let cpi_ctx = CpiContext::new(
+            ctx.accounts.timelock_program.to_account_info(),
+            timelock_wallet::cpi::accounts::ExplainUnlock {
+                lock_account: ctx.accounts.lock_account.to_account_info(),
+                price_feed: None,
+                condition: None,
+            },
+        );
+        timelock_wallet::cpi::attest_unlock(cpi_ctx)?;
+
+        // Return data can be left over from any program; only trust timelock-wallet's.
+        let (program_id, data) = get_return_data().ok_or(ConsumerError::MissingAttestation)?;
+        require_keys_eq!(program_id, timelock_wallet::ID, ConsumerError::MissingAttestation);
+        require!(data.len() == UNLOCK_ATTESTATION_LEN, ConsumerError::MissingAttestation);
+        let attestation = UnlockAttestation::try_from_slice(&data)?;
+        require_keys_eq!(
+            attestation.lock_account,
+            ctx.accounts.lock_account.key(),
+            ConsumerError::MissingAttestation
+        );
+        require!(attestation.matured, ConsumerError::LockNotMatured);
+
+        msg!(
+            "[require_matured] lock_account={} slot={} unix_timestamp={}",
+            attestation.lock_account,
+            attestation.slot,
+            attestation.unix_timestamp
+        );
+        Ok(())
+    }
+
+    // Succeed only if `lock_account` is a SOL lock of at least `min_amount` lamports whose
+    // payout nobody but its owner can steer: no arbiter, withdraw delegate, quorum, beneficiary,
+    // receipt (whose holder withdraws), password (whose knower claims) or charity fallback
+    // (which anyone can trigger). Read from timelock-wallet's serialize_lock_config in this same
+    // transaction.
+    pub fn require_unencumbered(ctx: Context<RequireUnencumbered>, min_amount: u64) -> Result<()> {
+        let cpi_ctx = CpiContext::new(
+            ctx.accounts.timelock_program.to_account_info(),
+            timelock_wallet::cpi::accounts::SerializeLockConfig {
+                lock_account: ctx.accounts.lock_account.to_account_info(),
+            },
+        );
+        timelock_wallet::cpi::serialize_lock_config(cpi_ctx)?;
+
+        let (program_id, data) = get_return_data().ok_or(ConsumerError::MissingLockConfig)?;
+        require_keys_eq!(program_id, timelock_wallet::ID, ConsumerError::MissingLockConfig);
+        // Check the version before decoding, since a newer layout may not parse as this one.
+        require!(
+            data.first() == Some(&LOCK_CONFIG_VERSION),
+            ConsumerError::UnsupportedLockConfig
+        );
+        let config = LockConfig::try_from_slice(&data)?;
+        require_keys_eq!(
+            config.lock_account,
+            ctx.accounts.lock_account.key(),
+            ConsumerError::MissingLockConfig
+        );
+        require!(config.kind == AssetKind::Sol, ConsumerError::LockEncumbered);
+        require!(config.amount >= min_amount, ConsumerError::LockTooSmall);
+        require!(
+            config.arbiter.is_none()
+                && config.withdraw_delegate.is_none()
+                && config.beneficiary.is_none()
+                && config.receipt_mint.is_none()
+                && !config.password_protected
+                && config.charity_fallback.is_none()
+                && config.quorum_threshold == 0,
+            ConsumerError::LockEncumbered
+        );
+
+        msg!(
+            "[require_unencumbered] lock_account={} amount={} unlock_timestamp={}",
+            config.lock_account,
+            config.amount,
+            config.unlock_timestamp
+        );
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct RequireMatured<'info> {
+    /// CHECK: validated by timelock-wallet, which owns and deserializes it.
+    pub lock_account: UncheckedAccount<'info>,
+    pub timelock_program: Program<'info, TimelockWallet>,
+}
+
+#[derive(Accounts)]
+pub struct RequireUnencumbered<'info> {
+    /// CHECK: validated by timelock-wallet, which owns and deserializes it.
+    pub lock_account: UncheckedAccount<'info>,
+    pub timelock_program: Program<'info, TimelockWallet>,
+}
+
+#[error_code]
+pub enum ConsumerError {
+    #[msg("No unlock attestation from timelock-wallet for this lock")]
+    MissingAttestation,
+    #[msg("Lock has not matured")]
+    LockNotMatured,
+    #[msg("No lock config from timelock-wallet for this lock")]
+    MissingLockConfig,
+    #[msg("Lock config is in a layout version this program doesn't know")]
+    UnsupportedLockConfig,
+    #[msg("Lock is not a SOL lock under its owner's sole control")]
+    LockEncumbered,
+    #[msg("Lock holds less than required")]
+    LockTooSmall,
+}
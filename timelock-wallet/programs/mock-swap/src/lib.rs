@@ -0,0 +1,57 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Token, TokenAccount, Transfer};
+
+declare_id!("Goo6BPGtcQDspBLixEy5MkJ6MLtg5RzqTASmFWtgU9rL");
+
+pub const RESERVE_SEED: &[u8] = b"reserve";
+
+// A stand-in swap venue for tests: it does no pricing at all. The caller names both legs, which
+// is enough to exercise timelock-wallet's spend, slippage and fee checks around a swap CPI.
+#[program]
+pub mod mock_swap {
+    use super::*;
+
+    // Take `amount_in` from `source`, signed by its owner, into the reserve's `sink`, and pay
+    // `amount_out` from the reserve to `destination`. Either leg may be 0.
+    pub fn swap(ctx: Context<Swap>, amount_in: u64, amount_out: u64) -> Result<()> {
+        let cpi_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.source.to_account_info(),
+                to: ctx.accounts.sink.to_account_info(),
+                authority: ctx.accounts.owner.to_account_info(),
+            },
+        );
+        token::transfer(cpi_ctx, amount_in)?;
+
+        let seeds: &[&[u8]] = &[RESERVE_SEED, &[ctx.bumps.reserve_authority]];
+        let signer_seeds: &[&[&[u8]]] = &[seeds];
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.reserve.to_account_info(),
+                to: ctx.accounts.destination.to_account_info(),
+                authority: ctx.accounts.reserve_authority.to_account_info(),
+            },
+            signer_seeds,
+        );
+        token::transfer(cpi_ctx, amount_out)
+    }
+}
+
+#[derive(Accounts)]
+pub struct Swap<'info> {
+    pub owner: Signer<'info>,
+    #[account(mut, token::authority = owner)]
+    pub source: Account<'info, TokenAccount>,
+    #[account(mut, token::mint = source.mint, token::authority = reserve_authority)]
+    pub sink: Account<'info, TokenAccount>,
+    #[account(mut, token::authority = reserve_authority)]
+    pub reserve: Account<'info, TokenAccount>,
+    #[account(mut, token::mint = reserve.mint)]
+    pub destination: Account<'info, TokenAccount>,
+    /// CHECK: a PDA with no data; it only signs for the reserve's token accounts.
+    #[account(seeds = [RESERVE_SEED], bump)]
+    pub reserve_authority: UncheckedAccount<'info>,
+    pub token_program: Program<'info, Token>,
+}
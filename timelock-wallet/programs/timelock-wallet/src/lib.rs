@@ -1,49 +1,621 @@
+// The CPI client Anchor generates mirrors each instruction's arguments, so the long initializers
+// trip this lint there too.
+#![cfg_attr(feature = "cpi", allow(clippy::too_many_arguments))]
+
 use anchor_lang::prelude::*;
-use anchor_spl::associated_token::AssociatedToken;
-use anchor_spl::token::{self, Mint, Token, TokenAccount, Transfer as SplTransfer};
+use anchor_lang::solana_program::hash::hash;
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_lang::solana_program::program::{invoke, invoke_signed, set_return_data};
+use anchor_lang::solana_program::sysvar::instructions::{
+    self as instructions_sysvar, load_current_index_checked, load_instruction_at_checked,
+};
+use anchor_spl::associated_token::{self, get_associated_token_address, AssociatedToken};
+use anchor_spl::token::spl_token::instruction::AuthorityType;
+use anchor_spl::token::{
+    self, Approve, Burn, CloseAccount, Mint, MintTo, Revoke, SetAuthority, Token, TokenAccount,
+    Transfer as SplTransfer,
+};
 
 declare_id!("8LQG6U5AQKe9t97ogxMtggbr24QgUKNFz22qvVPzBYYe");
 
-const TIME_LOCK_SEED: &[u8] = b"time-lock";
-const TIME_LOCK_SOL_SEED: &[u8] = b"time-lock-sol";
-const TIME_LOCK_SPL_SEED: &[u8] = b"time-lock-spl";
+// PDA seeds are public so programs composing with this one can derive its accounts without
+// copying them; see `find_lock_address`.
+pub const TIME_LOCK_SOL_SEED: &[u8] = b"time-lock-sol";
+pub const TIME_LOCK_SPL_SEED: &[u8] = b"time-lock-spl";
+pub const TIME_LOCK_SAFE_SEED: &[u8] = b"time-lock-safe";
+pub const TIME_LOCK_SAVINGS_SEED: &[u8] = b"time-lock-savings";
+pub const TIME_LOCK_BASKET_SEED: &[u8] = b"time-lock-basket";
+pub const TIME_LOCK_DELEGATED_SEED: &[u8] = b"time-lock-delegated";
+pub const CONFIG_SEED: &[u8] = b"config";
+pub const STATS_SEED: &[u8] = b"stats";
+pub const SWAP_PROGRAM_SEED: &[u8] = b"swap-program";
+pub const NOTIFY_PROGRAM_SEED: &[u8] = b"notify-program";
+pub const MINT_MIGRATION_SEED: &[u8] = b"mint-migration";
+pub const RECEIPT_SEED: &[u8] = b"receipt";
+pub const INVITE_SEED: &[u8] = b"invite";
+pub const SUMMARY_SEED: &[u8] = b"summary";
+pub const VAULT_AUTHORITY_SEED: &[u8] = b"vault-authority";
+pub const CONDITION_SEED: &[u8] = b"condition";
+pub const CHARITY_SEED: &[u8] = b"charity";
+pub const REWARD_POOL_SEED: &[u8] = b"reward-pool";
+const SOL_DECIMALS: u8 = 9;
+const PYTH_RECEIVER_PROGRAM_ID: Pubkey = pubkey!("rec5EKMGg6MxZYaMdyBfgwp4d5rB9T1VQH5pJv5LtFJ");
+const ORACLE_MAX_AGE_SECS: i64 = 60;
+const BPS_DENOMINATOR: u64 = 10_000;
+// Hard ceiling for any configured withdrawal fee tier.
+const MAX_FEE_BPS: u16 = 500;
+const FEE_TIER_COUNT: usize = 4;
+// Most parties a fee split can divide withdrawal fees between.
+const MAX_FEE_RECIPIENTS: usize = 4;
+// Most accounts any batch instruction will take from remaining_accounts. Batches are
+// all-or-nothing: anything larger is rejected up front rather than partially processed. The
+// heaviest per-item work (one token CPI, ~5k CU, plus deserialising and signing for a PDA) stays
+// under ~15k CU, so 10 items fit the default 200k CU budget with room for the fixed accounts, and
+// 10 items of two or three accounts each already nears the legacy transaction size limit.
+const MAX_BATCH: usize = 10;
+// withdraw_basket takes three accounts per mint through batch_accounts, so this keeps it under
+// MAX_BATCH.
+const MAX_BASKET_MINTS: usize = 3;
+// Most designated signers a quorum lock can have; they arrive as remaining accounts too.
+const MAX_QUORUM_SIGNERS: usize = 5;
+// Most contributions a SOL lock's funding schedule can hold: ten years of monthly deposits.
+const MAX_CONTRIBUTIONS: u16 = 120;
+// Config::log_level tiers. Essential events (lock creation and payouts) are always emitted;
+// query instructions like get_protocol_stats emit regardless, since the event is their output.
+const LOG_FUNDING: u8 = 1; // + funding and extension events
+const LOG_DIAGNOSTIC: u8 = 2; // + diagnostic logs
+// Coarsest unlock rounding accepted at initialization: one week.
+const MAX_UNLOCK_ROUNDING_SECS: i64 = 7 * 86_400;
+// Target slot time, used to put slot-mode locks on the seconds-based fee schedule.
+const SLOT_MS: u64 = 400;
+// Longest withdrawal freeze the admin can impose, and the cooldown before the next one.
+const MAX_WITHDRAWAL_FREEZE_SECS: i64 = 2 * 86_400;
+// Most a keeper can be tipped for an auto_release, whatever the config says: 0.0001 SOL.
+const MAX_KEEPER_TIP_LAMPORTS: u64 = 100_000;
+// Capability bits reported by get_capabilities. Bits are never reused; a feature that goes away
+// just stops being reported.
+const CAP_SOL_STREAMS: u64 = 1 << 0;
+const CAP_SPL_VESTING: u64 = 1 << 1;
+const CAP_BASKETS: u64 = 1 << 2;
+const CAP_RECEIPTS: u64 = 1 << 3;
+const CAP_SWAP_WITHDRAWAL: u64 = 1 << 4;
+const CAP_LOCK_SUMMARIES: u64 = 1 << 5;
+const CAP_GOALS: u64 = 1 << 6;
+const CAP_QUORUM_RELEASE: u64 = 1 << 7;
+// The rest depend on how the deployment is configured.
+const CAP_USD_LOCKS: u64 = 1 << 16;
+const CAP_INVITE_ONLY: u64 = 1 << 17;
+const CAP_WITHDRAWAL_FEES: u64 = 1 << 18;
+const CAP_CREATION_BOND: u64 = 1 << 19;
 
 #[program]
 pub mod timelock_wallet {
     use super::*;
 
+    // Create the program-wide config and stats accounts. The signer becomes the admin.
+    pub fn initialize_config(ctx: Context<InitializeConfig>, max_tvl_lamports: u64) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        config.admin = ctx.accounts.admin.key();
+        config.max_tvl_lamports = max_tvl_lamports;
+        // No fees until the admin sets a schedule.
+        config.treasury = ctx.accounts.admin.key();
+        config.fee_tiers = [FeeTier::default(); FEE_TIER_COUNT];
+        config.fee_split = [FeeSplit::default(); MAX_FEE_RECIPIENTS];
+        // USD-denominated locks stay disabled until the admin picks a feed.
+        config.sol_usd_oracle = Pubkey::default();
+        config.invite_only = false;
+        config.pending_admin = None;
+        config.min_lock_lamports = 0;
+        config.min_lock_tokens = 0;
+        config.max_lock_lamports = 0;
+        config.max_lock_tokens = 0;
+        config.log_level = LOG_DIAGNOSTIC;
+        config.bond_lamports = 0;
+        config.withdrawals_frozen = false;
+        config.frozen_until = 0;
+        config.reward_multiplier_bps = BPS_DENOMINATOR as u16;
+        config.keeper_tip_lamports = 0;
+        config.min_settlement_slots = 0;
+        config.beneficiary_change_delay = 0;
+        config.bump = ctx.bumps.config;
+
+        let stats = &mut ctx.accounts.stats;
+        stats.total_sol_locked = 0;
+        stats.total_locks_open = 0;
+        stats.total_fees_collected = 0;
+        stats.total_withdrawals = 0;
+        stats.bump = ctx.bumps.stats;
+
+        Ok(())
+    }
+
+    // Emit the protocol-wide counters as a `ProtocolStats` event, so dashboards get one
+    // authoritative read without decoding the stats account themselves.
+    pub fn get_protocol_stats(ctx: Context<GetProtocolStats>) -> Result<()> {
+        let stats = &ctx.accounts.stats;
+        emit!(ProtocolStats {
+            total_sol_locked: stats.total_sol_locked,
+            total_locks_open: stats.total_locks_open,
+            total_fees_collected: stats.total_fees_collected,
+            total_withdrawals: stats.total_withdrawals,
+            event_seq: stats.event_seq,
+        });
+        Ok(())
+    }
+
+    // Emit a `Capabilities` event with the program version and a CAP_* bitfield of what this
+    // deployment supports, so clients can adapt without guessing from the program id.
+    pub fn get_capabilities(ctx: Context<GetCapabilities>) -> Result<()> {
+        emit!(Capabilities {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            features: capabilities(&ctx.accounts.config),
+        });
+        Ok(())
+    }
+
+    // Emit the config as it applies right now, alongside the program's hard limits, as an
+    // `EffectiveConfig` event. Clients validating a form read this instead of decoding the config
+    // account, whose layout may change between versions. A freeze that has run out is reported
+    // as off.
+    pub fn get_config(ctx: Context<GetCapabilities>) -> Result<()> {
+        let config = &ctx.accounts.config;
+        let now = Clock::get()?.unix_timestamp;
+        emit!(EffectiveConfig {
+            admin: config.admin,
+            pending_admin: config.pending_admin,
+            treasury: config.treasury,
+            max_tvl_lamports: config.max_tvl_lamports,
+            fee_tiers: config.fee_tiers,
+            max_fee_bps: MAX_FEE_BPS,
+            deposit_fee_bps: config.deposit_fee_bps,
+            min_lock_lamports: config.min_lock_lamports,
+            min_lock_tokens: config.min_lock_tokens,
+            max_lock_lamports: config.max_lock_lamports,
+            max_lock_tokens: config.max_lock_tokens,
+            bond_lamports: config.bond_lamports,
+            invite_only: config.invite_only,
+            usd_locks_enabled: config.sol_usd_oracle != Pubkey::default(),
+            withdrawals_frozen: require_not_frozen(config, now).is_err(),
+            frozen_until: config.frozen_until,
+            reward_multiplier_bps: config.reward_multiplier_bps,
+            keeper_tip_lamports: config.keeper_tip_lamports,
+            max_keeper_tip_lamports: MAX_KEEPER_TIP_LAMPORTS,
+            min_settlement_slots: config.min_settlement_slots,
+            beneficiary_change_delay: config.beneficiary_change_delay,
+            fee_split: config.fee_split,
+            max_unlock_rounding_secs: MAX_UNLOCK_ROUNDING_SECS,
+            max_contributions: MAX_CONTRIBUTIONS,
+            log_level: config.log_level,
+        });
+        Ok(())
+    }
+
+    // Emit what creating a lock of `kind` costs in SOL right now, on top of the amount locked:
+    // rent for the lock account, rent for its token vault if the kind has one, and the creation
+    // bond if the kind pays one. Rent is read from the cluster rather than hard-coded. All of it
+    // comes back when the lock closes, except a bond forfeited by an orphaned lock.
+    pub fn estimate_creation_cost(ctx: Context<GetCapabilities>, kind: AssetKind) -> Result<()> {
+        let rent = Rent::get()?;
+        let lock_rent = rent.minimum_balance(8 + TimeLockAccount::LEN);
+        let vault_rent = match kind {
+            AssetKind::Spl | AssetKind::SolAndSpl => rent.minimum_balance(TokenAccount::LEN),
+            AssetKind::Sol | AssetKind::Savings | AssetKind::Delegated => 0,
+        };
+        let bond = match kind {
+            AssetKind::Spl => ctx.accounts.config.bond_lamports,
+            _ => 0,
+        };
+        let total = lock_rent
+            .checked_add(vault_rent)
+            .and_then(|sum| sum.checked_add(bond))
+            .ok_or(TimeLockError::MathOverflow)?;
+
+        emit!(CreationCost {
+            kind,
+            lock_rent,
+            vault_rent,
+            bond,
+            total,
+        });
+        Ok(())
+    }
+
+    // Admin only: start handing the admin role to `new_admin`. Nothing changes until
+    // `new_admin` signs `accept_admin`, so a mistyped key can't take over governance; proposing
+    // again replaces the pending admin.
+    pub fn propose_admin(ctx: Context<UpdateConfig>, new_admin: Pubkey) -> Result<()> {
+        ctx.accounts.config.pending_admin = Some(new_admin);
+        Ok(())
+    }
+
+    // Finish an admin handoff. Must be signed by the proposed admin.
+    pub fn accept_admin(ctx: Context<AcceptAdmin>) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        require!(
+            config.pending_admin == Some(ctx.accounts.pending_admin.key()),
+            TimeLockError::NotPendingAdmin
+        );
+        msg!("[accept_admin] old={} new={}", config.admin, ctx.accounts.pending_admin.key());
+        config.admin = ctx.accounts.pending_admin.key();
+        config.pending_admin = None;
+        Ok(())
+    }
+
+    // Admin only: raise or lower the program-wide SOL cap. Lowering it below the current
+    // TVL only blocks new deposits; existing locks can still be withdrawn.
+    pub fn set_max_tvl(ctx: Context<UpdateConfig>, max_tvl_lamports: u64) -> Result<()> {
+        ctx.accounts.config.max_tvl_lamports = max_tvl_lamports;
+        Ok(())
+    }
+
+    // Admin only: set the smallest lock worth creating, to keep dust locks out of state. SOL
+    // locks are checked when their deposit lands, not at init; the token minimum is a single
+    // base-unit threshold for every mint. 0 disables either check.
+    pub fn set_min_lock_amounts(
+        ctx: Context<UpdateConfig>,
+        min_lock_lamports: u64,
+        min_lock_tokens: u64,
+    ) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        config.min_lock_lamports = min_lock_lamports;
+        config.min_lock_tokens = min_lock_tokens;
+        Ok(())
+    }
+
+    // Admin only: cap how much a single lock can hold, to limit what any one lock puts at risk.
+    // Unlike the TVL cap this is per lock, and it applies to the lock's whole amount, so SOL
+    // top-ups and scheduled contributions can't grow a lock past it either. The token cap is a
+    // single base-unit threshold for every mint. 0 disables either cap.
+    pub fn set_max_lock_amounts(
+        ctx: Context<UpdateConfig>,
+        max_lock_lamports: u64,
+        max_lock_tokens: u64,
+    ) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        config.max_lock_lamports = max_lock_lamports;
+        config.max_lock_tokens = max_lock_tokens;
+        Ok(())
+    }
+
+    // Admin only: set the refundable SOL bond charged when an SPL lock is created. Locks keep the
+    // bond they paid, so changing this only affects new locks. 0 disables it.
+    pub fn set_bond(ctx: Context<UpdateConfig>, bond_lamports: u64) -> Result<()> {
+        ctx.accounts.config.bond_lamports = bond_lamports;
+        Ok(())
+    }
+
+    // Admin only: freeze every withdrawal path for `duration_secs`, at most
+    // MAX_WITHDRAWAL_FREEZE_SECS, e.g. while an upgrade migrates accounts. Creations and deposits
+    // carry on. The freeze lifts itself at `frozen_until` whether or not the admin unfreezes, and
+    // the next one can't start until another MAX_WITHDRAWAL_FREEZE_SECS have passed, so chained
+    // freezes can't keep funds shut in. Pass `frozen = false` to lift it early.
+    pub fn set_withdrawals_frozen(ctx: Context<UpdateConfig>, frozen: bool, duration_secs: i64) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let config = &mut ctx.accounts.config;
+        if !frozen {
+            if config.withdrawals_frozen {
+                config.frozen_until = config.frozen_until.min(now);
+            }
+            config.withdrawals_frozen = false;
+            return Ok(());
+        }
+        require!(
+            duration_secs > 0 && duration_secs <= MAX_WITHDRAWAL_FREEZE_SECS,
+            TimeLockError::InvalidDuration
+        );
+        require!(
+            config.frozen_until == 0
+                || now >= config.frozen_until.saturating_add(MAX_WITHDRAWAL_FREEZE_SECS),
+            TimeLockError::FreezeCooldown
+        );
+        config.withdrawals_frozen = true;
+        config.frozen_until = now + duration_secs;
+        msg!("[set_withdrawals_frozen] frozen_until={}", config.frozen_until);
+        Ok(())
+    }
+
+    // Admin only: set the reward multiplier new locks snapshot when they are created. Existing
+    // locks keep the rate they were created with, so this is never retroactive.
+    pub fn set_reward_multiplier(ctx: Context<UpdateConfig>, reward_multiplier_bps: u16) -> Result<()> {
+        ctx.accounts.config.reward_multiplier_bps = reward_multiplier_bps;
+        Ok(())
+    }
+
+    // Admin only: set the fee new SPL locks pay into the reward pool on deposit, up to
+    // MAX_FEE_BPS. The pool for a mint is the reward-pool PDA's ATA for it; withdraw_spl refunds
+    // the fee from there. 0 disables it.
+    pub fn set_deposit_fee(ctx: Context<UpdateConfig>, deposit_fee_bps: u16) -> Result<()> {
+        require!(deposit_fee_bps <= MAX_FEE_BPS, TimeLockError::InvalidFeeSchedule);
+        ctx.accounts.config.deposit_fee_bps = deposit_fee_bps;
+        Ok(())
+    }
+
+    // Admin only: set how many slots a SOL lock must wait after fund_sol_lock before it can be
+    // withdrawn, so a payout never races a deposit that could still be rolled back. 0 disables
+    // it.
+    pub fn set_min_settlement_slots(ctx: Context<UpdateConfig>, min_settlement_slots: u64) -> Result<()> {
+        ctx.accounts.config.min_settlement_slots = min_settlement_slots;
+        Ok(())
+    }
+
+    // Admin only: set how long a force_update_beneficiary waits before it takes effect, during
+    // which the current beneficiary can still be paid. 0 applies changes immediately.
+    pub fn set_beneficiary_change_delay(ctx: Context<UpdateConfig>, delay_secs: i64) -> Result<()> {
+        require!(delay_secs >= 0, TimeLockError::InvalidDuration);
+        ctx.accounts.config.beneficiary_change_delay = delay_secs;
+        Ok(())
+    }
+
+    // Admin only: set the tip auto_release pays its keeper, up to MAX_KEEPER_TIP_LAMPORTS.
+    pub fn set_keeper_tip(ctx: Context<UpdateConfig>, keeper_tip_lamports: u64) -> Result<()> {
+        require!(keeper_tip_lamports <= MAX_KEEPER_TIP_LAMPORTS, TimeLockError::InvalidAmount);
+        ctx.accounts.config.keeper_tip_lamports = keeper_tip_lamports;
+        Ok(())
+    }
+
+    // Admin only: choose how much the program logs, from 0 (essential events only) up to
+    // LOG_DIAGNOSTIC. Lower levels save log space and compute on busy deployments.
+    pub fn set_log_level(ctx: Context<UpdateConfig>, log_level: u8) -> Result<()> {
+        require!(log_level <= LOG_DIAGNOSTIC, TimeLockError::InvalidLogLevel);
+        ctx.accounts.config.log_level = log_level;
+        Ok(())
+    }
+
+    // Admin only: set the Pyth SOL/USD price update account that `initialize_lock_sol_usd` reads.
+    pub fn set_sol_usd_oracle(ctx: Context<UpdateConfig>, sol_usd_oracle: Pubkey) -> Result<()> {
+        ctx.accounts.config.sol_usd_oracle = sol_usd_oracle;
+        Ok(())
+    }
+
+    // Admin only: set the fee treasury and the duration-based fee tiers. Tiers are checked in
+    // order; a lock pays the first tier whose `max_duration_secs` exceeds its locked duration,
+    // and nothing if none does. Unused tiers (max_duration_secs == 0) must come last.
+    pub fn set_fee_schedule(
+        ctx: Context<UpdateConfig>,
+        treasury: Pubkey,
+        fee_tiers: [FeeTier; FEE_TIER_COUNT],
+    ) -> Result<()> {
+        let mut previous = 0i64;
+        let mut unused_seen = false;
+        for tier in fee_tiers.iter() {
+            require!(tier.fee_bps <= MAX_FEE_BPS, TimeLockError::InvalidFeeSchedule);
+            if tier.max_duration_secs == 0 {
+                unused_seen = true;
+                continue;
+            }
+            require!(
+                !unused_seen && tier.max_duration_secs > previous,
+                TimeLockError::InvalidFeeSchedule
+            );
+            previous = tier.max_duration_secs;
+        }
+
+        let config = &mut ctx.accounts.config;
+        config.treasury = treasury;
+        config.fee_tiers = fee_tiers;
+        Ok(())
+    }
+
+    // Admin only: divide withdrawal fees between several recipients instead of paying them all
+    // to the treasury. Entries with bps == 0 are unused; the rest must name a recipient and add
+    // up to exactly 10000. All unused turns the split off. While a split is on, every instruction
+    // that takes a fee needs the recipients as its last remaining accounts, in table order: their
+    // wallets for SOL fees and their token accounts for the lock's mint for SPL fees.
+    pub fn set_fee_split(
+        ctx: Context<UpdateConfig>,
+        fee_split: [FeeSplit; MAX_FEE_RECIPIENTS],
+    ) -> Result<()> {
+        let mut total_bps = 0u64;
+        for split in fee_split.iter().filter(|split| split.bps > 0) {
+            require!(split.recipient != Pubkey::default(), TimeLockError::InvalidFeeSplit);
+            total_bps += split.bps as u64;
+        }
+        require!(
+            total_bps == 0 || total_bps == BPS_DENOMINATOR,
+            TimeLockError::InvalidFeeSplit
+        );
+        ctx.accounts.config.fee_split = fee_split;
+        Ok(())
+    }
+
+    // Admin only: toggle invite-only mode. While on, creating a lock requires an invite.
+    pub fn set_invite_only(ctx: Context<UpdateConfig>, invite_only: bool) -> Result<()> {
+        ctx.accounts.config.invite_only = invite_only;
+        Ok(())
+    }
+
+    // Admin only: let `address` create locks while invite-only mode is on.
+    pub fn invite(ctx: Context<InviteAddress>, address: Pubkey) -> Result<()> {
+        let invite = &mut ctx.accounts.invite;
+        invite.address = address;
+        invite.bump = ctx.bumps.invite;
+        Ok(())
+    }
+
+    // Admin only: revoke an invite, refunding its rent to the admin. Existing locks are
+    // unaffected; withdrawals never check invites.
+    pub fn revoke_invite(_ctx: Context<RevokeInvite>) -> Result<()> {
+        Ok(())
+    }
+
+    // Admin only: allow `withdraw_spl_with_swap` to route through `program_id`.
+    pub fn allow_swap_program(ctx: Context<AllowSwapProgram>, program_id: Pubkey) -> Result<()> {
+        let swap_program = &mut ctx.accounts.swap_program;
+        swap_program.program_id = program_id;
+        swap_program.bump = ctx.bumps.swap_program;
+        Ok(())
+    }
+
+    // Admin only: remove a swap program from the allow-list, refunding its rent to the admin.
+    pub fn disallow_swap_program(_ctx: Context<DisallowSwapProgram>) -> Result<()> {
+        Ok(())
+    }
+
+    // Admin only: allow-list a program that locks may name as their `notify_program`.
+    pub fn allow_notify_program(ctx: Context<AllowNotifyProgram>, program_id: Pubkey) -> Result<()> {
+        let notify_program = &mut ctx.accounts.notify_program;
+        notify_program.program_id = program_id;
+        notify_program.bump = ctx.bumps.notify_program;
+        Ok(())
+    }
+
+    // Admin only: remove a notify program from the allow-list, refunding its rent to the admin.
+    // Locks still naming it can't withdraw until their initializer clears it.
+    pub fn disallow_notify_program(_ctx: Context<DisallowNotifyProgram>) -> Result<()> {
+        Ok(())
+    }
+
+    // Admin only: allow-list an address that SOL locks may name as their charity fallback.
+    pub fn allow_charity(ctx: Context<AllowCharity>, address: Pubkey) -> Result<()> {
+        let charity = &mut ctx.accounts.charity;
+        charity.address = address;
+        charity.bump = ctx.bumps.charity;
+        Ok(())
+    }
+
+    // Admin only: remove a charity from the allow-list, refunding its rent to the admin. Locks
+    // already naming it can no longer donate to it, and simply stay claimable by their owner.
+    pub fn disallow_charity(_ctx: Context<DisallowCharity>) -> Result<()> {
+        Ok(())
+    }
+
+    // Admin only: register `new_mint` as the official successor of `old_mint`. Locks migrate at
+    // `ratio_numerator / ratio_denominator` new base units per old base unit, paid out of the
+    // migration's reserve ATA, which the issuer funds with new tokens separately.
+    pub fn register_mint_migration(
+        ctx: Context<RegisterMintMigration>,
+        ratio_numerator: u64,
+        ratio_denominator: u64,
+    ) -> Result<()> {
+        require!(ratio_numerator > 0 && ratio_denominator > 0, TimeLockError::InvalidAmount);
+        require_keys_neq!(ctx.accounts.old_mint.key(), ctx.accounts.new_mint.key(), TimeLockError::NoMigrationPath);
+
+        let migration = &mut ctx.accounts.migration;
+        migration.old_mint = ctx.accounts.old_mint.key();
+        migration.new_mint = ctx.accounts.new_mint.key();
+        migration.ratio_numerator = ratio_numerator;
+        migration.ratio_denominator = ratio_denominator;
+        migration.bump = ctx.bumps.migration;
+        Ok(())
+    }
+
     // Initialize a SOL timelock. Funds are transferred into the PDA account lamports.
+    // With `stream_periods > 0` the lock is released in that many equal chunks through
+    // `claim_sol_stream`, the first at `unlock_timestamp` and one more every `stream_period_secs`.
+    // Pass `unlock_slot` (with `unlock_timestamp` 0) to unlock at a slot height instead.
+    // With `quorum_threshold > 0`, that many of `quorum_signers` can also release the lock early
+    // through `quorum_release`; the quorum can only be set here, at creation.
+    // `category` is a `LockCategory` discriminant, reported in `LockCreated` for indexers.
+    #[allow(clippy::too_many_arguments)]
     pub fn initialize_lock_sol(
         ctx: Context<InitializeLockSol>,
         amount_lamports: u64,
         unlock_timestamp: i64,
+        commitment: [u8; 32],
+        require_system_destination: bool,
+        trial_seconds: i64,
+        stream_periods: u16,
+        stream_period_secs: i64,
+        round_to_seconds: i64,
+        unlock_slot: Option<u64>,
+        quorum_signers: Vec<Pubkey>,
+        quorum_threshold: u8,
+        category: u8,
+        referrer: Option<Pubkey>,
     ) -> Result<()> {
-        msg!(
-            "[initialize_lock_sol] amount_lamports={} unlock_timestamp={} now={}",
-            amount_lamports,
-            unlock_timestamp,
-            Clock::get()?.unix_timestamp
-        );
-        msg!(
-            "[initialize_lock_sol] initializer={} lock_account={} system_program={}",
-            ctx.accounts.initializer.key(),
-            ctx.accounts.lock_account.key(),
-            ctx.accounts.system_program.key()
-        );
+        if ctx.accounts.config.log_level >= LOG_DIAGNOSTIC {
+            msg!(
+                "[initialize_lock_sol] amount_lamports={} unlock_timestamp={} now={}",
+                amount_lamports,
+                unlock_timestamp,
+                Clock::get()?.unix_timestamp
+            );
+            msg!(
+                "[initialize_lock_sol] initializer={} lock_account={} system_program={}",
+                ctx.accounts.initializer.key(),
+                ctx.accounts.lock_account.key(),
+                ctx.accounts.system_program.key()
+            );
+        }
 
+        require_invited(&ctx.accounts.config, &ctx.accounts.invite)?;
         require!(amount_lamports > 0, TimeLockError::InvalidAmount);
+        require_funding_follows(
+            &ctx.accounts.instructions.to_account_info(),
+            ctx.accounts.lock_account.key(),
+        )?;
         let now = Clock::get()?.unix_timestamp;
-        require!(unlock_timestamp > now, TimeLockError::UnlockInPast);
+        let unlock_timestamp =
+            resolve_unlock(unlock_timestamp, round_to_seconds, unlock_slot, stream_periods)?;
+        require!(trial_seconds >= 0, TimeLockError::InvalidDuration);
+        require_valid_quorum(&quorum_signers, quorum_threshold)?;
+        require_not_self_referral(referrer, &ctx.accounts.initializer.key())?;
+        let category = LockCategory::try_from(category)?;
+        require!(
+            stream_periods == 0 || stream_period_secs > 0,
+            TimeLockError::InvalidDuration
+        );
 
         let initializer = &ctx.accounts.initializer;
         let lock_account = &mut ctx.accounts.lock_account;
+        // Re-initialising would let the initializer rewrite a lock the receipt holder now owns.
+        require!(lock_account.receipt_mint.is_none(), TimeLockError::ReceiptRequired);
+        // Re-initialising a lock that still holds SOL would rewrite its unlock and reopen its
+        // trial over funds already locked. Only a drained lock can be treated as a fresh one.
+        require!(
+            lamports_above_rent(&lock_account.to_account_info())? == 0,
+            TimeLockError::AlreadyInitialized
+        );
+        let slot = Clock::get()?.slot;
+        if is_new_lock(lock_account, slot)? {
+            ctx.accounts.stats.record_lock_opened();
+            lock_account.reward_multiplier_bps = ctx.accounts.config.reward_multiplier_bps;
+        }
+        lock_account.created_slot = slot;
 
         // Persist state
         lock_account.initializer = initializer.key();
         lock_account.amount = amount_lamports;
         lock_account.unlock_timestamp = unlock_timestamp;
+        lock_account.unlock_slot = unlock_slot;
         lock_account.bump = ctx.bumps.lock_account;
+        lock_account.created_at = now;
         lock_account.kind = AssetKind::Sol;
+        lock_account.display_decimals = SOL_DECIMALS;
+        lock_account.commitment = commitment;
+        lock_account.require_system_destination = require_system_destination;
+        lock_account.trial_seconds = trial_seconds;
+        // The trial clock starts when the SOL actually lands, in fund_sol_lock.
+        lock_account.funded_at = 0;
+        lock_account.funding_slot = 0;
+        lock_account.usd_cents = 0;
+        lock_account.stream_periods = stream_periods;
+        lock_account.stream_period_secs = stream_period_secs;
+        lock_account.stream_claimed = 0;
+        lock_account.next_claim_at = if stream_periods > 0 { unlock_timestamp } else { 0 };
+        lock_account.stream_anchor = None;
+        lock_account.tranche_amount = 0;
+        lock_account.contribution_amount = 0;
+        lock_account.contribution_interval_secs = 0;
+        lock_account.contribution_count = 0;
+        lock_account.contributions_made = 0;
+        lock_account.contributions_missed = 0;
+        lock_account.next_contribution_at = 0;
+        lock_account.goal_amount = 0;
+        lock_account.goal_reached = false;
+        lock_account.goal_required = false;
+        lock_account.auto_extend_seconds = 0;
+        // Re-running init must not hand back powers the owner renounced.
+        require!(
+            !lock_account.authorities_renounced || quorum_threshold == 0,
+            TimeLockError::AuthorityRenounced
+        );
+        lock_account.quorum_signers = quorum_signers;
+        lock_account.quorum_threshold = quorum_threshold;
+        lock_account.category = category;
+        lock_account.referrer = referrer;
+        sync_lock_summary(lock_account, ctx.accounts.lock_summary.as_deref_mut(), false)?;
+        emit_lock_created(lock_account, &mut ctx.accounts.stats);
 
         // SOL is transferred from the client as a separate instruction in the same transaction.
         // This avoids CPI writable privilege issues when creating and funding in one go.
@@ -51,11 +623,117 @@ pub mod timelock_wallet {
         Ok(())
     }
 
+    // Initialize and fund a SOL lock worth `usd_cents` at the current SOL/USD price. Only the
+    // lamport amount is binding; `usd_cents` is stored for display and withdrawal pays lamports
+    // as usual. The price can't be known client-side in advance, so this path funds itself.
+    pub fn initialize_lock_sol_usd(
+        ctx: Context<InitializeLockSolUsd>,
+        usd_cents: u64,
+        unlock_timestamp: i64,
+        commitment: [u8; 32],
+        require_system_destination: bool,
+        trial_seconds: i64,
+        category: u8,
+    ) -> Result<()> {
+        require_invited(&ctx.accounts.config, &ctx.accounts.invite)?;
+        let category = LockCategory::try_from(category)?;
+        require!(usd_cents > 0, TimeLockError::InvalidAmount);
+        let now = Clock::get()?.unix_timestamp;
+        require!(unlock_in_future(unlock_timestamp, now), TimeLockError::UnlockInPast);
+        require!(trial_seconds >= 0, TimeLockError::InvalidDuration);
+
+        let price = read_oracle_price(&ctx.accounts.price_feed, now)?;
+        let amount_lamports = usd_cents_to_lamports(usd_cents, price.price, price.exponent)?;
+        require!(amount_lamports > 0, TimeLockError::InvalidAmount);
+        require!(
+            amount_lamports >= ctx.accounts.config.min_lock_lamports,
+            TimeLockError::BelowMinimumAmount
+        );
+        require_within_max(amount_lamports, ctx.accounts.config.max_lock_lamports)?;
+        let new_total = tvl_after_deposit(&ctx.accounts.config, &ctx.accounts.stats, amount_lamports)?;
+
+        let lock_account = &mut ctx.accounts.lock_account;
+        lock_account.initializer = ctx.accounts.initializer.key();
+        lock_account.amount = amount_lamports;
+        lock_account.unlock_timestamp = unlock_timestamp;
+        lock_account.unlock_slot = None;
+        lock_account.quorum_signers = Vec::new();
+        lock_account.quorum_threshold = 0;
+        lock_account.category = category;
+        lock_account.reward_multiplier_bps = ctx.accounts.config.reward_multiplier_bps;
+        lock_account.bump = ctx.bumps.lock_account;
+        lock_account.created_at = now;
+        lock_account.kind = AssetKind::Sol;
+        lock_account.display_decimals = SOL_DECIMALS;
+        lock_account.commitment = commitment;
+        lock_account.require_system_destination = require_system_destination;
+        lock_account.trial_seconds = trial_seconds;
+        lock_account.funded_at = now;
+        lock_account.usd_cents = usd_cents;
+        lock_account.created_slot = Clock::get()?.slot;
+
+        let cpi_accounts = anchor_lang::system_program::Transfer {
+            from: ctx.accounts.initializer.to_account_info(),
+            to: ctx.accounts.lock_account.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new(ctx.accounts.system_program.to_account_info(), cpi_accounts);
+        anchor_lang::system_program::transfer(cpi_ctx, amount_lamports)?;
+        ctx.accounts.stats.total_sol_locked = new_total;
+        ctx.accounts.stats.record_lock_opened();
+        emit_lock_created(&ctx.accounts.lock_account, &mut ctx.accounts.stats);
+
+        msg!(
+            "[initialize_lock_sol_usd] usd_cents={} price={} exponent={} amount_lamports={}",
+            usd_cents,
+            price.price,
+            price.exponent,
+            amount_lamports
+        );
+        Ok(())
+    }
+
     // Transfer SOL to lock account (separate instruction)
     pub fn fund_sol_lock(
         ctx: Context<FundSolLock>,
         amount_lamports: u64,
     ) -> Result<()> {
+        let clock = Clock::get()?;
+        let now = clock.unix_timestamp;
+        // Funding must bring the lock to exactly its declared amount, so `amount` always matches
+        // what withdrawal will pay out. Once it has, further deposits are only accepted as the
+        // lock's scheduled contributions, which raise `amount` by exactly what they add.
+        let funded = lamports_above_rent(&ctx.accounts.lock_account.to_account_info())?;
+        require!(amount_lamports > 0, TimeLockError::InvalidAmount);
+        let contribution = if ctx.accounts.lock_account.contribution_count > 0
+            && funded >= ctx.accounts.lock_account.amount
+        {
+            Some(match_contribution(&ctx.accounts.lock_account, amount_lamports, &clock)?)
+        } else {
+            require!(
+                funded.checked_add(amount_lamports) == Some(ctx.accounts.lock_account.amount),
+                TimeLockError::FundingMismatch
+            );
+            None
+        };
+        // Funding always brings the lock to its full amount, so checking that covers the deposit.
+        require!(
+            ctx.accounts.lock_account.amount >= ctx.accounts.config.min_lock_lamports,
+            TimeLockError::BelowMinimumAmount
+        );
+        // A contribution grows the amount, so the cap applies to where it leaves the lock.
+        let amount_after = match contribution {
+            Some(_) => ctx
+                .accounts
+                .lock_account
+                .amount
+                .checked_add(amount_lamports)
+                .ok_or(TimeLockError::MathOverflow)?,
+            None => ctx.accounts.lock_account.amount,
+        };
+        require_within_max(amount_after, ctx.accounts.config.max_lock_lamports)?;
+
+        let new_total = tvl_after_deposit(&ctx.accounts.config, &ctx.accounts.stats, amount_lamports)?;
+
         // Use system program transfer với anchor's system_program interface
         let cpi_accounts = anchor_lang::system_program::Transfer {
             from: ctx.accounts.initializer.to_account_info(),
@@ -63,184 +741,6581 @@ pub mod timelock_wallet {
         };
         let cpi_ctx = CpiContext::new(ctx.accounts.system_program.to_account_info(), cpi_accounts);
         anchor_lang::system_program::transfer(cpi_ctx, amount_lamports)?;
+
+        ctx.accounts.stats.total_sol_locked = new_total;
+        let lock_account = &mut ctx.accounts.lock_account;
+        lock_account.funding_slot = clock.slot;
+        match contribution {
+            Some(skipped) => {
+                lock_account.amount = lock_account
+                    .amount
+                    .checked_add(amount_lamports)
+                    .ok_or(TimeLockError::MathOverflow)?;
+                let index = lock_account.contributions_made + lock_account.contributions_missed + skipped;
+                lock_account.contributions_made += 1;
+                lock_account.contributions_missed += skipped;
+                lock_account.next_contribution_at = lock_account.next_contribution_at.saturating_add(
+                    lock_account.contribution_interval_secs.saturating_mul(skipped as i64 + 1),
+                );
+                emit!(ContributionReceived {
+                    lock_account: lock_account.key(),
+                    index,
+                    amount_lamports,
+                    amount: lock_account.amount,
+                    contributions_made: lock_account.contributions_made,
+                    contributions_missed: lock_account.contributions_missed,
+                    remaining: lock_account.contribution_count
+                        - lock_account.contributions_made
+                        - lock_account.contributions_missed,
+                    seq: ctx.accounts.stats.next_event_seq(),
+                });
+            }
+            // The trial clock runs from the initial deposit, not from later contributions.
+            None => lock_account.funded_at = now,
+        }
+        apply_auto_extend(&mut ctx.accounts.lock_account, &mut ctx.accounts.stats, &ctx.accounts.config, now)?;
+        emit_if_goal_reached(&mut ctx.accounts.lock_account, &mut ctx.accounts.stats);
+        sync_lock_summary(&ctx.accounts.lock_account, ctx.accounts.lock_summary.as_deref_mut(), false)?;
         
         Ok(())
     }
 
     // Withdraw SOL after unlock; closing the account returns remaining lamports to initializer
-    pub fn withdraw_sol(ctx: Context<WithdrawSol>) -> Result<()> {
+    pub fn withdraw_sol<'info>(ctx: Context<'_, '_, 'info, 'info, WithdrawSol<'info>>) -> Result<()> {
+        require_not_frozen(&ctx.accounts.config, Clock::get()?.unix_timestamp)?;
+        let clock = Clock::get()?;
+        let lock_account = &ctx.accounts.lock_account;
+        require!(lock_account.kind == AssetKind::Sol, TimeLockError::WrongAssetKind);
+        require_withdraw_authority(lock_account, &ctx.accounts.authority.key())?;
+        let unlock = evaluate_unlock(lock_account, &clock, None, &ctx.accounts.condition, &[])?;
+        require!(unlock.time_met, TimeLockError::TimeLockNotExpired);
+        require_settled(lock_account, &ctx.accounts.config, clock.slot)?;
+        // The close target is the initializer itself.
+        require_wallet_destination(lock_account, &ctx.accounts.initializer)?;
+        require!(unlock.condition_met, TimeLockError::ConditionNotMet);
+        require!(unlock.goal_met, TimeLockError::GoalNotReached);
+        require!(unlock.password_met, TimeLockError::PasswordRequired);
+
+        // Whoever holds the receipt controls the lock, not the initializer.
+        require!(lock_account.receipt_mint.is_none(), TimeLockError::ReceiptRequired);
+        sync_lock_summary(lock_account, ctx.accounts.lock_summary.as_deref_mut(), true)?;
+
+        // Streams pay out through claim_sol_stream; once drained, this just reclaims the rent.
+        if lock_account.stream_periods > 0 {
+            require!(
+                lock_account.stream_claimed == lock_account.amount,
+                TimeLockError::StreamNotDrained
+            );
+            ctx.accounts.stats.record_lock_closed();
+            emit_sol_withdrawn(lock_account, &mut ctx.accounts.stats, 0, 0, 0);
+            refund_rent(&ctx.accounts.lock_account, &ctx.accounts.rent_recipient)?;
+            msg!("[withdraw_sol] closed drained stream");
+            return Ok(());
+        }
+
+        top_up_rent_reserve(lock_account, &ctx.accounts.authority, &ctx.accounts.system_program)?;
+        let (principal, fee) = settle_sol_withdrawal(
+            lock_account,
+            &ctx.accounts.config,
+            &mut ctx.accounts.stats,
+            &ctx.accounts.treasury,
+            split_off_fee_accounts(&ctx.accounts.config, ctx.remaining_accounts)?.1,
+        )?;
+        ctx.accounts.stats.record_lock_closed();
+
+        let saved = if lock_account.auto_save_bps > 0 {
+            let saved = scale_amount(principal - fee, lock_account.auto_save_bps as u64, BPS_DENOMINATOR)?;
+            let savings_lock = ctx
+                .accounts
+                .savings_lock
+                .as_mut()
+                .ok_or(TimeLockError::SavingsLockMissing)?;
+            if savings_lock.initializer == Pubkey::default() {
+                savings_lock.initializer = lock_account.initializer;
+                savings_lock.unlock_timestamp = lock_account.savings_unlock_timestamp;
+                savings_lock.bump = ctx.bumps.savings_lock.ok_or(TimeLockError::SavingsLockMissing)?;
+                savings_lock.kind = AssetKind::Savings;
+                savings_lock.category = LockCategory::Savings;
+                savings_lock.display_decimals = SOL_DECIMALS;
+                savings_lock.created_at = clock.unix_timestamp;
+                savings_lock.funded_at = clock.unix_timestamp;
+                savings_lock.reward_multiplier_bps = ctx.accounts.config.reward_multiplier_bps;
+                ctx.accounts.stats.record_lock_opened();
+            }
+            // Moving into a lock that has already matured would just be a slower withdrawal.
+            require!(
+                unlock_in_future(savings_lock.unlock_timestamp, clock.unix_timestamp),
+                TimeLockError::UnlockInPast
+            );
+            savings_lock.amount = savings_lock
+                .amount
+                .checked_add(saved)
+                .ok_or(TimeLockError::MathOverflow)?;
+            lock_account.sub_lamports(saved)?;
+            savings_lock.add_lamports(saved)?;
+            // The saved share never left the program.
+            let stats = &mut ctx.accounts.stats;
+            stats.total_sol_locked = stats.total_sol_locked.saturating_add(saved);
+            saved
+        } else {
+            0
+        };
+
+        // Pay the principal out explicitly, so all that's left for the rent step is the reserve.
+        let principal_returned = principal - fee - saved;
+        lock_account.sub_lamports(principal_returned)?;
+        ctx.accounts.initializer.add_lamports(principal_returned)?;
+        emit_sol_withdrawn(lock_account, &mut ctx.accounts.stats, principal_returned, fee, saved);
+
+        refund_rent(&ctx.accounts.lock_account, &ctx.accounts.rent_recipient)?;
+        notify_withdrawal(
+            &ctx.accounts.lock_account,
+            principal_returned,
+            &ctx.accounts.notify_program,
+            &ctx.accounts.allowed_notify_program,
+        )?;
+        msg!("[withdraw_sol] principal={} fee={} saved={}", principal, fee, saved);
+        // close = initializer takes the rent reserve unless refund_rent already paid it out.
+        Ok(())
+    }
+
+    // withdraw_sol, but paying out explicit amounts to several destinations: one writable
+    // remaining account per entry of `amounts`, in order, at most MAX_BATCH. The amounts are net
+    // of the withdrawal fee and together must fit in what the lock pays out after it. The lock
+    // still closes, so whatever they leave goes to the initializer along with the rent reserve.
+    // Streams and auto-save locks pay out through their own paths and are refused. Shares
+    // WithdrawSol's accounts; the savings lock is unused.
+    pub fn withdraw_sol_split<'info>(ctx: Context<'_, '_, 'info, 'info, WithdrawSol<'info>>, amounts: Vec<u64>) -> Result<()> {
+        require_not_frozen(&ctx.accounts.config, Clock::get()?.unix_timestamp)?;
         let clock = Clock::get()?;
         let lock_account = &ctx.accounts.lock_account;
         require!(lock_account.kind == AssetKind::Sol, TimeLockError::WrongAssetKind);
-        require!(clock.unix_timestamp >= lock_account.unlock_timestamp, TimeLockError::TimeLockNotExpired);
-        // No explicit transfer needed; close = initializer will return lamports.
+        require_withdraw_authority(lock_account, &ctx.accounts.authority.key())?;
+        let unlock = evaluate_unlock(lock_account, &clock, None, &ctx.accounts.condition, &[])?;
+        require!(unlock.time_met, TimeLockError::TimeLockNotExpired);
+        require_settled(lock_account, &ctx.accounts.config, clock.slot)?;
+        require_wallet_destination(lock_account, &ctx.accounts.initializer)?;
+        require!(unlock.condition_met, TimeLockError::ConditionNotMet);
+        require!(unlock.goal_met, TimeLockError::GoalNotReached);
+        require!(unlock.password_met, TimeLockError::PasswordRequired);
+        require!(lock_account.receipt_mint.is_none(), TimeLockError::ReceiptRequired);
+        require!(
+            lock_account.stream_periods == 0 && lock_account.auto_save_bps == 0,
+            TimeLockError::ReleaseModeConflict
+        );
+
+        let (destinations, fee_accounts) =
+            split_off_fee_accounts(&ctx.accounts.config, ctx.remaining_accounts)?;
+        let destinations = batch_accounts(destinations)?;
+        require!(
+            !amounts.is_empty() && destinations.len() == amounts.len(),
+            TimeLockError::SplitMismatch
+        );
+        let mut split_total: u64 = 0;
+        for (destination, amount) in destinations.iter().zip(&amounts) {
+            require!(*amount > 0, TimeLockError::InvalidAmount);
+            require_wallet_destination(lock_account, destination)?;
+            split_total = split_total.checked_add(*amount).ok_or(TimeLockError::MathOverflow)?;
+        }
+        sync_lock_summary(lock_account, ctx.accounts.lock_summary.as_deref_mut(), true)?;
+
+        top_up_rent_reserve(lock_account, &ctx.accounts.authority, &ctx.accounts.system_program)?;
+        let (principal, fee) = settle_sol_withdrawal(
+            lock_account,
+            &ctx.accounts.config,
+            &mut ctx.accounts.stats,
+            &ctx.accounts.treasury,
+            fee_accounts,
+        )?;
+        ctx.accounts.stats.record_lock_closed();
+        // `principal` is everything above rent, so fitting in it leaves the reserve untouched.
+        let remainder = (principal - fee)
+            .checked_sub(split_total)
+            .ok_or(TimeLockError::SplitExceedsPayout)?;
+        for (destination, amount) in destinations.iter().zip(&amounts) {
+            lock_account.sub_lamports(*amount)?;
+            destination.add_lamports(*amount)?;
+        }
+        lock_account.sub_lamports(remainder)?;
+        ctx.accounts.initializer.add_lamports(remainder)?;
+
+        emit!(SplitWithdrawn {
+            lock_account: lock_account.key(),
+            destinations: destinations.iter().map(|info| info.key()).collect(),
+            amounts,
+            remainder,
+            seq: ctx.accounts.stats.next_event_seq(),
+        });
+        emit_sol_withdrawn(lock_account, &mut ctx.accounts.stats, remainder, fee, 0);
+        refund_rent(&ctx.accounts.lock_account, &ctx.accounts.rent_recipient)?;
+        notify_withdrawal(
+            &ctx.accounts.lock_account,
+            principal - fee,
+            &ctx.accounts.notify_program,
+            &ctx.accounts.allowed_notify_program,
+        )?;
+        msg!(
+            "[withdraw_sol_split] principal={} fee={} split={} remainder={}",
+            principal,
+            fee,
+            split_total,
+            remainder
+        );
+        Ok(())
+    }
+
+    // Opt a SOL lock in or out of auto_release. Streams pay out in chunks through
+    // claim_sol_stream and receipt-held locks pay the holder, so neither can opt in.
+    pub fn set_auto_release(ctx: Context<SetAutoSave>, enabled: bool) -> Result<()> {
+        let lock_account = &mut ctx.accounts.lock_account;
+        require!(lock_account.kind == AssetKind::Sol, TimeLockError::WrongAssetKind);
+        require!(lock_account.stream_periods == 0, TimeLockError::WrongAssetKind);
+        require!(lock_account.receipt_mint.is_none(), TimeLockError::ReceiptRequired);
+        lock_account.auto_release = enabled;
         Ok(())
     }
 
-    // Initialize an SPL timelock for a given mint (e.g., USDC on devnet)
-    pub fn initialize_lock_spl(
-        ctx: Context<InitializeLockSpl>,
-        amount: u64,
-        unlock_timestamp: i64,
+    // Permissionless: release an opted-in SOL lock once it unlocks, under the same gates as
+    // withdraw_sol, and close it. The keeper can't steer anything: the principal goes to the
+    // initializer and the rent to its usual recipient. The keeper gets the configured tip out
+    // of the payout, after the fee. A lock with auto-save on would need its savings lock
+    // opened, which is the owner's to pay for, so auto-save locks must be withdrawn by hand.
+    pub fn auto_release<'info>(ctx: Context<'_, '_, 'info, 'info, AutoRelease<'info>>) -> Result<()> {
+        require_not_frozen(&ctx.accounts.config, Clock::get()?.unix_timestamp)?;
+        let clock = Clock::get()?;
+        let lock_account = &ctx.accounts.lock_account;
+        require!(lock_account.kind == AssetKind::Sol, TimeLockError::WrongAssetKind);
+        require!(
+            lock_account.auto_release && lock_account.auto_save_bps == 0,
+            TimeLockError::AutoReleaseNotEnabled
+        );
+        require!(lock_account.stream_periods == 0, TimeLockError::WrongAssetKind);
+        require!(lock_account.receipt_mint.is_none(), TimeLockError::ReceiptRequired);
+        let unlock = evaluate_unlock(lock_account, &clock, None, &ctx.accounts.condition, &[])?;
+        require!(unlock.time_met, TimeLockError::TimeLockNotExpired);
+        require_settled(lock_account, &ctx.accounts.config, clock.slot)?;
+        require_wallet_destination(lock_account, &ctx.accounts.initializer)?;
+        require!(unlock.condition_met, TimeLockError::ConditionNotMet);
+        require!(unlock.goal_met, TimeLockError::GoalNotReached);
+        require!(unlock.password_met, TimeLockError::PasswordRequired);
+        sync_lock_summary(lock_account, ctx.accounts.lock_summary.as_deref_mut(), true)?;
+
+        let (principal, fee) = settle_sol_withdrawal(
+            lock_account,
+            &ctx.accounts.config,
+            &mut ctx.accounts.stats,
+            &ctx.accounts.treasury,
+            split_off_fee_accounts(&ctx.accounts.config, ctx.remaining_accounts)?.1,
+        )?;
+        ctx.accounts.stats.record_lock_closed();
+
+        let tip = ctx.accounts.config.keeper_tip_lamports.min(principal - fee);
+        lock_account.sub_lamports(tip)?;
+        ctx.accounts.keeper.add_lamports(tip)?;
+        let principal_returned = principal - fee - tip;
+        lock_account.sub_lamports(principal_returned)?;
+        ctx.accounts.initializer.add_lamports(principal_returned)?;
+        emit_sol_withdrawn(lock_account, &mut ctx.accounts.stats, principal_returned, fee, 0);
+
+        refund_rent(&ctx.accounts.lock_account, &ctx.accounts.rent_recipient)?;
+        notify_withdrawal(
+            &ctx.accounts.lock_account,
+            principal_returned,
+            &ctx.accounts.notify_program,
+            &ctx.accounts.allowed_notify_program,
+        )?;
+        emit!(AutoReleased {
+            lock_account: ctx.accounts.lock_account.key(),
+            keeper: ctx.accounts.keeper.key(),
+            tip,
+            seq: ctx.accounts.stats.next_event_seq(),
+        });
+        // close = initializer takes the rent reserve unless refund_rent already paid it out.
+        Ok(())
+    }
+
+    // Route `auto_save_bps` of this SOL lock's payout into the initializer's savings lock when
+    // `withdraw_sol` runs. The savings lock is created on first use with
+    // `savings_unlock_timestamp`, which must come after this lock's own unlock. Pass 0 bps to
+    // turn auto-save off. Receipt withdrawals pay the holder in full and ignore this.
+    pub fn set_auto_save(
+        ctx: Context<SetAutoSave>,
+        auto_save_bps: u16,
+        savings_unlock_timestamp: i64,
+    ) -> Result<()> {
+        let lock_account = &mut ctx.accounts.lock_account;
+        require!(lock_account.kind == AssetKind::Sol, TimeLockError::WrongAssetKind);
+        require!(lock_account.receipt_mint.is_none(), TimeLockError::ReceiptRequired);
+        require!(
+            auto_save_bps == 0 || password_met(lock_account),
+            TimeLockError::ReleaseModeConflict
+        );
+        require!(
+            auto_save_bps as u64 <= BPS_DENOMINATOR,
+            TimeLockError::InvalidAutoSave
+        );
+        if auto_save_bps > 0 {
+            let now = Clock::get()?.unix_timestamp;
+            require!(
+                unlock_in_future(savings_unlock_timestamp, now)
+                    && savings_unlock_timestamp > lock_account.unlock_timestamp,
+                TimeLockError::UnlockInPast
+            );
+        }
+        lock_account.auto_save_bps = auto_save_bps;
+        lock_account.savings_unlock_timestamp = savings_unlock_timestamp;
+        Ok(())
+    }
+
+    // Withdraw a savings lock after its unlock, closing it back to the initializer.
+    pub fn withdraw_savings<'info>(ctx: Context<'_, '_, 'info, 'info, WithdrawSavings<'info>>) -> Result<()> {
+        require_not_frozen(&ctx.accounts.config, Clock::get()?.unix_timestamp)?;
+        let clock = Clock::get()?;
+        let lock_account = &ctx.accounts.lock_account;
+        require!(lock_account.kind == AssetKind::Savings, TimeLockError::WrongAssetKind);
+        require!(
+            lock_unlocked(lock_account, &clock),
+            TimeLockError::TimeLockNotExpired
+        );
+
+        let (principal, fee) = settle_sol_withdrawal(
+            lock_account,
+            &ctx.accounts.config,
+            &mut ctx.accounts.stats,
+            &ctx.accounts.treasury,
+            split_off_fee_accounts(&ctx.accounts.config, ctx.remaining_accounts)?.1,
+        )?;
+        ctx.accounts.stats.record_lock_closed();
+
+        msg!("[withdraw_savings] principal={} fee={}", principal, fee);
+        // close = initializer returns the remaining lamports.
+        Ok(())
+    }
+
+    // Add SOL to a funded lock and move its unlock time in one step. Everything is validated
+    // before any lamports move, so a rejected extension never leaves the deposit behind under
+    // the old unlock time.
+    pub fn topup_and_extend_sol(
+        ctx: Context<FundSolLock>,
+        additional_lamports: u64,
+        new_unlock_timestamp: i64,
     ) -> Result<()> {
-        require!(amount > 0, TimeLockError::InvalidAmount);
         let now = Clock::get()?.unix_timestamp;
-        require!(unlock_timestamp > now, TimeLockError::UnlockInPast);
+        let lock_account = &ctx.accounts.lock_account;
+        require!(lock_account.kind == AssetKind::Sol, TimeLockError::WrongAssetKind);
+        require!(additional_lamports > 0, TimeLockError::InvalidAmount);
+        require!(lock_account.unlock_slot.is_none(), TimeLockError::InvalidUnlockMode);
+        require!(
+            new_unlock_timestamp >= lock_account.unlock_timestamp,
+            TimeLockError::CannotShortenLock
+        );
+        require!(unlock_in_future(new_unlock_timestamp, now), TimeLockError::UnlockInPast);
+        let funded = lamports_above_rent(&lock_account.to_account_info())?;
+        require!(funded >= lock_account.amount, TimeLockError::FundingMismatch);
+        let new_amount = lock_account
+            .amount
+            .checked_add(additional_lamports)
+            .ok_or(TimeLockError::MathOverflow)?;
+        require_within_max(new_amount, ctx.accounts.config.max_lock_lamports)?;
+        let new_total = tvl_after_deposit(&ctx.accounts.config, &ctx.accounts.stats, additional_lamports)?;
 
-        let initializer = &ctx.accounts.initializer;
+        let cpi_ctx = CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            anchor_lang::system_program::Transfer {
+                from: ctx.accounts.initializer.to_account_info(),
+                to: ctx.accounts.lock_account.to_account_info(),
+            },
+        );
+        anchor_lang::system_program::transfer(cpi_ctx, additional_lamports)?;
+
+        ctx.accounts.stats.total_sol_locked = new_total;
         let lock_account = &mut ctx.accounts.lock_account;
+        let old_unlock_timestamp = lock_account.unlock_timestamp;
+        lock_account.amount = new_amount;
+        lock_account.unlock_timestamp = new_unlock_timestamp;
 
-        // Persist state
-        lock_account.initializer = initializer.key();
-        lock_account.amount = amount;
-        lock_account.unlock_timestamp = unlock_timestamp;
-        lock_account.bump = ctx.bumps.lock_account;
-        lock_account.kind = AssetKind::Spl;
-        lock_account.mint = Some(ctx.accounts.mint.key());
+        if ctx.accounts.config.log_level >= LOG_FUNDING {
+            emit!(SolToppedUpAndExtended {
+                lock_account: lock_account.key(),
+                additional_lamports,
+                amount: new_amount,
+                old_unlock_timestamp,
+                unlock_timestamp: new_unlock_timestamp,
+                seq: ctx.accounts.stats.next_event_seq(),
+            });
+        }
+        apply_auto_extend(lock_account, &mut ctx.accounts.stats, &ctx.accounts.config, now)?;
+        emit_if_goal_reached(lock_account, &mut ctx.accounts.stats);
+        sync_lock_summary(lock_account, ctx.accounts.lock_summary.as_deref_mut(), false)?;
+        Ok(())
+    }
 
-        // Transfer SPL tokens from user ATA to vault ATA with PDA signer as authority after init.
-        let cpi_accounts = SplTransfer {
-            from: ctx.accounts.user_ata.to_account_info(),
-            to: ctx.accounts.vault_ata.to_account_info(),
-            authority: initializer.to_account_info(),
-        };
-        let cpi_ctx = CpiContext::new(ctx.accounts.token_program.to_account_info(), cpi_accounts);
-        token::transfer(cpi_ctx, amount)?;
+    // Make every later deposit into this SOL lock push the unlock out to at least
+    // `now + auto_extend_seconds`. Extension never shortens the lock. Pass 0 to turn it off.
+    pub fn set_auto_extend(ctx: Context<SetAutoSave>, auto_extend_seconds: i64) -> Result<()> {
+        let lock_account = &mut ctx.accounts.lock_account;
+        require!(lock_account.kind == AssetKind::Sol, TimeLockError::WrongAssetKind);
+        require!(auto_extend_seconds >= 0, TimeLockError::InvalidDuration);
+        // Extending moves the unlock timestamp, which a slot-mode lock doesn't use.
+        require!(lock_account.unlock_slot.is_none(), TimeLockError::InvalidUnlockMode);
+        lock_account.auto_extend_seconds = auto_extend_seconds;
+        Ok(())
+    }
+
+    // Commit a funded SOL lock to `count` further deposits of `contribution_lamports`, one every
+    // `interval_secs` from `first_at`, all due before the unlock. Each fund_sol_lock deposit is
+    // then matched to the schedule: it must be exactly one contribution, it is refused before the
+    // next one falls due, and a late deposit fills the interval it lands in, marking every
+    // interval it skipped over as missed. The last contribution stays open until the unlock. Set
+    // once, before any contribution.
+    pub fn set_contribution_schedule(
+        ctx: Context<SetAutoSave>,
+        contribution_lamports: u64,
+        interval_secs: i64,
+        count: u16,
+        first_at: i64,
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let lock_account = &mut ctx.accounts.lock_account;
+        require!(lock_account.kind == AssetKind::Sol, TimeLockError::WrongAssetKind);
+        require!(lock_account.funded_at > 0, TimeLockError::FundingMismatch);
+        require!(lock_account.contribution_count == 0, TimeLockError::InvalidContributionSchedule);
+        // Streams vest a fixed amount, and slot-mode locks have no unlock time to schedule against.
+        require!(lock_account.stream_periods == 0, TimeLockError::InvalidContributionSchedule);
+        require!(lock_account.unlock_slot.is_none(), TimeLockError::InvalidUnlockMode);
+        require!(contribution_lamports > 0, TimeLockError::InvalidAmount);
+        require!(
+            interval_secs > 0 && count > 0 && count <= MAX_CONTRIBUTIONS,
+            TimeLockError::InvalidContributionSchedule
+        );
+        let last_due = interval_secs
+            .checked_mul(count as i64 - 1)
+            .and_then(|span| first_at.checked_add(span))
+            .ok_or(TimeLockError::MathOverflow)?;
+        require!(
+            first_at >= now && last_due < lock_account.unlock_timestamp,
+            TimeLockError::InvalidContributionSchedule
+        );
+        lock_account.contribution_amount = contribution_lamports;
+        lock_account.contribution_interval_secs = interval_secs;
+        lock_account.contribution_count = count;
+        lock_account.contributions_made = 0;
+        lock_account.contributions_missed = 0;
+        lock_account.next_contribution_at = first_at;
+        Ok(())
+    }
+
+    // Attach a one-time savings goal to a SOL lock. Progress is the funded `amount`, so the goal
+    // is reached by funding or topping up past `goal_amount`. With `require_goal_for_withdrawal`
+    // the lock can't be withdrawn or claimed until then, but it can always be topped up.
+    pub fn set_goal(
+        ctx: Context<SetGoal>,
+        goal_amount: u64,
+        goal_label: [u8; 32],
+        require_goal_for_withdrawal: bool,
+    ) -> Result<()> {
+        let lock_account = &mut ctx.accounts.lock_account;
+        require!(lock_account.kind == AssetKind::Sol, TimeLockError::WrongAssetKind);
+        require!(goal_amount > 0, TimeLockError::InvalidAmount);
+        require!(lock_account.goal_amount == 0, TimeLockError::GoalAlreadySet);
+        lock_account.goal_amount = goal_amount;
+        lock_account.goal_label = goal_label;
+        lock_account.goal_required = require_goal_for_withdrawal;
+
+        msg!("[set_goal] lock_account={} goal_amount={}", lock_account.key(), goal_amount);
+        emit_if_goal_reached(lock_account, &mut ctx.accounts.stats);
+        Ok(())
+    }
+
+    // Hand the right to a SOL or SPL lock's rent refund to `new_recipient`, without touching the
+    // funds. Signed by whoever holds that right now: the initializer until it is first assigned.
+    // On close the lock account's rent-exempt reserve goes to the recipient and everything above
+    // it to the usual close target. An SPL vault's own rent still follows the initializer.
+    pub fn assign_rent_recipient(ctx: Context<AssignRentRecipient>, new_recipient: Pubkey) -> Result<()> {
+        let lock_account = &mut ctx.accounts.lock_account;
+        require!(
+            matches!(lock_account.kind, AssetKind::Sol | AssetKind::Spl),
+            TimeLockError::WrongAssetKind
+        );
+        require!(password_met(lock_account), TimeLockError::ReleaseModeConflict);
+        let old_recipient = lock_account.rent_recipient.unwrap_or(lock_account.initializer);
+        require_keys_eq!(
+            ctx.accounts.rent_recipient.key(),
+            old_recipient,
+            TimeLockError::Unauthorized
+        );
+        lock_account.rent_recipient = Some(new_recipient);
+
+        emit!(RentRecipientChanged {
+            lock_account: lock_account.key(),
+            old_recipient,
+            new_recipient,
+            seq: ctx.accounts.stats.next_event_seq(),
+        });
+        Ok(())
+    }
+
+    // Name a program for withdraw_sol and withdraw_spl to notify (see `notify_withdrawal`), or
+    // pass None to stop. Clearing is always allowed, so a notify program that starts failing
+    // can't trap the lock's funds.
+    pub fn set_notify_program(ctx: Context<SetNotifyProgram>, notify_program: Option<Pubkey>) -> Result<()> {
+        require!(
+            notify_program.is_none() || password_met(&ctx.accounts.lock_account),
+            TimeLockError::ReleaseModeConflict
+        );
+        ctx.accounts.lock_account.notify_program = notify_program;
+        Ok(())
+    }
+
+    // SPL only: require partial_withdraw_and_relock_spl to pay out whole multiples of `step`
+    // base units, or 0 for any amount. The step can't exceed the lock. A full withdraw_spl
+    // always drains whatever is left, step-aligned or not.
+    pub fn set_withdraw_step(ctx: Context<SetNotifyProgram>, step: u64) -> Result<()> {
+        let lock_account = &mut ctx.accounts.lock_account;
+        require!(lock_account.kind == AssetKind::Spl, TimeLockError::WrongAssetKind);
+        require!(step <= lock_account.amount, TimeLockError::InvalidAmount);
+        lock_account.withdraw_step = step;
+        Ok(())
+    }
+
+    // SPL only: allow at most `max` partial_withdraw_and_relock_spl calls over the lock's life,
+    // counting across relocks, since each relock is the same lock carrying on. The final
+    // withdraw_spl drain doesn't count, so a lock can always be emptied. Once set the cap can
+    // only be tightened, and never below the withdrawals already made.
+    pub fn set_max_withdrawals(ctx: Context<SetNotifyProgram>, max: u32) -> Result<()> {
+        let lock_account = &mut ctx.accounts.lock_account;
+        require!(lock_account.kind == AssetKind::Spl, TimeLockError::WrongAssetKind);
+        require!(
+            max > 0
+                && max >= lock_account.withdrawals_made
+                && (lock_account.max_withdrawals == 0 || max <= lock_account.max_withdrawals),
+            TimeLockError::InvalidAmount
+        );
+        lock_account.max_withdrawals = max;
+        Ok(())
+    }
+
+    // Let `delegate` sign withdraw_sol, withdraw_spl and claim_vested_spl for this lock, or pass
+    // None to revoke it. The payout still goes to the initializer's own accounts, so a delegate
+    // (say, a keeper bot) can trigger a matured withdrawal but never redirect it. Trial
+    // revocation and partial relocks stay with the initializer.
+    pub fn set_withdraw_delegate(ctx: Context<SetNotifyProgram>, delegate: Option<Pubkey>) -> Result<()> {
+        let lock_account = &mut ctx.accounts.lock_account;
+        require!(
+            matches!(lock_account.kind, AssetKind::Sol | AssetKind::Spl),
+            TimeLockError::WrongAssetKind
+        );
+        require!(
+            !lock_account.authorities_renounced || delegate.is_none(),
+            TimeLockError::AuthorityRenounced
+        );
+        lock_account.withdraw_delegate = delegate;
+        Ok(())
+    }
+
+    // Align a SOL stream or SPL vesting schedule to `anchor`: chunks then vest each time the
+    // clock crosses `anchor + k * stream_period_secs`, starting with the first such boundary at
+    // or after the unlock, rather than counting periods from the unlock itself. With a 30-day
+    // period and an anchor at midnight on the 1st, that's "the 1st of every month" in fixed
+    // lengths, no calendar needed. Only before the schedule starts, since it moves every chunk.
+    pub fn set_stream_anchor(ctx: Context<SetNotifyProgram>, anchor: i64) -> Result<()> {
+        let lock_account = &mut ctx.accounts.lock_account;
+        require!(lock_account.stream_periods > 0, TimeLockError::NotAStream);
+        require!(!lock_unlocked(lock_account, &Clock::get()?), TimeLockError::UnlockInPast);
+        lock_account.stream_anchor = Some(anchor);
+        lock_account.next_claim_at = stream_start(lock_account)?;
+        Ok(())
+    }
+
+    // Turn a vesting SPL lock into a DCA schedule: instead of equal chunks, `tranche_amount` base
+    // units are released per vesting period from the unlock on, the last tranche taking whatever
+    // is left. The period count follows from the amount, so the vesting_periods passed at init
+    // only needs to be non-zero. A keeper set as withdraw delegate can claim each tranche with
+    // claim_vested_spl; the owner can instead route it through claim_vested_spl_with_swap. Only
+    // before the unlock, so a running schedule can't be sped up.
+    pub fn set_dca_tranche(ctx: Context<SetNotifyProgram>, tranche_amount: u64) -> Result<()> {
+        let lock_account = &mut ctx.accounts.lock_account;
+        require!(lock_account.kind == AssetKind::Spl, TimeLockError::WrongAssetKind);
+        require!(lock_account.stream_periods > 0, TimeLockError::NotAStream);
+        require!(!lock_unlocked(lock_account, &Clock::get()?), TimeLockError::UnlockInPast);
+        require!(lock_account.claim_bps == 0, TimeLockError::ReleaseModeConflict);
+        require!(
+            tranche_amount > 0 && tranche_amount <= lock_account.amount,
+            TimeLockError::InvalidAmount
+        );
+        lock_account.stream_periods = u16::try_from(lock_account.amount.div_ceil(tranche_amount))
+            .map_err(|_| error!(TimeLockError::InvalidAmount))?;
+        lock_account.tranche_amount = tranche_amount;
+        Ok(())
+    }
+
+    // Release a vesting SPL lock as a share of its vault rather than fixed chunks: each period
+    // claim_vested_spl pays `claim_bps` of the vault balance at claim time, so a yield-bearing or
+    // rebasing vault's growth is released along with it, and the final period drains the rest.
+    // Periods missed between claims are paid together, as that many shares of the balance. Only
+    // before the unlock, and not alongside a DCA tranche. Swap claims don't support it.
+    pub fn set_claim_bps(ctx: Context<SetNotifyProgram>, claim_bps: u16) -> Result<()> {
+        let lock_account = &mut ctx.accounts.lock_account;
+        require!(lock_account.kind == AssetKind::Spl, TimeLockError::WrongAssetKind);
+        require!(lock_account.stream_periods > 0, TimeLockError::NotAStream);
+        require!(!lock_unlocked(lock_account, &Clock::get()?), TimeLockError::UnlockInPast);
+        require!(lock_account.tranche_amount == 0, TimeLockError::ReleaseModeConflict);
+        require!(
+            claim_bps > 0 && claim_bps as u64 <= BPS_DENOMINATOR,
+            TimeLockError::InvalidAmount
+        );
+        lock_account.claim_bps = claim_bps;
+        lock_account.percent_claims = 0;
+        Ok(())
+    }
+
+    // Create a condition flag: a one-bit gate, initially unreleased, that only `authority` can
+    // release. `id` lets one authority keep several. Locks opt in with set_condition_account.
+    pub fn create_condition(ctx: Context<CreateCondition>, id: u64) -> Result<()> {
+        let condition = &mut ctx.accounts.condition;
+        condition.authority = ctx.accounts.authority.key();
+        condition.id = id;
+        condition.released = false;
+        condition.bump = ctx.bumps.condition;
+        Ok(())
+    }
+
+    // Authority only: release a condition flag. Release is permanent, so a lock that has seen
+    // its condition met can't be trapped again.
+    pub fn release_condition(ctx: Context<ReleaseCondition>) -> Result<()> {
+        ctx.accounts.condition.released = true;
+        emit!(ConditionReleased {
+            condition: ctx.accounts.condition.key(),
+            authority: ctx.accounts.authority.key(),
+            seq: ctx.accounts.stats.next_event_seq(),
+        });
+        Ok(())
+    }
+
+    // Gate a SOL or SPL lock's withdrawals on a condition flag as well as its unlock. This only
+    // ever adds a requirement, so it can be set once and never changed or cleared; every
+    // withdrawal path then needs the flag passed as `condition`, released.
+    pub fn set_condition_account(ctx: Context<SetConditionAccount>) -> Result<()> {
+        let lock_account = &mut ctx.accounts.lock_account;
+        require!(
+            matches!(lock_account.kind, AssetKind::Sol | AssetKind::Spl),
+            TimeLockError::WrongAssetKind
+        );
+        require!(lock_account.condition_account.is_none(), TimeLockError::ConditionAlreadySet);
+        require!(password_met(lock_account), TimeLockError::ReleaseModeConflict);
+        lock_account.condition_account = Some(ctx.accounts.condition.key());
+        Ok(())
+    }
+
+    // SOL only: if the lock is still unclaimed `grace_seconds` after it unlocks (after its last
+    // period vests, for a stream), let anyone donate its funds to `charity`, which must be
+    // allow-listed, and close it. Until then the owner withdraws as usual. Setting it again
+    // replaces the charity and grace. Slot locks have no unlock timestamp to count the grace
+    // from, and a quorum lock's co-signers would be bypassed by the donation, so both are refused,
+    // as is a password lock, whose SOL is promised to whoever holds the secret.
+    pub fn set_charity_fallback(ctx: Context<SetCharityFallback>, grace_seconds: i64) -> Result<()> {
+        let lock_account = &mut ctx.accounts.lock_account;
+        require!(lock_account.kind == AssetKind::Sol, TimeLockError::WrongAssetKind);
+        require!(lock_account.unlock_slot.is_none(), TimeLockError::InvalidUnlockMode);
+        require!(lock_account.quorum_threshold == 0, TimeLockError::ReleaseModeConflict);
+        require!(password_met(lock_account), TimeLockError::ReleaseModeConflict);
+        require!(grace_seconds > 0, TimeLockError::InvalidDuration);
+        lock_account.charity_fallback = Some(ctx.accounts.allowed_charity.address);
+        lock_account.charity_grace_seconds = grace_seconds;
+        Ok(())
+    }
+
+    // Cancel a SOL lock during its trial window: refund everything and close the account. Once
+    // `funded_at + trial_seconds` has passed the lock is irrevocable until it unlocks.
+    pub fn revoke_within_trial(ctx: Context<WithdrawSol>) -> Result<()> {
+        require_not_frozen(&ctx.accounts.config, Clock::get()?.unix_timestamp)?;
+        let clock = Clock::get()?;
+        let lock_account = &ctx.accounts.lock_account;
+        require!(lock_account.kind == AssetKind::Sol, TimeLockError::WrongAssetKind);
+        // Revoking is the owner's call, not a matured withdrawal a delegate could trigger.
+        require_keys_eq!(ctx.accounts.authority.key(), lock_account.initializer, TimeLockError::NotAuthorized);
+        require!(
+            lock_account.funded_at > 0
+                && clock.unix_timestamp <= lock_account.funded_at.saturating_add(lock_account.trial_seconds),
+            TimeLockError::TrialExpired
+        );
+        require_wallet_destination(lock_account, &ctx.accounts.initializer)?;
+        require_condition(lock_account, &ctx.accounts.condition)?;
+        require!(lock_account.receipt_mint.is_none(), TimeLockError::ReceiptRequired);
+        // Taking back a password lock's SOL would pull it out from under whoever holds the secret.
+        require!(password_met(lock_account), TimeLockError::PasswordRequired);
+        sync_lock_summary(lock_account, ctx.accounts.lock_summary.as_deref_mut(), true)?;
+
+        let principal = lamports_above_rent(&lock_account.to_account_info())?;
+        let stats = &mut ctx.accounts.stats;
+        stats.total_sol_locked = stats.total_sol_locked.saturating_sub(principal);
+        stats.record_withdrawal(0);
+        stats.record_lock_closed();
+
+        refund_rent(lock_account, &ctx.accounts.rent_recipient)?;
+        msg!("[revoke_within_trial] lock_account={} refunded={}", lock_account.key(), principal);
+        // close = initializer returns the lamports.
+        Ok(())
+    }
+
+    // Release whatever a streaming SOL lock has vested since the last claim, and cache when the
+    // next chunk unlocks so clients don't need to redo the period math.
+    pub fn claim_sol_stream<'info>(ctx: Context<'_, '_, 'info, 'info, ClaimSolStream<'info>>) -> Result<()> {
+        require_not_frozen(&ctx.accounts.config, Clock::get()?.unix_timestamp)?;
+        let now = Clock::get()?.unix_timestamp;
+        let lock_account = &ctx.accounts.lock_account;
+        require!(lock_account.kind == AssetKind::Sol, TimeLockError::WrongAssetKind);
+        require!(lock_account.stream_periods > 0, TimeLockError::NotAStream);
+        require!(
+            lock_unlocked(lock_account, &Clock::get()?),
+            TimeLockError::TimeLockNotExpired
+        );
+        require_settled(lock_account, &ctx.accounts.config, Clock::get()?.slot)?;
+        require!(lock_account.receipt_mint.is_none(), TimeLockError::ReceiptRequired);
+        require_wallet_destination(lock_account, &ctx.accounts.initializer)?;
+        require_condition(lock_account, &ctx.accounts.condition)?;
+        require!(goal_met(lock_account), TimeLockError::GoalNotReached);
+        require!(password_met(lock_account), TimeLockError::PasswordRequired);
+
+        let (vested, next_claim_at) = stream_vested(lock_account, now)?;
+        let claimed = vested.saturating_sub(lock_account.stream_claimed);
+        require!(claimed > 0, TimeLockError::NothingToClaim);
+
+        let (fee, net) = split_withdrawal(&ctx.accounts.config, lock_account, claimed)?;
+        let fee_to = FeeTo {
+            config: &ctx.accounts.config,
+            treasury: Some(ctx.accounts.treasury.to_account_info()),
+            split_accounts: split_off_fee_accounts(&ctx.accounts.config, ctx.remaining_accounts)?.1,
+        };
+        pay_out_lamports(
+            &lock_account.to_account_info(),
+            &fee_to,
+            Some(&ctx.accounts.initializer),
+            (fee, net),
+        )?;
+
+        let stats = &mut ctx.accounts.stats;
+        stats.total_sol_locked = stats.total_sol_locked.saturating_sub(claimed);
+        stats.record_withdrawal(fee);
+
+        let lock_account = &mut ctx.accounts.lock_account;
+        lock_account.stream_claimed = vested;
+        lock_account.next_claim_at = next_claim_at;
+        sync_lock_summary(lock_account, ctx.accounts.lock_summary.as_deref_mut(), vested == lock_account.amount)?;
+
+        emit!(SolStreamClaimed {
+            lock_account: lock_account.key(),
+            claimed,
+            fee,
+            total_claimed: vested,
+            next_claim_at,
+            seq: ctx.accounts.stats.next_event_seq(),
+        });
+        Ok(())
+    }
+
+    // Release a quorum lock before (or after) its unlock: at least `quorum_threshold` of its
+    // designated signers must sign, passed as remaining accounts. Pays out like withdraw_sol,
+    // to the initializer, who needn't sign. Each designated signer counts once however many
+    // times it is passed. Auto-save doesn't apply.
+    pub fn quorum_release<'info>(ctx: Context<'_, '_, 'info, 'info, QuorumRelease<'info>>) -> Result<()> {
+        require_not_frozen(&ctx.accounts.config, Clock::get()?.unix_timestamp)?;
+        let lock_account = &ctx.accounts.lock_account;
+        require!(lock_account.kind == AssetKind::Sol, TimeLockError::WrongAssetKind);
+        require!(lock_account.quorum_threshold > 0, TimeLockError::InvalidQuorum);
+        require!(lock_account.receipt_mint.is_none(), TimeLockError::ReceiptRequired);
+        require_wallet_destination(lock_account, &ctx.accounts.initializer)?;
+        let (signers, fee_accounts) =
+            split_off_fee_accounts(&ctx.accounts.config, ctx.remaining_accounts)?;
+        let unlock = evaluate_unlock(
+            lock_account,
+            &Clock::get()?,
+            None,
+            &ctx.accounts.condition,
+            batch_accounts(signers)?,
+        )?;
+        require!(unlock.condition_met, TimeLockError::ConditionNotMet);
+        require!(unlock.goal_met, TimeLockError::GoalNotReached);
+        require!(unlock.password_met, TimeLockError::PasswordRequired);
+        require!(unlock.quorum_met, TimeLockError::ThresholdNotMet);
+        require_settled(lock_account, &ctx.accounts.config, Clock::get()?.slot)?;
+        let approvals = unlock.approvals;
+        sync_lock_summary(lock_account, ctx.accounts.lock_summary.as_deref_mut(), true)?;
+
+        let (principal, fee) = settle_sol_withdrawal(
+            lock_account,
+            &ctx.accounts.config,
+            &mut ctx.accounts.stats,
+            &ctx.accounts.treasury,
+            fee_accounts,
+        )?;
+        ctx.accounts.stats.record_lock_closed();
+        refund_rent(&ctx.accounts.lock_account, &ctx.accounts.rent_recipient)?;
+
+        emit!(QuorumReleased {
+            lock_account: ctx.accounts.lock_account.key(),
+            approvals: approvals as u8,
+            principal,
+            fee,
+            seq: ctx.accounts.stats.next_event_seq(),
+        });
+        // close = initializer returns the remaining lamports.
+        Ok(())
+    }
+
+    // Permissionless: once a SOL lock with a charity fallback has sat unclaimed for its whole
+    // grace period past unlock, or past its final vest for a stream that's still being claimed,
+    // send everything above rent to the charity and close the lock.
+    // The charity must still be allow-listed. No fee is taken from a donation. The rent goes
+    // back to the owner (or their rent_recipient) as on any other close. Receipt-held locks
+    // belong to the holder, who may not have abandoned them, and are refused.
+    pub fn donate_abandoned_sol(ctx: Context<DonateAbandonedSol>) -> Result<()> {
+        require_not_frozen(&ctx.accounts.config, Clock::get()?.unix_timestamp)?;
+        let now = Clock::get()?.unix_timestamp;
+        let lock_account = &ctx.accounts.lock_account;
+        require!(lock_account.kind == AssetKind::Sol, TimeLockError::WrongAssetKind);
+        require!(lock_account.receipt_mint.is_none(), TimeLockError::ReceiptRequired);
+        require!(lock_account.quorum_threshold == 0, TimeLockError::ReleaseModeConflict);
+        // A password lock's SOL is promised to whoever holds the secret.
+        require!(password_met(lock_account), TimeLockError::PasswordRequired);
+        let abandoned_at = final_release_at(lock_account)?
+            .checked_add(lock_account.charity_grace_seconds)
+            .ok_or(TimeLockError::MathOverflow)?;
+        require!(is_unlocked(abandoned_at, now), TimeLockError::NotAbandoned);
+        sync_lock_summary(lock_account, ctx.accounts.lock_summary.as_deref_mut(), true)?;
+
+        let amount = lamports_above_rent(&lock_account.to_account_info())?;
+        lock_account.sub_lamports(amount)?;
+        ctx.accounts.charity.add_lamports(amount)?;
+        let stats = &mut ctx.accounts.stats;
+        stats.total_sol_locked = stats.total_sol_locked.saturating_sub(amount);
+        stats.record_withdrawal(0);
+        stats.record_lock_closed();
+        refund_rent(&ctx.accounts.lock_account, &ctx.accounts.rent_recipient)?;
+
+        emit!(DonatedFromAbandoned {
+            lock_account: ctx.accounts.lock_account.key(),
+            charity: ctx.accounts.charity.key(),
+            amount,
+            seq: ctx.accounts.stats.next_event_seq(),
+        });
+        // close = initializer returns the remaining rent.
+        Ok(())
+    }
+
+    // Permissionless cleanup: close fully drained SOL streams that were never closed, returning
+    // each lock's rent to its owner. Remaining accounts are pairs [lock_account, payee], where
+    // payee must be the lock's rent_recipient if it has one and its initializer otherwise, so
+    // the caller has nothing to gain. Locks that aren't closable yet (undrained, receipt-held,
+    // tracked by a summary, SPL-backed) are skipped; an account that isn't a lock or a payee
+    // that doesn't match fails the whole batch.
+    pub fn sweep_closable_locks<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SweepClosableLocks<'info>>,
+    ) -> Result<()> {
+        let accounts = batch_accounts(ctx.remaining_accounts)?;
+        require!(accounts.len() % 2 == 0, TimeLockError::BatchMalformed);
+
+        let (mut closed, mut skipped) = (0u32, 0u32);
+        for pair in accounts.chunks_exact(2) {
+            let (lock_info, payee) = (&pair[0], &pair[1]);
+            let lock_account = Account::<TimeLockAccount>::try_from(lock_info)?;
+            let expected_payee = lock_account.rent_recipient.unwrap_or(lock_account.initializer);
+            require_keys_eq!(payee.key(), expected_payee, TimeLockError::Unauthorized);
+            if !is_closable(&lock_account) {
+                skipped += 1;
+                continue;
+            }
+            lock_account.close(payee.clone())?;
+            ctx.accounts.stats.record_lock_closed();
+            closed += 1;
+        }
+
+        msg!("[sweep_closable_locks] closed={} skipped={}", closed, skipped);
+        emit!(LocksSwept {
+            closed,
+            skipped,
+            seq: ctx.accounts.stats.next_event_seq(),
+        });
+        Ok(())
+    }
+
+    // Tag a lock as part of a named group, such as every lock in one vesting grant, or pass None
+    // to leave it. Joining a group opts the lock into release_group: once it has matured, anyone
+    // may release it along with the rest of the group, always to its initializer.
+    pub fn set_group(ctx: Context<SetNotifyProgram>, group_id: Option<[u8; 16]>) -> Result<()> {
+        ctx.accounts.lock_account.group_id = group_id;
+        Ok(())
+    }
+
+    // Gate an unmatured SOL lock behind a password: `password_hash` is the sha256 of a secret,
+    // and once the lock matures whoever knows the secret can claim it with claim_with_password,
+    // initializer included. Every other way of paying the lock out (withdraw_sol, auto_release,
+    // revoke_within_trial, ...) is refused from then on. Set once; it can't be changed or
+    // cleared, or the initializer could take back a gift already promised to somebody else. For
+    // the same reason nothing password_claimable rules out can be added to the lock afterwards.
+    pub fn set_password(ctx: Context<SetNotifyProgram>, password_hash: [u8; 32]) -> Result<()> {
+        let lock_account = &mut ctx.accounts.lock_account;
+        require!(lock_account.kind == AssetKind::Sol, TimeLockError::WrongAssetKind);
+        require!(!lock_unlocked(lock_account, &Clock::get()?), TimeLockError::UnlockInPast);
+        require!(password_hash != [0u8; 32], TimeLockError::InvalidAmount);
+        require!(password_met(lock_account), TimeLockError::PasswordAlreadySet);
+        // A lock claim_with_password won't take would have no way out at all.
+        require!(password_claimable(lock_account), TimeLockError::ReleaseModeConflict);
+        lock_account.password_hash = password_hash;
+        Ok(())
+    }
+
+    // Permissionless once the lock has matured: pay a password lock's SOL, less the usual fee,
+    // to the claimer, who proves the secret by passing its preimage. The rent reserve still goes
+    // back to the initializer. Only plain locks qualify; see password_claimable. The preimage is never logged or emitted,
+    // but it is in the transaction itself, so it's public from the moment the claim is sent and
+    // offers no secrecy against someone watching for it; treat the password as a bearer claim.
+    pub fn claim_with_password<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ClaimWithPassword<'info>>,
+        preimage: Vec<u8>,
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        require_not_frozen(&ctx.accounts.config, clock.unix_timestamp)?;
+        let lock_account = &ctx.accounts.lock_account;
+        require!(!password_met(lock_account), TimeLockError::NoPassword);
+        require!(
+            hash(&preimage).to_bytes() == lock_account.password_hash,
+            TimeLockError::WrongPassword
+        );
+        require!(lock_unlocked(lock_account, &clock), TimeLockError::TimeLockNotExpired);
+        require!(password_claimable(lock_account), TimeLockError::ReleaseModeConflict);
+        require!(goal_met(lock_account), TimeLockError::GoalNotReached);
+        require_settled(lock_account, &ctx.accounts.config, clock.slot)?;
+        require_wallet_destination(lock_account, &ctx.accounts.claimer)?;
+
+        let (principal, fee) = settle_sol_withdrawal(
+            lock_account,
+            &ctx.accounts.config,
+            &mut ctx.accounts.stats,
+            &ctx.accounts.treasury,
+            split_off_fee_accounts(&ctx.accounts.config, ctx.remaining_accounts)?.1,
+        )?;
+        ctx.accounts.stats.record_lock_closed();
+        let claimed = principal - fee;
+        lock_account.sub_lamports(claimed)?;
+        ctx.accounts.claimer.add_lamports(claimed)?;
+        emit!(PasswordClaimed {
+            lock_account: lock_account.key(),
+            claimer: ctx.accounts.claimer.key(),
+            amount: claimed,
+            fee_taken: fee,
+            seq: ctx.accounts.stats.next_event_seq(),
+        });
+        msg!("[claim_with_password] lock_account={} claimed={}", lock_account.key(), claimed);
+        // close = initializer takes the rent reserve.
+        Ok(())
+    }
+
+    // Permissionless: release every matured SOL lock in `group_id` and close it back to its
+    // initializer, under the same fee as withdraw_sol. Remaining accounts are pairs
+    // [lock_account, initializer]. Locks outside the group, not yet matured, or with anything
+    // withdraw_sol would need extra accounts or a signature for (streams, receipts, auto-save,
+    // summaries, conditions, unmet goals, quorums, notify programs, rent recipients) are
+    // skipped; an account that isn't a lock or an initializer that doesn't match fails the
+    // whole batch.
+    pub fn release_group<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ReleaseGroup<'info>>,
+        group_id: [u8; 16],
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        require_not_frozen(&ctx.accounts.config, clock.unix_timestamp)?;
+        let (accounts, fee_accounts) =
+            split_off_fee_accounts(&ctx.accounts.config, ctx.remaining_accounts)?;
+        let accounts = batch_accounts(accounts)?;
+        require!(accounts.len() % 2 == 0, TimeLockError::BatchMalformed);
+
+        let (mut released, mut skipped) = (0u32, 0u32);
+        for pair in accounts.chunks_exact(2) {
+            let (lock_info, initializer) = (&pair[0], &pair[1]);
+            let lock_account = Account::<TimeLockAccount>::try_from(lock_info)?;
+            require_keys_eq!(initializer.key(), lock_account.initializer, TimeLockError::Unauthorized);
+            if lock_account.group_id != Some(group_id)
+                || !group_releasable(&lock_account, &ctx.accounts.config, &clock)
+                || require_wallet_destination(&lock_account, initializer).is_err()
+            {
+                skipped += 1;
+                continue;
+            }
+
+            let (principal, fee) = settle_sol_withdrawal(
+                &lock_account,
+                &ctx.accounts.config,
+                &mut ctx.accounts.stats,
+                &ctx.accounts.treasury,
+                fee_accounts,
+            )?;
+            ctx.accounts.stats.record_lock_closed();
+            let principal_returned = principal - fee;
+            lock_account.sub_lamports(principal_returned)?;
+            initializer.add_lamports(principal_returned)?;
+            emit_sol_withdrawn(&lock_account, &mut ctx.accounts.stats, principal_returned, fee, 0);
+            lock_account.close(initializer.clone())?;
+            released += 1;
+        }
+
+        msg!("[release_group] released={} skipped={}", released, skipped);
+        emit!(GroupReleased {
+            group_id,
+            released,
+            skipped,
+            seq: ctx.accounts.stats.next_event_seq(),
+        });
+        Ok(())
+    }
+
+    // Mint a transferable receipt for a SOL lock: a 0-decimal token with a supply of exactly one
+    // (the mint authority is dropped right after), sent to the initializer. From then on only the
+    // receipt holder can withdraw, via `withdraw_sol_with_receipt`.
+    pub fn mint_lock_receipt(ctx: Context<MintLockReceipt>) -> Result<()> {
+        let lock_account = &ctx.accounts.lock_account;
+        require!(lock_account.kind == AssetKind::Sol, TimeLockError::WrongAssetKind);
+        require!(lock_account.receipt_mint.is_none(), TimeLockError::ReceiptAlreadyMinted);
+        require!(password_met(lock_account), TimeLockError::ReleaseModeConflict);
+
+        let initializer_key = ctx.accounts.initializer.key();
+        let seeds: &[&[u8]] = &[TIME_LOCK_SOL_SEED, initializer_key.as_ref(), &[lock_account.bump]];
+        let signer_seeds: &[&[&[u8]]] = &[seeds];
+
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            MintTo {
+                mint: ctx.accounts.receipt_mint.to_account_info(),
+                to: ctx.accounts.receipt_ata.to_account_info(),
+                authority: ctx.accounts.lock_account.to_account_info(),
+            },
+            signer_seeds,
+        );
+        token::mint_to(cpi_ctx, 1)?;
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            SetAuthority {
+                current_authority: ctx.accounts.lock_account.to_account_info(),
+                account_or_mint: ctx.accounts.receipt_mint.to_account_info(),
+            },
+            signer_seeds,
+        );
+        token::set_authority(cpi_ctx, AuthorityType::MintTokens, None)?;
+
+        ctx.accounts.lock_account.receipt_mint = Some(ctx.accounts.receipt_mint.key());
+        Ok(())
+    }
+
+    // Withdraw a receipted SOL lock as the current receipt holder. The receipt is burned and the
+    // lock closes to the holder.
+    pub fn withdraw_sol_with_receipt<'info>(ctx: Context<'_, '_, 'info, 'info, WithdrawSolWithReceipt<'info>>) -> Result<()> {
+        require_not_frozen(&ctx.accounts.config, Clock::get()?.unix_timestamp)?;
+        let clock = Clock::get()?;
+        let lock_account = &ctx.accounts.lock_account;
+        require!(lock_account.kind == AssetKind::Sol, TimeLockError::WrongAssetKind);
+        require!(
+            lock_unlocked(lock_account, &clock),
+            TimeLockError::TimeLockNotExpired
+        );
+        require_settled(lock_account, &ctx.accounts.config, clock.slot)?;
+        require_wallet_destination(lock_account, &ctx.accounts.holder)?;
+        require_condition(lock_account, &ctx.accounts.condition)?;
+        require!(goal_met(lock_account), TimeLockError::GoalNotReached);
+        require!(password_met(lock_account), TimeLockError::PasswordRequired);
+        sync_lock_summary(lock_account, ctx.accounts.lock_summary.as_deref_mut(), true)?;
+
+        let cpi_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            Burn {
+                mint: ctx.accounts.receipt_mint.to_account_info(),
+                from: ctx.accounts.holder_receipt_ata.to_account_info(),
+                authority: ctx.accounts.holder.to_account_info(),
+            },
+        );
+        token::burn(cpi_ctx, 1)?;
+
+        let (principal, fee) = settle_sol_withdrawal(
+            lock_account,
+            &ctx.accounts.config,
+            &mut ctx.accounts.stats,
+            &ctx.accounts.treasury,
+            split_off_fee_accounts(&ctx.accounts.config, ctx.remaining_accounts)?.1,
+        )?;
+        ctx.accounts.stats.record_lock_closed();
+
+        msg!(
+            "[withdraw_sol_with_receipt] holder={} principal={} fee={}",
+            ctx.accounts.holder.key(),
+            principal,
+            fee
+        );
+        refund_rent(&ctx.accounts.lock_account, &ctx.accounts.rent_recipient)?;
+        // close = holder returns the remaining lamports.
+        Ok(())
+    }
+
+    // Opt a SOL or SPL lock into a `LockSummary` mirroring just its unlock time and withdrawn
+    // state, for clients that poll. From then on every instruction that changes either must be
+    // passed the summary, so it can't fall out of step. The summary outlives the lock as a
+    // withdrawn marker and is reused if the lock is recreated and opted in again.
+    pub fn create_lock_summary(ctx: Context<CreateLockSummary>) -> Result<()> {
+        let lock_account = &mut ctx.accounts.lock_account;
+        require!(
+            matches!(lock_account.kind, AssetKind::Sol | AssetKind::Spl),
+            TimeLockError::WrongAssetKind
+        );
+        require!(password_met(lock_account), TimeLockError::ReleaseModeConflict);
+        lock_account.has_summary = true;
+        let lock_summary = &mut ctx.accounts.lock_summary;
+        lock_summary.unlock_timestamp = lock_account.unlock_timestamp;
+        lock_summary.is_withdrawn = false;
+        lock_summary.bump = ctx.bumps.lock_summary;
+        Ok(())
+    }
+
+    // Developer tool: re-derive the canonical bump from the lock's seeds and assert it matches the
+    // stored one. Read-only, so clients can simulate it to debug their own PDA derivation.
+    pub fn verify_bump(ctx: Context<VerifyBump>) -> Result<()> {
+        let lock_account = &ctx.accounts.lock_account;
+        let canonical_bump = check_canonical_bump(lock_account)?;
+
+        msg!("[verify_bump] lock_account={} bump={}", lock_account.key(), canonical_bump);
+        Ok(())
+    }
+
+    // Batch form of `verify_bump` over up to MAX_BATCH lock accounts passed as remaining accounts.
+    // Fails on the first lock whose bump doesn't check out.
+    pub fn verify_bumps<'info>(ctx: Context<'_, '_, 'info, 'info, VerifyBumps<'info>>) -> Result<()> {
+        for info in batch_accounts(ctx.remaining_accounts)? {
+            let lock_account = Account::<TimeLockAccount>::try_from(info)?;
+            check_canonical_bump(&lock_account)?;
+        }
+        msg!("[verify_bumps] verified={}", ctx.remaining_accounts.len());
+        Ok(())
+    }
+
+    // Compare a SOL lock's `amount` with what it actually holds above rent (plus whatever its
+    // stream already paid out) and emit the drift. With `repair` the amount is rewritten to
+    // match and the protocol's locked total follows it; a lock can grow this way only within the
+    // TVL and per-lock caps, like any deposit.
+    pub fn reconcile_sol(ctx: Context<ReconcileSol>, repair: bool) -> Result<()> {
+        let lock_account = &ctx.accounts.lock_account;
+        require!(lock_account.kind == AssetKind::Sol, TimeLockError::WrongAssetKind);
+        let held = lamports_above_rent(&lock_account.to_account_info())?;
+        let owed = lamports_owed(lock_account);
+        let old_amount = lock_account.amount;
+        let new_amount = held
+            .checked_add(lock_account.stream_claimed)
+            .ok_or(TimeLockError::MathOverflow)?;
+
+        if repair && new_amount != old_amount {
+            if new_amount > old_amount {
+                require_within_max(new_amount, ctx.accounts.config.max_lock_lamports)?;
+            }
+            let stats = &mut ctx.accounts.stats;
+            stats.total_sol_locked = if held > owed {
+                tvl_after_deposit(&ctx.accounts.config, stats, held - owed)?
+            } else {
+                stats.total_sol_locked.saturating_sub(owed - held)
+            };
+            let lock_account = &mut ctx.accounts.lock_account;
+            lock_account.amount = new_amount;
+            emit_if_goal_reached(lock_account, &mut ctx.accounts.stats);
+        }
+
+        emit!(Reconciled {
+            lock_account: ctx.accounts.lock_account.key(),
+            old_amount,
+            new_amount,
+            repaired: repair,
+            seq: ctx.accounts.stats.next_event_seq(),
+        });
+        Ok(())
+    }
+
+    // Fail with ParamsMismatch unless the lock's amount and unlock time are the ones the client
+    // expects. Meant to sit just before fund_sol_lock in the same transaction, so a wallet never
+    // deposits into a lock whose terms changed after the user approved them. `expected_unlock`
+    // is the stored unlock time, i.e. after any rounding the initializer applied.
+    pub fn verify_lock_params(
+        ctx: Context<VerifyLockParams>,
+        expected_amount: u64,
+        expected_unlock: i64,
+    ) -> Result<()> {
+        let lock_account = &ctx.accounts.lock_account;
+        require!(
+            lock_account.amount == expected_amount
+                && lock_account.unlock_timestamp == expected_unlock,
+            TimeLockError::ParamsMismatch
+        );
+        Ok(())
+    }
+
+    // Diagnostic: check every invariant that applies to this lock and emit a `HealthReport`
+    // with one flag per check. Never fails on an unhealthy lock and never mutates anything, so
+    // it can be simulated freely. Pass the vault for SPL and combined locks.
+    pub fn check_lock_health(ctx: Context<CheckLockHealth>) -> Result<()> {
+        let lock_account = &ctx.accounts.lock_account;
+        let now = Clock::get()?.unix_timestamp;
+        let holds_tokens = matches!(
+            lock_account.kind,
+            AssetKind::Spl | AssetKind::SolAndSpl | AssetKind::Delegated
+        );
+
+        let bump_ok = check_canonical_bump(lock_account).is_ok();
+        let mint_consistent = lock_account.mint.is_some() == holds_tokens;
+        let sol_funded = match lock_account.kind {
+            AssetKind::Spl | AssetKind::Delegated => true,
+            _ => lamports_above_rent(&lock_account.to_account_info())? >= lamports_owed(lock_account),
+        };
+        let vault_funded = !holds_tokens
+            || ctx.accounts.vault_ata.as_ref().is_some_and(|vault| {
+                Some(vault.mint) == lock_account.mint && vault.amount >= tokens_owed(lock_account)
+            });
+        let timestamps_sane = lock_account.created_at > 0
+            && lock_account.created_at <= now
+            && match lock_account.unlock_slot {
+                Some(unlock_slot) => lock_account.created_slot < unlock_slot,
+                None => lock_account.created_at < lock_account.unlock_timestamp,
+            };
+
+        emit!(HealthReport {
+            lock_account: lock_account.key(),
+            bump_ok,
+            mint_consistent,
+            sol_funded,
+            vault_funded,
+            timestamps_sane,
+            healthy: bump_ok && mint_consistent && sol_funded && vault_funded && timestamps_sane,
+        });
+        Ok(())
+    }
+
+    // Emit what withdrawing this lock would pay right now, split the same way the withdrawal
+    // itself splits it. Read-only; works before unlock too, with `unlocked` false. Pass the vault
+    // for SPL locks. Safes pay out two assets and aren't covered.
+    pub fn preview_withdrawal(ctx: Context<PreviewWithdrawal>) -> Result<()> {
+        let lock_account = &ctx.accounts.lock_account;
+        let now = Clock::get()?.unix_timestamp;
+        let gross = withdrawable_now(lock_account, ctx.accounts.vault_ata.as_deref(), now)?;
+        let (fee, net) = split_withdrawal(&ctx.accounts.config, lock_account, gross)?;
+
+        emit!(WithdrawalPreview {
+            lock_account: lock_account.key(),
+            gross,
+            fee,
+            penalty: 0,
+            net,
+            unlocked: lock_unlocked(lock_account, &Clock::get()?),
+        });
+        Ok(())
+    }
+
+    // Emit every condition this lock's release depends on, whether each is met right now, and
+    // whether a withdrawal would pass them. Read-only. Pass the price feed to evaluate a
+    // stop-loss, the condition flag if the lock has one, and any quorum signers as remaining
+    // accounts; a condition whose account is left out reports as unmet.
+    pub fn explain_unlock<'info>(ctx: Context<'_, '_, 'info, 'info, ExplainUnlock<'info>>) -> Result<()> {
+        let lock_account = &ctx.accounts.lock_account;
+        let unlock = evaluate_unlock(
+            lock_account,
+            &Clock::get()?,
+            ctx.accounts.price_feed.as_deref(),
+            &ctx.accounts.condition,
+            batch_accounts(ctx.remaining_accounts)?,
+        )?;
+
+        let release = |kind, met| UnlockCondition { kind, met, gate: false };
+        let gate = |kind, met| UnlockCondition { kind, met, gate: true };
+        let mut conditions = vec![match lock_account.unlock_slot {
+            Some(_) => release(UnlockConditionKind::Slot, unlock.time_met),
+            None => release(UnlockConditionKind::Timestamp, unlock.time_met),
+        }];
+        if lock_account.stop_loss_oracle.is_some() {
+            conditions.push(release(UnlockConditionKind::StopLoss, unlock.stop_loss_met));
+        }
+        if lock_account.quorum_threshold > 0 {
+            conditions.push(release(UnlockConditionKind::Quorum, unlock.quorum_met));
+        }
+        if lock_account.condition_account.is_some() {
+            conditions.push(gate(UnlockConditionKind::ConditionFlag, unlock.condition_met));
+        }
+        if lock_account.goal_required {
+            conditions.push(gate(UnlockConditionKind::Goal, unlock.goal_met));
+        }
+        if !unlock.password_met {
+            conditions.push(gate(UnlockConditionKind::Password, false));
+        }
+
+        emit!(UnlockExplained {
+            lock_account: lock_account.key(),
+            conditions,
+            withdrawable: unlock.withdrawable(),
+        });
+        Ok(())
+    }
+
+    // Attest whether a lock has matured, for other programs to consume through CPI instead of
+    // re-implementing the unlock rules. The verdict is the one explain_unlock reports, written as
+    // an UnlockAttestation with set_return_data. The runtime records which program set return
+    // data, so a caller that checks get_return_data's program id against this program can trust
+    // it without trusting the client. Shares ExplainUnlock's accounts.
+    pub fn attest_unlock<'info>(ctx: Context<'_, '_, 'info, 'info, ExplainUnlock<'info>>) -> Result<()> {
+        let clock = Clock::get()?;
+        let unlock = evaluate_unlock(
+            &ctx.accounts.lock_account,
+            &clock,
+            ctx.accounts.price_feed.as_deref(),
+            &ctx.accounts.condition,
+            batch_accounts(ctx.remaining_accounts)?,
+        )?;
+        let attestation = UnlockAttestation {
+            lock_account: ctx.accounts.lock_account.key(),
+            matured: unlock.withdrawable(),
+            slot: clock.slot,
+            unix_timestamp: clock.unix_timestamp,
+        };
+        set_return_data(&attestation.try_to_vec()?);
+        Ok(())
+    }
+
+    // Write the lock's whole configuration, every optional field that shapes who can withdraw
+    // what and when, as a LockConfig with set_return_data: the authoritative dump for programs
+    // that compose with locks through CPI, where attest_unlock only answers "matured?". As with
+    // attest_unlock, check get_return_data's program id against this program before trusting it.
+    pub fn serialize_lock_config(ctx: Context<SerializeLockConfig>) -> Result<()> {
+        let lock_account = &ctx.accounts.lock_account;
+        let config = LockConfig {
+            version: LOCK_CONFIG_VERSION,
+            lock_account: lock_account.key(),
+            initializer: lock_account.initializer,
+            kind: lock_account.kind,
+            category: lock_account.category,
+            mint: lock_account.mint,
+            amount: lock_account.amount,
+            token_amount: lock_account.token_amount,
+            unlock_timestamp: lock_account.unlock_timestamp,
+            unlock_slot: lock_account.unlock_slot,
+            require_system_destination: lock_account.require_system_destination,
+            withdraw_destination: lock_account.withdraw_destination,
+            beneficiary: lock_account.beneficiary,
+            pending_beneficiary: lock_account.pending_beneficiary,
+            pending_effective_at: lock_account.pending_effective_at,
+            rent_recipient: lock_account.rent_recipient,
+            receipt_mint: lock_account.receipt_mint,
+            charity_fallback: lock_account.charity_fallback,
+            charity_grace_seconds: lock_account.charity_grace_seconds,
+            stream_periods: lock_account.stream_periods,
+            stream_period_secs: lock_account.stream_period_secs,
+            stream_claimed: lock_account.stream_claimed,
+            claim_bps: lock_account.claim_bps,
+            tranche_amount: lock_account.tranche_amount,
+            auto_save_bps: lock_account.auto_save_bps,
+            auto_release: lock_account.auto_release,
+            max_withdrawals: lock_account.max_withdrawals,
+            withdraw_step: lock_account.withdraw_step,
+            trial_seconds: lock_account.trial_seconds,
+            funded_at: lock_account.funded_at,
+            stop_loss_oracle: lock_account.stop_loss_oracle,
+            stop_loss_price: lock_account.stop_loss_price,
+            condition_account: lock_account.condition_account,
+            goal_amount: lock_account.goal_amount,
+            goal_required: lock_account.goal_required,
+            password_protected: !password_met(lock_account),
+            arbiter: lock_account.arbiter,
+            withdraw_delegate: lock_account.withdraw_delegate,
+            authorities_renounced: lock_account.authorities_renounced,
+            vault_authority: lock_account.vault_authority,
+            notify_program: lock_account.notify_program,
+            group_id: lock_account.group_id,
+            referrer: lock_account.referrer,
+            quorum_threshold: lock_account.quorum_threshold,
+            quorum_signers: lock_account.quorum_signers.clone(),
+        };
+        set_return_data(&config.try_to_vec()?);
+        Ok(())
+    }
+
+    // Emit the soonest future time at which anything about withdrawing this lock could change,
+    // so countdown UIs know when to refresh: i64::MAX if nothing time-based is left to happen.
+    // Read-only. Releases that don't run on a clock (condition flags, quorums, goals) aren't
+    // predicted.
+    pub fn next_event_at(ctx: Context<NextEventAt>) -> Result<()> {
+        let lock_account = &ctx.accounts.lock_account;
+        emit!(NextLockEvent {
+            lock_account: lock_account.key(),
+            next_event_at: next_lock_event(lock_account, &ctx.accounts.config, &Clock::get()?)?,
+        });
+        Ok(())
+    }
+
+    // Emit whether `owner` has any open lock and how many. There is no owner registry: an owner
+    // has at most one lock per kind at a PDA fixed by their key, so the context derives each of
+    // them and this only checks which exist. A closed lock's account is gone, so the count drops
+    // back to zero once all of them are closed. Read-only.
+    pub fn has_active_locks(ctx: Context<HasActiveLocks>) -> Result<()> {
+        let accounts = &ctx.accounts;
+        let count = [
+            &accounts.sol_lock,
+            &accounts.spl_lock,
+            &accounts.safe_lock,
+            &accounts.savings_lock,
+            &accounts.basket_lock,
+            &accounts.delegated_lock,
+        ]
+        .into_iter()
+        .filter(|lock| *lock.owner == crate::ID && !lock.data_is_empty())
+        .count() as u8;
+        emit!(ActiveLocks {
+            owner: accounts.owner.key(),
+            has_active_locks: count > 0,
+            count,
+        });
+        Ok(())
+    }
+
+    // Reveal the preimage behind a lock's commitment. Anyone holding the preimage may call this;
+    // it only proves the off-chain details match what was committed at init.
+    pub fn reveal_commitment(ctx: Context<RevealCommitment>, preimage: Vec<u8>) -> Result<()> {
+        let lock_account = &ctx.accounts.lock_account;
+        require!(lock_account.commitment != [0u8; 32], TimeLockError::NoCommitment);
+        require!(
+            hash(&preimage).to_bytes() == lock_account.commitment,
+            TimeLockError::CommitmentMismatch
+        );
+
+        emit!(Revealed {
+            lock_account: lock_account.key(),
+            commitment: lock_account.commitment,
+        });
+        Ok(())
+    }
+
+    // Initialize an SPL timelock for a given mint (e.g., USDC on devnet)
+    // With `vesting_periods > 0` the tokens vest in that many equal chunks, the first at
+    // `unlock_timestamp` and one more every `vesting_period_secs`, and are released through
+    // `claim_vested_spl` instead of `withdraw_spl`. As with SOL locks, `unlock_slot` swaps the
+    // timestamp for a slot height.
+    #[allow(clippy::too_many_arguments)]
+    pub fn initialize_lock_spl(
+        ctx: Context<InitializeLockSpl>,
+        amount: u64,
+        unlock_timestamp: i64,
+        commitment: [u8; 32],
+        require_system_destination: bool,
+        stop_loss_oracle: Option<Pubkey>,
+        stop_loss_price: i64,
+        vesting_periods: u16,
+        vesting_period_secs: i64,
+        round_to_seconds: i64,
+        unlock_slot: Option<u64>,
+        category: u8,
+        referrer: Option<Pubkey>,
+    ) -> Result<()> {
+        require_invited(&ctx.accounts.config, &ctx.accounts.invite)?;
+        require!(amount > 0, TimeLockError::InvalidAmount);
+        require_not_self_referral(referrer, &ctx.accounts.initializer.key())?;
+        let category = LockCategory::try_from(category)?;
+        // Owner constraints already keep these apart; fail deterministically if that ever changes,
+        // since a self-transfer would leave the vault accounting looking funded.
+        require_keys_neq!(
+            ctx.accounts.user_ata.key(),
+            ctx.accounts.vault_ata.key(),
+            TimeLockError::SameTokenAccount
+        );
+        let now = Clock::get()?.unix_timestamp;
+        let unlock_timestamp =
+            resolve_unlock(unlock_timestamp, round_to_seconds, unlock_slot, vesting_periods)?;
+        require!(
+            vesting_periods == 0 || vesting_period_secs > 0,
+            TimeLockError::InvalidDuration
+        );
+        // The deposit fee comes out of `amount`, so the lock holds the rest.
+        let deposit_fee = scale_amount(amount, ctx.accounts.config.deposit_fee_bps as u64, BPS_DENOMINATOR)?;
+        if deposit_fee > 0 {
+            let reward_pool_ata = ctx.accounts.reward_pool_ata.as_ref().ok_or(TimeLockError::RewardPoolMissing)?;
+            reward_pool_authority(reward_pool_ata, &ctx.accounts.mint.key())?;
+        }
+        let amount = amount - deposit_fee;
+        // The bounds apply to what the lock will hold, not what the user sent.
+        require!(amount >= ctx.accounts.config.min_lock_tokens, TimeLockError::BelowMinimumAmount);
+        require_within_max(amount, ctx.accounts.config.max_lock_tokens)?;
+
+        let initializer = &ctx.accounts.initializer;
+        let lock_account = &mut ctx.accounts.lock_account;
+        // Re-running init on an existing lock must not re-point it at another mint, or the old
+        // vault's tokens could no longer be withdrawn.
+        require!(
+            lock_account.mint.is_none() || lock_account.mint == Some(ctx.accounts.mint.key()),
+            TimeLockError::MintMismatch
+        );
+        // `amount` is overwritten below, so tokens still in the vault from an earlier init would
+        // drop out of the lock's accounting. Only an empty vault can be treated as a fresh lock.
+        require!(ctx.accounts.vault_ata.amount == 0, TimeLockError::AlreadyInitialized);
+        let slot = Clock::get()?.slot;
+        let bond = if is_new_lock(lock_account, slot)? {
+            ctx.accounts.stats.record_lock_opened();
+            lock_account.reward_multiplier_bps = ctx.accounts.config.reward_multiplier_bps;
+            lock_account.bond_paid = ctx.accounts.config.bond_lamports;
+            lock_account.bond_paid
+        } else {
+            0
+        };
+        lock_account.created_slot = slot;
+
+        // Persist state
+        lock_account.initializer = initializer.key();
+        lock_account.amount = amount;
+        lock_account.unlock_timestamp = unlock_timestamp;
+        lock_account.unlock_slot = unlock_slot;
+        lock_account.bump = ctx.bumps.lock_account;
+        lock_account.created_at = now;
+        lock_account.kind = AssetKind::Spl;
+        lock_account.mint = Some(ctx.accounts.mint.key());
+        lock_account.display_decimals = ctx.accounts.mint.decimals;
+        lock_account.commitment = commitment;
+        lock_account.require_system_destination = require_system_destination;
+        lock_account.stop_loss_oracle = stop_loss_oracle;
+        lock_account.stop_loss_price = if stop_loss_oracle.is_some() { stop_loss_price } else { 0 };
+        // Vesting reuses the SOL stream schedule fields.
+        lock_account.stream_periods = vesting_periods;
+        lock_account.stream_period_secs = vesting_period_secs;
+        lock_account.stream_claimed = 0;
+        lock_account.next_claim_at = if vesting_periods > 0 { unlock_timestamp } else { 0 };
+        lock_account.stream_anchor = None;
+        lock_account.tranche_amount = 0;
+        lock_account.claim_bps = 0;
+        lock_account.percent_claims = 0;
+        lock_account.deposit_fee_taken = deposit_fee;
+        lock_account.withdraw_destination = None;
+        lock_account.category = category;
+        lock_account.referrer = referrer;
+        lock_account.vault_frozen = false;
+        lock_account.paid_out = false;
+
+        // Transfer SPL tokens from user ATA to vault ATA with PDA signer as authority after init.
+        let vault_before = ctx.accounts.vault_ata.amount;
+        let cpi_accounts = SplTransfer {
+            from: ctx.accounts.user_ata.to_account_info(),
+            to: ctx.accounts.vault_ata.to_account_info(),
+            authority: initializer.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new(ctx.accounts.token_program.to_account_info(), cpi_accounts);
+        token::transfer(cpi_ctx, amount)?;
+        // Lock what actually reached the vault rather than what was sent, so a mint that takes a
+        // cut in transit can't leave `amount` promising more than withdrawal can pay out.
+        ctx.accounts.vault_ata.reload()?;
+        let received = ctx
+            .accounts
+            .vault_ata
+            .amount
+            .checked_sub(vault_before)
+            .ok_or(TimeLockError::MathOverflow)?;
+        require!(received > 0, TimeLockError::InvalidAmount);
+        let lock_account = &mut ctx.accounts.lock_account;
+        lock_account.amount = received;
+        sync_lock_summary(lock_account, ctx.accounts.lock_summary.as_deref_mut(), false)?;
+        emit_lock_created(lock_account, &mut ctx.accounts.stats);
+
+        if let Some(reward_pool_ata) = ctx.accounts.reward_pool_ata.as_ref().filter(|_| deposit_fee > 0) {
+            let cpi_accounts = SplTransfer {
+                from: ctx.accounts.user_ata.to_account_info(),
+                to: reward_pool_ata.to_account_info(),
+                authority: ctx.accounts.initializer.to_account_info(),
+            };
+            let cpi_ctx = CpiContext::new(ctx.accounts.token_program.to_account_info(), cpi_accounts);
+            token::transfer(cpi_ctx, deposit_fee)?;
+        }
+
+        if bond > 0 {
+            let cpi_ctx = CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                anchor_lang::system_program::Transfer {
+                    from: ctx.accounts.initializer.to_account_info(),
+                    to: ctx.accounts.lock_account.to_account_info(),
+                },
+            );
+            anchor_lang::system_program::transfer(cpi_ctx, bond)?;
+        }
+
+        Ok(())
+    }
+
+    // Lock the user ATA's entire balance as read on-chain, so a balance that changed after the
+    // client read it can't make the transfer fail or leave dust behind. A plain one-shot lock:
+    // no commitment, stop-loss, vesting, rounding, slot unlock or referrer. An empty ATA is
+    // rejected like any zero amount.
+    pub fn lock_all_spl(ctx: Context<InitializeLockSpl>, unlock_timestamp: i64, category: u8) -> Result<()> {
+        let amount = ctx.accounts.user_ata.amount;
+        initialize_lock_spl(ctx, amount, unlock_timestamp, [0; 32], false, None, 0, 0, 0, 0, None, category, None)
+    }
+
+    // Lock `amount` of a token without moving it: the tokens stay in the initializer's own
+    // `user_ata`, which approves the lock PDA as its delegate for that amount, so there is no
+    // vault to pay rent for and the balance stays visible in wallets. It is non-custodial: the
+    // owner can still move the tokens or revoke the approval, and withdraw_delegated then fails
+    // with DelegationRevoked. The token account has a single delegate slot, so approving anyone
+    // else revokes the lock too.
+    pub fn initialize_lock_delegated(
+        ctx: Context<InitializeLockDelegated>,
+        amount: u64,
+        unlock_timestamp: i64,
+        category: u8,
+    ) -> Result<()> {
+        require_invited(&ctx.accounts.config, &ctx.accounts.invite)?;
+        require!(amount > 0, TimeLockError::InvalidAmount);
+        require!(amount >= ctx.accounts.config.min_lock_tokens, TimeLockError::BelowMinimumAmount);
+        require_within_max(amount, ctx.accounts.config.max_lock_tokens)?;
+        require!(ctx.accounts.user_ata.amount >= amount, TimeLockError::InsufficientVaultBalance);
+        let category = LockCategory::try_from(category)?;
+        let unlock_timestamp = resolve_unlock(unlock_timestamp, 0, None, 0)?;
+        let clock = Clock::get()?;
+
+        let lock_account = &mut ctx.accounts.lock_account;
+        lock_account.initializer = ctx.accounts.initializer.key();
+        lock_account.amount = amount;
+        lock_account.unlock_timestamp = unlock_timestamp;
+        lock_account.bump = ctx.bumps.lock_account;
+        lock_account.created_at = clock.unix_timestamp;
+        lock_account.created_slot = clock.slot;
+        lock_account.kind = AssetKind::Delegated;
+        lock_account.mint = Some(ctx.accounts.mint.key());
+        lock_account.display_decimals = ctx.accounts.mint.decimals;
+        lock_account.category = category;
+        lock_account.reward_multiplier_bps = ctx.accounts.config.reward_multiplier_bps;
+        lock_account.delegated_account = Some(ctx.accounts.user_ata.key());
+        ctx.accounts.stats.record_lock_opened();
+        emit_lock_created(lock_account, &mut ctx.accounts.stats);
+
+        let cpi_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            Approve {
+                to: ctx.accounts.user_ata.to_account_info(),
+                delegate: ctx.accounts.lock_account.to_account_info(),
+                authority: ctx.accounts.initializer.to_account_info(),
+            },
+        );
+        token::approve(cpi_ctx, amount)
+    }
+
+    // After unlock, move a delegated lock's tokens out of the initializer's token account into
+    // `destination_ata`, another of the initializer's accounts for the mint, with the lock
+    // signing as delegate, then close the lock. Fees apply as for withdraw_spl. Fails with
+    // DelegationRevoked if the approval no longer covers the amount; close_delegated_lock
+    // cleans up such a lock.
+    pub fn withdraw_delegated<'info>(ctx: Context<'_, '_, 'info, 'info, WithdrawDelegated<'info>>) -> Result<()> {
+        require_not_frozen(&ctx.accounts.config, Clock::get()?.unix_timestamp)?;
+        let lock_account = &ctx.accounts.lock_account;
+        require!(lock_account.kind == AssetKind::Delegated, TimeLockError::WrongAssetKind);
+        require!(
+            lock_unlocked(lock_account, &Clock::get()?),
+            TimeLockError::TimeLockNotExpired
+        );
+        require_keys_neq!(
+            ctx.accounts.source_ata.key(),
+            ctx.accounts.destination_ata.key(),
+            TimeLockError::SameTokenAccount
+        );
+        let source = &ctx.accounts.source_ata;
+        require!(
+            source.delegate == Some(lock_account.key()).into()
+                && source.delegated_amount >= lock_account.amount,
+            TimeLockError::DelegationRevoked
+        );
+
+        let initializer_key = ctx.accounts.initializer.key();
+        let seeds: &[&[u8]] = &[TIME_LOCK_DELEGATED_SEED, initializer_key.as_ref(), &[lock_account.bump]];
+        let signer_seeds: &[&[&[u8]]] = &[seeds];
+        let (fee, net) = split_withdrawal(&ctx.accounts.config, lock_account, lock_account.amount)?;
+        pay_out_tokens(
+            &ctx.accounts.token_program,
+            &ctx.accounts.source_ata,
+            &ctx.accounts.lock_account.to_account_info(),
+            signer_seeds,
+            FeeTo {
+                config: &ctx.accounts.config,
+                treasury: ctx.accounts.treasury_ata.as_ref().map(|ata| ata.to_account_info()),
+                split_accounts: split_off_fee_accounts(&ctx.accounts.config, ctx.remaining_accounts)?.1,
+            },
+            Some(ctx.accounts.destination_ata.to_account_info()),
+            (fee, net),
+        )?;
+        ctx.accounts.stats.record_withdrawal(0);
+        ctx.accounts.stats.record_lock_closed();
+
+        msg!(
+            "[withdraw_delegated] lock_account={} amount={} fee={}",
+            ctx.accounts.lock_account.key(),
+            ctx.accounts.lock_account.amount,
+            fee
+        );
+        // close = initializer reclaims the lock account itself.
+        Ok(())
+    }
+
+    // Close an unlocked delegated lock without moving any tokens, for one whose approval was
+    // revoked or spent. Whatever approval the lock still holds is revoked with it.
+    pub fn close_delegated_lock(ctx: Context<CloseDelegatedLock>) -> Result<()> {
+        let lock_account = &ctx.accounts.lock_account;
+        require!(lock_account.kind == AssetKind::Delegated, TimeLockError::WrongAssetKind);
+        require!(
+            lock_unlocked(lock_account, &Clock::get()?),
+            TimeLockError::TimeLockNotExpired
+        );
+        if ctx.accounts.source_ata.delegate == Some(lock_account.key()).into() {
+            let cpi_ctx = CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Revoke {
+                    source: ctx.accounts.source_ata.to_account_info(),
+                    authority: ctx.accounts.initializer.to_account_info(),
+                },
+            );
+            token::revoke(cpi_ctx)?;
+        }
+        ctx.accounts.stats.record_lock_closed();
+        Ok(())
+    }
+
+    // Make an SPL lock a gift to `beneficiary`. Its payout is pinned to the beneficiary's ATA,
+    // which also shuts out the initializer's own withdrawals, and the rent for creating that ATA
+    // moves from the initializer into the lock now, so the withdrawal never needs the
+    // beneficiary's SOL. Meant to share a transaction with the init. Vesting locks pay out
+    // through claim_vested_spl and can't take a beneficiary.
+    pub fn set_spl_beneficiary(ctx: Context<SetSplBeneficiary>, beneficiary: Pubkey) -> Result<()> {
+        let lock_account = &ctx.accounts.lock_account;
+        require!(
+            lock_account.kind == AssetKind::Spl && lock_account.stream_periods == 0,
+            TimeLockError::WrongAssetKind
+        );
+        require!(lock_account.withdraw_destination.is_none(), TimeLockError::DestinationLocked);
+
+        let reserve = Rent::get()?.minimum_balance(TokenAccount::LEN);
+        let cpi_accounts = anchor_lang::system_program::Transfer {
+            from: ctx.accounts.initializer.to_account_info(),
+            to: ctx.accounts.lock_account.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new(ctx.accounts.system_program.to_account_info(), cpi_accounts);
+        anchor_lang::system_program::transfer(cpi_ctx, reserve)?;
+
+        let lock_account = &mut ctx.accounts.lock_account;
+        lock_account.beneficiary = Some(beneficiary);
+        lock_account.withdraw_destination = Some(get_associated_token_address(&beneficiary, &ctx.accounts.mint.key()));
+        lock_account.ata_rent_reserve = reserve;
+        msg!("[set_spl_beneficiary] lock_account={} beneficiary={} reserve={}", lock_account.key(), beneficiary, reserve);
+        Ok(())
+    }
+
+    // Name the arbiter who, together with the initializer, may later correct the lock's
+    // beneficiary. Only before a beneficiary is set, and only once, so the initializer can't
+    // appoint an arbiter of their choosing to take back a gift already made.
+    pub fn set_arbiter(ctx: Context<SetNotifyProgram>, arbiter: Pubkey) -> Result<()> {
+        let lock_account = &mut ctx.accounts.lock_account;
+        require!(lock_account.kind == AssetKind::Spl, TimeLockError::WrongAssetKind);
+        require!(!lock_account.authorities_renounced, TimeLockError::AuthorityRenounced);
+        require!(
+            lock_account.arbiter.is_none() && lock_account.beneficiary.is_none(),
+            TimeLockError::DestinationLocked
+        );
+        lock_account.arbiter = Some(arbiter);
+        Ok(())
+    }
+
+    // Owner only, and irreversible: strip every third party's power over the lock. The arbiter
+    // can no longer change the beneficiary, the withdraw delegate can no longer trigger
+    // withdrawals, and the quorum can no longer release early; none of them can be appointed
+    // again. It only ever takes powers away, so it is allowed whatever else the lock has fixed.
+    pub fn renounce_authorities(ctx: Context<RenounceAuthorities>) -> Result<()> {
+        let lock_account = &mut ctx.accounts.lock_account;
+        require!(!lock_account.authorities_renounced, TimeLockError::AuthorityRenounced);
+        let arbiter = lock_account.arbiter.take();
+        let withdraw_delegate = lock_account.withdraw_delegate.take();
+        let quorum_signers = std::mem::take(&mut lock_account.quorum_signers);
+        lock_account.quorum_threshold = 0;
+        lock_account.authorities_renounced = true;
+        emit!(AuthoritiesRenounced {
+            lock_account: lock_account.key(),
+            arbiter,
+            withdraw_delegate,
+            quorum_signers,
+            seq: ctx.accounts.stats.next_event_seq(),
+        });
+        Ok(())
+    }
+
+    // Replace a beneficiary that was set by mistake. A set beneficiary is otherwise final, so
+    // this takes both the initializer and the lock's arbiter signing, as an escrow arbiter would
+    // settle a dispute. The ATA rent reserve carries over to the new beneficiary. With a
+    // beneficiary_change_delay configured the change only lands once the delay has passed, and
+    // until then withdraw_spl_to_beneficiary keeps paying the current beneficiary, so a claim
+    // can't be redirected at the last moment. Another update while one is pending replaces it.
+    pub fn force_update_beneficiary(ctx: Context<ForceUpdateBeneficiary>, new_beneficiary: Pubkey) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let lock_account = &mut ctx.accounts.lock_account;
+        require!(
+            lock_account.arbiter == Some(ctx.accounts.arbiter.key()),
+            TimeLockError::NotArbiter
+        );
+        require!(ctx.accounts.arbiter.is_signer, TimeLockError::MissingArbiterSignature);
+        let old_beneficiary = lock_account.beneficiary.ok_or(TimeLockError::BeneficiaryMismatch)?;
+
+        let effective_at = now.saturating_add(ctx.accounts.config.beneficiary_change_delay);
+        lock_account.pending_beneficiary = Some(new_beneficiary);
+        lock_account.pending_effective_at = effective_at;
+        apply_due_beneficiary(lock_account, &ctx.accounts.mint.key(), now);
+        emit!(BeneficiaryForceUpdated {
+            lock_account: lock_account.key(),
+            arbiter: ctx.accounts.arbiter.key(),
+            old_beneficiary,
+            new_beneficiary,
+            effective_at,
+            seq: ctx.accounts.stats.next_event_seq(),
+        });
+        Ok(())
+    }
+
+    // Permissionless: land a pending beneficiary change once its delay has passed.
+    // withdraw_spl_to_beneficiary does this itself, so this is only for clients that want the
+    // lock to show the new beneficiary before then.
+    pub fn apply_pending_beneficiary(ctx: Context<ApplyPendingBeneficiary>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let lock_account = &mut ctx.accounts.lock_account;
+        require!(lock_account.pending_beneficiary.is_some(), TimeLockError::NoPendingBeneficiary);
+        require!(
+            apply_due_beneficiary(lock_account, &ctx.accounts.mint.key(), now),
+            TimeLockError::BeneficiaryChangeNotDue
+        );
+        Ok(())
+    }
+
+    // Pay an unlocked SPL lock out to its beneficiary's ATA. Anyone may send this, since the
+    // tokens can only go one place. A missing ATA is created with the payer's lamports, and the
+    // payer is reimbursed from the lock's reserve first, so a beneficiary with no SOL at all
+    // still gets paid. A reserve left unused because the ATA already existed goes back with the
+    // lock's rent on close_spl_lock. A deposit fee refund follows the tokens to the beneficiary.
+    // Stop-loss exits aren't available here.
+    pub fn withdraw_spl_to_beneficiary<'info>(ctx: Context<'_, '_, 'info, 'info, WithdrawSplToBeneficiary<'info>>) -> Result<()> {
+        require_not_frozen(&ctx.accounts.config, Clock::get()?.unix_timestamp)?;
+        let clock = Clock::get()?;
+        // Pay whoever is the beneficiary right now: a force update lands here once its delay is up.
+        apply_due_beneficiary(&mut ctx.accounts.lock_account, &ctx.accounts.mint.key(), clock.unix_timestamp);
+        let lock_account = &ctx.accounts.lock_account;
+        require!(
+            lock_account.beneficiary == Some(ctx.accounts.beneficiary.key()),
+            TimeLockError::BeneficiaryMismatch
+        );
+        require!(lock_account.kind == AssetKind::Spl, TimeLockError::WrongAssetKind);
+        require!(lock_account.stream_periods == 0, TimeLockError::StreamNotDrained);
+        let unlock = evaluate_unlock(lock_account, &clock, None, &ctx.accounts.condition, &[])?;
+        require!(unlock.time_met, TimeLockError::TimeLockNotExpired);
+        require!(unlock.condition_met, TimeLockError::ConditionNotMet);
+        require_withdraw_destination(lock_account, &ctx.accounts.beneficiary_ata.key())?;
+        require_vault_authority(&ctx.accounts.vault_ata, lock_account)?;
+
+        let vault_balance = ctx.accounts.vault_ata.amount;
+        require!(vault_balance > 0, TimeLockError::InsufficientVaultBalance);
+
+        if ctx.accounts.beneficiary_ata.data_is_empty() {
+            let lock_account = &mut ctx.accounts.lock_account;
+            let reserve = lock_account.ata_rent_reserve;
+            lock_account.ata_rent_reserve = 0;
+            lock_account.sub_lamports(reserve)?;
+            ctx.accounts.payer.add_lamports(reserve)?;
+            associated_token::create(CpiContext::new(
+                ctx.accounts.associated_token_program.to_account_info(),
+                associated_token::Create {
+                    payer: ctx.accounts.payer.to_account_info(),
+                    associated_token: ctx.accounts.beneficiary_ata.to_account_info(),
+                    authority: ctx.accounts.beneficiary.to_account_info(),
+                    mint: ctx.accounts.mint.to_account_info(),
+                    system_program: ctx.accounts.system_program.to_account_info(),
+                    token_program: ctx.accounts.token_program.to_account_info(),
+                },
+            ))?;
+            msg!("[withdraw_spl_to_beneficiary] created beneficiary ATA rent={}", reserve);
+        }
+
+        let lock_account = &ctx.accounts.lock_account;
+        let initializer_key = lock_account.initializer;
+        let lock_key = lock_account.key();
+        let lock_seeds: &[&[u8]] = &[TIME_LOCK_SPL_SEED, initializer_key.as_ref(), &[lock_account.bump]];
+        let migrated_seeds: &[&[u8]] =
+            &[VAULT_AUTHORITY_SEED, lock_key.as_ref(), &[lock_account.vault_authority_bump]];
+        let (signer, seeds) =
+            vault_signer(lock_account, &ctx.accounts.vault_authority, lock_seeds, migrated_seeds)?;
+        let signer_seeds: &[&[&[u8]]] = &[seeds];
+
+        let (fee, net) = split_withdrawal(&ctx.accounts.config, lock_account, vault_balance)?;
+        pay_out_tokens(
+            &ctx.accounts.token_program,
+            &ctx.accounts.vault_ata,
+            &signer,
+            signer_seeds,
+            FeeTo {
+                config: &ctx.accounts.config,
+                treasury: ctx.accounts.treasury_ata.as_ref().map(|ata| ata.to_account_info()),
+                split_accounts: split_off_fee_accounts(&ctx.accounts.config, ctx.remaining_accounts)?.1,
+            },
+            Some(ctx.accounts.beneficiary_ata.to_account_info()),
+            (fee, net),
+        )?;
+        ctx.accounts.stats.record_withdrawal(0);
+        ctx.accounts.lock_account.paid_out = true;
+        refund_deposit_fee(
+            &mut ctx.accounts.lock_account,
+            RefundFrom {
+                reward_pool_ata: ctx.accounts.reward_pool_ata.as_deref(),
+                reward_pool_authority: ctx.accounts.reward_pool_authority.as_ref(),
+                destination: Some(ctx.accounts.beneficiary_ata.to_account_info()),
+                mint: ctx.accounts.mint.key(),
+            },
+            &mut ctx.accounts.stats,
+            &ctx.accounts.token_program,
+            clock.unix_timestamp,
+        )?;
+        let lock_account = &ctx.accounts.lock_account;
+
+        emit!(SplWithdrawn {
+            lock_account: lock_key,
+            category: lock_account.category,
+            amount: lock_account.amount,
+            vault_balance,
+            realized_delta: realized_delta(lock_account.amount, vault_balance),
+            fee,
+            seq: ctx.accounts.stats.next_event_seq(),
+        });
+        sync_lock_summary(lock_account, ctx.accounts.lock_summary.as_deref_mut(), true)?;
+        notify_withdrawal(
+            lock_account,
+            net,
+            &ctx.accounts.notify_program,
+            &ctx.accounts.allowed_notify_program,
+        )?;
+        Ok(())
+    }
+
+    // Withdraw SPL tokens back to the user's ATA after unlock, or earlier if the lock has a
+    // stop-loss and its oracle reports a price at or below the threshold.
+    pub fn withdraw_spl<'info>(ctx: Context<'_, '_, 'info, 'info, WithdrawSpl<'info>>) -> Result<()> {
+        require_not_frozen(&ctx.accounts.config, Clock::get()?.unix_timestamp)?;
+        let clock = Clock::get()?;
+        let lock_account = &ctx.accounts.lock_account;
+        require!(lock_account.kind == AssetKind::Spl, TimeLockError::WrongAssetKind);
+        require_withdraw_authority(lock_account, &ctx.accounts.authority.key())?;
+        require!(lock_account.stream_periods == 0, TimeLockError::StreamNotDrained);
+        require_keys_neq!(
+            ctx.accounts.user_ata.key(),
+            ctx.accounts.vault_ata.key(),
+            TimeLockError::SameTokenAccount
+        );
+        let unlock = evaluate_unlock(
+            lock_account,
+            &clock,
+            ctx.accounts.price_feed.as_deref(),
+            &ctx.accounts.condition,
+            &[],
+        )?;
+        require!(
+            unlock.time_met || unlock.stop_loss_met,
+            TimeLockError::TimeLockNotExpired
+        );
+        if unlock.stop_loss_met {
+            msg!("[withdraw_spl] stop-loss triggered");
+        }
+        // user_ata is constrained to be owned by the initializer.
+        require_wallet_destination(lock_account, &ctx.accounts.initializer)?;
+        require!(unlock.condition_met, TimeLockError::ConditionNotMet);
+        require_withdraw_destination(lock_account, &ctx.accounts.user_ata.key())?;
+
+        require_vault_authority(&ctx.accounts.vault_ata, &ctx.accounts.lock_account)?;
+        let initializer_key = ctx.accounts.initializer.key();
+        let lock_key = lock_account.key();
+        let lock_seeds: &[&[u8]] = &[TIME_LOCK_SPL_SEED, initializer_key.as_ref(), &[lock_account.bump]];
+        let migrated_seeds: &[&[u8]] =
+            &[VAULT_AUTHORITY_SEED, lock_key.as_ref(), &[lock_account.vault_authority_bump]];
+        let (signer, seeds) =
+            vault_signer(lock_account, &ctx.accounts.vault_authority, lock_seeds, migrated_seeds)?;
+        let signer_seeds: &[&[&[u8]]] = &[seeds];
+
+        // Transfer entire vault balance back to user
+        let vault_balance = ctx.accounts.vault_ata.amount;
+        require!(vault_balance > 0, TimeLockError::InsufficientVaultBalance);
+
+        let (fee, net) = split_withdrawal(&ctx.accounts.config, lock_account, vault_balance)?;
+        pay_out_tokens(
+            &ctx.accounts.token_program,
+            &ctx.accounts.vault_ata,
+            &signer,
+            signer_seeds,
+            FeeTo {
+                config: &ctx.accounts.config,
+                treasury: ctx.accounts.treasury_ata.as_ref().map(|ata| ata.to_account_info()),
+                split_accounts: split_off_fee_accounts(&ctx.accounts.config, ctx.remaining_accounts)?.1,
+            },
+            Some(ctx.accounts.user_ata.to_account_info()),
+            (fee, net),
+        )?;
+        // The fee here is in the lock's token, so it isn't added to the lamport fee total.
+        ctx.accounts.stats.record_withdrawal(0);
+        ctx.accounts.lock_account.paid_out = true;
+        refund_deposit_fee(
+            &mut ctx.accounts.lock_account,
+            RefundFrom {
+                reward_pool_ata: ctx.accounts.reward_pool_ata.as_deref(),
+                reward_pool_authority: ctx.accounts.reward_pool_authority.as_ref(),
+                destination: Some(ctx.accounts.user_ata.to_account_info()),
+                mint: ctx.accounts.mint.key(),
+            },
+            &mut ctx.accounts.stats,
+            &ctx.accounts.token_program,
+            clock.unix_timestamp,
+        )?;
+
+        // Rebasing mints let the vault drift from what was deposited; report the difference.
+        emit!(SplWithdrawn {
+            lock_account: ctx.accounts.lock_account.key(),
+            category: ctx.accounts.lock_account.category,
+            amount: ctx.accounts.lock_account.amount,
+            vault_balance,
+            realized_delta: realized_delta(ctx.accounts.lock_account.amount, vault_balance),
+            fee,
+            seq: ctx.accounts.stats.next_event_seq(),
+        });
+        sync_lock_summary(&ctx.accounts.lock_account, ctx.accounts.lock_summary.as_deref_mut(), true)?;
+        notify_withdrawal(
+            &ctx.accounts.lock_account,
+            net,
+            &ctx.accounts.notify_program,
+            &ctx.accounts.allowed_notify_program,
+        )?;
+
+        Ok(())
+    }
+
+    // Release the tokens a vesting SPL lock has vested since the last claim. Once the schedule is
+    // complete, a claim releases whatever the vault still holds instead of the formula's result,
+    // so rounding dust or tokens sent in later aren't stranded; close_spl_lock then closes the
+    // empty vault and reclaims the rent. The claim that drains the lock also refunds its deposit
+    // fee. Shares WithdrawSpl's accounts; the price feed is ignored.
+    pub fn claim_vested_spl<'info>(ctx: Context<'_, '_, 'info, 'info, WithdrawSpl<'info>>) -> Result<()> {
+        require_not_frozen(&ctx.accounts.config, Clock::get()?.unix_timestamp)?;
+        let now = Clock::get()?.unix_timestamp;
+        let lock_account = &ctx.accounts.lock_account;
+        require!(lock_account.kind == AssetKind::Spl, TimeLockError::WrongAssetKind);
+        require_withdraw_authority(lock_account, &ctx.accounts.authority.key())?;
+        require!(lock_account.stream_periods > 0, TimeLockError::NotAStream);
+        require!(
+            lock_unlocked(lock_account, &Clock::get()?),
+            TimeLockError::TimeLockNotExpired
+        );
+        require_wallet_destination(lock_account, &ctx.accounts.initializer)?;
+        require_condition(lock_account, &ctx.accounts.condition)?;
+        require_withdraw_destination(lock_account, &ctx.accounts.user_ata.key())?;
+
+        let (vested, next_claim_at) = stream_vested(lock_account, now)?;
+        let complete = next_claim_at == i64::MAX;
+        let vault_balance = ctx.accounts.vault_ata.amount;
+        let (claimed_now, periods_passed) = if lock_account.claim_bps > 0 {
+            percent_claim(lock_account, vault_balance, now)?
+        } else if complete {
+            (vault_balance, lock_account.stream_periods)
+        } else {
+            (vested.saturating_sub(lock_account.stream_claimed), 0)
+        };
+        require!(claimed_now > 0, TimeLockError::NothingToClaim);
+        require!(
+            ctx.accounts.vault_ata.amount >= claimed_now,
+            TimeLockError::InsufficientVaultBalance
+        );
+
+        require_vault_authority(&ctx.accounts.vault_ata, &ctx.accounts.lock_account)?;
+        let initializer_key = ctx.accounts.initializer.key();
+        let lock_key = lock_account.key();
+        let lock_seeds: &[&[u8]] = &[TIME_LOCK_SPL_SEED, initializer_key.as_ref(), &[lock_account.bump]];
+        let migrated_seeds: &[&[u8]] =
+            &[VAULT_AUTHORITY_SEED, lock_key.as_ref(), &[lock_account.vault_authority_bump]];
+        let (signer, seeds) =
+            vault_signer(lock_account, &ctx.accounts.vault_authority, lock_seeds, migrated_seeds)?;
+        let signer_seeds: &[&[&[u8]]] = &[seeds];
+
+        let (fee, net) = split_withdrawal(&ctx.accounts.config, lock_account, claimed_now)?;
+        pay_out_tokens(
+            &ctx.accounts.token_program,
+            &ctx.accounts.vault_ata,
+            &signer,
+            signer_seeds,
+            FeeTo {
+                config: &ctx.accounts.config,
+                treasury: ctx.accounts.treasury_ata.as_ref().map(|ata| ata.to_account_info()),
+                split_accounts: split_off_fee_accounts(&ctx.accounts.config, ctx.remaining_accounts)?.1,
+            },
+            Some(ctx.accounts.user_ata.to_account_info()),
+            (fee, net),
+        )?;
+        ctx.accounts.stats.record_withdrawal(0);
+
+        let lock_account = &mut ctx.accounts.lock_account;
+        // A percentage schedule pays out of whatever the vault has grown to, so its running total
+        // is what was actually claimed and what's left is what the vault still holds.
+        let remaining_locked = if lock_account.claim_bps > 0 {
+            lock_account.stream_claimed = lock_account
+                .stream_claimed
+                .checked_add(claimed_now)
+                .ok_or(TimeLockError::MathOverflow)?;
+            lock_account.percent_claims = periods_passed;
+            vault_balance - claimed_now
+        } else {
+            lock_account.stream_claimed = vested;
+            lock_account.amount.saturating_sub(vested)
+        };
+        lock_account.next_claim_at = next_claim_at;
+        lock_account.withdraw_destination.get_or_insert(ctx.accounts.user_ata.key());
+        let drained = complete || vested == lock_account.amount;
+        lock_account.paid_out |= drained;
+        sync_lock_summary(lock_account, ctx.accounts.lock_summary.as_deref_mut(), drained)?;
+
+        emit!(SplVestingClaimed {
+            lock_account: lock_account.key(),
+            claimed_now,
+            fee,
+            total_claimed: lock_account.stream_claimed,
+            remaining_locked,
+            seq: ctx.accounts.stats.next_event_seq(),
+        });
+        if drained {
+            refund_deposit_fee(
+                &mut ctx.accounts.lock_account,
+                RefundFrom {
+                    reward_pool_ata: ctx.accounts.reward_pool_ata.as_deref(),
+                    reward_pool_authority: ctx.accounts.reward_pool_authority.as_ref(),
+                    destination: Some(ctx.accounts.user_ata.to_account_info()),
+                    mint: ctx.accounts.mint.key(),
+                },
+                &mut ctx.accounts.stats,
+                &ctx.accounts.token_program,
+                now,
+            )?;
+        }
+        Ok(())
+    }
+
+    // After unlock, send `withdraw_amount` to the initializer and roll `keep_amount` into a fresh
+    // lock period ending at `new_unlock_timestamp`, all in one call. The fee is charged on the
+    // withdrawn part at the old period's tier; the kept part starts a new period, so `created_at`
+    // resets with it. Anything in the vault beyond the two stays locked alongside `keep_amount`.
+    // Shares WithdrawSpl's accounts; the price feed is ignored.
+    pub fn partial_withdraw_and_relock_spl<'info>(
+        ctx: Context<'_, '_, 'info, 'info, WithdrawSpl<'info>>,
+        withdraw_amount: u64,
+        keep_amount: u64,
+        new_unlock_timestamp: i64,
+    ) -> Result<()> {
+        require_not_frozen(&ctx.accounts.config, Clock::get()?.unix_timestamp)?;
+        let now = Clock::get()?.unix_timestamp;
+        let lock_account = &ctx.accounts.lock_account;
+        require!(lock_account.kind == AssetKind::Spl, TimeLockError::WrongAssetKind);
+        // Choosing what to keep locked, and for how long, stays with the owner.
+        require_keys_eq!(ctx.accounts.authority.key(), lock_account.initializer, TimeLockError::NotAuthorized);
+        require!(lock_account.stream_periods == 0, TimeLockError::StreamNotDrained);
+        require!(
+            lock_unlocked(lock_account, &Clock::get()?),
+            TimeLockError::TimeLockNotExpired
+        );
+        require!(withdraw_amount > 0 && keep_amount > 0, TimeLockError::InvalidAmount);
+        require!(
+            lock_account.withdraw_step == 0
+                || withdraw_amount.checked_rem(lock_account.withdraw_step) == Some(0),
+            TimeLockError::NotStepAligned
+        );
+        require!(
+            lock_account.max_withdrawals == 0 || lock_account.withdrawals_made < lock_account.max_withdrawals,
+            TimeLockError::MaxWithdrawalsReached
+        );
+        require!(
+            keep_amount >= ctx.accounts.config.min_lock_tokens,
+            TimeLockError::BelowMinimumAmount
+        );
+        require!(unlock_in_future(new_unlock_timestamp, now), TimeLockError::UnlockInPast);
+        let needed = withdraw_amount
+            .checked_add(keep_amount)
+            .ok_or(TimeLockError::MathOverflow)?;
+        require!(
+            ctx.accounts.vault_ata.amount >= needed,
+            TimeLockError::InsufficientVaultBalance
+        );
+        require_keys_neq!(
+            ctx.accounts.user_ata.key(),
+            ctx.accounts.vault_ata.key(),
+            TimeLockError::SameTokenAccount
+        );
+        require_wallet_destination(lock_account, &ctx.accounts.initializer)?;
+        require_condition(lock_account, &ctx.accounts.condition)?;
+        require_withdraw_destination(lock_account, &ctx.accounts.user_ata.key())?;
+
+        require_vault_authority(&ctx.accounts.vault_ata, &ctx.accounts.lock_account)?;
+        let initializer_key = ctx.accounts.initializer.key();
+        let lock_key = lock_account.key();
+        let lock_seeds: &[&[u8]] = &[TIME_LOCK_SPL_SEED, initializer_key.as_ref(), &[lock_account.bump]];
+        let migrated_seeds: &[&[u8]] =
+            &[VAULT_AUTHORITY_SEED, lock_key.as_ref(), &[lock_account.vault_authority_bump]];
+        let (signer, seeds) =
+            vault_signer(lock_account, &ctx.accounts.vault_authority, lock_seeds, migrated_seeds)?;
+        let signer_seeds: &[&[&[u8]]] = &[seeds];
+
+        let (fee, net) = split_withdrawal(&ctx.accounts.config, lock_account, withdraw_amount)?;
+        pay_out_tokens(
+            &ctx.accounts.token_program,
+            &ctx.accounts.vault_ata,
+            &signer,
+            signer_seeds,
+            FeeTo {
+                config: &ctx.accounts.config,
+                treasury: ctx.accounts.treasury_ata.as_ref().map(|ata| ata.to_account_info()),
+                split_accounts: split_off_fee_accounts(&ctx.accounts.config, ctx.remaining_accounts)?.1,
+            },
+            Some(ctx.accounts.user_ata.to_account_info()),
+            (fee, net),
+        )?;
+        ctx.accounts.stats.record_withdrawal(0);
+
+        let lock_account = &mut ctx.accounts.lock_account;
+        lock_account.amount = keep_amount;
+        lock_account.unlock_timestamp = new_unlock_timestamp;
+        // The kept part is relocked by time, whichever mode the old period used.
+        lock_account.unlock_slot = None;
+        lock_account.created_at = now;
+        lock_account.withdraw_destination.get_or_insert(ctx.accounts.user_ata.key());
+        lock_account.withdrawals_made += 1;
+        sync_lock_summary(lock_account, ctx.accounts.lock_summary.as_deref_mut(), false)?;
+
+        emit!(SplPartiallyRelocked {
+            lock_account: lock_account.key(),
+            withdrawn: withdraw_amount,
+            fee,
+            kept: keep_amount,
+            unlock_timestamp: new_unlock_timestamp,
+            seq: ctx.accounts.stats.next_event_seq(),
+        });
+        Ok(())
+    }
+
+    // Close an SPL lock once its tokens are gone, returning the rent of both the vault ATA and
+    // the lock account to the initializer. Closing the lock while its vault still held tokens
+    // would orphan them, since nothing could sign for the vault again; the token program would
+    // refuse to close a non-empty vault anyway, but check explicitly so the interlock doesn't
+    // hinge on CPI ordering.
+    pub fn close_spl_lock(ctx: Context<CloseSplLock>) -> Result<()> {
+        require!(ctx.accounts.lock_account.kind == AssetKind::Spl, TimeLockError::WrongAssetKind);
+        require!(ctx.accounts.vault_ata.amount == 0, TimeLockError::VaultNotEmpty);
+
+        let lock_account = &ctx.accounts.lock_account;
+        let initializer_key = ctx.accounts.initializer.key();
+        let lock_key = lock_account.key();
+        let lock_seeds: &[&[u8]] = &[TIME_LOCK_SPL_SEED, initializer_key.as_ref(), &[lock_account.bump]];
+        let migrated_seeds: &[&[u8]] =
+            &[VAULT_AUTHORITY_SEED, lock_key.as_ref(), &[lock_account.vault_authority_bump]];
+        let (signer, seeds) =
+            vault_signer(lock_account, &ctx.accounts.vault_authority, lock_seeds, migrated_seeds)?;
+        let signer_seeds: &[&[&[u8]]] = &[seeds];
+
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            CloseAccount {
+                account: ctx.accounts.vault_ata.to_account_info(),
+                destination: ctx.accounts.initializer.to_account_info(),
+                authority: signer,
+            },
+            signer_seeds,
+        );
+        token::close_account(cpi_ctx)?;
+        // Normally settled by the payout already; a fee still on record is refunded before the
+        // record closes with the lock.
+        refund_deposit_fee(
+            &mut ctx.accounts.lock_account,
+            RefundFrom {
+                reward_pool_ata: ctx.accounts.reward_pool_ata.as_deref(),
+                reward_pool_authority: ctx.accounts.reward_pool_authority.as_ref(),
+                destination: ctx.accounts.user_ata.as_ref().map(|ata| ata.to_account_info()),
+                mint: ctx.accounts.mint.key(),
+            },
+            &mut ctx.accounts.stats,
+            &ctx.accounts.token_program,
+            Clock::get()?.unix_timestamp,
+        )?;
+        ctx.accounts.stats.record_lock_closed();
+        sync_lock_summary(&ctx.accounts.lock_account, ctx.accounts.lock_summary.as_deref_mut(), true)?;
+        refund_rent(&ctx.accounts.lock_account, &ctx.accounts.rent_recipient)?;
+
+        // close = initializer reclaims the lock account itself.
+        Ok(())
+    }
+
+    // Admin only: record whether an SPL lock's vault is frozen by its mint's freeze authority,
+    // read from the vault itself, so clients can show "funds frozen by mint authority" rather
+    // than a withdrawal that keeps failing. Call again after a thaw to clear the flag. The flag
+    // is informational; withdrawals check the vault directly and fail with VaultFrozen.
+    pub fn acknowledge_frozen_lock(ctx: Context<AcknowledgeFrozenLock>) -> Result<()> {
+        let lock_account = &mut ctx.accounts.lock_account;
+        require!(lock_account.kind == AssetKind::Spl, TimeLockError::WrongAssetKind);
+        lock_account.vault_frozen = ctx.accounts.vault_ata.is_frozen();
+        emit!(VaultFreezeAcknowledged {
+            lock_account: lock_account.key(),
+            frozen: lock_account.vault_frozen,
+            seq: ctx.accounts.stats.next_event_seq(),
+        });
+        Ok(())
+    }
+
+    // Admin recovery for an SPL lock whose tokens were moved out-of-band (e.g. a freeze-authority
+    // clawback), leaving nothing for the initializer to withdraw. The vault must be gone or
+    // empty; a vault that still holds any tokens means the lock is live and is refused. An empty
+    // vault is closed alongside the lock, and all rent goes to the initializer. The bond is
+    // forfeited only by a real orphan: a lock that was paid out normally and merely left open
+    // gets it back, as close_spl_lock would have given it.
+    pub fn force_close_orphan_lock(ctx: Context<ForceCloseOrphanLock>) -> Result<()> {
+        let lock_account = &ctx.accounts.lock_account;
+        require!(lock_account.kind == AssetKind::Spl, TimeLockError::WrongAssetKind);
+        let mint = lock_account.mint.ok_or(TimeLockError::MintMismatch)?;
+        let vault = &ctx.accounts.vault_ata;
+        require_keys_eq!(
+            vault.key(),
+            get_associated_token_address(&lock_account.key(), &mint),
+            TimeLockError::MintMismatch
+        );
+
+        let vault_existed = vault.owner == &token::ID && !vault.data_is_empty();
+        if vault_existed {
+            let balance = TokenAccount::try_deserialize(&mut &vault.try_borrow_data()?[..])?.amount;
+            require!(balance == 0, TimeLockError::VaultNotEmpty);
+            // Closing signs as the lock PDA, which a migrated vault no longer answers to.
+            require!(lock_account.vault_authority.is_none(), TimeLockError::VaultAuthorityChanged);
+
+            let initializer_key = ctx.accounts.initializer.key();
+            let seeds: &[&[u8]] = &[TIME_LOCK_SPL_SEED, initializer_key.as_ref(), &[lock_account.bump]];
+            let signer_seeds: &[&[&[u8]]] = &[seeds];
+            let cpi_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                CloseAccount {
+                    account: vault.to_account_info(),
+                    destination: ctx.accounts.initializer.to_account_info(),
+                    authority: lock_account.to_account_info(),
+                },
+                signer_seeds,
+            );
+            token::close_account(cpi_ctx)?;
+        }
+        ctx.accounts.stats.record_lock_closed();
+        sync_lock_summary(lock_account, ctx.accounts.lock_summary.as_deref_mut(), true)?;
+        // An orphaned lock forfeits its bond; the rest of its lamports still go to the initializer.
+        let orphaned = !vault_existed || !lock_account.paid_out;
+        let bond_forfeited = if orphaned { lock_account.bond_paid } else { 0 };
+        if bond_forfeited > 0 {
+            lock_account.sub_lamports(bond_forfeited)?;
+            ctx.accounts.treasury.add_lamports(bond_forfeited)?;
+        }
+
+        msg!(
+            "[force_close_orphan_lock] lock_account={} vault_existed={}",
+            lock_account.key(),
+            vault_existed
+        );
+        emit!(OrphanLockClosed {
+            lock_account: lock_account.key(),
+            initializer: lock_account.initializer,
+            mint,
+            vault_existed,
+            bond_forfeited,
+            admin: ctx.accounts.admin.key(),
+            seq: ctx.accounts.stats.next_event_seq(),
+        });
+        refund_rent(lock_account, &ctx.accounts.rent_recipient)?;
+        // close = initializer reclaims the lock account itself.
+        Ok(())
+    }
+
+    // Admin recovery for upgrades that change how vault authorities are derived: hand an SPL
+    // lock's vault from the lock PDA to the PDA at [VAULT_AUTHORITY_SEED, lock_account], signed
+    // by the lock PDA. Withdrawals and close then sign with the new authority, which clients pass
+    // as `vault_authority`, and so do swaps. Mint migration and orphan closing still expect an
+    // unmigrated vault and will refuse a migrated one.
+    pub fn migrate_vault_authority(ctx: Context<MigrateVaultAuthority>) -> Result<()> {
+        let lock_account = &ctx.accounts.lock_account;
+        require!(lock_account.kind == AssetKind::Spl, TimeLockError::WrongAssetKind);
+        require!(lock_account.vault_authority.is_none(), TimeLockError::VaultAuthorityChanged);
+        require_vault_authority(&ctx.accounts.vault_ata, lock_account)?;
+
+        let initializer = lock_account.initializer;
+        let seeds: &[&[u8]] = &[TIME_LOCK_SPL_SEED, initializer.as_ref(), &[lock_account.bump]];
+        let signer_seeds: &[&[&[u8]]] = &[seeds];
+        let new_authority = ctx.accounts.new_authority.key();
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            SetAuthority {
+                current_authority: lock_account.to_account_info(),
+                account_or_mint: ctx.accounts.vault_ata.to_account_info(),
+            },
+            signer_seeds,
+        );
+        token::set_authority(cpi_ctx, AuthorityType::AccountOwner, Some(new_authority))?;
+
+        let lock_account = &mut ctx.accounts.lock_account;
+        lock_account.vault_authority = Some(new_authority);
+        lock_account.vault_authority_bump = ctx.bumps.new_authority;
+
+        emit!(VaultAuthorityMigrated {
+            lock_account: lock_account.key(),
+            vault: ctx.accounts.vault_ata.key(),
+            old_authority: lock_account.key(),
+            new_authority,
+            admin: ctx.accounts.admin.key(),
+            seq: ctx.accounts.stats.next_event_seq(),
+        });
+        Ok(())
+    }
+
+    // Return SOL that was sent to an SPL lock's PDA by mistake. Only lamports above the rent-exempt
+    // minimum move; the token vault is a separate account and is left untouched.
+    pub fn reclaim_stray_lamports(ctx: Context<ReclaimStrayLamports>) -> Result<()> {
+        let lock_account = &ctx.accounts.lock_account;
+        require!(lock_account.kind == AssetKind::Spl, TimeLockError::WrongAssetKind);
+
+        // The bond and the beneficiary's ATA rent aren't stray; they stay until spent or closed.
+        let excess = lamports_above_rent(&lock_account.to_account_info())?
+            .saturating_sub(lock_account.bond_paid)
+            .saturating_sub(lock_account.ata_rent_reserve);
+        require!(excess > 0, TimeLockError::NoStrayLamports);
+
+        lock_account.sub_lamports(excess)?;
+        ctx.accounts.initializer.add_lamports(excess)?;
+
+        msg!("[reclaim_stray_lamports] lock_account={} reclaimed={}", lock_account.key(), excess);
+        Ok(())
+    }
+
+    // Withdraw an SPL lock by converting it through an allow-listed swap program. The route is
+    // opaque to us: `swap_data` and the remaining accounts (less any fee split recipients, last)
+    // are forwarded verbatim, with the vault's authority signing for it. The usual withdrawal fee
+    // is taken from the vault first and the route must spend exactly what's left, so it can't
+    // dodge the fee or leave a remainder to be charged twice. Only the outcome is checked,
+    // against the user's output ATA. A deposit fee refund isn't swapped; it comes back in the
+    // lock's own token, to `user_ata`.
+    pub fn withdraw_spl_with_swap<'info>(
+        ctx: Context<'_, '_, 'info, 'info, WithdrawSplWithSwap<'info>>,
+        min_out: u64,
+        swap_data: Vec<u8>,
+    ) -> Result<()> {
+        require_not_frozen(&ctx.accounts.config, Clock::get()?.unix_timestamp)?;
+        let clock = Clock::get()?;
+        let lock_account = &ctx.accounts.lock_account;
+        require!(lock_account.kind == AssetKind::Spl, TimeLockError::WrongAssetKind);
+        // Vesting locks are claimed tranche by tranche, through claim_vested_spl_with_swap.
+        require!(lock_account.stream_periods == 0, TimeLockError::StreamNotDrained);
+        require!(
+            lock_unlocked(lock_account, &clock),
+            TimeLockError::TimeLockNotExpired
+        );
+        require!(min_out > 0, TimeLockError::InvalidAmount);
+        require_wallet_destination(lock_account, &ctx.accounts.initializer)?;
+        require_condition(lock_account, &ctx.accounts.condition)?;
+        // A swap pays out in another mint, so it can never land on a pinned destination.
+        require!(lock_account.withdraw_destination.is_none(), TimeLockError::DestinationLocked);
+
+        let vault_before = ctx.accounts.vault_ata.amount;
+        require!(vault_before > 0, TimeLockError::InsufficientVaultBalance);
+        let out_before = ctx.accounts.user_out_ata.amount;
+
+        require_vault_authority(&ctx.accounts.vault_ata, &ctx.accounts.lock_account)?;
+        let initializer_key = ctx.accounts.initializer.key();
+        let lock_key = lock_account.key();
+        let lock_seeds: &[&[u8]] = &[TIME_LOCK_SPL_SEED, initializer_key.as_ref(), &[lock_account.bump]];
+        let migrated_seeds: &[&[u8]] =
+            &[VAULT_AUTHORITY_SEED, lock_key.as_ref(), &[lock_account.vault_authority_bump]];
+        let (signer, seeds) =
+            vault_signer(lock_account, &ctx.accounts.vault_authority, lock_seeds, migrated_seeds)?;
+        let signer_seeds: &[&[&[u8]]] = &[seeds];
+
+        let (route_accounts, fee_accounts) =
+            split_off_fee_accounts(&ctx.accounts.config, ctx.remaining_accounts)?;
+        let (fee, net) = split_withdrawal(&ctx.accounts.config, lock_account, vault_before)?;
+        pay_out_tokens(
+            &ctx.accounts.token_program,
+            &ctx.accounts.vault_ata,
+            &signer,
+            signer_seeds,
+            FeeTo {
+                config: &ctx.accounts.config,
+                treasury: ctx.accounts.treasury_ata.as_ref().map(|ata| ata.to_account_info()),
+                split_accounts: fee_accounts,
+            },
+            None,
+            (fee, net),
+        )?;
+        invoke_swap(
+            &ctx.accounts.swap_program,
+            signer.key(),
+            route_accounts,
+            swap_data,
+            signer_seeds,
+        )?;
+
+        ctx.accounts.vault_ata.reload()?;
+        ctx.accounts.user_out_ata.reload()?;
+        require!(ctx.accounts.vault_ata.amount == 0, TimeLockError::SwapFailed);
+        let received = ctx
+            .accounts
+            .user_out_ata
+            .amount
+            .saturating_sub(out_before);
+        require!(received >= min_out, TimeLockError::SlippageExceeded);
+        // The fee here is in the lock's token, so it isn't added to the lamport fee total.
+        ctx.accounts.stats.record_withdrawal(0);
+        ctx.accounts.lock_account.paid_out = true;
+        refund_deposit_fee(
+            &mut ctx.accounts.lock_account,
+            RefundFrom {
+                reward_pool_ata: ctx.accounts.reward_pool_ata.as_deref(),
+                reward_pool_authority: ctx.accounts.reward_pool_authority.as_ref(),
+                destination: ctx.accounts.user_ata.as_ref().map(|ata| ata.to_account_info()),
+                mint: ctx.accounts.mint.key(),
+            },
+            &mut ctx.accounts.stats,
+            &ctx.accounts.token_program,
+            clock.unix_timestamp,
+        )?;
+        sync_lock_summary(&ctx.accounts.lock_account, ctx.accounts.lock_summary.as_deref_mut(), true)?;
+
+        msg!(
+            "[withdraw_spl_with_swap] spent={} fee={} received={} min_out={}",
+            net,
+            fee,
+            received,
+            min_out
+        );
+        Ok(())
+    }
+
+    // Claim what a vesting lock has vested since the last claim through an allow-listed swap
+    // program, as withdraw_spl_with_swap does for a whole lock: the fee on the claimable amount
+    // is taken first and the route must spend exactly the rest, and the claim that drains the
+    // lock refunds its deposit fee to `user_ata`. Shares WithdrawSplWithSwap's accounts, so only
+    // the owner can swap, and only while no plain claim has pinned the payout ATA.
+    pub fn claim_vested_spl_with_swap<'info>(
+        ctx: Context<'_, '_, 'info, 'info, WithdrawSplWithSwap<'info>>,
+        min_out: u64,
+        swap_data: Vec<u8>,
+    ) -> Result<()> {
+        require_not_frozen(&ctx.accounts.config, Clock::get()?.unix_timestamp)?;
+        let clock = Clock::get()?;
+        let lock_account = &ctx.accounts.lock_account;
+        require!(lock_account.kind == AssetKind::Spl, TimeLockError::WrongAssetKind);
+        require!(lock_account.stream_periods > 0, TimeLockError::NotAStream);
+        require!(
+            lock_unlocked(lock_account, &clock),
+            TimeLockError::TimeLockNotExpired
+        );
+        require!(min_out > 0, TimeLockError::InvalidAmount);
+        require_wallet_destination(lock_account, &ctx.accounts.initializer)?;
+        require_condition(lock_account, &ctx.accounts.condition)?;
+        require!(lock_account.withdraw_destination.is_none(), TimeLockError::DestinationLocked);
+        require!(lock_account.claim_bps == 0, TimeLockError::ReleaseModeConflict);
+
+        let (vested, next_claim_at) = stream_vested(lock_account, clock.unix_timestamp)?;
+        let claimable = vested.saturating_sub(lock_account.stream_claimed);
+        require!(claimable > 0, TimeLockError::NothingToClaim);
+        let vault_before = ctx.accounts.vault_ata.amount;
+        require!(vault_before >= claimable, TimeLockError::InsufficientVaultBalance);
+        let out_before = ctx.accounts.user_out_ata.amount;
+
+        require_vault_authority(&ctx.accounts.vault_ata, &ctx.accounts.lock_account)?;
+        let initializer_key = ctx.accounts.initializer.key();
+        let lock_key = lock_account.key();
+        let lock_seeds: &[&[u8]] = &[TIME_LOCK_SPL_SEED, initializer_key.as_ref(), &[lock_account.bump]];
+        let migrated_seeds: &[&[u8]] =
+            &[VAULT_AUTHORITY_SEED, lock_key.as_ref(), &[lock_account.vault_authority_bump]];
+        let (signer, seeds) =
+            vault_signer(lock_account, &ctx.accounts.vault_authority, lock_seeds, migrated_seeds)?;
+        let signer_seeds: &[&[&[u8]]] = &[seeds];
+        let (route_accounts, fee_accounts) =
+            split_off_fee_accounts(&ctx.accounts.config, ctx.remaining_accounts)?;
+        let (fee, net) = split_withdrawal(&ctx.accounts.config, lock_account, claimable)?;
+        pay_out_tokens(
+            &ctx.accounts.token_program,
+            &ctx.accounts.vault_ata,
+            &signer,
+            signer_seeds,
+            FeeTo {
+                config: &ctx.accounts.config,
+                treasury: ctx.accounts.treasury_ata.as_ref().map(|ata| ata.to_account_info()),
+                split_accounts: fee_accounts,
+            },
+            None,
+            (fee, net),
+        )?;
+        invoke_swap(
+            &ctx.accounts.swap_program,
+            signer.key(),
+            route_accounts,
+            swap_data,
+            signer_seeds,
+        )?;
+
+        ctx.accounts.vault_ata.reload()?;
+        ctx.accounts.user_out_ata.reload()?;
+        let spent = (vault_before - fee).saturating_sub(ctx.accounts.vault_ata.amount);
+        require!(spent == net, TimeLockError::SwapFailed);
+        let received = ctx
+            .accounts
+            .user_out_ata
+            .amount
+            .saturating_sub(out_before);
+        require!(received >= min_out, TimeLockError::SlippageExceeded);
+        ctx.accounts.stats.record_withdrawal(0);
+
+        let lock_account = &mut ctx.accounts.lock_account;
+        lock_account.stream_claimed = vested;
+        lock_account.next_claim_at = next_claim_at;
+        let drained = lock_account.stream_claimed == lock_account.amount;
+        lock_account.paid_out |= drained;
+        sync_lock_summary(lock_account, ctx.accounts.lock_summary.as_deref_mut(), drained)?;
+
+        emit!(SplVestingClaimed {
+            lock_account: lock_account.key(),
+            claimed_now: claimable,
+            fee,
+            total_claimed: lock_account.stream_claimed,
+            remaining_locked: lock_account.amount.saturating_sub(lock_account.stream_claimed),
+            seq: ctx.accounts.stats.next_event_seq(),
+        });
+        if drained {
+            refund_deposit_fee(
+                &mut ctx.accounts.lock_account,
+                RefundFrom {
+                    reward_pool_ata: ctx.accounts.reward_pool_ata.as_deref(),
+                    reward_pool_authority: ctx.accounts.reward_pool_authority.as_ref(),
+                    destination: ctx.accounts.user_ata.as_ref().map(|ata| ata.to_account_info()),
+                    mint: ctx.accounts.mint.key(),
+                },
+                &mut ctx.accounts.stats,
+                &ctx.accounts.token_program,
+                clock.unix_timestamp,
+            )?;
+        }
+        msg!(
+            "[claim_vested_spl_with_swap] spent={} received={} min_out={}",
+            spent,
+            received,
+            min_out
+        );
+        Ok(())
+    }
+
+    // Move a locked SPL balance onto the successor mint registered for it. The old tokens go to
+    // the migration PDA, the new ones come out of its reserve, and the unlock time is unchanged.
+    // A pinned withdraw destination follows to the same owner's ATA for the new mint, and the
+    // lock's other token amounts (claimed so far, tranche, step) are rescaled with it. A deposit
+    // fee sits in the old mint's reward pool and can only be refunded from there, so a lock still
+    // owed one can't migrate.
+    pub fn migrate_spl_mint(ctx: Context<MigrateSplMint>) -> Result<()> {
+        let lock_account = &ctx.accounts.lock_account;
+        let migration = &ctx.accounts.migration;
+        require!(lock_account.kind == AssetKind::Spl, TimeLockError::WrongAssetKind);
+        require!(
+            lock_account.mint == Some(migration.old_mint),
+            TimeLockError::NoMigrationPath
+        );
+        require!(lock_account.deposit_fee_taken == 0, TimeLockError::DepositFeeOutstanding);
+
+        let old_balance = ctx.accounts.old_vault.amount;
+        require!(old_balance > 0, TimeLockError::InsufficientVaultBalance);
+        let new_balance = scale_amount(old_balance, migration.ratio_numerator, migration.ratio_denominator)?;
+        require!(new_balance > 0, TimeLockError::InvalidAmount);
+        require!(
+            ctx.accounts.migration_new_ata.amount >= new_balance,
+            TimeLockError::InsufficientVaultBalance
+        );
+        // Everything the lock keeps in base units moves to the new mint's, rounded down like the
+        // balance. A step or tranche that would round to nothing has no meaning left, so refuse.
+        let rescale = |units| scale_amount(units, migration.ratio_numerator, migration.ratio_denominator);
+        let new_amount = rescale(lock_account.amount)?;
+        let new_stream_claimed = rescale(lock_account.stream_claimed)?;
+        let new_tranche_amount = rescale(lock_account.tranche_amount)?;
+        let new_withdraw_step = rescale(lock_account.withdraw_step)?;
+        require!(
+            (new_tranche_amount > 0 || lock_account.tranche_amount == 0)
+                && (new_withdraw_step > 0 || lock_account.withdraw_step == 0),
+            TimeLockError::InvalidAmount
+        );
+
+        let initializer_key = ctx.accounts.initializer.key();
+        let lock_seeds: &[&[u8]] = &[TIME_LOCK_SPL_SEED, initializer_key.as_ref(), &[lock_account.bump]];
+        let lock_signer: &[&[&[u8]]] = &[lock_seeds];
+        let old_mint_key = migration.old_mint;
+        let migration_seeds: &[&[u8]] = &[MINT_MIGRATION_SEED, old_mint_key.as_ref(), &[migration.bump]];
+        let migration_signer: &[&[&[u8]]] = &[migration_seeds];
+
+        // Old tokens out of the lock's vault, which is then closed back to the initializer.
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            SplTransfer {
+                from: ctx.accounts.old_vault.to_account_info(),
+                to: ctx.accounts.migration_old_ata.to_account_info(),
+                authority: ctx.accounts.lock_account.to_account_info(),
+            },
+            lock_signer,
+        );
+        token::transfer(cpi_ctx, old_balance)?;
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            CloseAccount {
+                account: ctx.accounts.old_vault.to_account_info(),
+                destination: ctx.accounts.initializer.to_account_info(),
+                authority: ctx.accounts.lock_account.to_account_info(),
+            },
+            lock_signer,
+        );
+        token::close_account(cpi_ctx)?;
+
+        // New tokens from the reserve into the lock's vault for the new mint.
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            SplTransfer {
+                from: ctx.accounts.migration_new_ata.to_account_info(),
+                to: ctx.accounts.new_vault.to_account_info(),
+                authority: ctx.accounts.migration.to_account_info(),
+            },
+            migration_signer,
+        );
+        token::transfer(cpi_ctx, new_balance)?;
+
+        let lock_account = &mut ctx.accounts.lock_account;
+        let new_mint_key = ctx.accounts.new_mint.key();
+        // A pinned payout account holds the old mint; pin its owner's ATA for the new one instead.
+        // Pins are the beneficiary's ATA or an account of the initializer's.
+        if let Some(pinned) = lock_account.withdraw_destination {
+            let owner = match lock_account.beneficiary {
+                Some(beneficiary) if pinned == get_associated_token_address(&beneficiary, &old_mint_key) => {
+                    beneficiary
+                }
+                _ => lock_account.initializer,
+            };
+            lock_account.withdraw_destination = Some(get_associated_token_address(&owner, &new_mint_key));
+        }
+        lock_account.mint = Some(new_mint_key);
+        lock_account.amount = new_amount;
+        lock_account.stream_claimed = new_stream_claimed;
+        lock_account.tranche_amount = new_tranche_amount;
+        lock_account.withdraw_step = new_withdraw_step;
+        lock_account.display_decimals = ctx.accounts.new_mint.decimals;
+
+        emit!(MintMigrated {
+            lock_account: lock_account.key(),
+            old_mint: old_mint_key,
+            new_mint: ctx.accounts.new_mint.key(),
+            old_balance,
+            new_balance,
+            seq: ctx.accounts.stats.next_event_seq(),
+        });
+        Ok(())
+    }
+
+    // Create a combined lock ("safe") holding both lamports and tokens until one unlock time.
+    // Both sides are funded here, so a safe never exists half-empty.
+    pub fn initialize_safe(
+        ctx: Context<InitializeSafe>,
+        amount_lamports: u64,
+        amount_tokens: u64,
+        unlock_timestamp: i64,
+        category: u8,
+    ) -> Result<()> {
+        require_invited(&ctx.accounts.config, &ctx.accounts.invite)?;
+        let category = LockCategory::try_from(category)?;
+        require!(amount_lamports > 0 && amount_tokens > 0, TimeLockError::SafeSideEmpty);
+        let config = &ctx.accounts.config;
+        require!(
+            amount_lamports >= config.min_lock_lamports && amount_tokens >= config.min_lock_tokens,
+            TimeLockError::BelowMinimumAmount
+        );
+        require_within_max(amount_lamports, config.max_lock_lamports)?;
+        require_within_max(amount_tokens, config.max_lock_tokens)?;
+        let now = Clock::get()?.unix_timestamp;
+        require!(unlock_in_future(unlock_timestamp, now), TimeLockError::UnlockInPast);
+        let new_total = tvl_after_deposit(&ctx.accounts.config, &ctx.accounts.stats, amount_lamports)?;
+
+        let lock_account = &mut ctx.accounts.lock_account;
+        lock_account.initializer = ctx.accounts.initializer.key();
+        lock_account.amount = amount_lamports;
+        lock_account.token_amount = amount_tokens;
+        lock_account.unlock_timestamp = unlock_timestamp;
+        lock_account.bump = ctx.bumps.lock_account;
+        lock_account.created_at = now;
+        lock_account.kind = AssetKind::SolAndSpl;
+        lock_account.category = category;
+        lock_account.mint = Some(ctx.accounts.mint.key());
+        lock_account.display_decimals = SOL_DECIMALS;
+        lock_account.reward_multiplier_bps = ctx.accounts.config.reward_multiplier_bps;
+        lock_account.funded_at = now;
+        lock_account.created_slot = Clock::get()?.slot;
+
+        let cpi_ctx = CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            anchor_lang::system_program::Transfer {
+                from: ctx.accounts.initializer.to_account_info(),
+                to: ctx.accounts.lock_account.to_account_info(),
+            },
+        );
+        anchor_lang::system_program::transfer(cpi_ctx, amount_lamports)?;
+
+        let cpi_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            SplTransfer {
+                from: ctx.accounts.user_ata.to_account_info(),
+                to: ctx.accounts.vault_ata.to_account_info(),
+                authority: ctx.accounts.initializer.to_account_info(),
+            },
+        );
+        token::transfer(cpi_ctx, amount_tokens)?;
+
+        ctx.accounts.stats.total_sol_locked = new_total;
+        ctx.accounts.stats.record_lock_opened();
+        emit_lock_created(&ctx.accounts.lock_account, &mut ctx.accounts.stats);
+        Ok(())
+    }
+
+    // Release both sides of a safe after unlock: the whole vault goes to the user's ATA, the
+    // vault is closed, and closing the lock account returns its lamports.
+    pub fn withdraw_safe(ctx: Context<WithdrawSafe>) -> Result<()> {
+        require_not_frozen(&ctx.accounts.config, Clock::get()?.unix_timestamp)?;
+        let clock = Clock::get()?;
+        let lock_account = &ctx.accounts.lock_account;
+        require!(lock_account.kind == AssetKind::SolAndSpl, TimeLockError::WrongAssetKind);
+        require!(
+            lock_unlocked(lock_account, &clock),
+            TimeLockError::TimeLockNotExpired
+        );
+        require_wallet_destination(lock_account, &ctx.accounts.initializer)?;
+
+        let principal = lamports_above_rent(&lock_account.to_account_info())?;
+        let vault_balance = ctx.accounts.vault_ata.amount;
+        require!(
+            principal >= lock_account.amount && vault_balance > 0,
+            TimeLockError::SafeSideEmpty
+        );
+
+        let initializer_key = ctx.accounts.initializer.key();
+        let seeds: &[&[u8]] = &[TIME_LOCK_SAFE_SEED, initializer_key.as_ref(), &[lock_account.bump]];
+        let signer_seeds: &[&[&[u8]]] = &[seeds];
+
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            SplTransfer {
+                from: ctx.accounts.vault_ata.to_account_info(),
+                to: ctx.accounts.user_ata.to_account_info(),
+                authority: ctx.accounts.lock_account.to_account_info(),
+            },
+            signer_seeds,
+        );
+        token::transfer(cpi_ctx, vault_balance)?;
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            CloseAccount {
+                account: ctx.accounts.vault_ata.to_account_info(),
+                destination: ctx.accounts.initializer.to_account_info(),
+                authority: ctx.accounts.lock_account.to_account_info(),
+            },
+            signer_seeds,
+        );
+        token::close_account(cpi_ctx)?;
+
+        let stats = &mut ctx.accounts.stats;
+        stats.total_sol_locked = stats.total_sol_locked.saturating_sub(principal);
+        stats.record_withdrawal(0);
+        stats.record_lock_closed();
+
+        msg!(
+            "[withdraw_safe] lamports={} tokens={}",
+            principal,
+            vault_balance
+        );
+        // close = initializer returns the lamports.
+        Ok(())
+    }
+
+    // Start an empty basket: several SPL mints locked together under one unlock time. Mints are
+    // deposited one at a time with add_basket_mint, and withdraw_basket releases them all at once.
+    pub fn initialize_basket(
+        ctx: Context<InitializeBasket>,
+        unlock_timestamp: i64,
+        category: u8,
+    ) -> Result<()> {
+        require_invited(&ctx.accounts.config, &ctx.accounts.invite)?;
+        let category = LockCategory::try_from(category)?;
+        let now = Clock::get()?.unix_timestamp;
+        require!(unlock_in_future(unlock_timestamp, now), TimeLockError::UnlockInPast);
+
+        let basket = &mut ctx.accounts.basket;
+        basket.initializer = ctx.accounts.initializer.key();
+        basket.unlock_timestamp = unlock_timestamp;
+        basket.created_at = now;
+        basket.bump = ctx.bumps.basket;
+        basket.category = category;
+        ctx.accounts.stats.record_lock_opened();
+        // A basket holds no tokens yet and no single mint, so it reports as an SPL lock with
+        // neither; add_basket_mint deposits follow.
+        emit!(LockCreated {
+            lock_account: basket.key(),
+            initializer: basket.initializer,
+            kind: AssetKind::Spl,
+            category,
+            mint: None,
+            amount: 0,
+            unlock_timestamp,
+            unlock_slot: None,
+            referrer: None,
+            seq: ctx.accounts.stats.next_event_seq(),
+        });
+        Ok(())
+    }
+
+    // Deposit `amount` of a new mint into the basket, creating its vault ATA. Each mint can be
+    // added once, up to MAX_BASKET_MINTS, and only while the basket is still locked.
+    pub fn add_basket_mint(ctx: Context<AddBasketMint>, amount: u64) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let basket = &ctx.accounts.basket;
+        require!(unlock_in_future(basket.unlock_timestamp, now), TimeLockError::UnlockInPast);
+        require!(amount > 0, TimeLockError::InvalidAmount);
+        require!(amount >= ctx.accounts.config.min_lock_tokens, TimeLockError::BelowMinimumAmount);
+        require_within_max(amount, ctx.accounts.config.max_lock_tokens)?;
+        let mint = ctx.accounts.mint.key();
+        require!(!basket.mints.contains(&mint), TimeLockError::BasketMintExists);
+        require!(basket.mints.len() < MAX_BASKET_MINTS, TimeLockError::BasketFull);
+
+        let cpi_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            SplTransfer {
+                from: ctx.accounts.user_ata.to_account_info(),
+                to: ctx.accounts.vault_ata.to_account_info(),
+                authority: ctx.accounts.initializer.to_account_info(),
+            },
+        );
+        token::transfer(cpi_ctx, amount)?;
+
+        let basket = &mut ctx.accounts.basket;
+        basket.mints.push(mint);
+        basket.amounts.push(amount);
+        msg!("[add_basket_mint] mint={} amount={}", mint, amount);
+        Ok(())
+    }
+
+    // Release every vault in the basket after unlock, closing the vaults and the basket back to
+    // the initializer. remaining_accounts holds, per mint in `mints` order, the vault ATA, the
+    // initializer's token account and the treasury's token account (only read when a fee is
+    // owed), or under a fee split each recipient's token account in its place. A vault found
+    // empty, e.g. after an external clawback, is just closed; it doesn't block the other mints.
+    pub fn withdraw_basket<'info>(
+        ctx: Context<'_, '_, 'info, 'info, WithdrawBasket<'info>>,
+    ) -> Result<()> {
+        require_not_frozen(&ctx.accounts.config, Clock::get()?.unix_timestamp)?;
+        let now = Clock::get()?.unix_timestamp;
+        let basket = &ctx.accounts.basket;
+        require!(
+            is_unlocked(basket.unlock_timestamp, now),
+            TimeLockError::TimeLockNotExpired
+        );
+        // Fee recipients come per mint, so this can run past MAX_BATCH; the exact count below
+        // still bounds it through MAX_BASKET_MINTS and MAX_FEE_RECIPIENTS.
+        let accounts = ctx.remaining_accounts;
+        let fee_split_on = active_fee_splits(&ctx.accounts.config).next().is_some();
+        let item_len = 2 + active_fee_splits(&ctx.accounts.config).count().max(1);
+        require!(
+            accounts.len() == basket.mints.len() * item_len,
+            TimeLockError::BasketAccountsMismatch
+        );
+
+        let initializer_key = ctx.accounts.initializer.key();
+        let seeds: &[&[u8]] = &[TIME_LOCK_BASKET_SEED, initializer_key.as_ref(), &[basket.bump]];
+        let signer_seeds: &[&[&[u8]]] = &[seeds];
+        let config = &ctx.accounts.config;
+        let duration = basket.unlock_timestamp.saturating_sub(basket.created_at);
+
+        for (mint, item) in basket.mints.iter().zip(accounts.chunks_exact(item_len)) {
+            let (vault_info, user_info, fee_infos) = (&item[0], &item[1], &item[2..]);
+            require_keys_eq!(
+                vault_info.key(),
+                get_associated_token_address(&basket.key(), mint),
+                TimeLockError::MintMismatch
+            );
+            let vault = Account::<TokenAccount>::try_from(vault_info)?;
+            let user_ata = Account::<TokenAccount>::try_from(user_info)?;
+            require!(
+                user_ata.owner == initializer_key && user_ata.mint == *mint,
+                TimeLockError::MintMismatch
+            );
+
+            let balance = vault.amount;
+            if balance > 0 {
+                let fee = withdrawal_fee(config, duration, balance)?;
+                if fee > 0 && !fee_split_on {
+                    let treasury_ata = Account::<TokenAccount>::try_from(&fee_infos[0])?;
+                    require!(
+                        treasury_ata.owner == config.treasury && treasury_ata.mint == *mint,
+                        TimeLockError::Unauthorized
+                    );
+                }
+                // Only one of the two is read, depending on whether the split is on.
+                pay_out_tokens(
+                    &ctx.accounts.token_program,
+                    &vault,
+                    &basket.to_account_info(),
+                    signer_seeds,
+                    FeeTo {
+                        config,
+                        treasury: Some(fee_infos[0].clone()),
+                        split_accounts: fee_infos,
+                    },
+                    Some(user_info.clone()),
+                    (fee, balance - fee),
+                )?;
+            }
+            let cpi_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                CloseAccount {
+                    account: vault_info.clone(),
+                    destination: ctx.accounts.initializer.to_account_info(),
+                    authority: basket.to_account_info(),
+                },
+                signer_seeds,
+            );
+            token::close_account(cpi_ctx)?;
+        }
+        let stats = &mut ctx.accounts.stats;
+        stats.record_withdrawal(0);
+        stats.record_lock_closed();
+
+        msg!("[withdraw_basket] mints={}", basket.mints.len());
+        // close = initializer reclaims the basket itself.
+        Ok(())
+    }
+}
+
+// Time boundaries. A new lock's unlock must be strictly after the current second, and a lock is
+// withdrawable from its unlock second onward, so the earliest possible lock (`now + 1`) can be
+// withdrawn exactly one second after creation and never in the second it was created.
+fn unlock_in_future(unlock_timestamp: i64, now: i64) -> bool {
+    unlock_timestamp > now
+}
+
+// Snap `unlock_timestamp` up to the next multiple of `round_to_seconds` (86_400 lands on 00:00
+// UTC). 0 leaves it as given; rounding only ever moves the unlock later.
+fn round_unlock_timestamp(unlock_timestamp: i64, round_to_seconds: i64) -> Result<i64> {
+    if round_to_seconds == 0 {
+        return Ok(unlock_timestamp);
+    }
+    require!(
+        round_to_seconds > 0 && round_to_seconds <= MAX_UNLOCK_ROUNDING_SECS,
+        TimeLockError::InvalidDuration
+    );
+    match unlock_timestamp.rem_euclid(round_to_seconds) {
+        0 => Ok(unlock_timestamp),
+        rem => unlock_timestamp
+            .checked_add(round_to_seconds - rem)
+            .ok_or_else(|| TimeLockError::MathOverflow.into()),
+    }
+}
+
+fn is_unlocked(unlock_timestamp: i64, now: i64) -> bool {
+    now >= unlock_timestamp
+}
+
+// A lock unlocks by slot height if it was created with an `unlock_slot`, and by timestamp
+// otherwise; never by both.
+fn lock_unlocked(lock_account: &TimeLockAccount, clock: &Clock) -> bool {
+    match lock_account.unlock_slot {
+        Some(unlock_slot) => clock.slot >= unlock_slot,
+        None => is_unlocked(lock_account.unlock_timestamp, clock.unix_timestamp),
+    }
+}
+
+// Validate a new lock's unlock condition and return the unlock timestamp to store. Exactly one
+// mode is allowed: a timestamp (optionally rounded), or an `unlock_slot` with the timestamp
+// left at 0. Slot locks can't stream or vest, since those schedules are in seconds.
+fn resolve_unlock(
+    unlock_timestamp: i64,
+    round_to_seconds: i64,
+    unlock_slot: Option<u64>,
+    stream_periods: u16,
+) -> Result<i64> {
+    let clock = Clock::get()?;
+    let Some(unlock_slot) = unlock_slot else {
+        let unlock_timestamp = round_unlock_timestamp(unlock_timestamp, round_to_seconds)?;
+        require!(unlock_in_future(unlock_timestamp, clock.unix_timestamp), TimeLockError::UnlockInPast);
+        return Ok(unlock_timestamp);
+    };
+    require!(
+        unlock_timestamp == 0 && round_to_seconds == 0 && stream_periods == 0,
+        TimeLockError::InvalidUnlockMode
+    );
+    require!(unlock_slot > clock.slot, TimeLockError::UnlockInPast);
+    Ok(0)
+}
+
+// How long a lock was set for, in seconds, for fee tiers. Slot locks are converted at the
+// target slot time.
+fn lock_duration(lock_account: &TimeLockAccount) -> i64 {
+    match lock_account.unlock_slot {
+        Some(unlock_slot) => {
+            let slots = unlock_slot.saturating_sub(lock_account.created_slot);
+            i64::try_from(slots.saturating_mul(SLOT_MS) / 1_000).unwrap_or(i64::MAX)
+        }
+        None => lock_account
+            .unlock_timestamp
+            .saturating_sub(lock_account.created_at),
+    }
+}
+
+// Every capability get_capabilities reports, in one place: the built-in ones always, and the
+// config-dependent ones only while the config actually enables them.
+fn capabilities(config: &Config) -> u64 {
+    let mut features = CAP_SOL_STREAMS
+        | CAP_SPL_VESTING
+        | CAP_BASKETS
+        | CAP_RECEIPTS
+        | CAP_SWAP_WITHDRAWAL
+        | CAP_LOCK_SUMMARIES
+        | CAP_GOALS
+        | CAP_QUORUM_RELEASE;
+    if config.sol_usd_oracle != Pubkey::default() {
+        features |= CAP_USD_LOCKS;
+    }
+    if config.invite_only {
+        features |= CAP_INVITE_ONLY;
+    }
+    if config.fee_tiers.iter().any(|tier| tier.max_duration_secs > 0 && tier.fee_bps > 0) {
+        features |= CAP_WITHDRAWAL_FEES;
+    }
+    if config.bond_lamports > 0 {
+        features |= CAP_CREATION_BOND;
+    }
+    features
+}
+
+// Fee owed on withdrawing `gross` from a lock, from the config tier matching how long the lock
+// was set for (`unlock_timestamp - created_at`). Product held in u128 so large balances can't
+// overflow before the division.
+fn withdrawal_fee(config: &Config, duration: i64, gross: u64) -> Result<u64> {
+    let fee_bps = config
+        .fee_tiers
+        .iter()
+        .find(|tier| tier.max_duration_secs > 0 && duration < tier.max_duration_secs)
+        .map_or(0, |tier| tier.fee_bps.min(MAX_FEE_BPS));
+    scale_amount(gross, fee_bps as u64, BPS_DENOMINATOR)
+}
+
+// Split a withdrawal of `gross` into (fee, net). Every lock withdrawal path and
+// preview_withdrawal go through this, so a preview can't drift from what execution pays. Locks
+// can't be exited early, so the fee tier is the only deduction.
+fn split_withdrawal(config: &Config, lock_account: &TimeLockAccount, gross: u64) -> Result<(u64, u64)> {
+    let fee = withdrawal_fee(config, lock_duration(lock_account), gross)?;
+    Ok((fee, gross - fee))
+}
+
+// What a withdrawal would pay out of this lock right now, before fees: the next stream or
+// vesting chunk, the vault balance for SPL, or everything above rent for SOL.
+fn withdrawable_now(
+    lock_account: &Account<TimeLockAccount>,
+    vault_ata: Option<&TokenAccount>,
+    now: i64,
+) -> Result<u64> {
+    if lock_account.stream_periods > 0 && lock_account.claim_bps > 0 {
+        let vault_ata = vault_ata.ok_or(TimeLockError::VaultAccountMissing)?;
+        return Ok(percent_claim(lock_account, vault_ata.amount, now)?.0);
+    }
+    if lock_account.stream_periods > 0 {
+        let (vested, _) = stream_vested(lock_account, now)?;
+        return Ok(vested.saturating_sub(lock_account.stream_claimed));
+    }
+    match lock_account.kind {
+        AssetKind::Spl => Ok(vault_ata.ok_or(TimeLockError::VaultAccountMissing)?.amount),
+        AssetKind::Delegated => Ok(vault_ata
+            .ok_or(TimeLockError::VaultAccountMissing)?
+            .amount
+            .min(lock_account.amount)),
+        AssetKind::Sol | AssetKind::Savings => lamports_above_rent(&lock_account.to_account_info()),
+        AssetKind::SolAndSpl => err!(TimeLockError::WrongAssetKind),
+    }
+}
+
+// Shared tail of every SOL withdrawal: check the lock still holds its declared amount (a lock
+// that was never fully funded must fail loudly rather than quietly pay out less, mirroring the
+// SPL vault balance check), move the fee to the treasury (or the fee split's `fee_accounts`) and
+// release the principal from the TVL counter. The caller's `close` constraint pays out what's
+// left. Returns (principal, fee).
+fn settle_sol_withdrawal<'info>(
+    lock_account: &Account<'info, TimeLockAccount>,
+    config: &Config,
+    stats: &mut Stats,
+    treasury: &AccountInfo<'info>,
+    fee_accounts: &[AccountInfo<'info>],
+) -> Result<(u64, u64)> {
+    let principal = lamports_above_rent(&lock_account.to_account_info())?;
+    require!(principal >= lamports_owed(lock_account), TimeLockError::InsufficientVaultBalance);
+
+    let (fee, _) = split_withdrawal(config, lock_account, principal)?;
+    let fee_to = FeeTo {
+        config,
+        treasury: Some(treasury.clone()),
+        split_accounts: fee_accounts,
+    };
+    pay_out_lamports(&lock_account.to_account_info(), &fee_to, None, (fee, 0))?;
+
+    // Everything above rent is principal leaving the program. Saturate so a counter that
+    // predates the stats account (or stray lamports) can never block a withdrawal.
+    stats.total_sol_locked = stats.total_sol_locked.saturating_sub(principal);
+    stats.record_withdrawal(fee);
+    Ok((principal, fee))
+}
+
+// Every SOL payout that takes a fee moves lamports in this order: all checks first, then the
+// fee to the treasury (or each fee split recipient), then `net` to the recipient (None when the
+// caller's `close` pays out the rest). The lock must cover fee + net above its rent reserve, and
+// a treasury the fee would leave short of rent-exemption (say, a fresh wallet and a tiny fee) is
+// refused up front. The runtime would reject either at the end of the transaction anyway, but
+// only after the lamports had notionally moved, with an error that names neither cause.
+fn pay_out_lamports<'info>(
+    lock_account: &AccountInfo<'info>,
+    fee_to: &FeeTo<'_, 'info>,
+    recipient: Option<&AccountInfo<'info>>,
+    (fee, net): (u64, u64),
+) -> Result<()> {
+    let total = fee.checked_add(net).ok_or(TimeLockError::MathOverflow)?;
+    require!(lamports_above_rent(lock_account)? >= total, TimeLockError::InsufficientVaultBalance);
+    let payees = fee_to.payees(fee, |account, recipient| Ok(account.key() == *recipient))?;
+    for (treasury, share) in &payees {
+        let treasury_after = treasury
+            .lamports()
+            .checked_add(*share)
+            .ok_or(TimeLockError::MathOverflow)?;
+        require!(
+            Rent::get()?.is_exempt(treasury_after, treasury.data_len()),
+            TimeLockError::TreasuryNotRentExempt
+        );
+    }
+    for (treasury, share) in &payees {
+        lock_account.sub_lamports(*share)?;
+        treasury.add_lamports(*share)?;
+    }
+    if let Some(recipient) = recipient {
+        lock_account.sub_lamports(net)?;
+        recipient.add_lamports(net)?;
+    }
+    Ok(())
+}
+
+// The SPL counterpart: check the vault covers fee + net and that a fee has somewhere to go,
+// then transfer the fee, then `net` (None when the caller moves it itself, as a swap does,
+// leaving it in the vault). Token transfers carry no rent risk, and a failing CPI
+// reverts the whole instruction, so the fee can never be taken without the principal landing.
+// A vault the mint's freeze authority froze is named as such up front; the token program would
+// only fail the transfer with a generic "account is frozen".
+fn pay_out_tokens<'info>(
+    token_program: &Program<'info, Token>,
+    vault: &Account<'info, TokenAccount>,
+    authority: &AccountInfo<'info>,
+    signer_seeds: &[&[&[u8]]],
+    fee_to: FeeTo<'_, 'info>,
+    recipient: Option<AccountInfo<'info>>,
+    (fee, net): (u64, u64),
+) -> Result<()> {
+    require!(!vault.is_frozen(), TimeLockError::VaultFrozen);
+    let total = fee.checked_add(net).ok_or(TimeLockError::MathOverflow)?;
+    require!(vault.amount >= total, TimeLockError::InsufficientVaultBalance);
+    let payees = fee_to.payees(fee, |account, recipient| {
+        if *account.owner != token::ID {
+            return Ok(false);
+        }
+        let ata = TokenAccount::try_deserialize(&mut &account.try_borrow_data()?[..])?;
+        Ok(ata.owner == *recipient && ata.mint == vault.mint)
+    })?;
+    let transfer = |to: AccountInfo<'info>, amount: u64| {
+        let cpi_ctx = CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            SplTransfer {
+                from: vault.to_account_info(),
+                to,
+                authority: authority.clone(),
+            },
+            signer_seeds,
+        );
+        token::transfer(cpi_ctx, amount)
+    };
+    for (treasury_ata, share) in payees {
+        transfer(treasury_ata, share)?;
+    }
+    match recipient {
+        Some(recipient) => transfer(recipient, net),
+        None => Ok(()),
+    }
+}
+
+// Where a withdrawal fee goes: all of it to `treasury`, or while config has a fee split, a share
+// to each recipient through `split_accounts`, which line up with the split's entries in order.
+struct FeeTo<'a, 'info> {
+    config: &'a Config,
+    treasury: Option<AccountInfo<'info>>,
+    split_accounts: &'a [AccountInfo<'info>],
+}
+
+impl<'info> FeeTo<'_, 'info> {
+    // (account, share) for everyone `fee` is paid to. Each split recipient gets its bps of the
+    // fee and the last one the rounding dust, so the shares always add up to `fee`. `owned_by`
+    // checks an account belongs to the recipient its entry names.
+    fn payees(
+        &self,
+        fee: u64,
+        owned_by: impl Fn(&AccountInfo<'info>, &Pubkey) -> Result<bool>,
+    ) -> Result<Vec<(AccountInfo<'info>, u64)>> {
+        if fee == 0 {
+            return Ok(Vec::new());
+        }
+        let splits: Vec<&FeeSplit> = active_fee_splits(self.config).collect();
+        if splits.is_empty() {
+            let treasury = self.treasury.clone().ok_or(TimeLockError::TreasuryAccountMissing)?;
+            return Ok(vec![(treasury, fee)]);
+        }
+        require!(
+            self.split_accounts.len() == splits.len(),
+            TimeLockError::FeeRecipientMismatch
+        );
+        let mut unpaid = fee;
+        let mut payees = Vec::with_capacity(splits.len());
+        for (index, (split, account)) in splits.iter().zip(self.split_accounts).enumerate() {
+            require!(owned_by(account, &split.recipient)?, TimeLockError::FeeRecipientMismatch);
+            let share = if index + 1 == splits.len() {
+                unpaid
+            } else {
+                scale_amount(fee, split.bps as u64, BPS_DENOMINATOR)?
+            };
+            unpaid -= share;
+            payees.push((account.clone(), share));
+        }
+        Ok(payees)
+    }
+}
+
+fn active_fee_splits(config: &Config) -> impl Iterator<Item = &FeeSplit> {
+    config.fee_split.iter().filter(|split| split.bps > 0)
+}
+
+// Under a fee split, an instruction's last remaining accounts are the split's recipients.
+// Returns (the instruction's own remaining accounts, the recipients); the latter is empty while
+// fees all go to the treasury.
+fn split_off_fee_accounts<'a, 'info>(
+    config: &Config,
+    remaining: &'a [AccountInfo<'info>],
+) -> Result<(&'a [AccountInfo<'info>], &'a [AccountInfo<'info>])> {
+    let count = active_fee_splits(config).count();
+    require!(remaining.len() >= count, TimeLockError::FeeRecipientMismatch);
+    Ok(remaining.split_at(remaining.len() - count))
+}
+
+// Report a SOL withdrawal by component, once the principal is out and only the rent reserve is
+// left in the lock: that reserve is what the rent recipient (or, through `close`, the
+// initializer) gets back.
+fn emit_sol_withdrawn(
+    lock_account: &Account<TimeLockAccount>,
+    stats: &mut Stats,
+    principal_returned: u64,
+    fee_taken: u64,
+    saved: u64,
+) {
+    emit!(SolWithdrawn {
+        lock_account: lock_account.key(),
+        principal_returned,
+        rent_refunded: lock_account.to_account_info().lamports(),
+        rent_recipient: lock_account.rent_recipient.unwrap_or(lock_account.initializer),
+        fee_taken,
+        saved,
+        seq: stats.next_event_seq(),
+    });
+}
+
+// Lamports a lock must still hold above rent: its amount, less anything a stream already paid.
+fn lamports_owed(lock_account: &TimeLockAccount) -> u64 {
+    match lock_account.kind {
+        AssetKind::Spl | AssetKind::Delegated => 0,
+        _ => lock_account.amount.saturating_sub(lock_account.stream_claimed),
+    }
+}
+
+// Base units a lock's vault must hold: `amount` for SPL and delegated locks, `token_amount` for
+// safes.
+fn tokens_owed(lock_account: &TimeLockAccount) -> u64 {
+    match lock_account.kind {
+        AssetKind::Spl | AssetKind::Delegated => lock_account.amount,
+        AssetKind::SolAndSpl => lock_account.token_amount,
+        _ => 0,
+    }
+}
+
+// Re-derive a lock's address from its stored seeds and return the canonical bump, checking the
+// stored one matches.
+fn check_canonical_bump(lock_account: &Account<TimeLockAccount>) -> Result<u8> {
+    let (expected_key, canonical_bump) = find_lock_address(lock_account.kind, &lock_account.initializer);
+    // If the stored seeds don't derive this address there is no bump to compare against.
+    require_keys_eq!(expected_key, lock_account.key(), TimeLockError::BumpMissing);
+    require!(lock_account.bump == canonical_bump, TimeLockError::BumpMismatch);
+    Ok(canonical_bump)
+}
+
+// Every batch instruction takes its items through here, so the MAX_BATCH cap is enforced before
+// any item is touched.
+fn batch_accounts<'a, 'info>(remaining: &'a [AccountInfo<'info>]) -> Result<&'a [AccountInfo<'info>]> {
+    require!(remaining.len() <= MAX_BATCH, TimeLockError::BatchTooLarge);
+    Ok(remaining)
+}
+
+// Essential event for indexers grouping locks: emitted by every lock creation path.
+fn emit_lock_created(lock_account: &Account<TimeLockAccount>, stats: &mut Stats) {
+    emit!(LockCreated {
+        lock_account: lock_account.key(),
+        initializer: lock_account.initializer,
+        kind: lock_account.kind,
+        category: lock_account.category,
+        mint: lock_account.mint,
+        amount: lock_account.amount,
+        unlock_timestamp: lock_account.unlock_timestamp,
+        unlock_slot: lock_account.unlock_slot,
+        referrer: lock_account.referrer,
+        seq: stats.next_event_seq(),
+    });
+}
+
+// Attribution only means something for someone else; nobody refers themselves.
+fn require_not_self_referral(referrer: Option<Pubkey>, initializer: &Pubkey) -> Result<()> {
+    require!(referrer != Some(*initializer), TimeLockError::SelfReferral);
+    Ok(())
+}
+
+// A lock with a condition_account can only pay out once that flag has been released.
+fn require_condition(
+    lock_account: &TimeLockAccount,
+    condition: &Option<Box<Account<ConditionFlag>>>,
+) -> Result<()> {
+    require!(condition_met(lock_account, condition), TimeLockError::ConditionNotMet);
+    Ok(())
+}
+
+// A lock without a condition_account is never held by one; otherwise its flag must be passed
+// and released.
+fn condition_met(lock_account: &TimeLockAccount, condition: &Option<Box<Account<ConditionFlag>>>) -> bool {
+    match lock_account.condition_account {
+        Some(expected) => condition
+            .as_ref()
+            .is_some_and(|condition| condition.key() == expected && condition.released),
+        None => true,
+    }
+}
+
+// A stop-loss fires once the configured oracle's price is at or below the trigger. Without a
+// configured oracle or a passed feed it simply hasn't fired; a feed that isn't the configured
+// oracle, or a stale one, is an error.
+fn stop_loss_triggered(lock_account: &TimeLockAccount, price_feed: Option<&AccountInfo>, now: i64) -> Result<bool> {
+    let (Some(oracle), Some(price_feed)) = (lock_account.stop_loss_oracle, price_feed) else {
+        return Ok(false);
+    };
+    require_keys_eq!(price_feed.key(), oracle, TimeLockError::OracleMismatch);
+    let price = read_oracle_price(price_feed, now)?;
+    Ok(price.price <= lock_account.stop_loss_price)
+}
+
+// Every condition a lock's release hangs on, evaluated once against the current state. The
+// withdrawals enforce these fields and explain_unlock reports them, so the two can't disagree.
+// Time, a triggered stop-loss or a quorum each release the lock; the condition flag and the
+// goal gate every path.
+struct UnlockState {
+    time_met: bool,
+    stop_loss_met: bool,
+    approvals: usize,
+    quorum_met: bool,
+    condition_met: bool,
+    goal_met: bool,
+    // No preimage is ever passed here, so this only holds for locks without a password.
+    password_met: bool,
+}
+
+impl UnlockState {
+    fn withdrawable(&self) -> bool {
+        (self.time_met || self.stop_loss_met || self.quorum_met)
+            && self.condition_met
+            && self.goal_met
+            && self.password_met
+    }
+}
+
+// The oracle is only read before the lock matures, so a stale feed never blocks a lock that
+// time has already released.
+fn evaluate_unlock(
+    lock_account: &TimeLockAccount,
+    clock: &Clock,
+    price_feed: Option<&AccountInfo>,
+    condition: &Option<Box<Account<ConditionFlag>>>,
+    approvers: &[AccountInfo],
+) -> Result<UnlockState> {
+    let time_met = lock_unlocked(lock_account, clock);
+    let stop_loss_met = !time_met && stop_loss_triggered(lock_account, price_feed, clock.unix_timestamp)?;
+    let approvals = quorum_approvals(&lock_account.quorum_signers, approvers);
+    Ok(UnlockState {
+        time_met,
+        stop_loss_met,
+        approvals,
+        quorum_met: lock_account.quorum_threshold > 0 && approvals >= lock_account.quorum_threshold as usize,
+        condition_met: condition_met(lock_account, condition),
+        goal_met: goal_met(lock_account),
+        password_met: password_met(lock_account),
+    })
+}
+
+// A quorum is either off (no signers, threshold 0) or a threshold between 1 and the number of
+// distinct signers.
+fn require_valid_quorum(signers: &[Pubkey], threshold: u8) -> Result<()> {
+    require!(signers.len() <= MAX_QUORUM_SIGNERS, TimeLockError::InvalidQuorum);
+    require!(
+        (signers.is_empty() && threshold == 0) || (threshold > 0 && threshold as usize <= signers.len()),
+        TimeLockError::InvalidQuorum
+    );
+    for (i, signer) in signers.iter().enumerate() {
+        require!(!signers[..i].contains(signer), TimeLockError::InvalidQuorum);
+    }
+    Ok(())
+}
+
+// Designated signers that actually signed. Counting from the signer list rather than the passed
+// accounts means a signer passed twice still counts once.
+fn quorum_approvals(signers: &[Pubkey], accounts: &[AccountInfo]) -> usize {
+    signers
+        .iter()
+        .filter(|signer| accounts.iter().any(|info| info.is_signer && info.key == *signer))
+        .count()
+}
+
+// A lock release_group may release: a matured, settled plain SOL lock whose withdrawal needs
+// nothing beyond the lock, its initializer and the treasury.
+fn group_releasable(lock_account: &TimeLockAccount, config: &Config, clock: &Clock) -> bool {
+    lock_account.kind == AssetKind::Sol
+        && lock_account.stream_periods == 0
+        && lock_account.receipt_mint.is_none()
+        && lock_account.auto_save_bps == 0
+        && !lock_account.has_summary
+        && lock_account.condition_account.is_none()
+        && goal_met(lock_account)
+        && password_met(lock_account)
+        && lock_account.quorum_threshold == 0
+        && lock_account.notify_program.is_none()
+        && lock_account.rent_recipient.is_none()
+        && lock_unlocked(lock_account, clock)
+        && require_settled(lock_account, config, clock.slot).is_ok()
+}
+
+// A lock sweep_closable_locks may close: a SOL stream with nothing left to claim, which
+// withdraw_sol would otherwise only close when its initializer gets around to it.
+fn is_closable(lock_account: &TimeLockAccount) -> bool {
+    lock_account.kind == AssetKind::Sol
+        && lock_account.stream_periods > 0
+        && lock_account.stream_claimed == lock_account.amount
+        && lock_account.receipt_mint.is_none()
+        && !lock_account.has_summary
+}
+
+// init_if_needed also accepts a lock that already exists and runs the init body again. An
+// existing lock created in this same slot is almost certainly an initialize instruction
+// duplicated within one transaction, so fail loudly instead of letting the second overwrite the
+// first. Returns whether the lock is brand new, which under `strict-init` it always is.
+fn is_new_lock(lock_account: &TimeLockAccount, slot: u64) -> Result<bool> {
+    if lock_account.initializer == Pubkey::default() {
+        return Ok(true);
+    }
+    require!(lock_account.created_slot != slot, TimeLockError::DuplicateInitialization);
+    Ok(false)
+}
+
+// In invite-only mode, creating a lock requires the initializer's invite; the context's seeds
+// constraint ties a provided invite to the initializer.
+fn require_invited(config: &Config, invite: &Option<Account<Invite>>) -> Result<()> {
+    require!(!config.invite_only || invite.is_some(), TimeLockError::NotInvited);
+    Ok(())
+}
+
+// A lock's whole amount against the configured per-lock cap; a cap of 0 is off.
+fn require_within_max(amount: u64, max: u64) -> Result<()> {
+    require!(max == 0 || amount <= max, TimeLockError::ExceedsMaxLockSize);
+    Ok(())
+}
+
+// Program-wide SOL locked after adding `deposit`, or TvlCapExceeded. Every transaction that
+// writes `stats` is serialized by the runtime, so deposits landing in the same block are each
+// checked against the counter left by the previous one.
+fn tvl_after_deposit(config: &Config, stats: &Stats, deposit: u64) -> Result<u64> {
+    let new_total = stats
+        .total_sol_locked
+        .checked_add(deposit)
+        .ok_or(TimeLockError::TvlCapExceeded)?;
+    require!(new_total <= config.max_tvl_lamports, TimeLockError::TvlCapExceeded);
+    Ok(new_total)
+}
+
+// initialize_lock_sol only records the lock; the SOL arrives in a separate fund_sol_lock. Look
+// through the rest of the transaction for that fund_sol_lock on `lock_account`, so a lock can't
+// be initialized and left unfunded. Only top-level instructions are visible here, so a program
+// calling initialize_lock_sol through CPI must still have the fund as its own instruction.
+fn require_funding_follows(instructions: &AccountInfo, lock_account: Pubkey) -> Result<()> {
+    let mut index = load_current_index_checked(instructions)? as usize + 1;
+    // Loading past the last instruction errors, which ends the scan.
+    while let Ok(ix) = load_instruction_at_checked(index, instructions) {
+        // FundSolLock's accounts put the lock second, after the initializer.
+        if ix.program_id == crate::ID
+            && ix.data.starts_with(instruction::FundSolLock::DISCRIMINATOR)
+            && ix.accounts.get(1).map(|meta| meta.pubkey) == Some(lock_account)
+        {
+            return Ok(());
+        }
+        index += 1;
+    }
+    err!(TimeLockError::MissingFundingInstruction)
+}
+
+fn lamports_above_rent(info: &AccountInfo) -> Result<u64> {
+    let rent_exempt = Rent::get()?.minimum_balance(info.data_len());
+    Ok(info.lamports().saturating_sub(rent_exempt))
+}
+
+// `amount * numerator / denominator`, rounded down, with the product held in u128. Fee, vesting
+// and stream math all scale through here, so a high-decimal mint with a balance near u64::MAX
+// can't overflow the intermediate; only a result too large for u64 errors, and never panics.
+fn scale_amount(amount: u64, numerator: u64, denominator: u64) -> Result<u64> {
+    let scaled = (amount as u128)
+        .checked_mul(numerator as u128)
+        .and_then(|product| product.checked_div(denominator as u128))
+        .ok_or(TimeLockError::MathOverflow)?;
+    u64::try_from(scaled).map_err(|_| error!(TimeLockError::MathOverflow))
+}
+
+// Signed `actual - stored`, computed in i128 so neither u64 can overflow the subtraction, then
+// clamped to the i64 range (only reachable when the two differ by more than i64::MAX).
+fn realized_delta(stored: u64, actual: u64) -> i64 {
+    let delta = actual as i128 - stored as i128;
+    delta.clamp(i64::MIN as i128, i64::MAX as i128) as i64
+}
+
+// A lock still holding everything it owes, but whose rent reserve has fallen short of the
+// current rent-exempt minimum (say, after rent went up), would otherwise fail its payout. Have
+// the signer cover the gap from their wallet so the withdrawal goes through; the top-up leaves
+// again with the reserve. Missing principal is never made up here. Returns the amount topped up.
+fn top_up_rent_reserve<'info>(
+    lock_account: &Account<'info, TimeLockAccount>,
+    payer: &Signer<'info>,
+    system_program: &Program<'info, System>,
+) -> Result<u64> {
+    let info = lock_account.to_account_info();
+    let owed = lamports_owed(lock_account);
+    let needed = Rent::get()?
+        .minimum_balance(info.data_len())
+        .checked_add(owed)
+        .ok_or(TimeLockError::MathOverflow)?;
+    let held = info.lamports();
+    if held >= needed || held < owed {
+        return Ok(0);
+    }
+    let shortfall = needed - held;
+    let cpi_ctx = CpiContext::new(
+        system_program.to_account_info(),
+        anchor_lang::system_program::Transfer {
+            from: payer.to_account_info(),
+            to: info,
+        },
+    );
+    anchor_lang::system_program::transfer(cpi_ctx, shortfall)?;
+    msg!("[top_up_rent_reserve] lock_account={} shortfall={}", lock_account.key(), shortfall);
+    Ok(shortfall)
+}
+
+// Pay the lock account's rent-exempt reserve to its assigned rent recipient, ahead of the
+// `close` constraint sweeping what's left to the close target. A lock without one is untouched.
+fn refund_rent<'info>(
+    lock_account: &Account<'info, TimeLockAccount>,
+    rent_recipient: &Option<UncheckedAccount<'info>>,
+) -> Result<()> {
+    let Some(recipient_key) = lock_account.rent_recipient else {
+        return Ok(());
+    };
+    let recipient = rent_recipient
+        .as_ref()
+        .ok_or(TimeLockError::RentRecipientMissing)?;
+    require_keys_eq!(recipient.key(), recipient_key, TimeLockError::RentRecipientMissing);
+
+    let info = lock_account.to_account_info();
+    let reserve = Rent::get()?.minimum_balance(info.data_len()).min(info.lamports());
+    lock_account.sub_lamports(reserve)?;
+    recipient.add_lamports(reserve)?;
+    Ok(())
+}
+
+// Mirror the lock's unlock time and withdrawn state into its summary, if it has opted into one.
+fn sync_lock_summary(
+    lock_account: &TimeLockAccount,
+    lock_summary: Option<&mut Account<LockSummary>>,
+    is_withdrawn: bool,
+) -> Result<()> {
+    if !lock_account.has_summary {
+        return Ok(());
+    }
+    let lock_summary = lock_summary.ok_or(TimeLockError::SummaryMissing)?;
+    lock_summary.unlock_timestamp = lock_account.unlock_timestamp;
+    lock_summary.is_withdrawn = is_withdrawn;
+    Ok(())
+}
+
+// Push the unlock out to `now + auto_extend_seconds` after a deposit, if that's later.
+fn apply_auto_extend(
+    lock_account: &mut Account<TimeLockAccount>,
+    stats: &mut Stats,
+    config: &Config,
+    now: i64,
+) -> Result<()> {
+    if lock_account.auto_extend_seconds == 0 {
+        return Ok(());
+    }
+    let extended = now
+        .checked_add(lock_account.auto_extend_seconds)
+        .ok_or(TimeLockError::MathOverflow)?;
+    let old_unlock_timestamp = lock_account.unlock_timestamp;
+    if extended <= old_unlock_timestamp {
+        return Ok(());
+    }
+    lock_account.unlock_timestamp = extended;
+    if config.log_level >= LOG_FUNDING {
+        emit!(UnlockAutoExtended {
+            lock_account: lock_account.key(),
+            old_unlock_timestamp,
+            unlock_timestamp: extended,
+            seq: stats.next_event_seq(),
+        });
+    }
+    Ok(())
+}
+
+// The vault's token authority is what lets the program sign for it: the lock PDA, or the
+// vault authority PDA after migrate_vault_authority. The account constraint already checks
+// this, but assert it again before any payout trusts the signer seeds.
+fn require_vault_authority(vault_ata: &TokenAccount, lock_account: &Account<TimeLockAccount>) -> Result<()> {
+    require_keys_eq!(
+        vault_ata.owner,
+        lock_account.vault_authority.unwrap_or(lock_account.key()),
+        TimeLockError::VaultAuthorityChanged
+    );
+    Ok(())
+}
+
+// The account and seeds that sign for a lock's vault, given the seeds for both schemes.
+fn vault_signer<'a, 'info>(
+    lock_account: &Account<'info, TimeLockAccount>,
+    vault_authority: &Option<UncheckedAccount<'info>>,
+    lock_seeds: &'a [&'a [u8]],
+    migrated_seeds: &'a [&'a [u8]],
+) -> Result<(AccountInfo<'info>, &'a [&'a [u8]])> {
+    if lock_account.vault_authority.is_none() {
+        return Ok((lock_account.to_account_info(), lock_seeds));
+    }
+    let authority = vault_authority
+        .as_ref()
+        .ok_or(TimeLockError::VaultAuthorityMissing)?;
+    Ok((authority.to_account_info(), migrated_seeds))
+}
+
+// A lock with a password only pays out through claim_with_password.
+fn password_met(lock_account: &TimeLockAccount) -> bool {
+    lock_account.password_hash == [0u8; 32]
+}
+
+// What claim_with_password's accounts and checks can pay out: a plain SOL lock with nothing
+// else gating or redirecting the payout.
+fn password_claimable(lock_account: &TimeLockAccount) -> bool {
+    lock_account.kind == AssetKind::Sol
+        && lock_account.stream_periods == 0
+        && lock_account.receipt_mint.is_none()
+        && lock_account.auto_save_bps == 0
+        && !lock_account.has_summary
+        && lock_account.condition_account.is_none()
+        && lock_account.quorum_threshold == 0
+        && lock_account.notify_program.is_none()
+        && lock_account.rent_recipient.is_none()
+        && lock_account.charity_fallback.is_none()
+}
+
+// A lock without a goal, or whose goal doesn't gate withdrawal, always passes.
+fn goal_met(lock_account: &TimeLockAccount) -> bool {
+    !lock_account.goal_required || lock_account.goal_reached
+}
+
+// Withdrawals are frozen while the admin's freeze is on and hasn't run out yet.
+fn require_not_frozen(config: &Config, now: i64) -> Result<()> {
+    require!(
+        !config.withdrawals_frozen || now >= config.frozen_until,
+        TimeLockError::WithdrawalsFrozen
+    );
+    Ok(())
+}
+
+// Match a deposit into a fully funded lock against its contribution schedule, returning how many
+// intervals it skipped over. A deposit must be exactly one contribution, landing no earlier than
+// the next one is due; the final contribution can't be skipped past, only paid late.
+fn match_contribution(lock_account: &TimeLockAccount, amount_lamports: u64, clock: &Clock) -> Result<u16> {
+    let remaining = lock_account.contribution_count
+        - lock_account.contributions_made
+        - lock_account.contributions_missed;
+    require!(remaining > 0, TimeLockError::ContributionScheduleComplete);
+    require!(!lock_unlocked(lock_account, clock), TimeLockError::UnlockInPast);
+    require!(
+        amount_lamports == lock_account.contribution_amount,
+        TimeLockError::FundingMismatch
+    );
+    let late_by = clock.unix_timestamp - lock_account.next_contribution_at;
+    require!(late_by >= 0, TimeLockError::ContributionNotDue);
+    let skipped = (late_by / lock_account.contribution_interval_secs).min(remaining as i64 - 1);
+    Ok(skipped as u16)
+}
+
+// Move a pending beneficiary change into place if its delay has passed, pointing the withdraw
+// destination at the new beneficiary's ATA. Returns whether it did.
+fn apply_due_beneficiary(lock_account: &mut TimeLockAccount, mint: &Pubkey, now: i64) -> bool {
+    let Some(new_beneficiary) = lock_account.pending_beneficiary else {
+        return false;
+    };
+    if now < lock_account.pending_effective_at {
+        return false;
+    }
+    lock_account.beneficiary = Some(new_beneficiary);
+    lock_account.withdraw_destination = Some(get_associated_token_address(&new_beneficiary, mint));
+    lock_account.pending_beneficiary = None;
+    lock_account.pending_effective_at = 0;
+    true
+}
+
+// A deposit made through fund_sol_lock only counts once `min_settlement_slots` slots have
+// passed since it landed. Locks funded at init have no funding_slot and are always settled.
+fn require_settled(lock_account: &TimeLockAccount, config: &Config, slot: u64) -> Result<()> {
+    require!(
+        lock_account.funding_slot == 0
+            || slot >= lock_account.funding_slot.saturating_add(config.min_settlement_slots),
+        TimeLockError::NotYetSettled
+    );
+    Ok(())
+}
+
+// Mark the goal reached the first time the funded amount covers it, and say so once.
+fn emit_if_goal_reached(lock_account: &mut Account<TimeLockAccount>, stats: &mut Stats) {
+    if lock_account.goal_amount == 0
+        || lock_account.goal_reached
+        || lock_account.funded_at == 0
+        || lock_account.amount < lock_account.goal_amount
+    {
+        return;
+    }
+    lock_account.goal_reached = true;
+    emit!(GoalReached {
+        lock_account: lock_account.key(),
+        goal_amount: lock_account.goal_amount,
+        deposited: lock_account.amount,
+        goal_label: lock_account.goal_label,
+        seq: stats.next_event_seq(),
+    });
+}
+
+// When the lock asks for it, the wallet receiving funds must be a plain System-owned account
+// rather than a program-owned one that could sweep them onward.
+fn require_wallet_destination(lock_account: &TimeLockAccount, destination: &AccountInfo) -> Result<()> {
+    if lock_account.require_system_destination {
+        require_keys_eq!(
+            *destination.owner,
+            System::id(),
+            TimeLockError::DestinationNotWallet
+        );
+    }
+    Ok(())
+}
+
+fn require_withdraw_authority(lock_account: &TimeLockAccount, authority: &Pubkey) -> Result<()> {
+    require!(
+        *authority == lock_account.initializer || lock_account.withdraw_delegate == Some(*authority),
+        TimeLockError::NotAuthorized
+    );
+    Ok(())
+}
+
+// The first partial withdrawal (a vesting claim or a partial relock) pins its token account as
+// the lock's destination, so a relayer submitting later withdrawals can't reroute them.
+fn require_withdraw_destination(lock_account: &TimeLockAccount, destination: &Pubkey) -> Result<()> {
+    if let Some(pinned) = lock_account.withdraw_destination {
+        require_keys_eq!(*destination, pinned, TimeLockError::DestinationLocked);
+    }
+    Ok(())
+}
+
+// If the lock names a notify_program, CPI its `on_lock_withdrawn` instruction (Anchor-style
+// discriminator, then a borsh `WithdrawalNotice`) with the lock as its only, read-only account.
+// The lock doesn't sign, since it is also an SPL vault authority. A notify that fails reverts
+// the whole withdrawal, because a failed CPI can't be caught; that is also why the program
+// must still be allow-listed.
+fn notify_withdrawal<'info>(
+    lock_account: &Account<'info, TimeLockAccount>,
+    amount: u64,
+    notify_program: &Option<UncheckedAccount<'info>>,
+    allowed_notify_program: &Option<Box<Account<'info, NotifyProgram>>>,
+) -> Result<()> {
+    let Some(program_id) = lock_account.notify_program else {
+        return Ok(());
+    };
+    let program = notify_program
+        .as_ref()
+        .ok_or(TimeLockError::NotifyProgramMissing)?;
+    require_keys_eq!(program.key(), program_id, TimeLockError::NotifyProgramMissing);
+    let allowed = allowed_notify_program
+        .as_ref()
+        .ok_or(TimeLockError::NotifyProgramNotAllowed)?;
+    require_keys_eq!(allowed.program_id, program_id, TimeLockError::NotifyProgramNotAllowed);
+
+    let mut data = hash(b"global:on_lock_withdrawn").to_bytes()[..8].to_vec();
+    WithdrawalNotice {
+        lock_account: lock_account.key(),
+        initializer: lock_account.initializer,
+        mint: lock_account.mint,
+        amount,
+    }
+    .serialize(&mut data)?;
+    let notify_ix = Instruction {
+        program_id,
+        accounts: vec![AccountMeta::new_readonly(lock_account.key(), false)],
+        data,
+    };
+    invoke(&notify_ix, &[lock_account.to_account_info(), program.to_account_info()])
+        .map_err(|_| error!(TimeLockError::NotifyFailed))
+}
+
+// Arguments of the `on_lock_withdrawn` instruction a notify program must implement.
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct WithdrawalNotice {
+    pub lock_account: Pubkey,
+    pub initializer: Pubkey,
+    // None for SOL.
+    pub mint: Option<Pubkey>,
+    // What the initializer received, after fees.
+    pub amount: u64,
+}
+
+// Mirror of the Pyth receiver's `PriceUpdateV2` account. Only the price message is used, so the
+// layout is decoded here instead of pulling in the receiver SDK.
+#[derive(AnchorDeserialize)]
+struct PriceUpdateV2 {
+    _write_authority: Pubkey,
+    _verification_level: VerificationLevel,
+    price_message: PriceFeedMessage,
+    _posted_slot: u64,
+}
+
+#[derive(AnchorDeserialize)]
+enum VerificationLevel {
+    Partial { _num_signatures: u8 },
+    Full,
+}
+
+#[derive(AnchorDeserialize)]
+struct PriceFeedMessage {
+    _feed_id: [u8; 32],
+    price: i64,
+    _conf: u64,
+    exponent: i32,
+    publish_time: i64,
+    _prev_publish_time: i64,
+    _ema_price: i64,
+    _ema_conf: u64,
+}
+
+// The reward-pool PDA and its bump, checking `reward_pool_ata` is its ATA for `mint`.
+fn reward_pool_authority(reward_pool_ata: &TokenAccount, mint: &Pubkey) -> Result<(Pubkey, u8)> {
+    let (authority, bump) = Pubkey::find_program_address(&[REWARD_POOL_SEED], &crate::ID);
+    require!(
+        reward_pool_ata.owner == authority && reward_pool_ata.mint == *mint,
+        TimeLockError::RewardPoolMismatch
+    );
+    Ok((authority, bump))
+}
+
+// Part of the deposit fee refunded at withdrawal: the fee scaled by how much of the committed
+// term was held, which is all of it unless a stop-loss released the lock early, and capped by
+// what the pool holds. A lock without a timestamp term counts as held in full.
+fn deposit_fee_refund(lock_account: &TimeLockAccount, now: i64, pool_balance: u64) -> Result<u64> {
+    let fee = lock_account.deposit_fee_taken;
+    let term = lock_account.unlock_timestamp.saturating_sub(lock_account.created_at);
+    let refund = if term > 0 {
+        let held = now.saturating_sub(lock_account.created_at).clamp(0, term);
+        scale_amount(fee, held as u64, term as u64)?
+    } else {
+        fee
+    };
+    Ok(refund.min(pool_balance))
+}
+
+// Where a deposit fee refund comes from and goes: the reward pool's ATA for `mint` and the PDA
+// that signs for it, and the token account the lock's payout went to.
+struct RefundFrom<'a, 'info> {
+    reward_pool_ata: Option<&'a Account<'info, TokenAccount>>,
+    reward_pool_authority: Option<&'a UncheckedAccount<'info>>,
+    destination: Option<AccountInfo<'info>>,
+    mint: Pubkey,
+}
+
+// Pay a lock's deposit fee refund out of the reward pool, on whichever payout ends the lock. The
+// pool and destination are required while the lock has a fee on record; the record is cleared
+// either way, so what the pool couldn't cover is not owed later.
+fn refund_deposit_fee<'info>(
+    lock_account: &mut Account<'info, TimeLockAccount>,
+    from: RefundFrom<'_, 'info>,
+    stats: &mut Stats,
+    token_program: &Program<'info, Token>,
+    now: i64,
+) -> Result<()> {
+    let fee_taken = lock_account.deposit_fee_taken;
+    if fee_taken == 0 {
+        return Ok(());
+    }
+    let reward_pool_ata = from.reward_pool_ata.ok_or(TimeLockError::RewardPoolMissing)?;
+    let pool_signer = from.reward_pool_authority.ok_or(TimeLockError::RewardPoolMissing)?;
+    let destination = from.destination.ok_or(TimeLockError::RefundDestinationMissing)?;
+    let (authority, bump) = reward_pool_authority(reward_pool_ata, &from.mint)?;
+    require_keys_eq!(pool_signer.key(), authority, TimeLockError::RewardPoolMismatch);
+
+    let refunded = deposit_fee_refund(lock_account, now, reward_pool_ata.amount)?;
+    if refunded > 0 {
+        let seeds: &[&[u8]] = &[REWARD_POOL_SEED, &[bump]];
+        let signer_seeds: &[&[&[u8]]] = &[seeds];
+        let cpi_ctx = CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            SplTransfer {
+                from: reward_pool_ata.to_account_info(),
+                to: destination,
+                authority: pool_signer.to_account_info(),
+            },
+            signer_seeds,
+        );
+        token::transfer(cpi_ctx, refunded)?;
+    }
+    lock_account.deposit_fee_taken = 0;
+    emit!(DepositFeeRefunded {
+        lock_account: lock_account.key(),
+        fee_taken,
+        refunded,
+        seq: stats.next_event_seq(),
+    });
+    Ok(())
+}
+
+// Invoke an allow-listed swap program with `swap_data` and the remaining accounts forwarded
+// verbatim, `signer_key` (the vault's authority) signing for the vault.
+fn invoke_swap<'info>(
+    swap_program: &UncheckedAccount<'info>,
+    signer_key: Pubkey,
+    remaining_accounts: &[AccountInfo<'info>],
+    swap_data: Vec<u8>,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<()> {
+    let metas = remaining_accounts
+        .iter()
+        .map(|acc| AccountMeta {
+            pubkey: acc.key(),
+            is_signer: acc.is_signer || acc.key() == signer_key,
+            is_writable: acc.is_writable,
+        })
+        .collect();
+    let swap_ix = Instruction {
+        program_id: swap_program.key(),
+        accounts: metas,
+        data: swap_data,
+    };
+    let mut swap_infos = remaining_accounts.to_vec();
+    swap_infos.push(swap_program.to_account_info());
+    // A CPI that errors aborts the transaction on its own; the mapping only labels the failure.
+    invoke_signed(&swap_ix, &swap_infos, signer_seeds).map_err(|_| error!(TimeLockError::SwapFailed))
+}
+
+// How much of a streaming lock has vested at `now` (it must be unlocked), and when the next chunk
+// unlocks: i64::MAX once every period has passed. Chunks are equal, or `tranche_amount` each for
+// a DCA schedule; either way the last chunk absorbs the remainder, so the final period always
+// vests exactly `amount`.
+fn stream_vested(lock_account: &TimeLockAccount, now: i64) -> Result<(u64, i64)> {
+    let start = stream_start(lock_account)?;
+    let periods = lock_account.stream_periods as i64;
+    let passed = stream_periods_passed(lock_account, now)?;
+    let vested = if lock_account.tranche_amount > 0 {
+        (passed as u64)
+            .checked_mul(lock_account.tranche_amount)
+            .ok_or(TimeLockError::MathOverflow)?
+            .min(lock_account.amount)
+    } else {
+        scale_amount(lock_account.amount, passed as u64, periods as u64)?
+    };
+    let next_claim_at = if passed == periods {
+        i64::MAX
+    } else {
+        passed
+            .checked_mul(lock_account.stream_period_secs)
+            .and_then(|offset| start.checked_add(offset))
+            .ok_or(TimeLockError::MathOverflow)?
+    };
+    Ok((vested, next_claim_at))
+}
+
+// How many of a stream's periods have come due by `now`, at most all of them. An anchored
+// schedule can start a little after the unlock, and nothing is due before it does.
+fn stream_periods_passed(lock_account: &TimeLockAccount, now: i64) -> Result<i64> {
+    let start = stream_start(lock_account)?;
+    if now < start {
+        return Ok(0);
+    }
+    let elapsed = now
+        .checked_sub(start)
+        .and_then(|since| (since / lock_account.stream_period_secs).checked_add(1))
+        .ok_or(TimeLockError::MathOverflow)?;
+    Ok(elapsed.min(lock_account.stream_periods as i64))
+}
+
+// Percentage-of-balance vesting: each period come due since the last claim releases
+// `claim_bps` of the vault as it stands at claim time, so growth in the vault carries over into
+// later claims. The last period releases everything left. Returns the release and the periods
+// passed so far.
+fn percent_claim(lock_account: &TimeLockAccount, vault_balance: u64, now: i64) -> Result<(u64, u16)> {
+    let passed = stream_periods_passed(lock_account, now)? as u16;
+    if passed == lock_account.stream_periods {
+        return Ok((vault_balance, passed));
+    }
+    let due = passed.saturating_sub(lock_account.percent_claims) as u64;
+    let bps = (lock_account.claim_bps as u64).saturating_mul(due).min(BPS_DENOMINATOR);
+    Ok((scale_amount(vault_balance, bps, BPS_DENOMINATOR)?, passed))
+}
+
+// The earliest of every clock boundary that changes what can be done with the lock: the unlock
+// (for slot locks, estimated at SLOT_MS per slot), the next stream or vesting chunk, the end of
+// the trial window, the charity grace deadline and the end of a withdrawal freeze. Boundaries
+// already behind us are ignored; i64::MAX when none are left.
+fn next_lock_event(lock_account: &TimeLockAccount, config: &Config, clock: &Clock) -> Result<i64> {
+    let now = clock.unix_timestamp;
+    let mut boundaries = Vec::with_capacity(5);
+    match lock_account.unlock_slot {
+        Some(unlock_slot) if clock.slot < unlock_slot => {
+            let millis = (unlock_slot - clock.slot).saturating_mul(SLOT_MS);
+            boundaries.push(now.saturating_add(millis.div_ceil(1_000) as i64));
+        }
+        Some(_) => {}
+        None => boundaries.push(lock_account.unlock_timestamp),
+    }
+    if lock_account.stream_periods > 0 {
+        boundaries.push(stream_vested(lock_account, now)?.1);
+    }
+    if lock_account.trial_seconds > 0 && lock_account.funded_at > 0 {
+        // Revocable through the trial's last second.
+        boundaries.push(lock_account.funded_at.saturating_add(lock_account.trial_seconds).saturating_add(1));
+    }
+    if lock_account.charity_fallback.is_some() {
+        boundaries.push(final_release_at(lock_account)?.saturating_add(lock_account.charity_grace_seconds));
+    }
+    if config.withdrawals_frozen {
+        boundaries.push(config.frozen_until);
+    }
+    Ok(boundaries
+        .into_iter()
+        .filter(|&at| at > now)
+        .min()
+        .unwrap_or(i64::MAX))
+}
+
+// When a schedule's first chunk vests: at the unlock, or for an anchored schedule at the first
+// anchor boundary at or after it.
+fn stream_start(lock_account: &TimeLockAccount) -> Result<i64> {
+    let Some(anchor) = lock_account.stream_anchor else {
+        return Ok(lock_account.unlock_timestamp);
+    };
+    let offset = anchor
+        .checked_sub(lock_account.unlock_timestamp)
+        .ok_or(TimeLockError::MathOverflow)?
+        .rem_euclid(lock_account.stream_period_secs);
+    let start = lock_account
+        .unlock_timestamp
+        .checked_add(offset)
+        .ok_or(TimeLockError::MathOverflow)?;
+    Ok(start)
+}
+
+// When the owner last has something coming due: the unlock, or a stream's final vest. Until
+// then a lock being claimed on schedule isn't abandoned.
+fn final_release_at(lock_account: &TimeLockAccount) -> Result<i64> {
+    if lock_account.stream_periods == 0 {
+        return Ok(lock_account.unlock_timestamp);
+    }
+    let last_offset = (lock_account.stream_periods as i64 - 1)
+        .checked_mul(lock_account.stream_period_secs)
+        .ok_or(TimeLockError::MathOverflow)?;
+    let final_vest = stream_start(lock_account)?
+        .checked_add(last_offset)
+        .ok_or(TimeLockError::MathOverflow)?;
+    Ok(final_vest)
+}
+
+// Lamports worth `usd_cents` at a SOL/USD price of `price * 10^exponent` dollars, rounded down:
+// usd_cents * 10^(SOL_DECIMALS - 2 - exponent) / price.
+fn usd_cents_to_lamports(usd_cents: u64, price: i64, exponent: i32) -> Result<u64> {
+    require!(price > 0, TimeLockError::OracleMismatch);
+    let shift = SOL_DECIMALS as i32 - 2 - exponent;
+    let ten_pow = |n: i32| {
+        10u128
+            .checked_pow(n.unsigned_abs())
+            .ok_or(error!(TimeLockError::MathOverflow))
+    };
+    let (numerator, denominator) = if shift >= 0 {
+        let numerator = (usd_cents as u128)
+            .checked_mul(ten_pow(shift)?)
+            .ok_or(TimeLockError::MathOverflow)?;
+        (numerator, price as u128)
+    } else {
+        let denominator = (price as u128)
+            .checked_mul(ten_pow(shift)?)
+            .ok_or(TimeLockError::MathOverflow)?;
+        (usd_cents as u128, denominator)
+    };
+    u64::try_from(numerator / denominator).map_err(|_| error!(TimeLockError::MathOverflow))
+}
+
+// Read a Pyth price update, rejecting accounts that aren't one and prices older than
+// ORACLE_MAX_AGE_SECS.
+fn read_oracle_price(price_feed: &AccountInfo, now: i64) -> Result<PriceFeedMessage> {
+    require_keys_eq!(*price_feed.owner, PYTH_RECEIVER_PROGRAM_ID, TimeLockError::OracleMismatch);
+    let data = price_feed.try_borrow_data()?;
+    let discriminator = &hash(b"account:PriceUpdateV2").to_bytes()[..8];
+    require!(
+        data.len() > 8 && &data[..8] == discriminator,
+        TimeLockError::OracleMismatch
+    );
+    let update = PriceUpdateV2::deserialize(&mut &data[8..])
+        .map_err(|_| error!(TimeLockError::OracleMismatch))?;
+    require!(
+        now.saturating_sub(update.price_message.publish_time) <= ORACLE_MAX_AGE_SECS,
+        TimeLockError::OracleStale
+    );
+    Ok(update.price_message)
+}
+
+#[account]
+pub struct TimeLockAccount {
+    pub initializer: Pubkey,
+    pub amount: u64,
+    pub unlock_timestamp: i64,
+    pub bump: u8,
+    pub kind: AssetKind,
+    pub mint: Option<Pubkey>,
+    // sha256 of off-chain details; all zeroes means no commitment was made.
+    pub commitment: [u8; 32],
+    pub require_system_destination: bool,
+    // Decimals a UI should use to render `amount`: 9 for SOL, the mint's decimals for SPL.
+    pub display_decimals: u8,
+    // SPL only: Pyth feed whose price (in the feed's own exponent) at or below `stop_loss_price`
+    // unlocks the lock early.
+    pub stop_loss_oracle: Option<Pubkey>,
+    pub stop_loss_price: i64,
+    // SOL only: the lock may be revoked until `funded_at + trial_seconds`.
+    pub funded_at: i64,
+    pub trial_seconds: i64,
+    // SolAndSpl only: tokens deposited alongside the `amount` lamports.
+    pub token_amount: u64,
+    pub created_at: i64,
+    // SOL only: once set, the holder of this supply-1 mint controls withdrawal.
+    pub receipt_mint: Option<Pubkey>,
+    // SOL only: the USD value (in cents) the lock was sized to at creation, or 0. Display only.
+    pub usd_cents: u64,
+    // SOL streams and SPL vesting: number of equal chunks the lock releases in, or 0 for a
+    // one-shot lock.
+    pub stream_periods: u16,
+    pub stream_period_secs: i64,
+    pub stream_claimed: u64,
+    // When the next chunk unlocks; i64::MAX once the stream has fully vested.
+    pub next_claim_at: i64,
+    // SOL only: share of the payout withdraw_sol moves into the savings lock instead.
+    pub auto_save_bps: u16,
+    pub savings_unlock_timestamp: i64,
+    // Slot of the most recent initialize, to catch the same init twice in one transaction.
+    pub created_slot: u64,
+    // SOL only: savings goal measured against the funded `amount`, or 0 for none.
+    pub goal_amount: u64,
+    pub goal_label: [u8; 32],
+    pub goal_reached: bool,
+    // Withdrawal waits for the goal as well as the unlock time.
+    pub goal_required: bool,
+    // SOL only: each deposit moves the unlock to at least this long after it, or 0 for off.
+    pub auto_extend_seconds: i64,
+    // Set by create_lock_summary; state changes must then keep the LockSummary in sync.
+    pub has_summary: bool,
+    // SPL only: anti-spam bond held in this account on top of rent. Refunded when the lock
+    // closes normally, forfeited to the treasury by force_close_orphan_lock.
+    pub bond_paid: u64,
+    // Receives this account's rent reserve on close; None means the close target does.
+    pub rent_recipient: Option<Pubkey>,
+    // SPL only: set by migrate_vault_authority once the vault answers to the PDA at
+    // [VAULT_AUTHORITY_SEED, lock_account] instead of this lock.
+    pub vault_authority: Option<Pubkey>,
+    pub vault_authority_bump: u8,
+    // SPL only: token account every withdrawal must pay once a partial withdrawal has set it.
+    pub withdraw_destination: Option<Pubkey>,
+    // Set instead of `unlock_timestamp` (left 0) for locks that unlock at a slot height.
+    pub unlock_slot: Option<u64>,
+    // SOL only: `quorum_threshold` of these may sign quorum_release at any time; 0 means off.
+    pub quorum_signers: Vec<Pubkey>,
+    pub quorum_threshold: u8,
+    // Allow-listed program withdraw_sol and withdraw_spl notify by CPI, if any.
+    pub notify_program: Option<Pubkey>,
+    // SPL only: partial withdrawals must be multiples of this, or 0 for any amount.
+    pub withdraw_step: u64,
+    pub category: LockCategory,
+    // SOL and SPL: ConditionFlag that must be released before any withdrawal, if any.
+    pub condition_account: Option<Pubkey>,
+    // SPL: who withdraw_spl_to_beneficiary pays, if anyone.
+    pub beneficiary: Option<Pubkey>,
+    // SPL: lamports set aside to create the beneficiary's ATA; 0 once spent or if unused.
+    pub ata_rent_reserve: u64,
+    // SPL: cap on partial withdrawals over the lock's life, or 0 for none.
+    pub max_withdrawals: u32,
+    pub withdrawals_made: u32,
+    // SOL: allow-listed charity that receives the lock if it is left unclaimed for
+    // `charity_grace_seconds` past unlock.
+    pub charity_fallback: Option<Pubkey>,
+    pub charity_grace_seconds: i64,
+    // Config::reward_multiplier_bps when the lock was first created. Re-running init on an
+    // existing lock and partial relocks keep it, since they continue the same lock.
+    pub reward_multiplier_bps: u16,
+    // SOL and SPL: who besides the initializer may sign withdrawals, if anyone.
+    pub withdraw_delegate: Option<Pubkey>,
+    // SOL: whether any keeper may auto_release the lock once it unlocks.
+    pub auto_release: bool,
+    // Streams and vesting: boundary the chunks are aligned to, if any; see set_stream_anchor.
+    pub stream_anchor: Option<i64>,
+    // Vesting: fixed base-unit amount released per period, or 0 for equal chunks; see
+    // set_dca_tranche.
+    pub tranche_amount: u64,
+    // SPL: who must co-sign force_update_beneficiary, if anyone; see set_arbiter.
+    pub arbiter: Option<Pubkey>,
+    // SPL: deposit fee paid into the reward pool at init, until withdraw_spl refunds it.
+    pub deposit_fee_taken: u64,
+    // Delegated: the initializer's token account the lock is a delegate of.
+    pub delegated_account: Option<Pubkey>,
+    // SOL: slot fund_sol_lock landed the latest deposit in, or 0 if it was funded at init.
+    pub funding_slot: u64,
+    // SOL: pre-committed deposits on top of `amount`; see set_contribution_schedule.
+    // `contribution_count` is 0 when there is no schedule.
+    pub contribution_amount: u64,
+    pub contribution_interval_secs: i64,
+    pub contribution_count: u16,
+    pub contributions_made: u16,
+    pub contributions_missed: u16,
+    // SOL: when the next scheduled contribution falls due.
+    pub next_contribution_at: i64,
+    // Named group for batch operations; see set_group.
+    pub group_id: Option<[u8; 16]>,
+    // Set by renounce_authorities; arbiter, withdraw delegate and quorum can't be set again.
+    pub authorities_renounced: bool,
+    // SPL: a force_update_beneficiary waiting out the configured delay, and when it lands.
+    pub pending_beneficiary: Option<Pubkey>,
+    pub pending_effective_at: i64,
+    // Vesting: share of the vault each period releases, or 0 for fixed amounts; see
+    // set_claim_bps. `percent_claims` is how many periods have been paid out so far.
+    pub claim_bps: u16,
+    pub percent_claims: u16,
+    // SOL and SPL: who referred the initializer, if anyone; never the initializer itself.
+    pub referrer: Option<Pubkey>,
+    // SOL: sha256 of the secret claim_with_password needs, or all zeroes for no password. Left
+    // alone by re-initialisation, so the initializer can't clear it that way either.
+    pub password_hash: [u8; 32],
+    // SPL: the vault was found frozen by the mint's freeze authority; see acknowledge_frozen_lock.
+    pub vault_frozen: bool,
+    // SPL: a withdrawal or final claim has emptied the vault, so an empty vault is a finished
+    // lock rather than an orphan; see force_close_orphan_lock.
+    pub paid_out: bool,
+}
+
+impl TimeLockAccount {
+    pub const LEN: usize = 8  // discriminator
+        + 32 // initializer
+        + 8  // amount
+        + 8  // unlock_timestamp
+        + 1  // bump
+        + 1  // kind (u8)
+        + 1 + 32 // Option<Pubkey>
+        + 32 // commitment
+        + 1  // require_system_destination
+        + 1  // display_decimals
+        + 1 + 32 // stop_loss_oracle
+        + 8  // stop_loss_price
+        + 8  // funded_at
+        + 8  // trial_seconds
+        + 8  // token_amount
+        + 8  // created_at
+        + 1 + 32 // receipt_mint
+        + 8  // usd_cents
+        + 2  // stream_periods
+        + 8  // stream_period_secs
+        + 8  // stream_claimed
+        + 8  // next_claim_at
+        + 2  // auto_save_bps
+        + 8  // savings_unlock_timestamp
+        + 8  // created_slot
+        + 8  // goal_amount
+        + 32 // goal_label
+        + 1  // goal_reached
+        + 1  // goal_required
+        + 8  // auto_extend_seconds
+        + 1  // has_summary
+        + 8  // bond_paid
+        + 1 + 32 // rent_recipient
+        + 1 + 32 // vault_authority
+        + 1  // vault_authority_bump
+        + 1 + 32 // withdraw_destination
+        + 1 + 8 // unlock_slot
+        + 4 + 32 * MAX_QUORUM_SIGNERS // quorum_signers
+        + 1  // quorum_threshold
+        + 1 + 32 // notify_program
+        + 8  // withdraw_step
+        + 1  // category (u8)
+        + 1 + 32  // condition_account
+        + 1 + 32  // beneficiary
+        + 8  // ata_rent_reserve
+        + 4  // max_withdrawals
+        + 4  // withdrawals_made
+        + 1 + 32  // charity_fallback
+        + 8  // charity_grace_seconds
+        + 2  // reward_multiplier_bps
+        + 1 + 32  // withdraw_delegate
+        + 1  // auto_release
+        + 1 + 8  // stream_anchor
+        + 8  // tranche_amount
+        + 1 + 32  // arbiter
+        + 8  // deposit_fee_taken
+        + 1 + 32  // delegated_account
+        + 8  // funding_slot
+        + 8  // contribution_amount
+        + 8  // contribution_interval_secs
+        + 2  // contribution_count
+        + 2  // contributions_made
+        + 2  // contributions_missed
+        + 8  // next_contribution_at
+        + 1 + 16  // group_id
+        + 1  // authorities_renounced
+        + 1 + 32  // pending_beneficiary
+        + 8  // pending_effective_at
+        + 2  // claim_bps
+        + 2  // percent_claims
+        + 1 + 32  // referrer
+        + 32 // password_hash
+        + 1 // vault_frozen
+        + 1; // paid_out
+}
+
+// Several SPL mints locked under one unlock time, each in its own vault ATA owned by this PDA.
+#[account]
+pub struct Basket {
+    pub initializer: Pubkey,
+    pub unlock_timestamp: i64,
+    pub created_at: i64,
+    pub bump: u8,
+    // Parallel lists, in the order withdraw_basket expects the vaults.
+    pub mints: Vec<Pubkey>,
+    pub amounts: Vec<u64>,
+    pub category: LockCategory,
+}
+
+impl Basket {
+    pub const LEN: usize = 8  // discriminator
+        + 32 // initializer
+        + 8  // unlock_timestamp
+        + 8  // created_at
+        + 1  // bump
+        + 4 + 32 * MAX_BASKET_MINTS // mints
+        + 4 + 8 * MAX_BASKET_MINTS // amounts
+        + 1; // category
+}
+
+// Compact mirror of a lock for polling clients, at [SUMMARY_SEED, lock_account].
+#[account]
+pub struct LockSummary {
+    pub unlock_timestamp: i64,
+    pub is_withdrawn: bool,
+    pub bump: u8,
+}
+
+impl LockSummary {
+    pub const LEN: usize = 8  // discriminator
+        + 8  // unlock_timestamp
+        + 1  // is_withdrawn
+        + 1; // bump
+}
+
+#[account]
+pub struct Config {
+    pub admin: Pubkey,
+    pub max_tvl_lamports: u64,
+    pub bump: u8,
+    pub treasury: Pubkey,
+    pub fee_tiers: [FeeTier; FEE_TIER_COUNT],
+    // Pyth SOL/USD price update account; the default key disables USD-denominated locks.
+    pub sol_usd_oracle: Pubkey,
+    // When set, only addresses with an `Invite` can create locks.
+    pub invite_only: bool,
+    // Proposed successor admin, until they accept.
+    pub pending_admin: Option<Pubkey>,
+    // Smallest lock accepted, in lamports and in token base units; 0 disables the check.
+    pub min_lock_lamports: u64,
+    pub min_lock_tokens: u64,
+    // Largest amount a single lock may hold, in lamports and in token base units; 0 is no cap.
+    pub max_lock_lamports: u64,
+    pub max_lock_tokens: u64,
+    // How verbose the program is; see LOG_FUNDING and LOG_DIAGNOSTIC.
+    pub log_level: u8,
+    // Refundable SOL bond a new SPL lock pays into its own account; 0 disables it.
+    pub bond_lamports: u64,
+    // Withdrawals are refused while this is set and `frozen_until` hasn't passed.
+    pub withdrawals_frozen: bool,
+    pub frozen_until: i64,
+    // Reward rate new locks snapshot at creation; BPS_DENOMINATOR is 1x.
+    pub reward_multiplier_bps: u16,
+    // Paid from a lock to the keeper that auto-releases it; at most MAX_KEEPER_TIP_LAMPORTS.
+    pub keeper_tip_lamports: u64,
+    // Share of an SPL deposit paid into the reward pool; at most MAX_FEE_BPS.
+    pub deposit_fee_bps: u16,
+    // Slots a fund_sol_lock deposit must age before withdrawal; 0 disables the check.
+    pub min_settlement_slots: u64,
+    // Seconds a force_update_beneficiary waits before taking effect; 0 applies it at once.
+    pub beneficiary_change_delay: i64,
+    // How withdrawal fees are divided when not all paid to the treasury; see set_fee_split.
+    pub fee_split: [FeeSplit; MAX_FEE_RECIPIENTS],
+}
+
+impl Config {
+    pub const LEN: usize = 8  // discriminator
+        + 32 // admin
+        + 8  // max_tvl_lamports
+        + 1  // bump
+        + 32 // treasury
+        + FEE_TIER_COUNT * FeeTier::LEN // fee_tiers
+        + 32 // sol_usd_oracle
+        + 1  // invite_only
+        + 1 + 32 // pending_admin
+        + 8  // min_lock_lamports
+        + 8  // min_lock_tokens
+        + 8  // max_lock_lamports
+        + 8  // max_lock_tokens
+        + 1  // log_level
+        + 8  // bond_lamports
+        + 1  // withdrawals_frozen
+        + 8  // frozen_until
+        + 2  // reward_multiplier_bps
+        + 8  // keeper_tip_lamports
+        + 2  // deposit_fee_bps
+        + 8  // min_settlement_slots
+        + 8  // beneficiary_change_delay
+        + MAX_FEE_RECIPIENTS * FeeSplit::LEN; // fee_split
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct FeeTier {
+    // Locks set for less than this many seconds pay `fee_bps`; 0 marks the tier unused.
+    pub max_duration_secs: i64,
+    pub fee_bps: u16,
+}
+
+impl FeeTier {
+    pub const LEN: usize = 8 // max_duration_secs
+        + 2; // fee_bps
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct FeeSplit {
+    pub recipient: Pubkey,
+    // Share of each fee paid to `recipient`; 0 marks the entry unused.
+    pub bps: u16,
+}
+
+impl FeeSplit {
+    pub const LEN: usize = 32 // recipient
+        + 2; // bps
+}
+
+#[account]
+pub struct Stats {
+    pub total_sol_locked: u64,
+    pub bump: u8,
+    pub total_locks_open: u64,
+    // Lamports only; SPL fees are paid in each lock's own token and can't be summed here.
+    pub total_fees_collected: u64,
+    // Payouts to users: withdrawals, stream claims and trial revocations.
+    pub total_withdrawals: u64,
+    // Sequence number of the last event emitted, carried as `seq` by every event that records a
+    // state change, so indexers can order them and spot gaps. Read-only query events (previews,
+    // reports, capabilities and the like) carry none, so simulating them never write-locks Stats.
+    pub event_seq: u64,
+}
+
+impl Stats {
+    pub const LEN: usize = 8  // discriminator
+        + 8  // total_sol_locked
+        + 1  // bump
+        + 8  // total_locks_open
+        + 8  // total_fees_collected
+        + 8  // total_withdrawals
+        + 8; // event_seq
+
+    // Counters saturate for the same reason as total_sol_locked: bookkeeping must never block
+    // a user's funds.
+    fn record_lock_opened(&mut self) {
+        self.total_locks_open = self.total_locks_open.saturating_add(1);
+    }
+
+    fn record_lock_closed(&mut self) {
+        self.total_locks_open = self.total_locks_open.saturating_sub(1);
+    }
+
+    fn record_withdrawal(&mut self, fee_lamports: u64) {
+        self.total_withdrawals = self.total_withdrawals.saturating_add(1);
+        self.total_fees_collected = self.total_fees_collected.saturating_add(fee_lamports);
+    }
+
+    // Sequence number for the event about to be emitted.
+    fn next_event_seq(&mut self) -> u64 {
+        self.event_seq = self.event_seq.saturating_add(1);
+        self.event_seq
+    }
+}
+
+#[account]
+pub struct Invite {
+    pub address: Pubkey,
+    pub bump: u8,
+}
+
+impl Invite {
+    pub const LEN: usize = 8  // discriminator
+        + 32 // address
+        + 1; // bump
+}
+
+#[account]
+pub struct SwapProgram {
+    pub program_id: Pubkey,
+    pub bump: u8,
+}
+
+impl SwapProgram {
+    pub const LEN: usize = 8  // discriminator
+        + 32 // program_id
+        + 1; // bump
+}
+
+// An external yes/no gate for locks (KYC passed, milestone approved). Only `authority` can
+// release it, and only once.
+#[account]
+pub struct ConditionFlag {
+    pub authority: Pubkey,
+    pub id: u64,
+    pub released: bool,
+    pub bump: u8,
+}
+
+impl ConditionFlag {
+    pub const LEN: usize = 8  // discriminator
+        + 32 // authority
+        + 8  // id
+        + 1  // released
+        + 1; // bump
+}
+
+#[account]
+pub struct NotifyProgram {
+    pub program_id: Pubkey,
+    pub bump: u8,
+}
+
+impl NotifyProgram {
+    pub const LEN: usize = 8  // discriminator
+        + 32 // program_id
+        + 1; // bump
+}
+
+#[account]
+pub struct Charity {
+    pub address: Pubkey,
+    pub bump: u8,
+}
+
+impl Charity {
+    pub const LEN: usize = 8  // discriminator
+        + 32 // address
+        + 1; // bump
+}
+
+#[account]
+pub struct MintMigration {
+    pub old_mint: Pubkey,
+    pub new_mint: Pubkey,
+    pub ratio_numerator: u64,
+    pub ratio_denominator: u64,
+    pub bump: u8,
+}
+
+impl MintMigration {
+    pub const LEN: usize = 8  // discriminator
+        + 32 // old_mint
+        + 32 // new_mint
+        + 8  // ratio_numerator
+        + 8  // ratio_denominator
+        + 1; // bump
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum AssetKind {
+    Sol = 0,
+    Spl = 1,
+    // A "safe" holding lamports and a token vault under one unlock time (Sol | Spl).
+    SolAndSpl = 2,
+    // SOL moved out of a maturing lock by auto-save, held until its own unlock.
+    Savings = 3,
+    // Tokens left in the initializer's own token account, delegated to the lock.
+    Delegated = 4,
+}
+
+// What a lock is for, as declared by its initializer. Purely descriptive: no instruction
+// behaves differently by category, it only gives indexers a fixed dimension to group by.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum LockCategory {
+    Uncategorized = 0,
+    Savings = 1,
+    Vesting = 2,
+    Escrow = 3,
+    Gift = 4,
+}
+
+impl TryFrom<u8> for LockCategory {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self> {
+        match value {
+            0 => Ok(LockCategory::Uncategorized),
+            1 => Ok(LockCategory::Savings),
+            2 => Ok(LockCategory::Vesting),
+            3 => Ok(LockCategory::Escrow),
+            4 => Ok(LockCategory::Gift),
+            _ => err!(TimeLockError::InvalidCategory),
+        }
+    }
+}
+
+// One of the conditions explain_unlock reports. Any met release condition (timestamp or slot,
+// stop-loss, quorum) frees the lock, but only once every gate (condition flag, goal) is met.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum UnlockConditionKind {
+    Timestamp,
+    Slot,
+    StopLoss,
+    Quorum,
+    ConditionFlag,
+    Goal,
+    // Only claim_with_password can meet it, so it always reports unmet here.
+    Password,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct UnlockCondition {
+    pub kind: UnlockConditionKind,
+    pub met: bool,
+    pub gate: bool,
+}
+
+// attest_unlock's return data, UNLOCK_ATTESTATION_LEN bytes in Borsh order: the lock's key
+// (bytes 0..32), 1 if it is withdrawable now and 0 if not (byte 32), then the slot (33..41) and
+// unix timestamp (41..49) it was evaluated at, little-endian.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct UnlockAttestation {
+    pub lock_account: Pubkey,
+    pub matured: bool,
+    pub slot: u64,
+    pub unix_timestamp: i64,
+}
+
+pub const UNLOCK_ATTESTATION_LEN: usize = 32 + 1 + 8 + 8;
+
+// Bumped whenever LockConfig's layout changes. Read the first byte of serialize_lock_config's
+// return data and only deserialize the rest if it is a version you know.
+pub const LOCK_CONFIG_VERSION: u8 = 1;
+
+// serialize_lock_config's return data, in Borsh order. Fields are only ever appended before
+// `quorum_signers`, under a new version; the signer list comes last since it is the only one of
+// variable length, so every other field keeps a fixed offset within a version. At most
+// MAX_QUORUM_SIGNERS signers keeps the whole thing well inside the 1024-byte return data limit.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
+pub struct LockConfig {
+    pub version: u8,
+    pub lock_account: Pubkey,
+    pub initializer: Pubkey,
+    pub kind: AssetKind,
+    pub category: LockCategory,
+    pub mint: Option<Pubkey>,
+    pub amount: u64,
+    // A safe's token side; `amount` is its lamports.
+    pub token_amount: u64,
+    pub unlock_timestamp: i64,
+    pub unlock_slot: Option<u64>,
+    // Where a withdrawal can go.
+    pub require_system_destination: bool,
+    pub withdraw_destination: Option<Pubkey>,
+    pub beneficiary: Option<Pubkey>,
+    pub pending_beneficiary: Option<Pubkey>,
+    pub pending_effective_at: i64,
+    pub rent_recipient: Option<Pubkey>,
+    pub receipt_mint: Option<Pubkey>,
+    pub charity_fallback: Option<Pubkey>,
+    pub charity_grace_seconds: i64,
+    // How it releases.
+    pub stream_periods: u16,
+    pub stream_period_secs: i64,
+    pub stream_claimed: u64,
+    pub claim_bps: u16,
+    pub tranche_amount: u64,
+    pub auto_save_bps: u16,
+    pub auto_release: bool,
+    pub max_withdrawals: u32,
+    pub withdraw_step: u64,
+    // Revocable by the owner until funded_at + trial_seconds.
+    pub trial_seconds: i64,
+    pub funded_at: i64,
+    // What else gates it.
+    pub stop_loss_oracle: Option<Pubkey>,
+    pub stop_loss_price: i64,
+    pub condition_account: Option<Pubkey>,
+    pub goal_amount: u64,
+    pub goal_required: bool,
+    pub password_protected: bool,
+    // Who besides the initializer has a say.
+    pub arbiter: Option<Pubkey>,
+    pub withdraw_delegate: Option<Pubkey>,
+    pub authorities_renounced: bool,
+    // The SPL vault's owner once migrate_vault_authority has moved it off the lock PDA.
+    pub vault_authority: Option<Pubkey>,
+    pub notify_program: Option<Pubkey>,
+    pub group_id: Option<[u8; 16]>,
+    pub referrer: Option<Pubkey>,
+    pub quorum_threshold: u8,
+    pub quorum_signers: Vec<Pubkey>,
+}
+
+impl AssetKind {
+    pub fn seed(self) -> &'static [u8] {
+        match self {
+            AssetKind::Sol => TIME_LOCK_SOL_SEED,
+            AssetKind::Spl => TIME_LOCK_SPL_SEED,
+            AssetKind::SolAndSpl => TIME_LOCK_SAFE_SEED,
+            AssetKind::Savings => TIME_LOCK_SAVINGS_SEED,
+            AssetKind::Delegated => TIME_LOCK_DELEGATED_SEED,
+        }
+    }
+}
+
+// The canonical address and bump of `initializer`'s lock of this kind. Each initializer has at
+// most one lock per kind, so there is no separate lock id to pass.
+pub fn find_lock_address(kind: AssetKind, initializer: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[kind.seed(), initializer.as_ref()], &crate::ID)
+}
+
+#[derive(Accounts)]
+pub struct InitializeConfig<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        init,
+        payer = admin,
+        space = Config::LEN,
+        seeds = [CONFIG_SEED],
+        bump,
+    )]
+    pub config: Account<'info, Config>,
+    #[account(
+        init,
+        payer = admin,
+        space = Stats::LEN,
+        seeds = [STATS_SEED],
+        bump,
+    )]
+    pub stats: Account<'info, Stats>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateConfig<'info> {
+    pub admin: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        has_one = admin @ TimeLockError::Unauthorized,
+    )]
+    pub config: Account<'info, Config>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptAdmin<'info> {
+    pub pending_admin: Signer<'info>,
+    #[account(mut, seeds = [CONFIG_SEED], bump = config.bump)]
+    pub config: Account<'info, Config>,
+}
+
+#[derive(Accounts)]
+#[instruction(address: Pubkey)]
+pub struct InviteAddress<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        has_one = admin @ TimeLockError::Unauthorized,
+    )]
+    pub config: Account<'info, Config>,
+    #[account(
+        init,
+        payer = admin,
+        space = Invite::LEN,
+        seeds = [INVITE_SEED, address.as_ref()],
+        bump,
+    )]
+    pub invite: Account<'info, Invite>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RevokeInvite<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        has_one = admin @ TimeLockError::Unauthorized,
+    )]
+    pub config: Account<'info, Config>,
+    #[account(
+        mut,
+        seeds = [INVITE_SEED, invite.address.as_ref()],
+        bump = invite.bump,
+        close = admin,
+    )]
+    pub invite: Account<'info, Invite>,
+}
+
+#[derive(Accounts)]
+#[instruction(program_id: Pubkey)]
+pub struct AllowSwapProgram<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        has_one = admin @ TimeLockError::Unauthorized,
+    )]
+    pub config: Account<'info, Config>,
+    #[account(
+        init,
+        payer = admin,
+        space = SwapProgram::LEN,
+        seeds = [SWAP_PROGRAM_SEED, program_id.as_ref()],
+        bump,
+    )]
+    pub swap_program: Account<'info, SwapProgram>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct DisallowSwapProgram<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        has_one = admin @ TimeLockError::Unauthorized,
+    )]
+    pub config: Account<'info, Config>,
+    #[account(
+        mut,
+        seeds = [SWAP_PROGRAM_SEED, swap_program.program_id.as_ref()],
+        bump = swap_program.bump,
+        close = admin,
+    )]
+    pub swap_program: Account<'info, SwapProgram>,
+}
+
+#[derive(Accounts)]
+#[instruction(program_id: Pubkey)]
+pub struct AllowNotifyProgram<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        has_one = admin @ TimeLockError::Unauthorized,
+    )]
+    pub config: Account<'info, Config>,
+    #[account(
+        init,
+        payer = admin,
+        space = NotifyProgram::LEN,
+        seeds = [NOTIFY_PROGRAM_SEED, program_id.as_ref()],
+        bump,
+    )]
+    pub notify_program: Account<'info, NotifyProgram>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct DisallowNotifyProgram<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        has_one = admin @ TimeLockError::Unauthorized,
+    )]
+    pub config: Account<'info, Config>,
+    #[account(
+        mut,
+        seeds = [NOTIFY_PROGRAM_SEED, notify_program.program_id.as_ref()],
+        bump = notify_program.bump,
+        close = admin,
+    )]
+    pub notify_program: Account<'info, NotifyProgram>,
+}
+
+#[derive(Accounts)]
+#[instruction(address: Pubkey)]
+pub struct AllowCharity<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        has_one = admin @ TimeLockError::Unauthorized,
+    )]
+    pub config: Account<'info, Config>,
+    #[account(
+        init,
+        payer = admin,
+        space = Charity::LEN,
+        seeds = [CHARITY_SEED, address.as_ref()],
+        bump,
+    )]
+    pub charity: Account<'info, Charity>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct DisallowCharity<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        has_one = admin @ TimeLockError::Unauthorized,
+    )]
+    pub config: Account<'info, Config>,
+    #[account(
+        mut,
+        seeds = [CHARITY_SEED, charity.address.as_ref()],
+        bump = charity.bump,
+        close = admin,
+    )]
+    pub charity: Account<'info, Charity>,
+}
+
+#[derive(Accounts)]
+pub struct SetCharityFallback<'info> {
+    pub initializer: Signer<'info>,
+    #[account(mut, has_one = initializer)]
+    pub lock_account: Account<'info, TimeLockAccount>,
+    #[account(seeds = [CHARITY_SEED, allowed_charity.address.as_ref()], bump = allowed_charity.bump)]
+    pub allowed_charity: Account<'info, Charity>,
+}
+
+#[derive(Accounts)]
+pub struct DonateAbandonedSol<'info> {
+    /// CHECK: only receives the lock's rent on close; tied to the lock by has_one.
+    #[account(mut)]
+    pub initializer: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        seeds = [TIME_LOCK_SOL_SEED, initializer.key().as_ref()],
+        bump = lock_account.bump,
+        has_one = initializer,
+        close = initializer,
+    )]
+    pub lock_account: Account<'info, TimeLockAccount>,
+    /// CHECK: only credited with the donation; must be the lock's charity_fallback.
+    #[account(
+        mut,
+        constraint = lock_account.charity_fallback == Some(charity.key()) @ TimeLockError::CharityMismatch,
+    )]
+    pub charity: UncheckedAccount<'info>,
+    // Proves the charity is still allow-listed.
+    #[account(seeds = [CHARITY_SEED, charity.key().as_ref()], bump = allowed_charity.bump)]
+    pub allowed_charity: Account<'info, Charity>,
+    #[account(seeds = [CONFIG_SEED], bump = config.bump)]
+    pub config: Box<Account<'info, Config>>,
+    #[account(mut, seeds = [STATS_SEED], bump = stats.bump)]
+    pub stats: Account<'info, Stats>,
+    // Required once the lock has a summary.
+    #[account(
+        mut,
+        seeds = [SUMMARY_SEED, lock_account.key().as_ref()],
+        bump = lock_summary.bump,
+    )]
+    pub lock_summary: Option<Box<Account<'info, LockSummary>>>,
+    /// CHECK: only credited with the rent reserve; must match the lock's rent_recipient.
+    #[account(mut)]
+    pub rent_recipient: Option<UncheckedAccount<'info>>,
+}
+
+#[derive(Accounts)]
+#[instruction(id: u64)]
+pub struct CreateCondition<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        init,
+        payer = authority,
+        space = ConditionFlag::LEN,
+        seeds = [CONDITION_SEED, authority.key().as_ref(), &id.to_le_bytes()],
+        bump,
+    )]
+    pub condition: Account<'info, ConditionFlag>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ReleaseCondition<'info> {
+    pub authority: Signer<'info>,
+    #[account(mut, has_one = authority @ TimeLockError::Unauthorized)]
+    pub condition: Account<'info, ConditionFlag>,
+    #[account(mut, seeds = [STATS_SEED], bump = stats.bump)]
+    pub stats: Box<Account<'info, Stats>>,
+}
+
+#[derive(Accounts)]
+pub struct SetConditionAccount<'info> {
+    pub initializer: Signer<'info>,
+    #[account(mut, has_one = initializer)]
+    pub lock_account: Account<'info, TimeLockAccount>,
+    pub condition: Account<'info, ConditionFlag>,
+}
+
+#[derive(Accounts)]
+pub struct SetNotifyProgram<'info> {
+    pub initializer: Signer<'info>,
+    #[account(mut, has_one = initializer)]
+    pub lock_account: Account<'info, TimeLockAccount>,
+}
+
+#[derive(Accounts)]
+pub struct RegisterMintMigration<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        has_one = admin @ TimeLockError::Unauthorized,
+    )]
+    pub config: Account<'info, Config>,
+
+    pub old_mint: Account<'info, Mint>,
+    pub new_mint: Account<'info, Mint>,
+
+    #[account(
+        init,
+        payer = admin,
+        space = MintMigration::LEN,
+        seeds = [MINT_MIGRATION_SEED, old_mint.key().as_ref()],
+        bump,
+    )]
+    pub migration: Account<'info, MintMigration>,
+
+    // Receives the old tokens surrendered by migrating locks.
+    #[account(
+        init_if_needed,
+        payer = admin,
+        associated_token::mint = old_mint,
+        associated_token::authority = migration,
+    )]
+    pub migration_old_ata: Account<'info, TokenAccount>,
+
+    // Reserve of new tokens paid out to migrating locks.
+    #[account(
+        init_if_needed,
+        payer = admin,
+        associated_token::mint = new_mint,
+        associated_token::authority = migration,
+    )]
+    pub migration_new_ata: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeLockSol<'info> {
+    #[account(mut)]
+    pub initializer: Signer<'info>,
+
+    // A plain `init` under the `strict-init` feature, so an existing lock can't be initialized
+    // again at all.
+    #[cfg_attr(
+        not(feature = "strict-init"),
+        account(
+            init_if_needed,
+            payer = initializer,
+            space = 8 + TimeLockAccount::LEN,
+            seeds = [TIME_LOCK_SOL_SEED, initializer.key().as_ref()],
+            bump,
+        )
+    )]
+    #[cfg_attr(
+        feature = "strict-init",
+        account(
+            init,
+            payer = initializer,
+            space = 8 + TimeLockAccount::LEN,
+            seeds = [TIME_LOCK_SOL_SEED, initializer.key().as_ref()],
+            bump,
+        )
+    )]
+    pub lock_account: Account<'info, TimeLockAccount>,
+    #[account(seeds = [CONFIG_SEED], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    #[account(mut, seeds = [STATS_SEED], bump = stats.bump)]
+    pub stats: Account<'info, Stats>,
+    // Required once the lock has a summary.
+    #[account(
+        mut,
+        seeds = [SUMMARY_SEED, lock_account.key().as_ref()],
+        bump = lock_summary.bump,
+    )]
+    pub lock_summary: Option<Box<Account<'info, LockSummary>>>,
+    // Only required in invite-only mode.
+    #[account(seeds = [INVITE_SEED, initializer.key().as_ref()], bump = invite.bump)]
+    pub invite: Option<Account<'info, Invite>>,
+    /// CHECK: the instructions sysvar, pinned by address; read to find the funding instruction.
+    #[account(address = instructions_sysvar::ID)]
+    pub instructions: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeLockSolUsd<'info> {
+    #[account(mut)]
+    pub initializer: Signer<'info>,
+    #[account(
+        init,
+        payer = initializer,
+        space = 8 + TimeLockAccount::LEN,
+        seeds = [TIME_LOCK_SOL_SEED, initializer.key().as_ref()],
+        bump,
+    )]
+    pub lock_account: Box<Account<'info, TimeLockAccount>>,
+    /// CHECK: pinned to the configured feed; owner and layout are checked in the handler.
+    #[account(address = config.sol_usd_oracle @ TimeLockError::OracleMismatch)]
+    pub price_feed: UncheckedAccount<'info>,
+    #[account(seeds = [CONFIG_SEED], bump = config.bump)]
+    pub config: Box<Account<'info, Config>>,
+    // Only required in invite-only mode.
+    #[account(seeds = [INVITE_SEED, initializer.key().as_ref()], bump = invite.bump)]
+    pub invite: Option<Account<'info, Invite>>,
+    #[account(mut, seeds = [STATS_SEED], bump = stats.bump)]
+    pub stats: Box<Account<'info, Stats>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct FundSolLock<'info> {
+    #[account(mut)]
+    pub initializer: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [TIME_LOCK_SOL_SEED, initializer.key().as_ref()],
+        bump = lock_account.bump,
+    )]
+    pub lock_account: Account<'info, TimeLockAccount>,
+    #[account(seeds = [CONFIG_SEED], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    #[account(mut, seeds = [STATS_SEED], bump = stats.bump)]
+    pub stats: Account<'info, Stats>,
+    // Required once the lock has a summary.
+    #[account(
+        mut,
+        seeds = [SUMMARY_SEED, lock_account.key().as_ref()],
+        bump = lock_summary.bump,
+    )]
+    pub lock_summary: Option<Box<Account<'info, LockSummary>>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawSol<'info> {
+    // The initializer or the lock's withdraw_delegate; pays for the savings lock if one opens.
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    /// CHECK: receives the payout; tied to the lock by has_one.
+    #[account(mut)]
+    pub initializer: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        seeds = [TIME_LOCK_SOL_SEED, initializer.key().as_ref()],
+        bump = lock_account.bump,
+        has_one = initializer,
+        close = initializer,
+    )]
+    pub lock_account: Account<'info, TimeLockAccount>,
+    // Only needed when the lock has auto-save on; created on first use.
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = 8 + TimeLockAccount::LEN,
+        seeds = [TIME_LOCK_SAVINGS_SEED, initializer.key().as_ref()],
+        bump,
+    )]
+    pub savings_lock: Option<Box<Account<'info, TimeLockAccount>>>,
+    #[account(seeds = [CONFIG_SEED], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    #[account(mut, seeds = [STATS_SEED], bump = stats.bump)]
+    pub stats: Account<'info, Stats>,
+    // Required once the lock has a summary.
+    #[account(
+        mut,
+        seeds = [SUMMARY_SEED, lock_account.key().as_ref()],
+        bump = lock_summary.bump,
+    )]
+    pub lock_summary: Option<Box<Account<'info, LockSummary>>>,
+    // Required once the lock has a condition_account.
+    pub condition: Option<Box<Account<'info, ConditionFlag>>>,
+    /// CHECK: only credited with the rent reserve; must match the lock's rent_recipient.
+    #[account(mut)]
+    pub rent_recipient: Option<UncheckedAccount<'info>>,
+    // Only needed when the lock has a notify_program.
+    /// CHECK: only invoked; must match the lock's notify_program, checked in the handler.
+    #[account(executable)]
+    pub notify_program: Option<UncheckedAccount<'info>>,
+    #[account(
+        seeds = [NOTIFY_PROGRAM_SEED, allowed_notify_program.program_id.as_ref()],
+        bump = allowed_notify_program.bump,
+    )]
+    pub allowed_notify_program: Option<Box<Account<'info, NotifyProgram>>>,
+    /// CHECK: only credited with the fee; pinned to the configured treasury.
+    #[account(mut, address = config.treasury @ TimeLockError::Unauthorized)]
+    pub treasury: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct AutoRelease<'info> {
+    // Anyone; paid the configured tip.
+    #[account(mut)]
+    pub keeper: Signer<'info>,
+    /// CHECK: receives the payout; tied to the lock by has_one.
+    #[account(mut)]
+    pub initializer: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        seeds = [TIME_LOCK_SOL_SEED, initializer.key().as_ref()],
+        bump = lock_account.bump,
+        has_one = initializer,
+        close = initializer,
+    )]
+    pub lock_account: Account<'info, TimeLockAccount>,
+    #[account(seeds = [CONFIG_SEED], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    #[account(mut, seeds = [STATS_SEED], bump = stats.bump)]
+    pub stats: Account<'info, Stats>,
+    // Required once the lock has a summary.
+    #[account(
+        mut,
+        seeds = [SUMMARY_SEED, lock_account.key().as_ref()],
+        bump = lock_summary.bump,
+    )]
+    pub lock_summary: Option<Box<Account<'info, LockSummary>>>,
+    // Required once the lock has a condition_account.
+    pub condition: Option<Box<Account<'info, ConditionFlag>>>,
+    /// CHECK: only credited with the rent reserve; must match the lock's rent_recipient.
+    #[account(mut)]
+    pub rent_recipient: Option<UncheckedAccount<'info>>,
+    // Only needed when the lock has a notify_program.
+    /// CHECK: only invoked; must match the lock's notify_program, checked in the handler.
+    #[account(executable)]
+    pub notify_program: Option<UncheckedAccount<'info>>,
+    #[account(
+        seeds = [NOTIFY_PROGRAM_SEED, allowed_notify_program.program_id.as_ref()],
+        bump = allowed_notify_program.bump,
+    )]
+    pub allowed_notify_program: Option<Box<Account<'info, NotifyProgram>>>,
+    /// CHECK: only credited with the fee; pinned to the configured treasury.
+    #[account(mut, address = config.treasury @ TimeLockError::Unauthorized)]
+    pub treasury: UncheckedAccount<'info>,
+}
+
+// SetNotifyProgram plus Stats, for the AuthoritiesRenounced event.
+#[derive(Accounts)]
+pub struct RenounceAuthorities<'info> {
+    pub initializer: Signer<'info>,
+    #[account(mut, has_one = initializer)]
+    pub lock_account: Account<'info, TimeLockAccount>,
+    #[account(mut, seeds = [STATS_SEED], bump = stats.bump)]
+    pub stats: Account<'info, Stats>,
+}
+
+// SetAutoSave plus Stats, for the GoalReached event.
+#[derive(Accounts)]
+pub struct SetGoal<'info> {
+    pub initializer: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [TIME_LOCK_SOL_SEED, initializer.key().as_ref()],
+        bump = lock_account.bump,
+        has_one = initializer,
+    )]
+    pub lock_account: Account<'info, TimeLockAccount>,
+    #[account(mut, seeds = [STATS_SEED], bump = stats.bump)]
+    pub stats: Box<Account<'info, Stats>>,
+}
+
+#[derive(Accounts)]
+pub struct SetAutoSave<'info> {
+    pub initializer: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [TIME_LOCK_SOL_SEED, initializer.key().as_ref()],
+        bump = lock_account.bump,
+        has_one = initializer,
+    )]
+    pub lock_account: Account<'info, TimeLockAccount>,
+}
+
+#[derive(Accounts)]
+pub struct AssignRentRecipient<'info> {
+    pub rent_recipient: Signer<'info>,
+    #[account(mut)]
+    pub lock_account: Account<'info, TimeLockAccount>,
+    #[account(mut, seeds = [STATS_SEED], bump = stats.bump)]
+    pub stats: Box<Account<'info, Stats>>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawSavings<'info> {
+    #[account(mut)]
+    pub initializer: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [TIME_LOCK_SAVINGS_SEED, initializer.key().as_ref()],
+        bump = lock_account.bump,
+        has_one = initializer,
+        close = initializer,
+    )]
+    pub lock_account: Account<'info, TimeLockAccount>,
+    #[account(seeds = [CONFIG_SEED], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    #[account(mut, seeds = [STATS_SEED], bump = stats.bump)]
+    pub stats: Account<'info, Stats>,
+    /// CHECK: only credited with the fee; pinned to the configured treasury.
+    #[account(mut, address = config.treasury @ TimeLockError::Unauthorized)]
+    pub treasury: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimSolStream<'info> {
+    #[account(mut)]
+    pub initializer: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [TIME_LOCK_SOL_SEED, initializer.key().as_ref()],
+        bump = lock_account.bump,
+        has_one = initializer,
+    )]
+    pub lock_account: Account<'info, TimeLockAccount>,
+    #[account(seeds = [CONFIG_SEED], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    #[account(mut, seeds = [STATS_SEED], bump = stats.bump)]
+    pub stats: Account<'info, Stats>,
+    // Required once the lock has a summary.
+    #[account(
+        mut,
+        seeds = [SUMMARY_SEED, lock_account.key().as_ref()],
+        bump = lock_summary.bump,
+    )]
+    pub lock_summary: Option<Box<Account<'info, LockSummary>>>,
+    // Required once the lock has a condition_account.
+    pub condition: Option<Box<Account<'info, ConditionFlag>>>,
+    /// CHECK: only credited with the fee; pinned to the configured treasury.
+    #[account(mut, address = config.treasury @ TimeLockError::Unauthorized)]
+    pub treasury: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct MintLockReceipt<'info> {
+    #[account(mut)]
+    pub initializer: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [TIME_LOCK_SOL_SEED, initializer.key().as_ref()],
+        bump = lock_account.bump,
+        has_one = initializer,
+    )]
+    pub lock_account: Account<'info, TimeLockAccount>,
+    #[account(
+        init,
+        payer = initializer,
+        seeds = [RECEIPT_SEED, lock_account.key().as_ref()],
+        bump,
+        mint::decimals = 0,
+        mint::authority = lock_account,
+    )]
+    pub receipt_mint: Account<'info, Mint>,
+    #[account(
+        init_if_needed,
+        payer = initializer,
+        associated_token::mint = receipt_mint,
+        associated_token::authority = initializer,
+    )]
+    pub receipt_ata: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawSolWithReceipt<'info> {
+    #[account(mut)]
+    pub holder: Signer<'info>,
+    /// CHECK: only used to derive the lock PDA; pinned by has_one.
+    pub initializer: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        seeds = [TIME_LOCK_SOL_SEED, initializer.key().as_ref()],
+        bump = lock_account.bump,
+        has_one = initializer,
+        constraint = lock_account.receipt_mint == Some(receipt_mint.key()) @ TimeLockError::ReceiptRequired,
+        close = holder,
+    )]
+    pub lock_account: Box<Account<'info, TimeLockAccount>>,
+    #[account(mut)]
+    pub receipt_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        constraint = holder_receipt_ata.owner == holder.key(),
+        constraint = holder_receipt_ata.mint == receipt_mint.key(),
+        constraint = holder_receipt_ata.amount == 1 @ TimeLockError::ReceiptRequired,
+    )]
+    pub holder_receipt_ata: Box<Account<'info, TokenAccount>>,
+    #[account(seeds = [CONFIG_SEED], bump = config.bump)]
+    pub config: Box<Account<'info, Config>>,
+    #[account(mut, seeds = [STATS_SEED], bump = stats.bump)]
+    pub stats: Box<Account<'info, Stats>>,
+    // Required once the lock has a summary.
+    #[account(
+        mut,
+        seeds = [SUMMARY_SEED, lock_account.key().as_ref()],
+        bump = lock_summary.bump,
+    )]
+    pub lock_summary: Option<Box<Account<'info, LockSummary>>>,
+    // Required once the lock has a condition_account.
+    pub condition: Option<Box<Account<'info, ConditionFlag>>>,
+    /// CHECK: only credited with the rent reserve; must match the lock's rent_recipient.
+    #[account(mut)]
+    pub rent_recipient: Option<UncheckedAccount<'info>>,
+    /// CHECK: only credited with the fee; pinned to the configured treasury.
+    #[account(mut, address = config.treasury @ TimeLockError::Unauthorized)]
+    pub treasury: UncheckedAccount<'info>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct GetCapabilities<'info> {
+    #[account(seeds = [CONFIG_SEED], bump = config.bump)]
+    pub config: Account<'info, Config>,
+}
+
+#[derive(Accounts)]
+pub struct GetProtocolStats<'info> {
+    #[account(seeds = [STATS_SEED], bump = stats.bump)]
+    pub stats: Account<'info, Stats>,
+}
+
+#[derive(Accounts)]
+pub struct CreateLockSummary<'info> {
+    #[account(mut)]
+    pub initializer: Signer<'info>,
+    #[account(mut, has_one = initializer)]
+    pub lock_account: Account<'info, TimeLockAccount>,
+    #[account(
+        init_if_needed,
+        payer = initializer,
+        space = LockSummary::LEN,
+        seeds = [SUMMARY_SEED, lock_account.key().as_ref()],
+        bump,
+    )]
+    pub lock_summary: Account<'info, LockSummary>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct VerifyBump<'info> {
+    pub lock_account: Account<'info, TimeLockAccount>,
+}
+
+// Locks to check are passed as remaining accounts. The system program isn't used; it gives the
+// context the lifetime Anchor's generated CPI client needs.
+#[derive(Accounts)]
+pub struct VerifyBumps<'info> {
+    pub system_program: Program<'info, System>,
+}
+
+// Approving signers are passed as remaining accounts.
+#[derive(Accounts)]
+pub struct QuorumRelease<'info> {
+    /// CHECK: receives the payout; tied to the lock by has_one.
+    #[account(mut)]
+    pub initializer: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        seeds = [TIME_LOCK_SOL_SEED, initializer.key().as_ref()],
+        bump = lock_account.bump,
+        has_one = initializer,
+        close = initializer,
+    )]
+    pub lock_account: Account<'info, TimeLockAccount>,
+    #[account(seeds = [CONFIG_SEED], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    #[account(mut, seeds = [STATS_SEED], bump = stats.bump)]
+    pub stats: Account<'info, Stats>,
+    // Required once the lock has a summary.
+    #[account(
+        mut,
+        seeds = [SUMMARY_SEED, lock_account.key().as_ref()],
+        bump = lock_summary.bump,
+    )]
+    pub lock_summary: Option<Box<Account<'info, LockSummary>>>,
+    // Required once the lock has a condition_account.
+    pub condition: Option<Box<Account<'info, ConditionFlag>>>,
+    /// CHECK: only credited with the rent reserve; must match the lock's rent_recipient.
+    #[account(mut)]
+    pub rent_recipient: Option<UncheckedAccount<'info>>,
+    /// CHECK: only credited with the fee; pinned to the configured treasury.
+    #[account(mut, address = config.treasury @ TimeLockError::Unauthorized)]
+    pub treasury: UncheckedAccount<'info>,
+}
+
+// Lock and payee pairs are passed as remaining accounts.
+#[derive(Accounts)]
+pub struct SweepClosableLocks<'info> {
+    #[account(mut, seeds = [STATS_SEED], bump = stats.bump)]
+    pub stats: Account<'info, Stats>,
+}
+
+#[derive(Accounts)]
+pub struct ReleaseGroup<'info> {
+    #[account(seeds = [CONFIG_SEED], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    #[account(mut, seeds = [STATS_SEED], bump = stats.bump)]
+    pub stats: Account<'info, Stats>,
+    /// CHECK: only credited with fees; pinned to the configured treasury.
+    #[account(mut, address = config.treasury @ TimeLockError::Unauthorized)]
+    pub treasury: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimWithPassword<'info> {
+    // Anyone holding the preimage; receives the payout.
+    #[account(mut)]
+    pub claimer: Signer<'info>,
+    /// CHECK: only receives the rent reserve; tied to the lock by has_one.
+    #[account(mut)]
+    pub initializer: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        seeds = [TIME_LOCK_SOL_SEED, initializer.key().as_ref()],
+        bump = lock_account.bump,
+        has_one = initializer,
+        close = initializer,
+    )]
+    pub lock_account: Account<'info, TimeLockAccount>,
+    #[account(seeds = [CONFIG_SEED], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    #[account(mut, seeds = [STATS_SEED], bump = stats.bump)]
+    pub stats: Account<'info, Stats>,
+    /// CHECK: only credited with the fee; pinned to the configured treasury.
+    #[account(mut, address = config.treasury @ TimeLockError::Unauthorized)]
+    pub treasury: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ReconcileSol<'info> {
+    pub initializer: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [TIME_LOCK_SOL_SEED, initializer.key().as_ref()],
+        bump = lock_account.bump,
+        has_one = initializer,
+    )]
+    pub lock_account: Account<'info, TimeLockAccount>,
+    #[account(seeds = [CONFIG_SEED], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    #[account(mut, seeds = [STATS_SEED], bump = stats.bump)]
+    pub stats: Account<'info, Stats>,
+}
+
+#[derive(Accounts)]
+pub struct CheckLockHealth<'info> {
+    pub lock_account: Account<'info, TimeLockAccount>,
+    #[account(constraint = vault_ata.owner == lock_account.key())]
+    pub vault_ata: Option<Account<'info, TokenAccount>>,
+}
+
+#[derive(Accounts)]
+pub struct SerializeLockConfig<'info> {
+    pub lock_account: Account<'info, TimeLockAccount>,
+}
+
+#[derive(Accounts)]
+pub struct VerifyLockParams<'info> {
+    pub lock_account: Account<'info, TimeLockAccount>,
+}
+
+#[derive(Accounts)]
+pub struct PreviewWithdrawal<'info> {
+    pub lock_account: Account<'info, TimeLockAccount>,
+    #[account(constraint = vault_ata.owner == lock_account.key())]
+    pub vault_ata: Option<Account<'info, TokenAccount>>,
+    #[account(seeds = [CONFIG_SEED], bump = config.bump)]
+    pub config: Account<'info, Config>,
+}
+
+#[derive(Accounts)]
+pub struct ExplainUnlock<'info> {
+    pub lock_account: Account<'info, TimeLockAccount>,
+    /// CHECK: compared against the lock's stop_loss_oracle before it is read.
+    pub price_feed: Option<UncheckedAccount<'info>>,
+    pub condition: Option<Box<Account<'info, ConditionFlag>>>,
+}
+
+// The owner's lock PDAs, one per kind, whether or not they exist yet.
+#[derive(Accounts)]
+pub struct HasActiveLocks<'info> {
+    /// CHECK: only its key seeds the lock addresses.
+    pub owner: UncheckedAccount<'info>,
+    /// CHECK: only checked for existence.
+    #[account(seeds = [TIME_LOCK_SOL_SEED, owner.key().as_ref()], bump)]
+    pub sol_lock: UncheckedAccount<'info>,
+    /// CHECK: only checked for existence.
+    #[account(seeds = [TIME_LOCK_SPL_SEED, owner.key().as_ref()], bump)]
+    pub spl_lock: UncheckedAccount<'info>,
+    /// CHECK: only checked for existence.
+    #[account(seeds = [TIME_LOCK_SAFE_SEED, owner.key().as_ref()], bump)]
+    pub safe_lock: UncheckedAccount<'info>,
+    /// CHECK: only checked for existence.
+    #[account(seeds = [TIME_LOCK_SAVINGS_SEED, owner.key().as_ref()], bump)]
+    pub savings_lock: UncheckedAccount<'info>,
+    /// CHECK: only checked for existence.
+    #[account(seeds = [TIME_LOCK_BASKET_SEED, owner.key().as_ref()], bump)]
+    pub basket_lock: UncheckedAccount<'info>,
+    /// CHECK: only checked for existence.
+    #[account(seeds = [TIME_LOCK_DELEGATED_SEED, owner.key().as_ref()], bump)]
+    pub delegated_lock: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct NextEventAt<'info> {
+    pub lock_account: Account<'info, TimeLockAccount>,
+    #[account(seeds = [CONFIG_SEED], bump = config.bump)]
+    pub config: Account<'info, Config>,
+}
+
+#[derive(Accounts)]
+pub struct RevealCommitment<'info> {
+    pub lock_account: Account<'info, TimeLockAccount>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeLockSpl<'info> {
+    #[account(mut)]
+    pub initializer: Signer<'info>,
+    // A plain `init` under the `strict-init` feature, so an existing lock can't be initialized
+    // again at all.
+    #[cfg_attr(
+        not(feature = "strict-init"),
+        account(
+            init_if_needed,
+            payer = initializer,
+            space = 8 + TimeLockAccount::LEN,
+            seeds = [TIME_LOCK_SPL_SEED, initializer.key().as_ref()],
+            bump,
+        )
+    )]
+    #[cfg_attr(
+        feature = "strict-init",
+        account(
+            init,
+            payer = initializer,
+            space = 8 + TimeLockAccount::LEN,
+            seeds = [TIME_LOCK_SPL_SEED, initializer.key().as_ref()],
+            bump,
+        )
+    )]
+    pub lock_account: Account<'info, TimeLockAccount>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        constraint = user_ata.owner == initializer.key(),
+        constraint = user_ata.mint == mint.key(),
+    )]
+    pub user_ata: Account<'info, TokenAccount>,
+
+    #[account(
+        init_if_needed,
+        payer = initializer,
+        associated_token::mint = mint,
+        associated_token::authority = lock_account,
+    )]
+    pub vault_ata: Account<'info, TokenAccount>,
+
+    #[account(seeds = [CONFIG_SEED], bump = config.bump)]
+    pub config: Box<Account<'info, Config>>,
+    #[account(mut, seeds = [STATS_SEED], bump = stats.bump)]
+    pub stats: Box<Account<'info, Stats>>,
+    // Required once the lock has a summary.
+    #[account(
+        mut,
+        seeds = [SUMMARY_SEED, lock_account.key().as_ref()],
+        bump = lock_summary.bump,
+    )]
+    pub lock_summary: Option<Box<Account<'info, LockSummary>>>,
+    // Only required in invite-only mode.
+    #[account(seeds = [INVITE_SEED, initializer.key().as_ref()], bump = invite.bump)]
+    pub invite: Option<Account<'info, Invite>>,
+    // Only needed while a deposit fee is set; checked in the handler.
+    #[account(mut)]
+    pub reward_pool_ata: Option<Box<Account<'info, TokenAccount>>>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawSpl<'info> {
+    // The initializer or the lock's withdraw_delegate; pays to recreate a closed user_ata.
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    /// CHECK: owns user_ata; tied to the lock by has_one.
+    #[account(mut)]
+    pub initializer: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        seeds = [TIME_LOCK_SPL_SEED, initializer.key().as_ref()],
+        bump = lock_account.bump,
+        has_one = initializer,
+        constraint = lock_account.mint == Some(mint.key()) @ TimeLockError::MintMismatch,
+    )]
+    pub lock_account: Account<'info, TimeLockAccount>,
+
+    pub mint: Account<'info, Mint>,
+
+    // The initializer's ATA, recreated if they closed it while the tokens were locked.
+    #[account(
+        init_if_needed,
+        payer = authority,
+        associated_token::mint = mint,
+        associated_token::authority = initializer,
+    )]
+    pub user_ata: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = vault_ata.owner == lock_account.vault_authority.unwrap_or(lock_account.key())
+            @ TimeLockError::VaultAuthorityChanged,
+        constraint = vault_ata.mint == mint.key(),
+    )]
+    pub vault_ata: Account<'info, TokenAccount>,
+
+    /// CHECK: signs for the vault once it has been migrated; seeds checked here.
+    #[account(seeds = [VAULT_AUTHORITY_SEED, lock_account.key().as_ref()], bump = lock_account.vault_authority_bump)]
+    pub vault_authority: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only read for a stop-loss unlock; key and owner are checked in the handler.
+    pub price_feed: Option<UncheckedAccount<'info>>,
+
+    #[account(seeds = [CONFIG_SEED], bump = config.bump)]
+    pub config: Box<Account<'info, Config>>,
+
+    // Only needed when the withdrawal owes a fee.
+    #[account(
+        mut,
+        constraint = treasury_ata.owner == config.treasury @ TimeLockError::Unauthorized,
+        constraint = treasury_ata.mint == mint.key(),
+    )]
+    pub treasury_ata: Option<Box<Account<'info, TokenAccount>>>,
+
+    #[account(mut, seeds = [STATS_SEED], bump = stats.bump)]
+    pub stats: Box<Account<'info, Stats>>,
+
+    // Required once the lock has a summary.
+    #[account(
+        mut,
+        seeds = [SUMMARY_SEED, lock_account.key().as_ref()],
+        bump = lock_summary.bump,
+    )]
+    pub lock_summary: Option<Box<Account<'info, LockSummary>>>,
+
+    // Required once the lock has a condition_account.
+    pub condition: Option<Box<Account<'info, ConditionFlag>>>,
+
+    // Only needed when the lock has a notify_program.
+    /// CHECK: only invoked; must match the lock's notify_program, checked in the handler.
+    #[account(executable)]
+    pub notify_program: Option<UncheckedAccount<'info>>,
+    #[account(
+        seeds = [NOTIFY_PROGRAM_SEED, allowed_notify_program.program_id.as_ref()],
+        bump = allowed_notify_program.bump,
+    )]
+    pub allowed_notify_program: Option<Box<Account<'info, NotifyProgram>>>,
+
+    // Required once the lock has a deposit_fee_taken; both are checked in the handler.
+    #[account(mut)]
+    pub reward_pool_ata: Option<Box<Account<'info, TokenAccount>>>,
+    /// CHECK: signs for the reward pool; must be the reward-pool PDA.
+    pub reward_pool_authority: Option<UncheckedAccount<'info>>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeLockDelegated<'info> {
+    #[account(mut)]
+    pub initializer: Signer<'info>,
+    #[account(
+        init,
+        payer = initializer,
+        space = 8 + TimeLockAccount::LEN,
+        seeds = [TIME_LOCK_DELEGATED_SEED, initializer.key().as_ref()],
+        bump,
+    )]
+    pub lock_account: Account<'info, TimeLockAccount>,
+    pub mint: Account<'info, Mint>,
+    #[account(
+        mut,
+        constraint = user_ata.owner == initializer.key(),
+        constraint = user_ata.mint == mint.key(),
+    )]
+    pub user_ata: Account<'info, TokenAccount>,
+    #[account(seeds = [CONFIG_SEED], bump = config.bump)]
+    pub config: Box<Account<'info, Config>>,
+    #[account(mut, seeds = [STATS_SEED], bump = stats.bump)]
+    pub stats: Box<Account<'info, Stats>>,
+    // Only required in invite-only mode.
+    #[account(seeds = [INVITE_SEED, initializer.key().as_ref()], bump = invite.bump)]
+    pub invite: Option<Account<'info, Invite>>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawDelegated<'info> {
+    #[account(mut)]
+    pub initializer: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [TIME_LOCK_DELEGATED_SEED, initializer.key().as_ref()],
+        bump = lock_account.bump,
+        has_one = initializer,
+        close = initializer,
+        constraint = lock_account.mint == Some(mint.key()) @ TimeLockError::MintMismatch,
+    )]
+    pub lock_account: Account<'info, TimeLockAccount>,
+    pub mint: Account<'info, Mint>,
+    #[account(
+        mut,
+        constraint = Some(source_ata.key()) == lock_account.delegated_account @ TimeLockError::VaultAuthorityChanged,
+    )]
+    pub source_ata: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = destination_ata.owner == initializer.key(),
+        constraint = destination_ata.mint == mint.key(),
+    )]
+    pub destination_ata: Account<'info, TokenAccount>,
+    #[account(seeds = [CONFIG_SEED], bump = config.bump)]
+    pub config: Box<Account<'info, Config>>,
+    // Only needed when the withdrawal owes a fee.
+    #[account(
+        mut,
+        constraint = treasury_ata.owner == config.treasury @ TimeLockError::Unauthorized,
+        constraint = treasury_ata.mint == mint.key(),
+    )]
+    pub treasury_ata: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(mut, seeds = [STATS_SEED], bump = stats.bump)]
+    pub stats: Box<Account<'info, Stats>>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct CloseDelegatedLock<'info> {
+    #[account(mut)]
+    pub initializer: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [TIME_LOCK_DELEGATED_SEED, initializer.key().as_ref()],
+        bump = lock_account.bump,
+        has_one = initializer,
+        close = initializer,
+    )]
+    pub lock_account: Account<'info, TimeLockAccount>,
+    #[account(
+        mut,
+        constraint = Some(source_ata.key()) == lock_account.delegated_account @ TimeLockError::VaultAuthorityChanged,
+    )]
+    pub source_ata: Account<'info, TokenAccount>,
+    #[account(mut, seeds = [STATS_SEED], bump = stats.bump)]
+    pub stats: Box<Account<'info, Stats>>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct SetSplBeneficiary<'info> {
+    #[account(mut)]
+    pub initializer: Signer<'info>,
+    #[account(
+        mut,
+        has_one = initializer,
+        constraint = lock_account.mint == Some(mint.key()) @ TimeLockError::MintMismatch,
+    )]
+    pub lock_account: Account<'info, TimeLockAccount>,
+    pub mint: Account<'info, Mint>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ForceUpdateBeneficiary<'info> {
+    pub initializer: Signer<'info>,
+    /// CHECK: must be the lock's arbiter and a signer, both checked in the handler so each
+    /// failure gets its own error.
+    pub arbiter: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        has_one = initializer,
+        constraint = lock_account.mint == Some(mint.key()) @ TimeLockError::MintMismatch,
+    )]
+    pub lock_account: Account<'info, TimeLockAccount>,
+    pub mint: Account<'info, Mint>,
+    #[account(seeds = [CONFIG_SEED], bump = config.bump)]
+    pub config: Box<Account<'info, Config>>,
+    #[account(mut, seeds = [STATS_SEED], bump = stats.bump)]
+    pub stats: Box<Account<'info, Stats>>,
+}
+
+#[derive(Accounts)]
+pub struct ApplyPendingBeneficiary<'info> {
+    #[account(
+        mut,
+        constraint = lock_account.mint == Some(mint.key()) @ TimeLockError::MintMismatch,
+    )]
+    pub lock_account: Account<'info, TimeLockAccount>,
+    pub mint: Account<'info, Mint>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawSplToBeneficiary<'info> {
+    // Anyone; reimbursed from the lock's reserve if the beneficiary's ATA has to be created.
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [TIME_LOCK_SPL_SEED, lock_account.initializer.as_ref()],
+        bump = lock_account.bump,
+        constraint = lock_account.mint == Some(mint.key()) @ TimeLockError::MintMismatch,
+    )]
+    pub lock_account: Box<Account<'info, TimeLockAccount>>,
+
+    /// CHECK: only the owner of the ATA paid into; must be the lock's beneficiary once any due
+    /// change has landed, checked in the handler.
+    pub beneficiary: UncheckedAccount<'info>,
+
+    pub mint: Box<Account<'info, Mint>>,
+
+    /// CHECK: created here if missing; the address ties it to the beneficiary and mint.
+    #[account(mut, address = get_associated_token_address(&beneficiary.key(), &mint.key()))]
+    pub beneficiary_ata: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        constraint = vault_ata.owner == lock_account.vault_authority.unwrap_or(lock_account.key())
+            @ TimeLockError::VaultAuthorityChanged,
+        constraint = vault_ata.mint == mint.key(),
+    )]
+    pub vault_ata: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: signs for the vault once it has been migrated; seeds checked here.
+    #[account(seeds = [VAULT_AUTHORITY_SEED, lock_account.key().as_ref()], bump = lock_account.vault_authority_bump)]
+    pub vault_authority: Option<UncheckedAccount<'info>>,
+
+    #[account(seeds = [CONFIG_SEED], bump = config.bump)]
+    pub config: Box<Account<'info, Config>>,
+
+    // Only needed when the withdrawal owes a fee.
+    #[account(
+        mut,
+        constraint = treasury_ata.owner == config.treasury @ TimeLockError::Unauthorized,
+        constraint = treasury_ata.mint == mint.key(),
+    )]
+    pub treasury_ata: Option<Box<Account<'info, TokenAccount>>>,
+
+    #[account(mut, seeds = [STATS_SEED], bump = stats.bump)]
+    pub stats: Box<Account<'info, Stats>>,
+
+    // Required once the lock has a summary.
+    #[account(
+        mut,
+        seeds = [SUMMARY_SEED, lock_account.key().as_ref()],
+        bump = lock_summary.bump,
+    )]
+    pub lock_summary: Option<Box<Account<'info, LockSummary>>>,
+
+    // Required once the lock has a condition_account.
+    pub condition: Option<Box<Account<'info, ConditionFlag>>>,
+
+    // Only needed when the lock has a notify_program.
+    /// CHECK: only invoked; must match the lock's notify_program, checked in the handler.
+    #[account(executable)]
+    pub notify_program: Option<UncheckedAccount<'info>>,
+    #[account(
+        seeds = [NOTIFY_PROGRAM_SEED, allowed_notify_program.program_id.as_ref()],
+        bump = allowed_notify_program.bump,
+    )]
+    pub allowed_notify_program: Option<Box<Account<'info, NotifyProgram>>>,
+
+    // Required once the lock has a deposit_fee_taken; both are checked in the handler.
+    #[account(mut)]
+    pub reward_pool_ata: Option<Box<Account<'info, TokenAccount>>>,
+    /// CHECK: signs for the reward pool; must be the reward-pool PDA.
+    pub reward_pool_authority: Option<UncheckedAccount<'info>>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CloseSplLock<'info> {
+    #[account(mut)]
+    pub initializer: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [TIME_LOCK_SPL_SEED, initializer.key().as_ref()],
+        bump = lock_account.bump,
+        has_one = initializer,
+        constraint = lock_account.mint == Some(mint.key()) @ TimeLockError::MintMismatch,
+        close = initializer,
+    )]
+    pub lock_account: Account<'info, TimeLockAccount>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        address = get_associated_token_address(&lock_account.key(), &mint.key()) @ TimeLockError::MintMismatch,
+        constraint = vault_ata.owner == lock_account.vault_authority.unwrap_or(lock_account.key())
+            @ TimeLockError::VaultAuthorityChanged,
+    )]
+    pub vault_ata: Account<'info, TokenAccount>,
+
+    /// CHECK: signs for the vault once it has been migrated; seeds checked here.
+    #[account(seeds = [VAULT_AUTHORITY_SEED, lock_account.key().as_ref()], bump = lock_account.vault_authority_bump)]
+    pub vault_authority: Option<UncheckedAccount<'info>>,
+
+    #[account(mut, seeds = [STATS_SEED], bump = stats.bump)]
+    pub stats: Box<Account<'info, Stats>>,
+
+    // Required once the lock has a summary.
+    #[account(
+        mut,
+        seeds = [SUMMARY_SEED, lock_account.key().as_ref()],
+        bump = lock_summary.bump,
+    )]
+    pub lock_summary: Option<Box<Account<'info, LockSummary>>>,
+
+    /// CHECK: only credited with the rent reserve; must match the lock's rent_recipient.
+    #[account(mut)]
+    pub rent_recipient: Option<UncheckedAccount<'info>>,
+
+    // Required once the lock has a deposit_fee_taken; both are checked in the handler.
+    #[account(mut)]
+    pub reward_pool_ata: Option<Box<Account<'info, TokenAccount>>>,
+    /// CHECK: signs for the reward pool; must be the reward-pool PDA.
+    pub reward_pool_authority: Option<UncheckedAccount<'info>>,
+    // Where a deposit fee refund goes, in the lock's own token; only needed while one is owed.
+    #[account(
+        mut,
+        constraint = user_ata.owner == initializer.key(),
+        constraint = user_ata.mint == mint.key(),
+    )]
+    pub user_ata: Option<Box<Account<'info, TokenAccount>>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct AcknowledgeFrozenLock<'info> {
+    pub admin: Signer<'info>,
+    #[account(seeds = [CONFIG_SEED], bump = config.bump, has_one = admin @ TimeLockError::Unauthorized)]
+    pub config: Account<'info, Config>,
+    #[account(mut)]
+    pub lock_account: Account<'info, TimeLockAccount>,
+    #[account(
+        constraint = vault_ata.owner == lock_account.vault_authority.unwrap_or(lock_account.key())
+            @ TimeLockError::VaultAuthorityChanged,
+        constraint = lock_account.mint == Some(vault_ata.mint) @ TimeLockError::MintMismatch,
+    )]
+    pub vault_ata: Account<'info, TokenAccount>,
+    #[account(mut, seeds = [STATS_SEED], bump = stats.bump)]
+    pub stats: Account<'info, Stats>,
+}
+
+#[derive(Accounts)]
+pub struct ForceCloseOrphanLock<'info> {
+    pub admin: Signer<'info>,
+    #[account(seeds = [CONFIG_SEED], bump = config.bump, has_one = admin @ TimeLockError::Unauthorized)]
+    pub config: Account<'info, Config>,
+    /// CHECK: only receives rent; pinned to the lock's initializer by has_one below.
+    #[account(mut)]
+    pub initializer: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        seeds = [TIME_LOCK_SPL_SEED, initializer.key().as_ref()],
+        bump = lock_account.bump,
+        has_one = initializer,
+        close = initializer,
+    )]
+    pub lock_account: Account<'info, TimeLockAccount>,
+    /// CHECK: may already be closed; checked against the lock's ATA address in the handler.
+    #[account(mut)]
+    pub vault_ata: UncheckedAccount<'info>,
+    #[account(mut, seeds = [STATS_SEED], bump = stats.bump)]
+    pub stats: Box<Account<'info, Stats>>,
+    // Required once the lock has a summary.
+    #[account(
+        mut,
+        seeds = [SUMMARY_SEED, lock_account.key().as_ref()],
+        bump = lock_summary.bump,
+    )]
+    pub lock_summary: Option<Box<Account<'info, LockSummary>>>,
+    /// CHECK: only credited with the rent reserve; must match the lock's rent_recipient.
+    #[account(mut)]
+    pub rent_recipient: Option<UncheckedAccount<'info>>,
+    /// CHECK: only credited with the forfeited bond; pinned to the configured treasury.
+    #[account(mut, address = config.treasury @ TimeLockError::Unauthorized)]
+    pub treasury: UncheckedAccount<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateVaultAuthority<'info> {
+    pub admin: Signer<'info>,
+    #[account(seeds = [CONFIG_SEED], bump = config.bump, has_one = admin @ TimeLockError::Unauthorized)]
+    pub config: Account<'info, Config>,
+    #[account(
+        mut,
+        seeds = [TIME_LOCK_SPL_SEED, lock_account.initializer.as_ref()],
+        bump = lock_account.bump,
+    )]
+    pub lock_account: Account<'info, TimeLockAccount>,
+    #[account(
+        mut,
+        address = get_associated_token_address(&lock_account.key(), &vault_ata.mint) @ TimeLockError::MintMismatch,
+        constraint = lock_account.mint == Some(vault_ata.mint) @ TimeLockError::MintMismatch,
+    )]
+    pub vault_ata: Account<'info, TokenAccount>,
+    /// CHECK: a bare PDA that only ever signs; becomes the vault's owner.
+    #[account(seeds = [VAULT_AUTHORITY_SEED, lock_account.key().as_ref()], bump)]
+    pub new_authority: UncheckedAccount<'info>,
+    #[account(mut, seeds = [STATS_SEED], bump = stats.bump)]
+    pub stats: Box<Account<'info, Stats>>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct ReclaimStrayLamports<'info> {
+    #[account(mut)]
+    pub initializer: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [TIME_LOCK_SPL_SEED, initializer.key().as_ref()],
+        bump = lock_account.bump,
+        has_one = initializer,
+    )]
+    pub lock_account: Account<'info, TimeLockAccount>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawSplWithSwap<'info> {
+    #[account(mut)]
+    pub initializer: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [TIME_LOCK_SPL_SEED, initializer.key().as_ref()],
+        bump = lock_account.bump,
+        has_one = initializer,
+        constraint = lock_account.mint == Some(mint.key()) @ TimeLockError::MintMismatch,
+    )]
+    pub lock_account: Account<'info, TimeLockAccount>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        constraint = vault_ata.owner == lock_account.vault_authority.unwrap_or(lock_account.key())
+            @ TimeLockError::VaultAuthorityChanged,
+        constraint = vault_ata.mint == mint.key(),
+    )]
+    pub vault_ata: Account<'info, TokenAccount>,
 
-        Ok(())
-    }
+    /// CHECK: signs for the vault once it has been migrated; seeds checked here.
+    #[account(seeds = [VAULT_AUTHORITY_SEED, lock_account.key().as_ref()], bump = lock_account.vault_authority_bump)]
+    pub vault_authority: Option<UncheckedAccount<'info>>,
 
-    // Withdraw SPL tokens back to the user's ATA after unlock
-    pub fn withdraw_spl(ctx: Context<WithdrawSpl>) -> Result<()> {
-        let clock = Clock::get()?;
-        let lock_account = &ctx.accounts.lock_account;
-        require!(lock_account.kind == AssetKind::Spl, TimeLockError::WrongAssetKind);
-        require!(clock.unix_timestamp >= lock_account.unlock_timestamp, TimeLockError::TimeLockNotExpired);
+    pub out_mint: Account<'info, Mint>,
 
-        let initializer_key = ctx.accounts.initializer.key();
-        let seeds: &[&[u8]] = &[TIME_LOCK_SPL_SEED, initializer_key.as_ref(), &[lock_account.bump]];
-        let signer_seeds: &[&[&[u8]]] = &[seeds];
+    #[account(
+        mut,
+        constraint = user_out_ata.owner == initializer.key(),
+        constraint = user_out_ata.mint == out_mint.key(),
+    )]
+    pub user_out_ata: Account<'info, TokenAccount>,
 
-        // Transfer entire vault balance back to user
-        let vault_balance = ctx.accounts.vault_ata.amount;
-        require!(vault_balance > 0, TimeLockError::InsufficientVaultBalance);
+    // Only needed when the withdrawal owes a fee.
+    #[account(
+        mut,
+        constraint = treasury_ata.owner == config.treasury @ TimeLockError::Unauthorized,
+        constraint = treasury_ata.mint == mint.key(),
+    )]
+    pub treasury_ata: Option<Box<Account<'info, TokenAccount>>>,
 
-        let cpi_accounts = SplTransfer {
-            from: ctx.accounts.vault_ata.to_account_info(),
-            to: ctx.accounts.user_ata.to_account_info(),
-            authority: ctx.accounts.lock_account.to_account_info(),
-        };
-        let cpi_ctx = CpiContext::new_with_signer(
-            ctx.accounts.token_program.to_account_info(),
-            cpi_accounts,
-            signer_seeds,
-        );
-        // Transfer the entire vault balance, not just the stored amount
-        token::transfer(cpi_ctx, vault_balance)?;
+    #[account(
+        seeds = [SWAP_PROGRAM_SEED, swap_program.key().as_ref()],
+        bump = allowed_swap_program.bump,
+    )]
+    pub allowed_swap_program: Account<'info, SwapProgram>,
 
-        Ok(())
-    }
-}
+    /// CHECK: only invoked; its id must match the allow-list PDA above.
+    #[account(executable)]
+    pub swap_program: UncheckedAccount<'info>,
 
-#[account]
-pub struct TimeLockAccount {
-    pub initializer: Pubkey,
-    pub amount: u64,
-    pub unlock_timestamp: i64,
-    pub bump: u8,
-    pub kind: AssetKind,
-    pub mint: Option<Pubkey>,
-}
+    #[account(seeds = [CONFIG_SEED], bump = config.bump)]
+    pub config: Box<Account<'info, Config>>,
+    #[account(mut, seeds = [STATS_SEED], bump = stats.bump)]
+    pub stats: Box<Account<'info, Stats>>,
 
-impl TimeLockAccount {
-    pub const LEN: usize = 8  // discriminator
-        + 32 // initializer
-        + 8  // amount
-        + 8  // unlock_timestamp
-        + 1  // bump
-        + 1  // kind (u8)
-        + 1 + 32; // Option<Pubkey>
-}
+    // Required once the lock has a summary.
+    #[account(
+        mut,
+        seeds = [SUMMARY_SEED, lock_account.key().as_ref()],
+        bump = lock_summary.bump,
+    )]
+    pub lock_summary: Option<Box<Account<'info, LockSummary>>>,
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
-pub enum AssetKind {
-    Sol = 0,
-    Spl = 1,
+    // Required once the lock has a condition_account.
+    pub condition: Option<Box<Account<'info, ConditionFlag>>>,
+
+    // Required once the lock has a deposit_fee_taken; both are checked in the handler.
+    #[account(mut)]
+    pub reward_pool_ata: Option<Box<Account<'info, TokenAccount>>>,
+    /// CHECK: signs for the reward pool; must be the reward-pool PDA.
+    pub reward_pool_authority: Option<UncheckedAccount<'info>>,
+    // Where a deposit fee refund goes, in the lock's own token; only needed while one is owed.
+    #[account(
+        mut,
+        constraint = user_ata.owner == initializer.key(),
+        constraint = user_ata.mint == mint.key(),
+    )]
+    pub user_ata: Option<Box<Account<'info, TokenAccount>>>,
+
+    pub token_program: Program<'info, Token>,
 }
 
 #[derive(Accounts)]
-pub struct InitializeLockSol<'info> {
+pub struct MigrateSplMint<'info> {
     #[account(mut)]
     pub initializer: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [TIME_LOCK_SPL_SEED, initializer.key().as_ref()],
+        bump = lock_account.bump,
+        has_one = initializer,
+    )]
+    pub lock_account: Account<'info, TimeLockAccount>,
+
+    pub old_mint: Box<Account<'info, Mint>>,
+    pub new_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        seeds = [MINT_MIGRATION_SEED, old_mint.key().as_ref()],
+        bump = migration.bump,
+        constraint = migration.new_mint == new_mint.key() @ TimeLockError::NoMigrationPath,
+    )]
+    pub migration: Box<Account<'info, MintMigration>>,
+
+    #[account(
+        mut,
+        constraint = old_vault.owner == lock_account.key(),
+        constraint = old_vault.mint == old_mint.key(),
+    )]
+    pub old_vault: Box<Account<'info, TokenAccount>>,
 
     #[account(
         init_if_needed,
         payer = initializer,
-        space = 8 + TimeLockAccount::LEN,
-        seeds = [TIME_LOCK_SOL_SEED, initializer.key().as_ref()],
-        bump,
+        associated_token::mint = new_mint,
+        associated_token::authority = lock_account,
     )]
-    pub lock_account: Account<'info, TimeLockAccount>,
+    pub new_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        associated_token::mint = old_mint,
+        associated_token::authority = migration,
+    )]
+    pub migration_old_ata: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        associated_token::mint = new_mint,
+        associated_token::authority = migration,
+    )]
+    pub migration_new_ata: Box<Account<'info, TokenAccount>>,
+
+    #[account(mut, seeds = [STATS_SEED], bump = stats.bump)]
+    pub stats: Box<Account<'info, Stats>>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct FundSolLock<'info> {
+pub struct InitializeSafe<'info> {
     #[account(mut)]
     pub initializer: Signer<'info>,
+    #[account(
+        init,
+        payer = initializer,
+        space = 8 + TimeLockAccount::LEN,
+        seeds = [TIME_LOCK_SAFE_SEED, initializer.key().as_ref()],
+        bump,
+    )]
+    pub lock_account: Box<Account<'info, TimeLockAccount>>,
+
+    pub mint: Box<Account<'info, Mint>>,
+
     #[account(
         mut,
-        seeds = [TIME_LOCK_SOL_SEED, initializer.key().as_ref()],
-        bump = lock_account.bump,
+        constraint = user_ata.owner == initializer.key(),
+        constraint = user_ata.mint == mint.key(),
     )]
-    pub lock_account: Account<'info, TimeLockAccount>,
+    pub user_ata: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        init_if_needed,
+        payer = initializer,
+        associated_token::mint = mint,
+        associated_token::authority = lock_account,
+    )]
+    pub vault_ata: Box<Account<'info, TokenAccount>>,
+
+    #[account(seeds = [CONFIG_SEED], bump = config.bump)]
+    pub config: Box<Account<'info, Config>>,
+    #[account(mut, seeds = [STATS_SEED], bump = stats.bump)]
+    pub stats: Box<Account<'info, Stats>>,
+    // Only required in invite-only mode.
+    #[account(seeds = [INVITE_SEED, initializer.key().as_ref()], bump = invite.bump)]
+    pub invite: Option<Account<'info, Invite>>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct WithdrawSol<'info> {
+pub struct InitializeBasket<'info> {
     #[account(mut)]
     pub initializer: Signer<'info>,
     #[account(
-        mut,
-        seeds = [TIME_LOCK_SOL_SEED, initializer.key().as_ref()],
-        bump = lock_account.bump,
-        has_one = initializer,
-        close = initializer,
+        init,
+        payer = initializer,
+        space = Basket::LEN,
+        seeds = [TIME_LOCK_BASKET_SEED, initializer.key().as_ref()],
+        bump,
     )]
-    pub lock_account: Account<'info, TimeLockAccount>,
+    pub basket: Account<'info, Basket>,
+    #[account(seeds = [CONFIG_SEED], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    #[account(mut, seeds = [STATS_SEED], bump = stats.bump)]
+    pub stats: Account<'info, Stats>,
+    // Only required in invite-only mode.
+    #[account(seeds = [INVITE_SEED, initializer.key().as_ref()], bump = invite.bump)]
+    pub invite: Option<Account<'info, Invite>>,
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct InitializeLockSpl<'info> {
+pub struct AddBasketMint<'info> {
     #[account(mut)]
     pub initializer: Signer<'info>,
     #[account(
-        init_if_needed,
-        payer = initializer,
-        space = 8 + TimeLockAccount::LEN,
-        seeds = [TIME_LOCK_SPL_SEED, initializer.key().as_ref()],
-        bump,
+        mut,
+        seeds = [TIME_LOCK_BASKET_SEED, initializer.key().as_ref()],
+        bump = basket.bump,
+        has_one = initializer,
     )]
-    pub lock_account: Account<'info, TimeLockAccount>,
+    pub basket: Box<Account<'info, Basket>>,
 
-    pub mint: Account<'info, Mint>,
+    pub mint: Box<Account<'info, Mint>>,
 
     #[account(
         mut,
         constraint = user_ata.owner == initializer.key(),
         constraint = user_ata.mint == mint.key(),
     )]
-    pub user_ata: Account<'info, TokenAccount>,
+    pub user_ata: Box<Account<'info, TokenAccount>>,
 
     #[account(
         init_if_needed,
         payer = initializer,
         associated_token::mint = mint,
-        associated_token::authority = lock_account,
+        associated_token::authority = basket,
     )]
-    pub vault_ata: Account<'info, TokenAccount>,
+    pub vault_ata: Box<Account<'info, TokenAccount>>,
+
+    #[account(seeds = [CONFIG_SEED], bump = config.bump)]
+    pub config: Box<Account<'info, Config>>,
 
     pub token_program: Program<'info, Token>,
     pub associated_token_program: Program<'info, AssociatedToken>,
@@ -248,34 +7323,430 @@ pub struct InitializeLockSpl<'info> {
 }
 
 #[derive(Accounts)]
-pub struct WithdrawSpl<'info> {
+pub struct WithdrawBasket<'info> {
     #[account(mut)]
     pub initializer: Signer<'info>,
     #[account(
         mut,
-        seeds = [TIME_LOCK_SPL_SEED, initializer.key().as_ref()],
+        seeds = [TIME_LOCK_BASKET_SEED, initializer.key().as_ref()],
+        bump = basket.bump,
+        has_one = initializer,
+        close = initializer,
+    )]
+    pub basket: Account<'info, Basket>,
+    #[account(seeds = [CONFIG_SEED], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    #[account(mut, seeds = [STATS_SEED], bump = stats.bump)]
+    pub stats: Account<'info, Stats>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawSafe<'info> {
+    #[account(mut)]
+    pub initializer: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [TIME_LOCK_SAFE_SEED, initializer.key().as_ref()],
         bump = lock_account.bump,
         has_one = initializer,
+        constraint = lock_account.mint == Some(mint.key()) @ TimeLockError::MintMismatch,
+        close = initializer,
     )]
-    pub lock_account: Account<'info, TimeLockAccount>,
+    pub lock_account: Box<Account<'info, TimeLockAccount>>,
 
-    pub mint: Account<'info, Mint>,
+    pub mint: Box<Account<'info, Mint>>,
 
     #[account(
         mut,
         constraint = user_ata.owner == initializer.key(),
         constraint = user_ata.mint == mint.key(),
     )]
-    pub user_ata: Account<'info, TokenAccount>,
+    pub user_ata: Box<Account<'info, TokenAccount>>,
 
     #[account(
         mut,
-        constraint = vault_ata.owner == lock_account.key(),
-        constraint = vault_ata.mint == mint.key(),
+        associated_token::mint = mint,
+        associated_token::authority = lock_account,
     )]
-    pub vault_ata: Account<'info, TokenAccount>,
+    pub vault_ata: Box<Account<'info, TokenAccount>>,
+
+    #[account(seeds = [CONFIG_SEED], bump = config.bump)]
+    pub config: Box<Account<'info, Config>>,
+    #[account(mut, seeds = [STATS_SEED], bump = stats.bump)]
+    pub stats: Box<Account<'info, Stats>>,
 
     pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event]
+pub struct Capabilities {
+    pub version: String,
+    pub features: u64,
+}
+
+#[event]
+pub struct EffectiveConfig {
+    pub admin: Pubkey,
+    pub pending_admin: Option<Pubkey>,
+    pub treasury: Pubkey,
+    pub max_tvl_lamports: u64,
+    pub fee_tiers: [FeeTier; FEE_TIER_COUNT],
+    pub max_fee_bps: u16,
+    pub deposit_fee_bps: u16,
+    pub min_lock_lamports: u64,
+    pub min_lock_tokens: u64,
+    pub max_lock_lamports: u64,
+    pub max_lock_tokens: u64,
+    pub bond_lamports: u64,
+    pub invite_only: bool,
+    pub usd_locks_enabled: bool,
+    // Whether withdrawals are refused right now, not just whether a freeze was ever set.
+    pub withdrawals_frozen: bool,
+    pub frozen_until: i64,
+    pub reward_multiplier_bps: u16,
+    pub keeper_tip_lamports: u64,
+    pub max_keeper_tip_lamports: u64,
+    pub min_settlement_slots: u64,
+    pub beneficiary_change_delay: i64,
+    pub fee_split: [FeeSplit; MAX_FEE_RECIPIENTS],
+    pub max_unlock_rounding_secs: i64,
+    pub max_contributions: u16,
+    pub log_level: u8,
+}
+
+#[event]
+pub struct CreationCost {
+    pub kind: AssetKind,
+    pub lock_rent: u64,
+    pub vault_rent: u64,
+    pub bond: u64,
+    pub total: u64,
+}
+
+#[event]
+pub struct ProtocolStats {
+    pub total_sol_locked: u64,
+    pub total_locks_open: u64,
+    pub total_fees_collected: u64,
+    pub total_withdrawals: u64,
+    // Sequence number of the last sequenced event, for indexers checking they are caught up.
+    pub event_seq: u64,
+}
+
+#[event]
+pub struct HealthReport {
+    pub lock_account: Pubkey,
+    pub bump_ok: bool,
+    pub mint_consistent: bool,
+    // Lamports above rent cover what the lock still owes (always true for SPL locks).
+    pub sol_funded: bool,
+    // The vault holds at least the locked tokens (always true for locks without one).
+    pub vault_funded: bool,
+    pub timestamps_sane: bool,
+    pub healthy: bool,
+}
+
+#[event]
+pub struct Revealed {
+    pub lock_account: Pubkey,
+    pub commitment: [u8; 32],
+}
+
+#[event]
+pub struct SolToppedUpAndExtended {
+    pub lock_account: Pubkey,
+    pub additional_lamports: u64,
+    pub amount: u64,
+    pub old_unlock_timestamp: i64,
+    pub unlock_timestamp: i64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct ContributionReceived {
+    pub lock_account: Pubkey,
+    // Position of this deposit in the schedule, counting missed contributions.
+    pub index: u16,
+    pub amount_lamports: u64,
+    pub amount: u64,
+    pub contributions_made: u16,
+    pub contributions_missed: u16,
+    pub remaining: u16,
+    pub seq: u64,
+}
+
+#[event]
+pub struct SplWithdrawn {
+    pub lock_account: Pubkey,
+    pub category: LockCategory,
+    pub amount: u64,
+    pub vault_balance: u64,
+    pub realized_delta: i64,
+    pub fee: u64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct SolStreamClaimed {
+    pub lock_account: Pubkey,
+    pub claimed: u64,
+    pub fee: u64,
+    pub total_claimed: u64,
+    pub next_claim_at: i64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct SplVestingClaimed {
+    pub lock_account: Pubkey,
+    pub claimed_now: u64,
+    pub fee: u64,
+    pub total_claimed: u64,
+    pub remaining_locked: u64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct GoalReached {
+    pub lock_account: Pubkey,
+    pub goal_amount: u64,
+    pub deposited: u64,
+    pub goal_label: [u8; 32],
+    pub seq: u64,
+}
+
+#[event]
+pub struct OrphanLockClosed {
+    pub lock_account: Pubkey,
+    pub initializer: Pubkey,
+    pub mint: Pubkey,
+    pub vault_existed: bool,
+    pub bond_forfeited: u64,
+    pub admin: Pubkey,
+    pub seq: u64,
+}
+
+#[event]
+pub struct SplPartiallyRelocked {
+    pub lock_account: Pubkey,
+    pub withdrawn: u64,
+    pub fee: u64,
+    pub kept: u64,
+    pub unlock_timestamp: i64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct UnlockAutoExtended {
+    pub lock_account: Pubkey,
+    pub old_unlock_timestamp: i64,
+    pub unlock_timestamp: i64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct WithdrawalPreview {
+    pub lock_account: Pubkey,
+    pub gross: u64,
+    pub fee: u64,
+    // Always 0: locks can't be exited early, so nothing is forfeited.
+    pub penalty: u64,
+    pub net: u64,
+    pub unlocked: bool,
+}
+
+#[event]
+pub struct ActiveLocks {
+    pub owner: Pubkey,
+    pub has_active_locks: bool,
+    pub count: u8,
+}
+
+#[event]
+pub struct NextLockEvent {
+    pub lock_account: Pubkey,
+    // i64::MAX when no time-based change is left.
+    pub next_event_at: i64,
+}
+
+#[event]
+pub struct UnlockExplained {
+    pub lock_account: Pubkey,
+    // Only the conditions this lock actually has configured.
+    pub conditions: Vec<UnlockCondition>,
+    pub withdrawable: bool,
+}
+
+#[event]
+pub struct RentRecipientChanged {
+    pub lock_account: Pubkey,
+    pub old_recipient: Pubkey,
+    pub new_recipient: Pubkey,
+    pub seq: u64,
+}
+
+#[event]
+pub struct VaultAuthorityMigrated {
+    pub lock_account: Pubkey,
+    pub vault: Pubkey,
+    pub old_authority: Pubkey,
+    pub new_authority: Pubkey,
+    pub admin: Pubkey,
+    pub seq: u64,
+}
+
+#[event]
+pub struct Reconciled {
+    pub lock_account: Pubkey,
+    pub old_amount: u64,
+    pub new_amount: u64,
+    // False when only reporting the drift.
+    pub repaired: bool,
+    pub seq: u64,
+}
+
+#[event]
+pub struct ConditionReleased {
+    pub condition: Pubkey,
+    pub authority: Pubkey,
+    pub seq: u64,
+}
+
+#[event]
+pub struct DonatedFromAbandoned {
+    pub lock_account: Pubkey,
+    pub charity: Pubkey,
+    pub amount: u64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct LockCreated {
+    pub lock_account: Pubkey,
+    pub initializer: Pubkey,
+    pub kind: AssetKind,
+    pub category: LockCategory,
+    pub mint: Option<Pubkey>,
+    pub amount: u64,
+    pub unlock_timestamp: i64,
+    pub unlock_slot: Option<u64>,
+    // Who referred the initializer, for referral programs built on top; set at creation.
+    pub referrer: Option<Pubkey>,
+    pub seq: u64,
+}
+
+#[event]
+pub struct SolWithdrawn {
+    pub lock_account: Pubkey,
+    // Paid to the initializer.
+    pub principal_returned: u64,
+    // The lock account's rent reserve, paid to `rent_recipient`.
+    pub rent_refunded: u64,
+    pub rent_recipient: Pubkey,
+    pub fee_taken: u64,
+    // Moved into the initializer's savings lock by auto-save.
+    pub saved: u64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct VaultFreezeAcknowledged {
+    pub lock_account: Pubkey,
+    pub frozen: bool,
+    pub seq: u64,
+}
+
+#[event]
+pub struct PasswordClaimed {
+    pub lock_account: Pubkey,
+    pub claimer: Pubkey,
+    pub amount: u64,
+    pub fee_taken: u64,
+    pub seq: u64,
+}
+
+// Emitted by withdraw_sol_split ahead of its SolWithdrawn, whose principal_returned is the
+// remainder paid to the initializer.
+#[event]
+pub struct SplitWithdrawn {
+    pub lock_account: Pubkey,
+    pub destinations: Vec<Pubkey>,
+    pub amounts: Vec<u64>,
+    pub remainder: u64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct AutoReleased {
+    pub lock_account: Pubkey,
+    pub keeper: Pubkey,
+    pub tip: u64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct QuorumReleased {
+    pub lock_account: Pubkey,
+    pub approvals: u8,
+    pub principal: u64,
+    pub fee: u64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct LocksSwept {
+    pub closed: u32,
+    pub skipped: u32,
+    pub seq: u64,
+}
+
+#[event]
+pub struct AuthoritiesRenounced {
+    pub lock_account: Pubkey,
+    // What was given up, for indexers that tracked it.
+    pub arbiter: Option<Pubkey>,
+    pub withdraw_delegate: Option<Pubkey>,
+    pub quorum_signers: Vec<Pubkey>,
+    pub seq: u64,
+}
+
+#[event]
+pub struct GroupReleased {
+    pub group_id: [u8; 16],
+    pub released: u32,
+    pub skipped: u32,
+    pub seq: u64,
+}
+
+#[event]
+pub struct MintMigrated {
+    pub lock_account: Pubkey,
+    pub old_mint: Pubkey,
+    pub new_mint: Pubkey,
+    pub old_balance: u64,
+    pub new_balance: u64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct BeneficiaryForceUpdated {
+    pub lock_account: Pubkey,
+    pub arbiter: Pubkey,
+    pub old_beneficiary: Pubkey,
+    pub new_beneficiary: Pubkey,
+    // When the change lands; until then the old beneficiary is still paid.
+    pub effective_at: i64,
+    pub seq: u64,
+}
+
+#[event]
+pub struct DepositFeeRefunded {
+    pub lock_account: Pubkey,
+    pub fee_taken: u64,
+    pub refunded: u64,
+    pub seq: u64,
 }
 
 #[error_code]
@@ -286,10 +7757,202 @@ pub enum TimeLockError {
     InvalidAmount,
     #[msg("Unlock timestamp must be in the future")] 
     UnlockInPast,
-    #[msg("Missing bump")] 
+    #[msg("No bump derives this lock account from its seeds")] 
     BumpMissing,
     #[msg("Incorrect asset kind for this operation")] 
     WrongAssetKind,
     #[msg("Vault balance lower than expected amount")] 
     InsufficientVaultBalance,
+    #[msg("Signer is not the config admin")]
+    Unauthorized,
+    #[msg("Deposit would exceed the program-wide SOL cap")]
+    TvlCapExceeded,
+    #[msg("Stored bump is not the canonical bump for the lock seeds")]
+    BumpMismatch,
+    #[msg("Swap program failed to convert the vault balance")]
+    SwapFailed,
+    #[msg("Swap output is below the requested minimum")]
+    SlippageExceeded,
+    #[msg("Lock account holds no lamports above its rent-exempt minimum")]
+    NoStrayLamports,
+    #[msg("Lock has no commitment to reveal")]
+    NoCommitment,
+    #[msg("Preimage does not hash to the stored commitment")]
+    CommitmentMismatch,
+    #[msg("Withdrawal destination must be a System-owned wallet")]
+    DestinationNotWallet,
+    #[msg("No registered migration from this mint to the requested one")]
+    NoMigrationPath,
+    #[msg("Arithmetic overflow")]
+    MathOverflow,
+    #[msg("Oracle account does not match the one configured for this lock")]
+    OracleMismatch,
+    #[msg("Oracle price is too old")]
+    OracleStale,
+    #[msg("Funding must bring the lock to exactly its declared amount")]
+    FundingMismatch,
+    #[msg("Mint does not match the lock's mint")]
+    MintMismatch,
+    #[msg("Duration must not be negative")]
+    InvalidDuration,
+    #[msg("Trial window has expired; the lock can no longer be revoked")]
+    TrialExpired,
+    #[msg("Combined lock needs both a SOL and a token balance")]
+    SafeSideEmpty,
+    #[msg("New unlock timestamp is earlier than the current one")]
+    CannotShortenLock,
+    #[msg("Fee tiers must be ascending, contiguous and within the fee cap")]
+    InvalidFeeSchedule,
+    #[msg("A fee is owed but no treasury token account was provided")]
+    TreasuryAccountMissing,
+    #[msg("Lock has a receipt; withdraw by holding it")]
+    ReceiptRequired,
+    #[msg("Lock already has a receipt")]
+    ReceiptAlreadyMinted,
+    #[msg("User and vault token accounts must differ")]
+    SameTokenAccount,
+    #[msg("Lock is not a stream")]
+    NotAStream,
+    #[msg("Stream or vesting lock still has unclaimed funds")]
+    StreamNotDrained,
+    #[msg("Nothing new to claim yet")]
+    NothingToClaim,
+    #[msg("Invite-only mode: this address has no invite")]
+    NotInvited,
+    #[msg("Too many accounts for one batch")]
+    BatchTooLarge,
+    #[msg("Signer is not the pending admin")]
+    NotPendingAdmin,
+    #[msg("Auto-save share must be at most 10000 bps")]
+    InvalidAutoSave,
+    #[msg("Auto-save is on; pass the savings lock")]
+    SavingsLockMissing,
+    #[msg("Lock was already initialized in this slot")]
+    DuplicateInitialization,
+    #[msg("Lock amount is below the configured minimum")]
+    BelowMinimumAmount,
+    #[msg("Unknown log level")]
+    InvalidLogLevel,
+    #[msg("Vault still holds tokens")]
+    VaultNotEmpty,
+    #[msg("Lock already has a goal")]
+    GoalAlreadySet,
+    #[msg("Lock goal has not been reached")]
+    GoalNotReached,
+    #[msg("Lock has a summary; pass it")]
+    SummaryMissing,
+    #[msg("Pass the lock's vault account")]
+    VaultAccountMissing,
+    #[msg("Vault authority is no longer the lock")]
+    VaultAuthorityChanged,
+    #[msg("Basket already holds its maximum number of mints")]
+    BasketFull,
+    #[msg("Mint is already in the basket")]
+    BasketMintExists,
+    #[msg("Pass a vault, user and treasury account for every basket mint")]
+    BasketAccountsMismatch,
+    #[msg("Lock has a rent recipient; pass it")]
+    RentRecipientMissing,
+    #[msg("Vault has been migrated; pass its vault authority")]
+    VaultAuthorityMissing,
+    #[msg("Withdrawals from this lock must go to its pinned destination")]
+    DestinationLocked,
+    #[msg("Batch accounts must come in complete groups")]
+    BatchMalformed,
+    #[msg("Set exactly one of an unlock timestamp or an unlock slot")]
+    InvalidUnlockMode,
+    #[msg("Quorum must list distinct signers with a threshold between 1 and their count")]
+    InvalidQuorum,
+    #[msg("Not enough designated signers approved the release")]
+    ThresholdNotMet,
+    #[msg("Lock has a notify program; pass it and its allow-list entry")]
+    NotifyProgramMissing,
+    #[msg("Notify program is not on the allow-list")]
+    NotifyProgramNotAllowed,
+    #[msg("Notify program rejected the withdrawal notice")]
+    NotifyFailed,
+    #[msg("Partial withdrawal must be a multiple of the lock's withdraw step")]
+    NotStepAligned,
+    #[msg("Unknown lock category")]
+    InvalidCategory,
+    #[msg("Lock's condition account is missing or not yet released")]
+    ConditionNotMet,
+    #[msg("Lock already has a condition account")]
+    ConditionAlreadySet,
+    #[msg("Fee would leave the treasury below rent-exemption")]
+    TreasuryNotRentExempt,
+    #[msg("Account is not this lock's beneficiary")]
+    BeneficiaryMismatch,
+    #[msg("Lock has used all of its partial withdrawals")]
+    MaxWithdrawalsReached,
+    #[msg("Lock's charity grace period has not elapsed")]
+    NotAbandoned,
+    #[msg("Account is not this lock's charity fallback")]
+    CharityMismatch,
+    #[msg("Withdrawals are frozen for maintenance")]
+    WithdrawalsFrozen,
+    #[msg("A new withdrawal freeze can't start until the last one's cooldown has passed")]
+    FreezeCooldown,
+    #[msg("Signer is neither the lock's owner nor its withdraw delegate")]
+    NotAuthorized,
+    #[msg("Lock is not opted into auto-release")]
+    AutoReleaseNotEnabled,
+    #[msg("Lock's vault still holds tokens from an earlier initialization")]
+    AlreadyInitialized,
+    #[msg("Account is not this lock's arbiter")]
+    NotArbiter,
+    #[msg("The lock's arbiter must sign")]
+    MissingArbiterSignature,
+    #[msg("Reward pool account is required for this operation")]
+    RewardPoolMissing,
+    #[msg("Account is not the reward pool for this mint")]
+    RewardPoolMismatch,
+    #[msg("Token account no longer delegates the locked amount to the lock")]
+    DelegationRevoked,
+    #[msg("Lock deposit has not settled for the required number of slots yet")]
+    NotYetSettled,
+    #[msg("Invalid contribution schedule")]
+    InvalidContributionSchedule,
+    #[msg("The next scheduled contribution is not due yet")]
+    ContributionNotDue,
+    #[msg("Every scheduled contribution has already been made or missed")]
+    ContributionScheduleComplete,
+    #[msg("The lock's authorities were renounced")]
+    AuthorityRenounced,
+    #[msg("No beneficiary change is pending")]
+    NoPendingBeneficiary,
+    #[msg("The pending beneficiary change has not taken effect yet")]
+    BeneficiaryChangeNotDue,
+    #[msg("The lock already uses another release mode")]
+    ReleaseModeConflict,
+    #[msg("Lock amount exceeds the configured maximum lock size")]
+    ExceedsMaxLockSize,
+    #[msg("initialize_lock_sol must be followed by a fund_sol_lock for the same lock")]
+    MissingFundingInstruction,
+    #[msg("A split withdrawal needs exactly one destination account per amount")]
+    SplitMismatch,
+    #[msg("Split amounts exceed what the lock pays out after fees")]
+    SplitExceedsPayout,
+    #[msg("Lock amount or unlock time differs from what was expected")]
+    ParamsMismatch,
+    #[msg("Fee split entries must name a recipient and add up to 10000 bps")]
+    InvalidFeeSplit,
+    #[msg("Fee split recipients missing or out of order in the remaining accounts")]
+    FeeRecipientMismatch,
+    #[msg("A lock can't name its own initializer as referrer")]
+    SelfReferral,
+    #[msg("Lock is password protected; claim it with claim_with_password")]
+    PasswordRequired,
+    #[msg("Lock already has a password")]
+    PasswordAlreadySet,
+    #[msg("Lock has no password")]
+    NoPassword,
+    #[msg("Preimage does not hash to the lock's password")]
+    WrongPassword,
+    #[msg("Vault token account is frozen by the mint's freeze authority")]
+    VaultFrozen,
+    #[msg("A deposit fee refund needs a token account for the lock's mint to go to")]
+    RefundDestinationMissing,
+    #[msg("Lock is still owed a deposit fee refund from its current mint's reward pool")]
+    DepositFeeOutstanding,
 }
\ No newline at end of file
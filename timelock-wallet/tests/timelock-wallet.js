@@ -1,13 +1,5175 @@
 const anchor = require("@coral-xyz/anchor");
+const { assert } = require("chai");
+const { createHash } = require("crypto");
+const {
+  closeAccount,
+  createAccount,
+  createMint,
+  freezeAccount,
+  getAccount,
+  getAssociatedTokenAddressSync,
+  getOrCreateAssociatedTokenAccount,
+  mintTo,
+  revoke,
+  thawAccount,
+  transfer,
+  TOKEN_PROGRAM_ID,
+  ASSOCIATED_TOKEN_PROGRAM_ID,
+} = require("@solana/spl-token");
+
+const { BN } = anchor;
+const { Keypair, LAMPORTS_PER_SOL, PublicKey, SystemProgram } = anchor.web3;
+
+const ZERO_COMMITMENT = Array(32).fill(0);
+// Loaded by Anchor.toml from tests/fixtures/stale_price_update.json.
+const STALE_PRICE_FEED = new anchor.web3.PublicKey(
+  "riYno76RNEyZxVz79UqbsGKCPfujgkLTxLx2HNfH6Pe"
+);
 
 describe("timelock-wallet", () => {
   // Configure the client to use the local cluster.
-  anchor.setProvider(anchor.AnchorProvider.env());
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.timelockWallet;
+  const connection = provider.connection;
+  const payer = provider.wallet.payer;
+
+  const pda = (...seeds) =>
+    PublicKey.findProgramAddressSync(seeds, program.programId)[0];
+  const configPda = pda(Buffer.from("config"));
+  const statsPda = pda(Buffer.from("stats"));
+  const solLockPda = (user) =>
+    pda(Buffer.from("time-lock-sol"), user.toBuffer());
+  const splLockPda = (user) =>
+    pda(Buffer.from("time-lock-spl"), user.toBuffer());
+  const safePda = (user) =>
+    pda(Buffer.from("time-lock-safe"), user.toBuffer());
+  const savingsPda = (user) =>
+    pda(Buffer.from("time-lock-savings"), user.toBuffer());
+  const basketPda = (user) =>
+    pda(Buffer.from("time-lock-basket"), user.toBuffer());
+  const summaryPda = (lockAccount) =>
+    pda(Buffer.from("summary"), lockAccount.toBuffer());
+  const vaultAuthorityPda = (lockAccount) =>
+    pda(Buffer.from("vault-authority"), lockAccount.toBuffer());
+
+  // Set when the program was built with the `strict-init` feature, where initializing an
+  // existing lock always fails; tests that re-run init only apply without it.
+  const STRICT_INIT = Boolean(process.env.STRICT_INIT);
+  const itReinit = STRICT_INIT ? it.skip : it;
+
+  const sleep = (ms) => new Promise((resolve) => setTimeout(resolve, ms));
+  const now = async () => {
+    const slot = await connection.getSlot();
+    return connection.getBlockTime(slot);
+  };
+
+  // Wait until the cluster clock has reached `timestamp`.
+  async function waitUntil(timestamp) {
+    while ((await now()) < timestamp) {
+      await sleep(200);
+    }
+  }
+
+  async function newUser(sol = 5) {
+    const user = Keypair.generate();
+    const sig = await connection.requestAirdrop(
+      user.publicKey,
+      sol * LAMPORTS_PER_SOL
+    );
+    await connection.confirmTransaction(sig, "confirmed");
+    return user;
+  }
+
+  const fetchTx = (signature) =>
+    connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+
+  // Compute units per instruction, printed once the suite finishes.
+  const computeUnits = {};
+  async function recordComputeUnits(name, signature) {
+    const tx = await fetchTx(signature);
+    computeUnits[name] = tx.meta.computeUnitsConsumed;
+  }
+
+  async function eventsOf(signature) {
+    const tx = await fetchTx(signature);
+    const parser = new anchor.EventParser(program.programId, program.coder);
+    return Array.from(parser.parseLogs(tx.meta.logMessages));
+  }
+
+  async function expectError(promise, code) {
+    try {
+      await promise;
+    } catch (err) {
+      assert.include(String(err), code);
+      return;
+    }
+    assert.fail(`expected ${code}`);
+  }
+
+  // Mint `amount` of a fresh 6-decimal token to `user` and return the mint and user ATA.
+  async function fundedMint(user, amount) {
+    const mint = await createMint(connection, payer, payer.publicKey, null, 6);
+    const ata = await getOrCreateAssociatedTokenAccount(
+      connection,
+      payer,
+      mint,
+      user.publicKey
+    );
+    await mintTo(connection, payer, mint, ata.address, payer, amount);
+    return { mint, userAta: ata.address };
+  }
+
+  // Initialize and fund a SOL lock in one transaction, as the frontend does.
+  async function initSolLock(
+    user,
+    amount,
+    unlockTimestamp,
+    {
+      fundAmount = amount,
+      trialSeconds = 0,
+      streamPeriods = 0,
+      streamPeriodSecs = 0,
+      invite = null,
+      roundToSeconds = 0,
+      unlockSlot = null,
+      quorumSigners = [],
+      quorumThreshold = 0,
+      category = 0,
+      referrer = null,
+    } = {}
+  ) {
+    const lockAccount = solLockPda(user.publicKey);
+    const initIx = await program.methods
+      .initializeLockSol(
+        new BN(amount),
+        new BN(unlockTimestamp),
+        ZERO_COMMITMENT,
+        false,
+        new BN(trialSeconds),
+        streamPeriods,
+        new BN(streamPeriodSecs),
+        new BN(roundToSeconds),
+        unlockSlot === null ? null : new BN(unlockSlot),
+        quorumSigners,
+        quorumThreshold,
+        category,
+        referrer
+      )
+      .accountsPartial({
+        initializer: user.publicKey,
+        lockAccount,
+        config: configPda,
+        stats: statsPda,
+        lockSummary: null,
+        invite,
+        systemProgram: SystemProgram.programId,
+      })
+      .instruction();
+    const signature = await program.methods
+      .fundSolLock(new BN(fundAmount))
+      .accountsPartial({
+        initializer: user.publicKey,
+        lockAccount,
+        config: configPda,
+        stats: statsPda,
+        lockSummary: null,
+        systemProgram: SystemProgram.programId,
+      })
+      .preInstructions([initIx])
+      .signers([user])
+      .rpc();
+    return { lockAccount, signature };
+  }
+
+  const treasury = async () =>
+    (await program.account.config.fetch(configPda)).treasury;
+
+  // withdraw_sol and revoke_within_trial share the same accounts.
+  async function closeSolLock(
+    method,
+    user,
+    lockAccount = solLockPda(user.publicKey),
+    savingsLock = null
+  ) {
+    return program.methods[method]()
+      .accountsPartial({
+        authority: user.publicKey,
+        initializer: user.publicKey,
+        lockAccount,
+        savingsLock,
+        config: configPda,
+        stats: statsPda,
+        lockSummary: null,
+        condition: null,
+        rentRecipient: null,
+        notifyProgram: null,
+        allowedNotifyProgram: null,
+        treasury: await treasury(),
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([user])
+      .rpc();
+  }
+
+  const withdrawSol = (user, lockAccount) =>
+    closeSolLock("withdrawSol", user, lockAccount);
+
+  async function initSplLock(
+    user,
+    mint,
+    userAta,
+    amount,
+    unlockTimestamp,
+    {
+      stopLossOracle = null,
+      stopLossPrice = 0,
+      invite = null,
+      vestingPeriods = 0,
+      vestingPeriodSecs = 0,
+      roundToSeconds = 0,
+      unlockSlot = null,
+      category = 0,
+      rewardPoolAta = null,
+      referrer = null,
+    } = {}
+  ) {
+    const lockAccount = splLockPda(user.publicKey);
+    const vaultAta = getAssociatedTokenAddressSync(mint, lockAccount, true);
+    await program.methods
+      .initializeLockSpl(
+        new BN(amount),
+        new BN(unlockTimestamp),
+        ZERO_COMMITMENT,
+        false,
+        stopLossOracle,
+        new BN(stopLossPrice),
+        vestingPeriods,
+        new BN(vestingPeriodSecs),
+        new BN(roundToSeconds),
+        unlockSlot === null ? null : new BN(unlockSlot),
+        category,
+        referrer
+      )
+      .accountsPartial({
+        initializer: user.publicKey,
+        lockAccount,
+        mint,
+        userAta,
+        vaultAta,
+        config: configPda,
+        stats: statsPda,
+        lockSummary: null,
+        invite,
+        rewardPoolAta,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([user])
+      .rpc();
+    return { lockAccount, vaultAta };
+  }
+
+  function withdrawSpl(
+    user,
+    mint,
+    userAta,
+    lockAccount,
+    vaultAta,
+    priceFeed = null,
+    treasuryAta = null,
+    vaultAuthority = null,
+    rewardPoolAta = null,
+    rewardPoolAuthority = null
+  ) {
+    return program.methods
+      .withdrawSpl()
+      .accountsPartial({
+        authority: user.publicKey,
+        initializer: user.publicKey,
+        lockAccount,
+        mint,
+        userAta,
+        vaultAta,
+        vaultAuthority,
+        priceFeed,
+        config: configPda,
+        treasuryAta,
+        stats: statsPda,
+        lockSummary: null,
+        condition: null,
+        notifyProgram: null,
+        allowedNotifyProgram: null,
+        rewardPoolAta,
+        rewardPoolAuthority,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([user])
+      .rpc();
+  }
+
+  function closeSplLock(user, mint, lockAccount, vaultAta, vaultAuthority = null) {
+    return program.methods
+      .closeSplLock()
+      .accountsPartial({
+        initializer: user.publicKey,
+        lockAccount,
+        mint,
+        vaultAta,
+        vaultAuthority,
+        stats: statsPda,
+        lockSummary: null,
+        rentRecipient: null,
+        rewardPoolAta: null,
+        rewardPoolAuthority: null,
+        userAta: null,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([user])
+      .rpc();
+  }
+
+  before(async () => {
+    // The config is a singleton; reuse it if an earlier run already created it.
+    if ((await connection.getAccountInfo(configPda)) === null) {
+      await program.methods
+        .initializeConfig(new BN(1_000 * LAMPORTS_PER_SOL))
+        .accountsPartial({
+          admin: payer.publicKey,
+          config: configPda,
+          stats: statsPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+    }
+  });
+
+  after(() => {
+    console.table(computeUnits);
+  });
+
+  describe("SOL locks", () => {
+    it("locks, funds and withdraws SOL after unlock", async () => {
+      const user = await newUser();
+      const amount = LAMPORTS_PER_SOL;
+      const unlock = (await now()) + 2;
+      const { lockAccount, signature } = await initSolLock(user, amount, unlock);
+      await recordComputeUnits("initialize_lock_sol + fund_sol_lock", signature);
+
+      const lock = await program.account.timeLockAccount.fetch(lockAccount);
+      assert.equal(lock.amount.toNumber(), amount);
+      assert.equal(lock.unlockTimestamp.toNumber(), unlock);
+      const statsBefore = await program.account.stats.fetch(statsPda);
+
+      await sleep(3_000);
+      const before = await connection.getBalance(user.publicKey);
+      const sig = await withdrawSol(user);
+      await recordComputeUnits("withdraw_sol", sig);
+
+      assert.isNull(await connection.getAccountInfo(lockAccount));
+      assert.isAbove(
+        await connection.getBalance(user.publicKey),
+        before + amount - 10_000
+      );
+      const statsAfter = await program.account.stats.fetch(statsPda);
+      assert.equal(
+        statsBefore.totalSolLocked.sub(statsAfter.totalSolLocked).toNumber(),
+        amount
+      );
+    });
+
+    it("rejects withdrawing SOL before unlock", async () => {
+      const user = await newUser();
+      await initSolLock(user, LAMPORTS_PER_SOL, (await now()) + 3_600);
+
+      await expectError(withdrawSol(user), "TimeLockNotExpired");
+    });
+
+    it("rejects funding a different amount than declared", async () => {
+      const user = await newUser();
+
+      await expectError(
+        initSolLock(user, LAMPORTS_PER_SOL, (await now()) + 3_600, {
+          fundAmount: LAMPORTS_PER_SOL / 2,
+        }),
+        "FundingMismatch"
+      );
+    });
+
+    it("rejects initializing a SOL lock without funding it in the same transaction", async () => {
+      const user = await newUser();
+      await expectError(
+        program.methods
+          .initializeLockSol(
+            new BN(LAMPORTS_PER_SOL),
+            new BN((await now()) + 2),
+            ZERO_COMMITMENT,
+            false,
+            new BN(0),
+            0,
+            new BN(0),
+            new BN(0),
+            null,
+            [],
+            0,
+            0,
+            null
+          )
+          .accountsPartial({
+            initializer: user.publicKey,
+            lockAccount: solLockPda(user.publicKey),
+            config: configPda,
+            stats: statsPda,
+            lockSummary: null,
+            invite: null,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([user])
+          .rpc(),
+        "MissingFundingInstruction"
+      );
+      assert.isNull(await connection.getAccountInfo(solLockPda(user.publicKey)));
+    });
+
+    function topupAndExtend(user, lamports, unlockTimestamp) {
+      return program.methods
+        .topupAndExtendSol(new BN(lamports), new BN(unlockTimestamp))
+        .accountsPartial({
+          initializer: user.publicKey,
+          lockAccount: solLockPda(user.publicKey),
+          config: configPda,
+          stats: statsPda,
+          lockSummary: null,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+    }
+
+    it("tops up and extends a lock in one instruction", async () => {
+      const user = await newUser();
+      const unlock = (await now()) + 3_600;
+      const { lockAccount } = await initSolLock(user, LAMPORTS_PER_SOL, unlock);
+
+      const sig = await topupAndExtend(user, LAMPORTS_PER_SOL / 2, unlock + 600);
+
+      const lock = await program.account.timeLockAccount.fetch(lockAccount);
+      assert.equal(lock.amount.toNumber(), 1.5 * LAMPORTS_PER_SOL);
+      assert.equal(lock.unlockTimestamp.toNumber(), unlock + 600);
+      const [event] = await eventsOf(sig);
+      assert.equal(event.name, "solToppedUpAndExtended");
+      assert.equal(event.data.oldUnlockTimestamp.toNumber(), unlock);
+    });
+
+    it("rejects a top-up that would shorten the lock", async () => {
+      const user = await newUser();
+      const unlock = (await now()) + 3_600;
+      const { lockAccount } = await initSolLock(user, LAMPORTS_PER_SOL, unlock);
+      const before = await connection.getBalance(lockAccount);
+
+      await expectError(
+        topupAndExtend(user, LAMPORTS_PER_SOL / 2, unlock - 60),
+        "CannotShortenLock"
+      );
+      assert.equal(await connection.getBalance(lockAccount), before);
+    });
+
+    it("refunds and closes a lock revoked within its trial window", async () => {
+      const user = await newUser();
+      const { lockAccount } = await initSolLock(
+        user,
+        LAMPORTS_PER_SOL,
+        (await now()) + 3_600,
+        { trialSeconds: 600 }
+      );
+
+      const before = await connection.getBalance(user.publicKey);
+      await closeSolLock("revokeWithinTrial", user);
+
+      assert.isNull(await connection.getAccountInfo(lockAccount));
+      assert.isAbove(
+        await connection.getBalance(user.publicKey),
+        before + LAMPORTS_PER_SOL - 10_000
+      );
+    });
+
+    it("rejects revoking once the trial window has passed", async () => {
+      const user = await newUser();
+      await initSolLock(user, LAMPORTS_PER_SOL, (await now()) + 3_600, {
+        trialSeconds: 1,
+      });
+      await sleep(3_000);
+
+      await expectError(closeSolLock("revokeWithinTrial", user), "TrialExpired");
+    });
+
+    it("rejects an SPL lock passed as a SOL lock", async () => {
+      const user = await newUser();
+      const { mint, userAta } = await fundedMint(user, 100);
+      const { lockAccount } = await initSplLock(
+        user,
+        mint,
+        userAta,
+        100,
+        (await now()) + 2
+      );
+      await sleep(3_000);
+
+      // Lock PDAs are seeded by asset kind, so the seeds check fires first.
+      await expectError(withdrawSol(user, lockAccount), "ConstraintSeeds");
+    });
+  });
+
+  describe("SPL locks", () => {
+    it("locks and withdraws SPL tokens after unlock", async () => {
+      const user = await newUser();
+      const { mint, userAta } = await fundedMint(user, 1_000);
+      const unlock = (await now()) + 2;
+      const { lockAccount, vaultAta } = await initSplLock(
+        user,
+        mint,
+        userAta,
+        400,
+        unlock
+      );
+      assert.equal(Number((await getAccount(connection, vaultAta)).amount), 400);
+      assert.equal(Number((await getAccount(connection, userAta)).amount), 600);
+
+      await sleep(3_000);
+      const sig = await withdrawSpl(user, mint, userAta, lockAccount, vaultAta);
+      await recordComputeUnits("withdraw_spl", sig);
+
+      assert.equal(Number((await getAccount(connection, vaultAta)).amount), 0);
+      assert.equal(Number((await getAccount(connection, userAta)).amount), 1_000);
+    });
+
+    it("recreates a user ATA closed while the tokens were locked", async () => {
+      const user = await newUser();
+      const { mint, userAta } = await fundedMint(user, 100);
+      const { lockAccount, vaultAta } = await initSplLock(
+        user,
+        mint,
+        userAta,
+        100,
+        (await now()) + 2
+      );
+      await closeAccount(connection, payer, userAta, user.publicKey, user);
+      assert.isNull(await connection.getAccountInfo(userAta));
+
+      await sleep(3_000);
+      await withdrawSpl(user, mint, userAta, lockAccount, vaultAta);
+      assert.equal(Number((await getAccount(connection, userAta)).amount), 100);
+    });
+
+    it("closes the vault and lock account after withdrawal", async () => {
+      const user = await newUser();
+      const { mint, userAta } = await fundedMint(user, 100);
+      const { lockAccount, vaultAta } = await initSplLock(
+        user,
+        mint,
+        userAta,
+        100,
+        (await now()) + 2
+      );
+      await sleep(3_000);
+      await withdrawSpl(user, mint, userAta, lockAccount, vaultAta);
+
+      const rent =
+        (await connection.getBalance(lockAccount)) +
+        (await connection.getBalance(vaultAta));
+      const before = await connection.getBalance(user.publicKey);
+      await closeSplLock(user, mint, lockAccount, vaultAta);
+
+      assert.isNull(await connection.getAccountInfo(lockAccount));
+      assert.isNull(await connection.getAccountInfo(vaultAta));
+      assert.isAbove(
+        await connection.getBalance(user.publicKey),
+        before + rent - 10_000
+      );
+    });
+
+    it("refuses to close a lock whose vault still holds tokens", async () => {
+      const user = await newUser();
+      const { mint, userAta } = await fundedMint(user, 100);
+      const { lockAccount, vaultAta } = await initSplLock(
+        user,
+        mint,
+        userAta,
+        100,
+        (await now()) + 3_600
+      );
+
+      await expectError(
+        closeSplLock(user, mint, lockAccount, vaultAta),
+        "VaultNotEmpty"
+      );
+      assert.equal(Number((await getAccount(connection, vaultAta)).amount), 100);
+      assert.isNotNull(await connection.getAccountInfo(lockAccount));
+    });
+
+    it("rejects withdrawing SPL tokens before unlock", async () => {
+      const user = await newUser();
+      const { mint, userAta } = await fundedMint(user, 100);
+      const { lockAccount, vaultAta } = await initSplLock(
+        user,
+        mint,
+        userAta,
+        100,
+        (await now()) + 3_600
+      );
+
+      await expectError(
+        withdrawSpl(user, mint, userAta, lockAccount, vaultAta),
+        "TimeLockNotExpired"
+      );
+    });
+
+    it("rejects withdrawing from an empty vault", async () => {
+      const user = await newUser();
+      const { mint, userAta } = await fundedMint(user, 100);
+      const { lockAccount, vaultAta } = await initSplLock(
+        user,
+        mint,
+        userAta,
+        100,
+        (await now()) + 2
+      );
+      await sleep(3_000);
+      await withdrawSpl(user, mint, userAta, lockAccount, vaultAta);
+
+      await expectError(
+        withdrawSpl(user, mint, userAta, lockAccount, vaultAta),
+        "InsufficientVaultBalance"
+      );
+    });
+
+    it("rejects withdrawing with a mint other than the locked one", async () => {
+      const user = await newUser();
+      const locked = await fundedMint(user, 100);
+      const { lockAccount } = await initSplLock(
+        user,
+        locked.mint,
+        locked.userAta,
+        100,
+        (await now()) + 2
+      );
+      const other = await fundedMint(user, 100);
+      const otherVault = await getOrCreateAssociatedTokenAccount(
+        connection,
+        payer,
+        other.mint,
+        lockAccount,
+        true
+      );
+      await transfer(connection, payer, other.userAta, otherVault.address, user, 100);
+      await sleep(3_000);
+
+      await expectError(
+        withdrawSpl(user, other.mint, other.userAta, lockAccount, otherVault.address),
+        "MintMismatch"
+      );
+    });
+
+    itReinit("rejects re-initializing a lock with a different mint", async () => {
+      const user = await newUser();
+      const first = await fundedMint(user, 100);
+      await initSplLock(user, first.mint, first.userAta, 100, (await now()) + 3_600);
+      const second = await fundedMint(user, 100);
+
+      await expectError(
+        initSplLock(user, second.mint, second.userAta, 100, (await now()) + 3_600),
+        "MintMismatch"
+      );
+    });
+
+    itReinit("rejects re-initializing a lock whose vault still holds tokens", async () => {
+      const user = await newUser();
+      const { mint, userAta } = await fundedMint(user, 200);
+      const { lockAccount, vaultAta } = await initSplLock(
+        user,
+        mint,
+        userAta,
+        100,
+        (await now()) + 3_600
+      );
+      await sleep(1_000);
+
+      await expectError(
+        initSplLock(user, mint, userAta, 100, (await now()) + 3_600),
+        "AlreadyInitialized"
+      );
+      assert.equal(Number((await getAccount(connection, vaultAta)).amount), 100);
+      const lock = await program.account.timeLockAccount.fetch(lockAccount);
+      assert.equal(lock.amount.toNumber(), 100);
+    });
+
+    itReinit("rejects re-initializing a SOL lock that still holds lamports", async () => {
+      const user = await newUser();
+      const unlock = (await now()) + 3_600;
+      const { lockAccount } = await initSolLock(user, LAMPORTS_PER_SOL, unlock);
+      await sleep(1_000);
+
+      await expectError(
+        initSolLock(user, LAMPORTS_PER_SOL + 1, (await now()) + 1),
+        "AlreadyInitialized"
+      );
+      const lock = await program.account.timeLockAccount.fetch(lockAccount);
+      assert.equal(lock.amount.toNumber(), LAMPORTS_PER_SOL);
+      assert.equal(lock.unlockTimestamp.toNumber(), unlock);
+    });
+  });
+
+  describe("withdrawal fees", () => {
+    const noTier = { maxDurationSecs: new BN(0), feeBps: 0 };
+    const tier = (maxDurationSecs, feeBps) => ({
+      maxDurationSecs: new BN(maxDurationSecs),
+      feeBps,
+    });
+    let feeTreasury;
+
+    function setFeeSchedule(treasuryKey, tiers) {
+      return program.methods
+        .setFeeSchedule(treasuryKey, tiers)
+        .accountsPartial({ admin: payer.publicKey, config: configPda })
+        .rpc();
+    }
+
+    before(async () => {
+      // A funded wallet, so small fee credits keep it rent exempt.
+      feeTreasury = await newUser(1);
+      await setFeeSchedule(feeTreasury.publicKey, [
+        tier(4, 100),
+        tier(7, 50),
+        noTier,
+        noTier,
+      ]);
+    });
+
+    after(async () => {
+      await setFeeSchedule(payer.publicKey, [noTier, noTier, noTier, noTier]);
+    });
+
+    async function solFeeFor(lockSeconds) {
+      const user = await newUser();
+      const unlock = (await now()) + lockSeconds;
+      await initSolLock(user, LAMPORTS_PER_SOL, unlock);
+      await waitUntil(unlock);
+      const before = await connection.getBalance(feeTreasury.publicKey);
+      await withdrawSol(user);
+      return (await connection.getBalance(feeTreasury.publicKey)) - before;
+    }
+
+    it("adds SOL fees to the protocol stats", async () => {
+      const before = await program.account.stats.fetch(statsPda);
+      const fee = await solFeeFor(2);
+      const after = await program.account.stats.fetch(statsPda);
+      assert.equal(
+        after.totalFeesCollected.sub(before.totalFeesCollected).toNumber(),
+        fee
+      );
+    });
+
+    it("charges the shortest tier's fee on SOL locks", async () => {
+      assert.equal(await solFeeFor(2), LAMPORTS_PER_SOL / 100);
+    });
+
+    it("charges the next tier once the duration passes the first", async () => {
+      assert.equal(await solFeeFor(5), LAMPORTS_PER_SOL / 200);
+    });
+
+    it("charges nothing beyond the last tier", async () => {
+      assert.equal(await solFeeFor(9), 0);
+    });
+
+    describe("fee split", () => {
+      const unused = { recipient: PublicKey.default, bps: 0 };
+      const setFeeSplit = (entries) =>
+        program.methods
+          .setFeeSplit(entries)
+          .accountsPartial({ admin: payer.publicKey, config: configPda })
+          .rpc();
+
+      after(() => setFeeSplit([unused, unused, unused, unused]));
+
+      it("rejects a split that doesn't add up to 100%", async () => {
+        await expectError(
+          setFeeSplit([
+            { recipient: Keypair.generate().publicKey, bps: 6_000 },
+            { recipient: Keypair.generate().publicKey, bps: 3_000 },
+            unused,
+            unused,
+          ]),
+          "InvalidFeeSplit"
+        );
+      });
+
+      it("divides a SOL fee between the recipients", async () => {
+        const [dao, referrer] = [await newUser(1), await newUser(1)];
+        await setFeeSplit([
+          { recipient: dao.publicKey, bps: 6_000 },
+          { recipient: referrer.publicKey, bps: 4_000 },
+          unused,
+          unused,
+        ]);
+        const user = await newUser();
+        const unlock = (await now()) + 2;
+        await initSolLock(user, LAMPORTS_PER_SOL, unlock);
+        await waitUntil(unlock);
+        const withdraw = async (recipients) =>
+          program.methods
+            .withdrawSol()
+            .accountsPartial({
+              authority: user.publicKey,
+              initializer: user.publicKey,
+              lockAccount: solLockPda(user.publicKey),
+              savingsLock: null,
+              config: configPda,
+              stats: statsPda,
+              lockSummary: null,
+              condition: null,
+              rentRecipient: null,
+              notifyProgram: null,
+              allowedNotifyProgram: null,
+              treasury: await treasury(),
+              systemProgram: SystemProgram.programId,
+            })
+            .remainingAccounts(
+              recipients.map((pubkey) => ({ pubkey, isSigner: false, isWritable: true }))
+            )
+            .signers([user])
+            .rpc();
+
+        await expectError(
+          withdraw([referrer.publicKey, dao.publicKey]),
+          "FeeRecipientMismatch"
+        );
+        const before = await Promise.all(
+          [dao, referrer, feeTreasury].map((wallet) => connection.getBalance(wallet.publicKey))
+        );
+        await withdraw([dao.publicKey, referrer.publicKey]);
+        const after = await Promise.all(
+          [dao, referrer, feeTreasury].map((wallet) => connection.getBalance(wallet.publicKey))
+        );
+        // 1% of 1 SOL, split 60/40; the treasury gets nothing.
+        assert.equal(after[0] - before[0], (LAMPORTS_PER_SOL / 100) * 0.6);
+        assert.equal(after[1] - before[1], (LAMPORTS_PER_SOL / 100) * 0.4);
+        assert.equal(after[2], before[2]);
+      });
+    });
+
+    it("previews exactly what the SOL withdrawal pays", async () => {
+      const user = await newUser();
+      const unlock = (await now()) + 2;
+      const { lockAccount } = await initSolLock(user, LAMPORTS_PER_SOL, unlock);
+      const preview = async () => {
+        const signature = await program.methods
+          .previewWithdrawal()
+          .accountsPartial({ lockAccount, vaultAta: null, config: configPda })
+          .rpc();
+        return (await eventsOf(signature))[0].data;
+      };
+
+      const early = await preview();
+      assert.isFalse(early.unlocked);
+      assert.equal(early.gross.toNumber(), LAMPORTS_PER_SOL);
+
+      await waitUntil(unlock);
+      const quote = await preview();
+      assert.isTrue(quote.unlocked);
+      assert.equal(quote.penalty.toNumber(), 0);
+      assert.equal(quote.fee.toNumber() + quote.net.toNumber(), quote.gross.toNumber());
+      const before = await connection.getBalance(feeTreasury.publicKey);
+      await withdrawSol(user);
+      assert.equal(
+        (await connection.getBalance(feeTreasury.publicKey)) - before,
+        quote.fee.toNumber()
+      );
+    });
+
+    it("sends the SPL fee to the treasury token account", async () => {
+      const user = await newUser();
+      const { mint, userAta } = await fundedMint(user, 10_000);
+      const unlock = (await now()) + 2;
+      const { lockAccount, vaultAta } = await initSplLock(
+        user,
+        mint,
+        userAta,
+        10_000,
+        unlock
+      );
+      const treasuryAta = await getOrCreateAssociatedTokenAccount(
+        connection,
+        payer,
+        mint,
+        feeTreasury.publicKey
+      );
+      await waitUntil(unlock);
+
+      await expectError(
+        withdrawSpl(user, mint, userAta, lockAccount, vaultAta),
+        "TreasuryAccountMissing"
+      );
+      await withdrawSpl(
+        user,
+        mint,
+        userAta,
+        lockAccount,
+        vaultAta,
+        null,
+        treasuryAta.address
+      );
+      assert.equal(
+        Number((await getAccount(connection, treasuryAta.address)).amount),
+        100
+      );
+      assert.equal(Number((await getAccount(connection, userAta)).amount), 9_900);
+    });
+
+    it("refuses a fee that leaves an empty treasury below rent", async () => {
+      const emptyTreasury = Keypair.generate().publicKey;
+      await setFeeSchedule(emptyTreasury, [tier(4, 100), noTier, noTier, noTier]);
+      try {
+        const user = await newUser();
+        const unlock = (await now()) + 2;
+        // 1% of 0.01 SOL is far below a bare account's rent minimum.
+        const { lockAccount } = await initSolLock(
+          user,
+          LAMPORTS_PER_SOL / 100,
+          unlock
+        );
+        await waitUntil(unlock);
+        const before = await connection.getBalance(lockAccount);
+
+        await expectError(withdrawSol(user), "TreasuryNotRentExempt");
+        assert.equal(await connection.getBalance(lockAccount), before);
+        assert.equal(await connection.getBalance(emptyTreasury), 0);
+      } finally {
+        await setFeeSchedule(feeTreasury.publicKey, [
+          tier(4, 100),
+          tier(7, 50),
+          noTier,
+          noTier,
+        ]);
+      }
+    });
+
+    it("rejects tiers above the fee cap", async () => {
+      await expectError(
+        setFeeSchedule(feeTreasury.publicKey, [
+          tier(4, 501),
+          noTier,
+          noTier,
+          noTier,
+        ]),
+        "InvalidFeeSchedule"
+      );
+    });
+
+    it("rejects unordered tiers", async () => {
+      await expectError(
+        setFeeSchedule(feeTreasury.publicKey, [
+          tier(7, 50),
+          tier(4, 100),
+          noTier,
+          noTier,
+        ]),
+        "InvalidFeeSchedule"
+      );
+    });
+  });
+
+  describe("unlock boundaries", () => {
+    it("rejects creating a lock that unlocks in the current second", async () => {
+      const user = await newUser();
+
+      await expectError(
+        initSolLock(user, LAMPORTS_PER_SOL, await now()),
+        "UnlockInPast"
+      );
+    });
+
+    it("withdraws SOL as soon as the unlock second is reached", async () => {
+      const user = await newUser();
+      const unlock = (await now()) + 1;
+      const { lockAccount } = await initSolLock(user, LAMPORTS_PER_SOL, unlock);
+
+      await waitUntil(unlock);
+      await withdrawSol(user);
+      assert.isNull(await connection.getAccountInfo(lockAccount));
+    });
+
+    it("withdraws SPL tokens as soon as the unlock second is reached", async () => {
+      const user = await newUser();
+      const { mint, userAta } = await fundedMint(user, 100);
+      const unlock = (await now()) + 1;
+      const { lockAccount, vaultAta } = await initSplLock(
+        user,
+        mint,
+        userAta,
+        100,
+        unlock
+      );
+
+      await waitUntil(unlock);
+      await withdrawSpl(user, mint, userAta, lockAccount, vaultAta);
+      assert.equal(Number((await getAccount(connection, userAta)).amount), 100);
+    });
+  });
+
+  describe("combined locks", () => {
+    function initSafe(user, mint, userAta, lamports, tokens, unlockTimestamp) {
+      const lockAccount = safePda(user.publicKey);
+      return program.methods
+        .initializeSafe(new BN(lamports), new BN(tokens), new BN(unlockTimestamp), 0)
+        .accountsPartial({
+          initializer: user.publicKey,
+          lockAccount,
+          mint,
+          userAta,
+          vaultAta: getAssociatedTokenAddressSync(mint, lockAccount, true),
+          config: configPda,
+          stats: statsPda,
+          invite: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+    }
+
+    it("releases both SOL and tokens in one withdrawal", async () => {
+      const user = await newUser();
+      const { mint, userAta } = await fundedMint(user, 500);
+      await initSafe(user, mint, userAta, LAMPORTS_PER_SOL, 500, (await now()) + 2);
+      const lockAccount = safePda(user.publicKey);
+      const vaultAta = getAssociatedTokenAddressSync(mint, lockAccount, true);
+      assert.equal(Number((await getAccount(connection, vaultAta)).amount), 500);
+
+      await sleep(3_000);
+      const before = await connection.getBalance(user.publicKey);
+      await program.methods
+        .withdrawSafe()
+        .accountsPartial({
+          initializer: user.publicKey,
+          lockAccount,
+          mint,
+          userAta,
+          vaultAta,
+          stats: statsPda,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+
+      assert.isNull(await connection.getAccountInfo(lockAccount));
+      assert.isNull(await connection.getAccountInfo(vaultAta));
+      assert.equal(Number((await getAccount(connection, userAta)).amount), 500);
+      assert.isAbove(
+        await connection.getBalance(user.publicKey),
+        before + LAMPORTS_PER_SOL - 10_000
+      );
+    });
+
+    it("rejects a safe with an empty side", async () => {
+      const user = await newUser();
+      const { mint, userAta } = await fundedMint(user, 500);
+
+      await expectError(
+        initSafe(user, mint, userAta, LAMPORTS_PER_SOL, 0, (await now()) + 60),
+        "SafeSideEmpty"
+      );
+    });
+  });
+
+  describe("lock receipts", () => {
+    const receiptMintPda = (lockAccount) =>
+      pda(Buffer.from("receipt"), lockAccount.toBuffer());
+
+    async function mintReceipt(user, lockAccount) {
+      const receiptMint = receiptMintPda(lockAccount);
+      await program.methods
+        .mintLockReceipt()
+        .accountsPartial({
+          initializer: user.publicKey,
+          lockAccount,
+          receiptMint,
+          receiptAta: getAssociatedTokenAddressSync(
+            receiptMint,
+            user.publicKey
+          ),
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+      return receiptMint;
+    }
+
+    it("lets only the receipt holder withdraw", async () => {
+      const user = await newUser();
+      const holder = await newUser(1);
+      const { lockAccount } = await initSolLock(
+        user,
+        LAMPORTS_PER_SOL,
+        (await now()) + 2
+      );
+      const receiptMint = await mintReceipt(user, lockAccount);
+
+      const holderAta = await getOrCreateAssociatedTokenAccount(
+        connection,
+        payer,
+        receiptMint,
+        holder.publicKey
+      );
+      await transfer(
+        connection,
+        payer,
+        getAssociatedTokenAddressSync(receiptMint, user.publicKey),
+        holderAta.address,
+        user,
+        1
+      );
+      await sleep(3_000);
+
+      await expectError(withdrawSol(user, lockAccount), "ReceiptRequired");
+
+      const before = await connection.getBalance(holder.publicKey);
+      await program.methods
+        .withdrawSolWithReceipt()
+        .accountsPartial({
+          holder: holder.publicKey,
+          initializer: user.publicKey,
+          lockAccount,
+          receiptMint,
+          holderReceiptAta: holderAta.address,
+          config: configPda,
+          stats: statsPda,
+          lockSummary: null,
+          condition: null,
+          rentRecipient: null,
+          treasury: await treasury(),
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([holder])
+        .rpc();
+
+      assert.isNull(await connection.getAccountInfo(lockAccount));
+      assert.isAbove(
+        await connection.getBalance(holder.publicKey),
+        before + LAMPORTS_PER_SOL - 10_000
+      );
+      const supply = await connection.getTokenSupply(receiptMint);
+      assert.equal(supply.value.amount, "0");
+    });
+
+    it("mints at most one receipt per lock", async () => {
+      const user = await newUser();
+      const { lockAccount } = await initSolLock(
+        user,
+        LAMPORTS_PER_SOL,
+        (await now()) + 3_600
+      );
+      await mintReceipt(user, lockAccount);
+
+      // The receipt mint PDA already exists, so the init constraint fails first.
+      await expectError(mintReceipt(user, lockAccount), "already in use");
+    });
+  });
+
+  describe("withdraw_spl", () => {
+    it("reports a positive realized delta when the vault grew", async () => {
+      const user = await newUser();
+      const { mint, userAta } = await fundedMint(user, 1_000);
+      const unlock = (await now()) + 2;
+      const { lockAccount, vaultAta } = await initSplLock(
+        user,
+        mint,
+        userAta,
+        100,
+        unlock
+      );
+
+      // Stand-in for a rebase: tokens arriving in the vault outside the program.
+      await transfer(connection, payer, userAta, vaultAta, user, 25);
+
+      await sleep(3_000);
+      const sig = await withdrawSpl(user, mint, userAta, lockAccount, vaultAta);
+
+      const [event] = (await eventsOf(sig)).filter(
+        (e) => e.name === "splWithdrawn"
+      );
+      assert.equal(event.data.amount.toNumber(), 100);
+      assert.equal(event.data.vaultBalance.toNumber(), 125);
+      assert.equal(event.data.realizedDelta.toNumber(), 25);
+      assert.equal(Number((await getAccount(connection, userAta)).amount), 1_000);
+    });
+  });
+
+  // The price-triggered success path needs a fresh Pyth update, which a static fixture can't
+  // provide; these cover everything around it.
+  describe("stop-loss", () => {
+    const stopLoss = { stopLossOracle: STALE_PRICE_FEED, stopLossPrice: 2e8 };
+
+    it("stays locked before unlock when no price feed is passed", async () => {
+      const user = await newUser();
+      const { mint, userAta } = await fundedMint(user, 100);
+      const unlock = (await now()) + 3_600;
+      const { lockAccount, vaultAta } = await initSplLock(
+        user,
+        mint,
+        userAta,
+        100,
+        unlock,
+        stopLoss
+      );
+
+      await expectError(
+        withdrawSpl(user, mint, userAta, lockAccount, vaultAta),
+        "TimeLockNotExpired"
+      );
+    });
+
+    it("rejects a stale oracle price", async () => {
+      const user = await newUser();
+      const { mint, userAta } = await fundedMint(user, 100);
+      const unlock = (await now()) + 3_600;
+      const { lockAccount, vaultAta } = await initSplLock(
+        user,
+        mint,
+        userAta,
+        100,
+        unlock,
+        stopLoss
+      );
+
+      await expectError(
+        withdrawSpl(user, mint, userAta, lockAccount, vaultAta, STALE_PRICE_FEED),
+        "OracleStale"
+      );
+    });
+
+    it("rejects a price feed other than the configured one", async () => {
+      const user = await newUser();
+      const { mint, userAta } = await fundedMint(user, 100);
+      const unlock = (await now()) + 3_600;
+      const { lockAccount, vaultAta } = await initSplLock(
+        user,
+        mint,
+        userAta,
+        100,
+        unlock,
+        { stopLossOracle: Keypair.generate().publicKey, stopLossPrice: 2e8 }
+      );
+
+      await expectError(
+        withdrawSpl(user, mint, userAta, lockAccount, vaultAta, STALE_PRICE_FEED),
+        "OracleMismatch"
+      );
+    });
+
+    it("still unlocks on time without consulting the oracle", async () => {
+      const user = await newUser();
+      const { mint, userAta } = await fundedMint(user, 100);
+      const unlock = (await now()) + 2;
+      const { lockAccount, vaultAta } = await initSplLock(
+        user,
+        mint,
+        userAta,
+        100,
+        unlock,
+        stopLoss
+      );
+
+      await sleep(3_000);
+      await withdrawSpl(user, mint, userAta, lockAccount, vaultAta);
+      assert.equal(Number((await getAccount(connection, userAta)).amount), 100);
+    });
+  });
+
+  describe("USD-denominated SOL locks", () => {
+    function setSolUsdOracle(oracle) {
+      return program.methods
+        .setSolUsdOracle(oracle)
+        .accountsPartial({ admin: payer.publicKey, config: configPda })
+        .rpc();
+    }
+
+    function initUsdLock(user, priceFeed) {
+      return program.methods
+        .initializeLockSolUsd(
+          new BN(50_000),
+          new BN(Math.floor(Date.now() / 1_000) + 3_600),
+          ZERO_COMMITMENT,
+          false,
+          new BN(0),
+          0
+        )
+        .accountsPartial({
+          initializer: user.publicKey,
+          lockAccount: solLockPda(user.publicKey),
+          priceFeed,
+          config: configPda,
+          invite: null,
+          stats: statsPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+    }
+
+    after(() => setSolUsdOracle(PublicKey.default));
+
+    it("is disabled until the admin configures a feed", async () => {
+      await setSolUsdOracle(PublicKey.default);
+      const user = await newUser();
+      await expectError(initUsdLock(user, STALE_PRICE_FEED), "OracleMismatch");
+    });
+
+    it("rejects a stale SOL/USD price", async () => {
+      await setSolUsdOracle(STALE_PRICE_FEED);
+      const user = await newUser();
+      await expectError(initUsdLock(user, STALE_PRICE_FEED), "OracleStale");
+      assert.isNull(await connection.getAccountInfo(solLockPda(user.publicKey)));
+    });
+
+    // The price math itself needs a fresh price update, which a static fixture can't provide.
+  });
+
+  describe("SOL streams", () => {
+    async function claimSolStream(user) {
+      return program.methods
+        .claimSolStream()
+        .accountsPartial({
+          initializer: user.publicKey,
+          lockAccount: solLockPda(user.publicKey),
+          config: configPda,
+          stats: statsPda,
+          lockSummary: null,
+          condition: null,
+          treasury: await treasury(),
+        })
+        .signers([user])
+        .rpc();
+    }
+
+    it("caches the next claim time and marks the stream drained after the last period", async () => {
+      const user = await newUser();
+      const unlock = (await now()) + 2;
+      const { lockAccount } = await initSolLock(user, 300_000_000, unlock, {
+        streamPeriods: 3,
+        streamPeriodSecs: 3,
+      });
+      let lock = await program.account.timeLockAccount.fetch(lockAccount);
+      assert.equal(lock.nextClaimAt.toNumber(), unlock);
+
+      await waitUntil(unlock);
+      let [event] = await eventsOf(await claimSolStream(user));
+      assert.equal(event.name, "solStreamClaimed");
+      assert.equal(event.data.claimed.toNumber(), 100_000_000);
+      assert.equal(event.data.nextClaimAt.toNumber(), unlock + 3);
+      lock = await program.account.timeLockAccount.fetch(lockAccount);
+      assert.equal(lock.nextClaimAt.toNumber(), unlock + 3);
+
+      // Nothing more until the next period starts.
+      await expectError(claimSolStream(user), "NothingToClaim");
+      await expectError(withdrawSol(user, lockAccount), "StreamNotDrained");
+
+      // Final period: the remainder vests and the cache moves to the sentinel.
+      await waitUntil(unlock + 6);
+      [event] = await eventsOf(await claimSolStream(user));
+      assert.equal(event.data.claimed.toNumber(), 200_000_000);
+      assert.equal(event.data.totalClaimed.toNumber(), 300_000_000);
+      assert.equal(event.data.nextClaimAt.toString(), "9223372036854775807");
+      lock = await program.account.timeLockAccount.fetch(lockAccount);
+      assert.equal(lock.nextClaimAt.toString(), "9223372036854775807");
+
+      // A drained stream closes through withdraw_sol to reclaim the rent.
+      await withdrawSol(user, lockAccount);
+      assert.isNull(await connection.getAccountInfo(lockAccount));
+    });
+
+    it("vests anchored chunks exactly at each anchor crossing", async () => {
+      const user = await newUser();
+      const unlock = (await now()) + 3;
+      const { lockAccount } = await initSolLock(user, 300_000_000, unlock, {
+        streamPeriods: 3,
+        streamPeriodSecs: 4,
+      });
+      // Boundaries fall at unlock + 2 + 4k, so the first chunk waits for unlock + 2.
+      await program.methods
+        .setStreamAnchor(new BN(unlock - 2))
+        .accountsPartial({ initializer: user.publicKey, lockAccount })
+        .signers([user])
+        .rpc();
+      const lock = await program.account.timeLockAccount.fetch(lockAccount);
+      assert.equal(lock.nextClaimAt.toNumber(), unlock + 2);
+
+      await waitUntil(unlock);
+      await expectError(claimSolStream(user), "NothingToClaim");
+
+      await waitUntil(unlock + 2);
+      const [event] = await eventsOf(await claimSolStream(user));
+      assert.equal(event.data.claimed.toNumber(), 100_000_000);
+      assert.equal(event.data.nextClaimAt.toNumber(), unlock + 6);
+    });
+
+    it("rejects claiming from a one-shot lock", async () => {
+      const user = await newUser();
+      await initSolLock(user, LAMPORTS_PER_SOL, (await now()) + 2);
+      await sleep(3_000);
+      await expectError(claimSolStream(user), "NotAStream");
+    });
+
+    it("lets anyone sweep drained streams, skipping live ones", async () => {
+      const drained = await newUser();
+      const live = await newUser();
+      const unlock = (await now()) + 2;
+      const stream = { streamPeriods: 1, streamPeriodSecs: 1 };
+      const { lockAccount: drainedLock } = await initSolLock(drained, 100_000_000, unlock, stream);
+      const { lockAccount: liveLock } = await initSolLock(live, 100_000_000, unlock, stream);
+      await waitUntil(unlock + 1);
+      await claimSolStream(drained);
+
+      const sweep = (pairs) =>
+        program.methods
+          .sweepClosableLocks()
+          .accountsPartial({ stats: statsPda })
+          .remainingAccounts(
+            pairs.flat().map((pubkey) => ({ pubkey, isWritable: true, isSigner: false }))
+          )
+          .rpc();
+
+      // The payee must be the lock's own initializer.
+      await expectError(sweep([[drainedLock, live.publicKey]]), "Unauthorized");
+
+      const before = await connection.getBalance(drained.publicKey);
+      const rent = await connection.getBalance(drainedLock);
+      const [event] = await eventsOf(
+        await sweep([
+          [drainedLock, drained.publicKey],
+          [liveLock, live.publicKey],
+        ])
+      );
+      assert.equal(event.name, "locksSwept");
+      assert.equal(event.data.closed, 1);
+      assert.equal(event.data.skipped, 1);
+      assert.isNull(await connection.getAccountInfo(drainedLock));
+      assert.isNotNull(await connection.getAccountInfo(liveLock));
+      assert.equal(await connection.getBalance(drained.publicKey), before + rent);
+    });
+  });
+
+  describe("invite-only mode", () => {
+    const invitePda = (address) => pda(Buffer.from("invite"), address.toBuffer());
+
+    const setInviteOnly = (inviteOnly) =>
+      program.methods
+        .setInviteOnly(inviteOnly)
+        .accountsPartial({ admin: payer.publicKey, config: configPda })
+        .rpc();
+
+    const invite = (address) =>
+      program.methods
+        .invite(address)
+        .accountsPartial({
+          admin: payer.publicKey,
+          config: configPda,
+          invite: invitePda(address),
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+
+    const revokeInvite = (address) =>
+      program.methods
+        .revokeInvite()
+        .accountsPartial({
+          admin: payer.publicKey,
+          config: configPda,
+          invite: invitePda(address),
+        })
+        .rpc();
+
+    before(() => setInviteOnly(true));
+    after(() => setInviteOnly(false));
+
+    it("rejects locks from addresses without an invite", async () => {
+      const user = await newUser();
+      await expectError(
+        initSolLock(user, LAMPORTS_PER_SOL, (await now()) + 3_600),
+        "NotInvited"
+      );
+      const { mint, userAta } = await fundedMint(user, 100);
+      await expectError(
+        initSplLock(user, mint, userAta, 100, (await now()) + 3_600),
+        "NotInvited"
+      );
+    });
+
+    it("rejects another address's invite", async () => {
+      const user = await newUser();
+      const other = Keypair.generate().publicKey;
+      await invite(other);
+      await expectError(
+        initSolLock(user, LAMPORTS_PER_SOL, (await now()) + 3_600, {
+          invite: invitePda(other),
+        }),
+        "ConstraintSeeds"
+      );
+      await revokeInvite(other);
+    });
+
+    it("keeps withdrawals open after an invite is revoked", async () => {
+      const user = await newUser();
+      await invite(user.publicKey);
+      const { lockAccount } = await initSolLock(
+        user,
+        LAMPORTS_PER_SOL,
+        (await now()) + 2,
+        { invite: invitePda(user.publicKey) }
+      );
+      await revokeInvite(user.publicKey);
+      assert.isNull(await connection.getAccountInfo(invitePda(user.publicKey)));
+
+      await sleep(3_000);
+      await withdrawSol(user, lockAccount);
+      assert.isNull(await connection.getAccountInfo(lockAccount));
+    });
+  });
+
+  describe("protocol stats", () => {
+    async function protocolStats() {
+      const signature = await program.methods
+        .getProtocolStats()
+        .accountsPartial({ stats: statsPda })
+        .rpc();
+      const [event] = await eventsOf(signature);
+      assert.equal(event.name, "protocolStats");
+      return event.data;
+    }
+
+    it("numbers state-changing events in strictly increasing order", async () => {
+      const seqOf = async (signature, name) =>
+        (await eventsOf(signature)).find((e) => e.name === name).data.seq.toNumber();
+      const first = await newUser();
+      const second = await newUser();
+      const unlock = (await now()) + 2;
+      const { lockAccount, signature: created } = await initSolLock(first, LAMPORTS_PER_SOL, unlock);
+      const { signature: createdAgain } = await initSolLock(second, LAMPORTS_PER_SOL, unlock);
+      await waitUntil(unlock);
+      const withdrawn = await withdrawSol(first, lockAccount);
+
+      const seqs = [
+        await seqOf(created, "lockCreated"),
+        await seqOf(createdAgain, "lockCreated"),
+        await seqOf(withdrawn, "solWithdrawn"),
+      ];
+      assert.isAbove(seqs[1], seqs[0]);
+      assert.isAbove(seqs[2], seqs[1]);
+      assert.isAtLeast((await protocolStats()).eventSeq.toNumber(), seqs[2]);
+    });
+
+    it("tracks open locks and withdrawals through a lock's lifecycle", async () => {
+      const start = await protocolStats();
+      const stored = await program.account.stats.fetch(statsPda);
+      assert.equal(start.totalSolLocked.toString(), stored.totalSolLocked.toString());
+      assert.equal(start.totalLocksOpen.toString(), stored.totalLocksOpen.toString());
+
+      const user = await newUser();
+      const { lockAccount } = await initSolLock(
+        user,
+        LAMPORTS_PER_SOL,
+        (await now()) + 2
+      );
+      const opened = await protocolStats();
+      assert.equal(opened.totalLocksOpen.sub(start.totalLocksOpen).toNumber(), 1);
+      assert.equal(
+        opened.totalSolLocked.sub(start.totalSolLocked).toNumber(),
+        LAMPORTS_PER_SOL
+      );
+
+      await sleep(3_000);
+      await withdrawSol(user, lockAccount);
+      const closed = await protocolStats();
+      assert.equal(closed.totalLocksOpen.toString(), start.totalLocksOpen.toString());
+      assert.equal(closed.totalSolLocked.toString(), start.totalSolLocked.toString());
+      assert.equal(
+        closed.totalWithdrawals.sub(start.totalWithdrawals).toNumber(),
+        1
+      );
+    });
+
+    it("counts an SPL lock as open until it is closed", async () => {
+      const start = await program.account.stats.fetch(statsPda);
+      const user = await newUser();
+      const { mint, userAta } = await fundedMint(user, 100);
+      const { lockAccount, vaultAta } = await initSplLock(
+        user,
+        mint,
+        userAta,
+        100,
+        (await now()) + 2
+      );
+      await sleep(3_000);
+      await withdrawSpl(user, mint, userAta, lockAccount, vaultAta);
+
+      let stats = await program.account.stats.fetch(statsPda);
+      assert.equal(stats.totalLocksOpen.sub(start.totalLocksOpen).toNumber(), 1);
+      assert.equal(stats.totalWithdrawals.sub(start.totalWithdrawals).toNumber(), 1);
+
+      await closeSplLock(user, mint, lockAccount, vaultAta);
+      stats = await program.account.stats.fetch(statsPda);
+      assert.equal(stats.totalLocksOpen.toString(), start.totalLocksOpen.toString());
+    });
+  });
+
+  describe("batch limits", () => {
+    const MAX_BATCH = 10;
+    const asRemaining = (keys) =>
+      keys.map((pubkey) => ({ pubkey, isSigner: false, isWritable: false }));
+
+    it("verifies bumps for a batch of locks", async () => {
+      const locks = [];
+      for (let i = 0; i < 2; i++) {
+        const user = await newUser();
+        const { lockAccount } = await initSolLock(
+          user,
+          LAMPORTS_PER_SOL,
+          (await now()) + 3_600
+        );
+        locks.push(lockAccount);
+      }
+      await program.methods
+        .verifyBumps()
+        .remainingAccounts(asRemaining(locks))
+        .rpc();
+    });
+
+    it("rejects a batch over MAX_BATCH before touching any account", async () => {
+      const keys = Array.from(
+        { length: MAX_BATCH + 1 },
+        () => Keypair.generate().publicKey
+      );
+      await expectError(
+        program.methods.verifyBumps().remainingAccounts(asRemaining(keys)).rpc(),
+        "BatchTooLarge"
+      );
+    });
+  });
+
+  describe("admin handoff", () => {
+    const proposeAdmin = (admin, newAdmin) =>
+      program.methods
+        .proposeAdmin(newAdmin)
+        .accountsPartial({ admin: admin.publicKey, config: configPda })
+        .signers([admin])
+        .rpc();
+
+    const acceptAdmin = (signer) =>
+      program.methods
+        .acceptAdmin()
+        .accountsPartial({ pendingAdmin: signer.publicKey, config: configPda })
+        .signers([signer])
+        .rpc();
+
+    it("only changes the admin once the proposed key accepts", async () => {
+      const newAdmin = Keypair.generate();
+      await proposeAdmin(payer, newAdmin.publicKey);
+
+      let config = await program.account.config.fetch(configPda);
+      assert.isTrue(config.admin.equals(payer.publicKey));
+      assert.isTrue(config.pendingAdmin.equals(newAdmin.publicKey));
+
+      await expectError(acceptAdmin(Keypair.generate()), "NotPendingAdmin");
+
+      await acceptAdmin(newAdmin);
+      config = await program.account.config.fetch(configPda);
+      assert.isTrue(config.admin.equals(newAdmin.publicKey));
+      assert.isNull(config.pendingAdmin);
+
+      // The old admin is locked out; hand the role back for the rest of the suite.
+      await expectError(
+        proposeAdmin(payer, payer.publicKey),
+        "Unauthorized"
+      );
+      await proposeAdmin(newAdmin, payer.publicKey);
+      await acceptAdmin(payer);
+      config = await program.account.config.fetch(configPda);
+      assert.isTrue(config.admin.equals(payer.publicKey));
+    });
+  });
+
+  describe("auto-save", () => {
+    const setAutoSave = (user, bps, savingsUnlock) =>
+      program.methods
+        .setAutoSave(bps, new BN(savingsUnlock))
+        .accountsPartial({
+          initializer: user.publicKey,
+          lockAccount: solLockPda(user.publicKey),
+        })
+        .signers([user])
+        .rpc();
+
+    it("moves the configured share into a new savings lock", async () => {
+      const user = await newUser();
+      const unlock = (await now()) + 2;
+      const { lockAccount } = await initSolLock(user, LAMPORTS_PER_SOL, unlock);
+      await setAutoSave(user, 2_500, unlock + 3_600);
+
+      const savingsLock = savingsPda(user.publicKey);
+      await waitUntil(unlock);
+      await expectError(withdrawSol(user, lockAccount), "SavingsLockMissing");
+
+      const before = await connection.getBalance(user.publicKey);
+      await closeSolLock("withdrawSol", user, lockAccount, savingsLock);
+
+      const savings = await program.account.timeLockAccount.fetch(savingsLock);
+      assert.deepEqual(savings.kind, { savings: {} });
+      assert.equal(savings.amount.toNumber(), LAMPORTS_PER_SOL / 4);
+      assert.equal(savings.unlockTimestamp.toNumber(), unlock + 3_600);
+      // 75% comes back, less the savings lock's rent and the fee.
+      assert.isAbove(
+        await connection.getBalance(user.publicKey),
+        before + (3 * LAMPORTS_PER_SOL) / 4 - 10_000_000
+      );
+      await expectError(
+        program.methods
+          .withdrawSavings()
+          .accountsPartial({
+            initializer: user.publicKey,
+            lockAccount: savingsLock,
+            config: configPda,
+            stats: statsPda,
+            treasury: await treasury(),
+          })
+          .signers([user])
+          .rpc(),
+        "TimeLockNotExpired"
+      );
+    });
+
+    it("rejects a share above 100% or a savings unlock before the lock's", async () => {
+      const user = await newUser();
+      const unlock = (await now()) + 3_600;
+      await initSolLock(user, LAMPORTS_PER_SOL, unlock);
+      await expectError(setAutoSave(user, 10_001, unlock + 60), "InvalidAutoSave");
+      await expectError(setAutoSave(user, 1_000, unlock), "UnlockInPast");
+    });
+  });
+
+  describe("duplicate initialization", () => {
+    itReinit("rejects the same initialize instruction twice in one transaction", async () => {
+      const user = await newUser();
+      const lockAccount = solLockPda(user.publicKey);
+      const initIx = (amount) =>
+        program.methods
+          .initializeLockSol(
+            new BN(amount),
+            new BN(Math.floor(Date.now() / 1_000) + 3_600),
+            ZERO_COMMITMENT,
+            false,
+            new BN(0),
+            0,
+            new BN(0),
+            new BN(0),
+            null,
+            [],
+            0,
+            0,
+            null
+          )
+          .accountsPartial({
+            initializer: user.publicKey,
+            lockAccount,
+            config: configPda,
+            stats: statsPda,
+            lockSummary: null,
+            invite: null,
+            systemProgram: SystemProgram.programId,
+          })
+          .instruction();
+
+      const fundIx = await program.methods
+        .fundSolLock(new BN(2 * LAMPORTS_PER_SOL))
+        .accountsPartial({
+          initializer: user.publicKey,
+          lockAccount,
+          config: configPda,
+          stats: statsPda,
+          lockSummary: null,
+          systemProgram: SystemProgram.programId,
+        })
+        .instruction();
+      const tx = new anchor.web3.Transaction().add(
+        await initIx(LAMPORTS_PER_SOL),
+        await initIx(2 * LAMPORTS_PER_SOL),
+        fundIx
+      );
+      await expectError(
+        provider.sendAndConfirm(tx, [user]),
+        "DuplicateInitialization"
+      );
+      assert.isNull(await connection.getAccountInfo(lockAccount));
+    });
+  });
+
+  describe("minimum lock amounts", () => {
+    const setMinLockAmounts = (lamports, tokens) =>
+      program.methods
+        .setMinLockAmounts(new BN(lamports), new BN(tokens))
+        .accountsPartial({ admin: payer.publicKey, config: configPda })
+        .rpc();
+
+    before(() => setMinLockAmounts(LAMPORTS_PER_SOL / 2, 50));
+    after(() => setMinLockAmounts(0, 0));
+
+    it("checks SOL locks when the deposit lands", async () => {
+      const user = await newUser();
+      await expectError(
+        initSolLock(user, LAMPORTS_PER_SOL / 10, (await now()) + 3_600),
+        "BelowMinimumAmount"
+      );
+      const { lockAccount } = await initSolLock(
+        user,
+        LAMPORTS_PER_SOL / 2,
+        (await now()) + 3_600
+      );
+      const lock = await program.account.timeLockAccount.fetch(lockAccount);
+      assert.equal(lock.amount.toNumber(), LAMPORTS_PER_SOL / 2);
+    });
+
+    it("rejects SPL locks under the token minimum", async () => {
+      const user = await newUser();
+      const { mint, userAta } = await fundedMint(user, 100);
+      await expectError(
+        initSplLock(user, mint, userAta, 10, (await now()) + 3_600),
+        "BelowMinimumAmount"
+      );
+      await initSplLock(user, mint, userAta, 50, (await now()) + 3_600);
+    });
+  });
+
+  describe("maximum lock size", () => {
+    const setMaxLockAmounts = (lamports, tokens) =>
+      program.methods
+        .setMaxLockAmounts(new BN(lamports), new BN(tokens))
+        .accountsPartial({ admin: payer.publicKey, config: configPda })
+        .rpc();
+
+    before(() => setMaxLockAmounts(1.5 * LAMPORTS_PER_SOL, 0));
+    after(() => setMaxLockAmounts(0, 0));
+
+    it("caps the lock's total amount, including top-ups", async () => {
+      const user = await newUser();
+      const unlock = (await now()) + 3_600;
+      await initSolLock(user, LAMPORTS_PER_SOL, unlock);
+      const topup = (lamports) =>
+        program.methods
+          .topupAndExtendSol(new BN(lamports), new BN(unlock + 60))
+          .accountsPartial({
+            initializer: user.publicKey,
+            lockAccount: solLockPda(user.publicKey),
+            config: configPda,
+            stats: statsPda,
+            lockSummary: null,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([user])
+          .rpc();
+
+      await expectError(topup(LAMPORTS_PER_SOL), "ExceedsMaxLockSize");
+      await topup(LAMPORTS_PER_SOL / 2);
+      const lock = await program.account.timeLockAccount.fetch(
+        solLockPda(user.publicKey)
+      );
+      assert.equal(lock.amount.toNumber(), 1.5 * LAMPORTS_PER_SOL);
+    });
+
+    it("rejects an initial deposit over the cap", async () => {
+      const user = await newUser();
+      await expectError(
+        initSolLock(user, 2 * LAMPORTS_PER_SOL, (await now()) + 3_600),
+        "ExceedsMaxLockSize"
+      );
+    });
+
+    it("won't let a reconcile repair grow the lock past the cap", async () => {
+      const user = await newUser();
+      const { lockAccount } = await initSolLock(user, LAMPORTS_PER_SOL, (await now()) + 3_600);
+      await provider.sendAndConfirm(
+        new anchor.web3.Transaction().add(
+          SystemProgram.transfer({
+            fromPubkey: user.publicKey,
+            toPubkey: lockAccount,
+            lamports: LAMPORTS_PER_SOL,
+          })
+        ),
+        [user]
+      );
+
+      await expectError(
+        program.methods
+          .reconcileSol(true)
+          .accountsPartial({ initializer: user.publicKey, lockAccount, config: configPda, stats: statsPda })
+          .signers([user])
+          .rpc(),
+        "ExceedsMaxLockSize"
+      );
+      const lock = await program.account.timeLockAccount.fetch(lockAccount);
+      assert.equal(lock.amount.toNumber(), LAMPORTS_PER_SOL);
+    });
+  });
+
+  describe("split withdrawals", () => {
+    async function withdrawSplit(user, amounts, destinations) {
+      return program.methods
+        .withdrawSolSplit(amounts.map((amount) => new BN(amount)))
+        .accountsPartial({
+          authority: user.publicKey,
+          initializer: user.publicKey,
+          lockAccount: solLockPda(user.publicKey),
+          savingsLock: null,
+          config: configPda,
+          stats: statsPda,
+          lockSummary: null,
+          condition: null,
+          rentRecipient: null,
+          notifyProgram: null,
+          allowedNotifyProgram: null,
+          treasury: await treasury(),
+          systemProgram: SystemProgram.programId,
+        })
+        .remainingAccounts(
+          destinations.map((pubkey) => ({ pubkey, isSigner: false, isWritable: true }))
+        )
+        .signers([user])
+        .rpc();
+    }
+
+    it("pays each destination its amount and the rest to the initializer", async () => {
+      const user = await newUser();
+      const unlock = (await now()) + 2;
+      const { lockAccount } = await initSolLock(user, LAMPORTS_PER_SOL, unlock);
+      await waitUntil(unlock);
+      const [first, second] = [Keypair.generate().publicKey, Keypair.generate().publicKey];
+
+      const signature = await withdrawSplit(
+        user,
+        [0.3 * LAMPORTS_PER_SOL, 0.2 * LAMPORTS_PER_SOL],
+        [first, second]
+      );
+
+      assert.equal(await connection.getBalance(first), 0.3 * LAMPORTS_PER_SOL);
+      assert.equal(await connection.getBalance(second), 0.2 * LAMPORTS_PER_SOL);
+      assert.isNull(await connection.getAccountInfo(lockAccount));
+      const events = await eventsOf(signature);
+      const split = events.find((event) => event.name === "splitWithdrawn").data;
+      const withdrawn = events.find((event) => event.name === "solWithdrawn").data;
+      assert.equal(
+        split.remainder.toNumber() + withdrawn.feeTaken.toNumber(),
+        0.5 * LAMPORTS_PER_SOL
+      );
+      assert.equal(withdrawn.principalReturned.toNumber(), split.remainder.toNumber());
+    });
+
+    it("rejects amounts that overrun the payout or don't match the destinations", async () => {
+      const user = await newUser();
+      const unlock = (await now()) + 2;
+      await initSolLock(user, LAMPORTS_PER_SOL, unlock);
+      await waitUntil(unlock);
+      const [first, second] = [Keypair.generate().publicKey, Keypair.generate().publicKey];
+
+      await expectError(
+        withdrawSplit(user, [0.6 * LAMPORTS_PER_SOL, 0.6 * LAMPORTS_PER_SOL], [first, second]),
+        "SplitExceedsPayout"
+      );
+      await expectError(
+        withdrawSplit(user, [0.3 * LAMPORTS_PER_SOL, 0.2 * LAMPORTS_PER_SOL], [first]),
+        "SplitMismatch"
+      );
+    });
+  });
+
+  describe("verify_lock_params", () => {
+    const verify = (lockAccount, amount, unlock) =>
+      program.methods
+        .verifyLockParams(new BN(amount), new BN(unlock))
+        .accountsPartial({ lockAccount })
+        .rpc();
+
+    it("passes only for the lock's own amount and unlock time", async () => {
+      const user = await newUser();
+      const unlock = (await now()) + 3_600;
+      const { lockAccount } = await initSolLock(user, LAMPORTS_PER_SOL, unlock);
+
+      await verify(lockAccount, LAMPORTS_PER_SOL, unlock);
+      await expectError(verify(lockAccount, 2 * LAMPORTS_PER_SOL, unlock), "ParamsMismatch");
+      await expectError(verify(lockAccount, LAMPORTS_PER_SOL, unlock + 1), "ParamsMismatch");
+    });
+  });
+
+  describe("referrals", () => {
+    it("records the referrer on the lock and in LockCreated", async () => {
+      const user = await newUser();
+      const referrer = Keypair.generate().publicKey;
+      const { lockAccount, signature } = await initSolLock(
+        user,
+        LAMPORTS_PER_SOL,
+        (await now()) + 3_600,
+        { referrer }
+      );
+      const lock = await program.account.timeLockAccount.fetch(lockAccount);
+      assert.isTrue(lock.referrer.equals(referrer));
+      const created = (await eventsOf(signature)).find((event) => event.name === "lockCreated");
+      assert.isTrue(created.data.referrer.equals(referrer));
+    });
+
+    it("rejects a self-referral", async () => {
+      const user = await newUser();
+      await expectError(
+        initSolLock(user, LAMPORTS_PER_SOL, (await now()) + 3_600, {
+          referrer: user.publicKey,
+        }),
+        "SelfReferral"
+      );
+    });
+  });
+
+  describe("password locks", () => {
+    const secret = Buffer.from("open sesame");
+    const passwordHash = [...createHash("sha256").update(secret).digest()];
+
+    const claim = async (claimer, initializer, preimage) =>
+      program.methods
+        .claimWithPassword(preimage)
+        .accountsPartial({
+          claimer: claimer.publicKey,
+          initializer: initializer.publicKey,
+          lockAccount: solLockPda(initializer.publicKey),
+          treasury: await treasury(),
+        })
+        .signers([claimer])
+        .rpc();
+
+    it("pays whoever knows the secret once matured, and nobody else", async () => {
+      const [user, friend] = [await newUser(), await newUser(1)];
+      const unlock = (await now()) + 3;
+      const { lockAccount } = await initSolLock(user, LAMPORTS_PER_SOL, unlock);
+      await program.methods
+        .setPassword(passwordHash)
+        .accountsPartial({ initializer: user.publicKey, lockAccount })
+        .signers([user])
+        .rpc();
+
+      await expectError(claim(friend, user, secret), "TimeLockNotExpired");
+      await waitUntil(unlock);
+      await expectError(withdrawSol(user), "PasswordRequired");
+      await expectError(claim(friend, user, Buffer.from("open says me")), "WrongPassword");
+
+      const before = await connection.getBalance(friend.publicKey);
+      const signature = await claim(friend, user, secret);
+      const [event] = (await eventsOf(signature)).filter(
+        (event) => event.name === "passwordClaimed"
+      );
+      assert.isTrue(event.data.claimer.equals(friend.publicKey));
+      assert.equal(
+        (await connection.getBalance(friend.publicKey)) - before,
+        event.data.amount.toNumber()
+      );
+      assert.isNull(await connection.getAccountInfo(lockAccount));
+    });
+
+    it("refuses settings that would leave the lock unclaimable", async () => {
+      const user = await newUser();
+      const { lockAccount } = await initSolLock(user, LAMPORTS_PER_SOL, (await now()) + 3_600);
+      await program.methods
+        .setPassword(passwordHash)
+        .accountsPartial({ initializer: user.publicKey, lockAccount })
+        .signers([user])
+        .rpc();
+      const condition = pda(
+        Buffer.from("condition"),
+        user.publicKey.toBuffer(),
+        new BN(1).toArrayLike(Buffer, "le", 8)
+      );
+      await program.methods
+        .createCondition(new BN(1))
+        .accountsPartial({ authority: user.publicKey, condition })
+        .signers([user])
+        .rpc();
+      const receiptMint = pda(Buffer.from("receipt"), lockAccount.toBuffer());
+
+      for (const setter of [
+        program.methods
+          .createLockSummary()
+          .accountsPartial({
+            initializer: user.publicKey,
+            lockAccount,
+            lockSummary: summaryPda(lockAccount),
+            systemProgram: SystemProgram.programId,
+          }),
+        program.methods.mintLockReceipt().accountsPartial({
+          initializer: user.publicKey,
+          lockAccount,
+          receiptMint,
+          receiptAta: getAssociatedTokenAddressSync(receiptMint, user.publicKey),
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        }),
+        program.methods
+          .setConditionAccount()
+          .accountsPartial({ initializer: user.publicKey, lockAccount, condition }),
+        program.methods
+          .assignRentRecipient(Keypair.generate().publicKey)
+          .accountsPartial({ rentRecipient: user.publicKey, lockAccount }),
+      ]) {
+        await expectError(setter.signers([user]).rpc(), "ReleaseModeConflict");
+      }
+      const lock = await program.account.timeLockAccount.fetch(lockAccount);
+      assert.isFalse(lock.hasSummary);
+      assert.isNull(lock.receiptMint);
+      assert.isNull(lock.conditionAccount);
+      assert.isNull(lock.rentRecipient);
+    });
+
+    it("keeps a charity fallback and a password apart", async () => {
+      const charity = Keypair.generate().publicKey;
+      const allowedCharity = pda(Buffer.from("charity"), charity.toBuffer());
+      await program.methods
+        .allowCharity(charity)
+        .accountsPartial({ admin: payer.publicKey, config: configPda, charity: allowedCharity })
+        .rpc();
+      const setFallback = (user) =>
+        program.methods
+          .setCharityFallback(new BN(2))
+          .accountsPartial({
+            initializer: user.publicKey,
+            lockAccount: solLockPda(user.publicKey),
+            allowedCharity,
+          })
+          .signers([user])
+          .rpc();
+      const setPassword = (user) =>
+        program.methods
+          .setPassword(passwordHash)
+          .accountsPartial({ initializer: user.publicKey, lockAccount: solLockPda(user.publicKey) })
+          .signers([user])
+          .rpc();
+
+      const gifter = await newUser();
+      await initSolLock(gifter, LAMPORTS_PER_SOL, (await now()) + 3_600);
+      await setPassword(gifter);
+      await expectError(setFallback(gifter), "ReleaseModeConflict");
+
+      const donor = await newUser();
+      await initSolLock(donor, LAMPORTS_PER_SOL, (await now()) + 3_600);
+      await setFallback(donor);
+      await expectError(setPassword(donor), "ReleaseModeConflict");
+    });
+  });
+
+  describe("check_lock_health", () => {
+    async function healthOf(lockAccount, vaultAta = null) {
+      const signature = await program.methods
+        .checkLockHealth()
+        .accountsPartial({ lockAccount, vaultAta })
+        .rpc();
+      const [event] = await eventsOf(signature);
+      assert.equal(event.name, "healthReport");
+      return event.data;
+    }
+
+    it("reports a funded SOL lock as healthy", async () => {
+      const user = await newUser();
+      const { lockAccount } = await initSolLock(
+        user,
+        LAMPORTS_PER_SOL,
+        (await now()) + 3_600
+      );
+      const report = await healthOf(lockAccount);
+      assert.isTrue(report.healthy);
+    });
+
+    it("needs the vault to vouch for an SPL lock", async () => {
+      const user = await newUser();
+      const { mint, userAta } = await fundedMint(user, 100);
+      const { lockAccount, vaultAta } = await initSplLock(
+        user,
+        mint,
+        userAta,
+        100,
+        (await now()) + 3_600
+      );
+      assert.isTrue((await healthOf(lockAccount, vaultAta)).healthy);
+      const report = await healthOf(lockAccount);
+      assert.isFalse(report.vaultFunded);
+      assert.isTrue(report.mintConsistent);
+    });
+  });
+
+  describe("SPL vesting", () => {
+    const claimVestedSpl = (user, mint, userAta, lockAccount, vaultAta, authority = user) =>
+      program.methods
+        .claimVestedSpl()
+        .accountsPartial({
+          authority: authority.publicKey,
+          initializer: user.publicKey,
+          lockAccount,
+          mint,
+          userAta,
+          vaultAta,
+          vaultAuthority: null,
+          priceFeed: null,
+          config: configPda,
+          treasuryAta: null,
+          stats: statsPda,
+          lockSummary: null,
+          condition: null,
+          notifyProgram: null,
+          allowedNotifyProgram: null,
+          rewardPoolAta: null,
+          rewardPoolAuthority: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([authority])
+        .rpc();
+
+    it("releases vested chunks and the exact remainder at the end", async () => {
+      const user = await newUser();
+      const { mint, userAta } = await fundedMint(user, 100);
+      const unlock = (await now()) + 2;
+      const { lockAccount, vaultAta } = await initSplLock(
+        user,
+        mint,
+        userAta,
+        100,
+        unlock,
+        { vestingPeriods: 3, vestingPeriodSecs: 3 }
+      );
+      const claim = () =>
+        claimVestedSpl(user, mint, userAta, lockAccount, vaultAta);
+
+      await expectError(claim(), "TimeLockNotExpired");
+      await waitUntil(unlock);
+      let [event] = await eventsOf(await claim());
+      assert.equal(event.name, "splVestingClaimed");
+      assert.equal(event.data.claimedNow.toNumber(), 33);
+      assert.equal(event.data.totalClaimed.toNumber(), 33);
+      assert.equal(event.data.remainingLocked.toNumber(), 67);
+
+      await expectError(claim(), "NothingToClaim");
+      await expectError(
+        withdrawSpl(user, mint, userAta, lockAccount, vaultAta),
+        "StreamNotDrained"
+      );
+
+      await waitUntil(unlock + 6);
+      [event] = await eventsOf(await claim());
+      assert.equal(event.data.claimedNow.toNumber(), 67);
+      assert.equal(event.data.remainingLocked.toNumber(), 0);
+      assert.equal(Number((await getAccount(connection, userAta)).amount), 100);
+      await expectError(claim(), "NothingToClaim");
+
+      await closeSplLock(user, mint, lockAccount, vaultAta);
+      assert.isNull(await connection.getAccountInfo(lockAccount));
+    });
+
+    it("vests a u64::MAX balance of an 18-decimal mint without overflowing", async () => {
+      const MAX = 18446744073709551615n;
+      const user = await newUser();
+      const mint = await createMint(connection, payer, payer.publicKey, null, 18);
+      const userAta = (
+        await getOrCreateAssociatedTokenAccount(connection, payer, mint, user.publicKey)
+      ).address;
+      await mintTo(connection, payer, mint, userAta, payer, MAX);
+      const unlock = (await now()) + 2;
+      const { lockAccount, vaultAta } = await initSplLock(
+        user,
+        mint,
+        userAta,
+        MAX.toString(),
+        unlock,
+        { vestingPeriods: 3, vestingPeriodSecs: 3_600 }
+      );
+
+      await waitUntil(unlock);
+      const [event] = await eventsOf(
+        await claimVestedSpl(user, mint, userAta, lockAccount, vaultAta)
+      );
+      assert.equal(event.data.claimedNow.toString(), (MAX / 3n).toString());
+      assert.equal(event.data.remainingLocked.toString(), (MAX - MAX / 3n).toString());
+    });
+
+    it("sweeps the whole vault once the schedule has completed", async () => {
+      const user = await newUser();
+      const { mint, userAta } = await fundedMint(user, 105);
+      const unlock = (await now()) + 2;
+      const { lockAccount, vaultAta } = await initSplLock(
+        user,
+        mint,
+        userAta,
+        100,
+        unlock,
+        { vestingPeriods: 3, vestingPeriodSecs: 1 }
+      );
+      // Tokens that reach the vault outside the schedule, beyond the 100 it vests.
+      await transfer(connection, payer, userAta, vaultAta, user, 5);
+
+      await waitUntil(unlock + 2);
+      const [event] = await eventsOf(
+        await claimVestedSpl(user, mint, userAta, lockAccount, vaultAta)
+      );
+      assert.equal(event.data.claimedNow.toNumber(), 105);
+      assert.equal(event.data.remainingLocked.toNumber(), 0);
+      assert.equal(Number((await getAccount(connection, vaultAta)).amount), 0);
+      await expectError(
+        claimVestedSpl(user, mint, userAta, lockAccount, vaultAta),
+        "NothingToClaim"
+      );
+    });
+
+    it("releases a share of the vault as it stands at each claim", async () => {
+      const user = await newUser();
+      const { mint, userAta } = await fundedMint(user, 150);
+      const unlock = (await now()) + 3;
+      const { lockAccount, vaultAta } = await initSplLock(
+        user,
+        mint,
+        userAta,
+        100,
+        unlock,
+        { vestingPeriods: 3, vestingPeriodSecs: 5 }
+      );
+      await program.methods
+        .setClaimBps(5_000)
+        .accountsPartial({ initializer: user.publicKey, lockAccount })
+        .signers([user])
+        .rpc();
+      const claim = async () =>
+        (await eventsOf(await claimVestedSpl(user, mint, userAta, lockAccount, vaultAta)))[0].data;
+
+      await waitUntil(unlock);
+      assert.equal((await claim()).claimedNow.toNumber(), 50);
+      await expectError(claimVestedSpl(user, mint, userAta, lockAccount, vaultAta), "NothingToClaim");
+
+      // The vault grows between claims, and the next share is taken from the larger balance.
+      await transfer(connection, payer, userAta, vaultAta, user, 50);
+      await waitUntil(unlock + 5);
+      let event = await claim();
+      assert.equal(event.claimedNow.toNumber(), 50);
+      assert.equal(event.remainingLocked.toNumber(), 50);
+
+      await waitUntil(unlock + 10);
+      event = await claim();
+      assert.equal(event.claimedNow.toNumber(), 50);
+      assert.equal(event.totalClaimed.toNumber(), 150);
+      assert.equal(Number((await getAccount(connection, vaultAta)).amount), 0);
+    });
+
+    it("releases fixed DCA tranches to a keeper-triggered claim", async () => {
+      const user = await newUser();
+      const keeper = await newUser();
+      const { mint, userAta } = await fundedMint(user, 100);
+      const unlock = (await now()) + 3;
+      const { lockAccount, vaultAta } = await initSplLock(
+        user,
+        mint,
+        userAta,
+        100,
+        unlock,
+        { vestingPeriods: 1, vestingPeriodSecs: 3 }
+      );
+      const setTranche = (amount) =>
+        program.methods
+          .setDcaTranche(new BN(amount))
+          .accountsPartial({ initializer: user.publicKey, lockAccount })
+          .signers([user])
+          .rpc();
+      await expectError(setTranche(101), "InvalidAmount");
+      await setTranche(40);
+      let lock = await program.account.timeLockAccount.fetch(lockAccount);
+      assert.equal(lock.streamPeriods, 3);
+      await program.methods
+        .setWithdrawDelegate(keeper.publicKey)
+        .accountsPartial({ initializer: user.publicKey, lockAccount })
+        .signers([user])
+        .rpc();
+      const claim = () =>
+        claimVestedSpl(user, mint, userAta, lockAccount, vaultAta, keeper);
+
+      await waitUntil(unlock);
+      await expectError(setTranche(50), "UnlockInPast");
+      let [event] = await eventsOf(await claim());
+      assert.equal(event.data.claimedNow.toNumber(), 40);
+      await expectError(claim(), "NothingToClaim");
+
+      await waitUntil(unlock + 3);
+      [event] = await eventsOf(await claim());
+      assert.equal(event.data.claimedNow.toNumber(), 40);
+
+      await waitUntil(unlock + 6);
+      [event] = await eventsOf(await claim());
+      assert.equal(event.data.claimedNow.toNumber(), 20);
+      assert.equal(event.data.remainingLocked.toNumber(), 0);
+      assert.equal(Number((await getAccount(connection, userAta)).amount), 100);
+    });
+  });
+
+  describe("log level", () => {
+    const setLogLevel = (level) =>
+      program.methods
+        .setLogLevel(level)
+        .accountsPartial({ admin: payer.publicKey, config: configPda })
+        .rpc();
+
+    after(() => setLogLevel(2));
+
+    async function topupEvents(level) {
+      await setLogLevel(level);
+      const user = await newUser();
+      const unlock = (await now()) + 3_600;
+      await initSolLock(user, LAMPORTS_PER_SOL, unlock);
+      const signature = await program.methods
+        .topupAndExtendSol(new BN(LAMPORTS_PER_SOL / 2), new BN(unlock + 60))
+        .accountsPartial({
+          initializer: user.publicKey,
+          lockAccount: solLockPda(user.publicKey),
+          config: configPda,
+          stats: statsPda,
+          lockSummary: null,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+      return eventsOf(signature);
+    }
+
+    it("drops funding events below level 1", async () => {
+      assert.lengthOf(await topupEvents(0), 0);
+      const events = await topupEvents(1);
+      assert.lengthOf(events, 1);
+      assert.equal(events[0].name, "solToppedUpAndExtended");
+    });
+
+    it("rejects an unknown level", async () => {
+      await expectError(setLogLevel(3), "InvalidLogLevel");
+    });
+  });
+
+  describe("savings goals", () => {
+    const label = (text) => {
+      const bytes = Buffer.alloc(32);
+      bytes.write(text);
+      return Array.from(bytes);
+    };
+
+    it("holds withdrawal until a top-up reaches the goal", async () => {
+      const user = await newUser();
+      const unlock = (await now()) + 3;
+      const { lockAccount } = await initSolLock(user, LAMPORTS_PER_SOL, unlock);
+      await program.methods
+        .setGoal(new BN(2 * LAMPORTS_PER_SOL), label("bike"), true)
+        .accountsPartial({ initializer: user.publicKey, lockAccount })
+        .signers([user])
+        .rpc();
+      await expectError(
+        program.methods
+          .setGoal(new BN(LAMPORTS_PER_SOL), label("car"), false)
+          .accountsPartial({ initializer: user.publicKey, lockAccount })
+          .signers([user])
+          .rpc(),
+        "GoalAlreadySet"
+      );
+
+      await waitUntil(unlock);
+      await expectError(closeSolLock("withdrawSol", user), "GoalNotReached");
+
+      const extended = (await now()) + 3;
+      const signature = await program.methods
+        .topupAndExtendSol(new BN(LAMPORTS_PER_SOL), new BN(extended))
+        .accountsPartial({
+          initializer: user.publicKey,
+          lockAccount,
+          config: configPda,
+          stats: statsPda,
+          lockSummary: null,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+      const reached = (await eventsOf(signature)).find((e) => e.name === "goalReached");
+      assert.ok(reached);
+      assert.equal(reached.data.deposited.toNumber(), 2 * LAMPORTS_PER_SOL);
+      assert.isTrue((await program.account.timeLockAccount.fetch(lockAccount)).goalReached);
+
+      await waitUntil(extended);
+      await closeSolLock("withdrawSol", user);
+    });
+  });
+
+  describe("mint migration", () => {
+    const migrationPda = (oldMint) => pda(Buffer.from("mint-migration"), oldMint.toBuffer());
+    const ata = (mint, owner) => getAssociatedTokenAddressSync(mint, owner, true);
+
+    // Register `oldMint` -> a fresh mint at numerator/denominator and stock the reserve.
+    async function registerMigration(oldMint, numerator, denominator) {
+      const newMint = await createMint(connection, payer, payer.publicKey, null, 6);
+      const migration = migrationPda(oldMint);
+      await program.methods
+        .registerMintMigration(new BN(numerator), new BN(denominator))
+        .accountsPartial({
+          admin: payer.publicKey,
+          config: configPda,
+          oldMint,
+          newMint,
+          migration,
+          migrationOldAta: ata(oldMint, migration),
+          migrationNewAta: ata(newMint, migration),
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+      await mintTo(connection, payer, newMint, ata(newMint, migration), payer, 1_000_000);
+      return newMint;
+    }
+
+    function migrate(user, lockAccount, oldMint, newMint) {
+      const migration = migrationPda(oldMint);
+      return program.methods
+        .migrateSplMint()
+        .accountsPartial({
+          initializer: user.publicKey,
+          lockAccount,
+          oldMint,
+          newMint,
+          migration,
+          oldVault: ata(oldMint, lockAccount),
+          newVault: ata(newMint, lockAccount),
+          migrationOldAta: ata(oldMint, migration),
+          migrationNewAta: ata(newMint, migration),
+          stats: statsPda,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+    }
+
+    it("moves the lock onto the new mint at the registered ratio", async () => {
+      const user = await newUser();
+      const { mint, userAta } = await fundedMint(user, 1_000);
+      const unlock = (await now()) + 3;
+      const { lockAccount, vaultAta } = await initSplLock(user, mint, userAta, 1_000, unlock);
+      await program.methods
+        .setWithdrawStep(new BN(10))
+        .accountsPartial({ initializer: user.publicKey, lockAccount })
+        .signers([user])
+        .rpc();
+      const newMint = await registerMigration(mint, 3, 2);
+
+      await migrate(user, lockAccount, mint, newMint);
+      assert.isNull(await connection.getAccountInfo(vaultAta));
+      const newVault = ata(newMint, lockAccount);
+      assert.equal(Number((await getAccount(connection, newVault)).amount), 1_500);
+      const lock = await program.account.timeLockAccount.fetch(lockAccount);
+      assert.isTrue(lock.mint.equals(newMint));
+      assert.equal(lock.amount.toNumber(), 1_500);
+      assert.equal(lock.withdrawStep.toNumber(), 15);
+
+      const newUserAta = (
+        await getOrCreateAssociatedTokenAccount(connection, payer, newMint, user.publicKey)
+      ).address;
+      await waitUntil(unlock);
+      await withdrawSpl(user, newMint, newUserAta, lockAccount, newVault);
+      assert.equal(Number((await getAccount(connection, newUserAta)).amount), 1_500);
+    });
+
+    it("carries a vesting lock's claims and pinned destination over", async () => {
+      const user = await newUser();
+      const { mint, userAta } = await fundedMint(user, 1_000);
+      const unlock = (await now()) + 2;
+      const { lockAccount, vaultAta } = await initSplLock(user, mint, userAta, 1_000, unlock, {
+        vestingPeriods: 2,
+        vestingPeriodSecs: 3_600,
+      });
+      await waitUntil(unlock);
+      await program.methods
+        .claimVestedSpl()
+        .accountsPartial({
+          authority: user.publicKey,
+          initializer: user.publicKey,
+          lockAccount,
+          mint,
+          userAta,
+          vaultAta,
+          vaultAuthority: null,
+          priceFeed: null,
+          config: configPda,
+          treasuryAta: null,
+          stats: statsPda,
+          lockSummary: null,
+          condition: null,
+          notifyProgram: null,
+          allowedNotifyProgram: null,
+          rewardPoolAta: null,
+          rewardPoolAuthority: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user])
+        .rpc();
+      const newMint = await registerMigration(mint, 1, 2);
+
+      await migrate(user, lockAccount, mint, newMint);
+      const lock = await program.account.timeLockAccount.fetch(lockAccount);
+      assert.equal(lock.amount.toNumber(), 500);
+      assert.equal(lock.streamClaimed.toNumber(), 250);
+      assert.isTrue(lock.withdrawDestination.equals(ata(newMint, user.publicKey)));
+      assert.equal(Number((await getAccount(connection, ata(newMint, lockAccount))).amount), 250);
+    });
+
+    it("refuses a lock that is no longer on the migration's old mint", async () => {
+      const user = await newUser();
+      const { mint, userAta } = await fundedMint(user, 100);
+      const { lockAccount } = await initSplLock(user, mint, userAta, 100, (await now()) + 3_600);
+      const newMint = await registerMigration(mint, 1, 1);
+      await migrate(user, lockAccount, mint, newMint);
+
+      // The old vault was closed; recreate it so only the lock's own mint stands in the way.
+      await getOrCreateAssociatedTokenAccount(connection, payer, mint, lockAccount, true);
+      await expectError(migrate(user, lockAccount, mint, newMint), "NoMigrationPath");
+    });
+
+    it("refuses a lock still owed a deposit fee refund", async () => {
+      const setDepositFee = (bps) =>
+        program.methods
+          .setDepositFee(bps)
+          .accountsPartial({ admin: payer.publicKey, config: configPda })
+          .rpc();
+      await setDepositFee(100);
+      try {
+        const user = await newUser();
+        const { mint, userAta } = await fundedMint(user, 1_000);
+        const rewardPoolAta = (
+          await getOrCreateAssociatedTokenAccount(connection, payer, mint, pda(Buffer.from("reward-pool")), true)
+        ).address;
+        const { lockAccount } = await initSplLock(user, mint, userAta, 1_000, (await now()) + 3_600, {
+          rewardPoolAta,
+        });
+        const newMint = await registerMigration(mint, 1, 1);
+        await expectError(migrate(user, lockAccount, mint, newMint), "DepositFeeOutstanding");
+      } finally {
+        await setDepositFee(0);
+      }
+    });
+  });
+
+  describe("orphaned SPL locks", () => {
+    const forceClose = async (admin, user, lockAccount, vaultAta) =>
+      program.methods
+        .forceCloseOrphanLock()
+        .accountsPartial({
+          admin: admin.publicKey,
+          config: configPda,
+          initializer: user.publicKey,
+          lockAccount,
+          vaultAta,
+          stats: statsPda,
+          lockSummary: null,
+          rentRecipient: null,
+          treasury: await treasury(),
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers(admin === payer ? [] : [admin])
+        .rpc();
+
+    it("refuses live locks and non-admin callers", async () => {
+      const user = await newUser();
+      const { mint, userAta } = await fundedMint(user, 100);
+      const { lockAccount, vaultAta } = await initSplLock(
+        user,
+        mint,
+        userAta,
+        100,
+        (await now()) + 3_600
+      );
+
+      await expectError(forceClose(payer, user, lockAccount, vaultAta), "VaultNotEmpty");
+      await expectError(forceClose(user, user, lockAccount, vaultAta), "Unauthorized");
+      assert.isNotNull(await connection.getAccountInfo(lockAccount));
+    });
+
+    it("closes an emptied lock and its vault back to the initializer", async () => {
+      const user = await newUser();
+      const { mint, userAta } = await fundedMint(user, 100);
+      const { lockAccount, vaultAta } = await initSplLock(
+        user,
+        mint,
+        userAta,
+        100,
+        (await now()) + 2
+      );
+      await sleep(3_000);
+      await withdrawSpl(user, mint, userAta, lockAccount, vaultAta);
+
+      const rent =
+        (await connection.getBalance(lockAccount)) +
+        (await connection.getBalance(vaultAta));
+      const before = await connection.getBalance(user.publicKey);
+      const signature = await forceClose(payer, user, lockAccount, vaultAta);
+
+      assert.isNull(await connection.getAccountInfo(lockAccount));
+      assert.isNull(await connection.getAccountInfo(vaultAta));
+      assert.equal(await connection.getBalance(user.publicKey), before + rent);
+      const [event] = await eventsOf(signature);
+      assert.equal(event.name, "orphanLockClosed");
+      assert.isTrue(event.data.vaultExisted);
+    });
+
+    describe("with a creation bond", () => {
+      const BOND = LAMPORTS_PER_SOL / 100;
+      const setBond = (lamports) =>
+        program.methods
+          .setBond(new BN(lamports))
+          .accountsPartial({ admin: payer.publicKey, config: configPda })
+          .rpc();
+
+      before(() => setBond(BOND));
+      after(() => setBond(0));
+
+      async function withdrawnLock() {
+        const user = await newUser();
+        const { mint, userAta } = await fundedMint(user, 100);
+        const { lockAccount, vaultAta } = await initSplLock(
+          user,
+          mint,
+          userAta,
+          100,
+          (await now()) + 2
+        );
+        assert.equal(
+          (await program.account.timeLockAccount.fetch(lockAccount)).bondPaid.toNumber(),
+          BOND
+        );
+        await sleep(3_000);
+        await withdrawSpl(user, mint, userAta, lockAccount, vaultAta);
+        return { user, mint, lockAccount, vaultAta };
+      }
+
+      it("refunds the bond when the lock closes normally", async () => {
+        const { user, mint, lockAccount, vaultAta } = await withdrawnLock();
+        const lockLamports = await connection.getBalance(lockAccount);
+        const vaultLamports = await connection.getBalance(vaultAta);
+        const before = await connection.getBalance(user.publicKey);
+        await closeSplLock(user, mint, lockAccount, vaultAta);
+        // The user pays the transaction fee out of the refund.
+        assert.isAbove(
+          await connection.getBalance(user.publicKey),
+          before + lockLamports + vaultLamports - 10_000
+        );
+      });
+
+      // Only a lock emptied out-of-band forfeits its bond; a withdrawn one was never orphaned.
+      it("refunds the bond when the admin force-closes a withdrawn lock", async () => {
+        const { user, lockAccount, vaultAta } = await withdrawnLock();
+        assert.isTrue((await program.account.timeLockAccount.fetch(lockAccount)).paidOut);
+        const rent =
+          (await connection.getBalance(lockAccount)) +
+          (await connection.getBalance(vaultAta));
+        const before = await connection.getBalance(user.publicKey);
+        const signature = await forceClose(payer, user, lockAccount, vaultAta);
+
+        assert.equal(await connection.getBalance(user.publicKey), before + rent);
+        const [event] = await eventsOf(signature);
+        assert.equal(event.data.bondForfeited.toNumber(), 0);
+      });
+    });
+  });
+
+  describe("unlock rounding", () => {
+    const DAY = 86_400;
+
+    it("rounds the unlock up to the next day boundary", async () => {
+      const user = await newUser();
+      const requested = (await now()) + 60;
+      const { lockAccount } = await initSolLock(user, LAMPORTS_PER_SOL, requested, {
+        roundToSeconds: DAY,
+      });
+      const lock = await program.account.timeLockAccount.fetch(lockAccount);
+      assert.equal(lock.unlockTimestamp.toNumber(), Math.ceil(requested / DAY) * DAY);
+    });
+
+    it("rejects a granularity outside (0, 1 week]", async () => {
+      const user = await newUser();
+      const unlock = (await now()) + 60;
+      await expectError(
+        initSolLock(user, LAMPORTS_PER_SOL, unlock, { roundToSeconds: -1 }),
+        "InvalidDuration"
+      );
+      await expectError(
+        initSolLock(user, LAMPORTS_PER_SOL, unlock, { roundToSeconds: 8 * DAY }),
+        "InvalidDuration"
+      );
+    });
+  });
+
+  describe("partial SPL relock", () => {
+    const relock = (user, mint, userAta, lockAccount, vaultAta, withdraw, keep, unlock) =>
+      program.methods
+        .partialWithdrawAndRelockSpl(new BN(withdraw), new BN(keep), new BN(unlock))
+        .accountsPartial({
+          authority: user.publicKey,
+          initializer: user.publicKey,
+          lockAccount,
+          mint,
+          userAta,
+          vaultAta,
+          vaultAuthority: null,
+          priceFeed: null,
+          config: configPda,
+          treasuryAta: null,
+          stats: statsPda,
+          lockSummary: null,
+          condition: null,
+          notifyProgram: null,
+          allowedNotifyProgram: null,
+          rewardPoolAta: null,
+          rewardPoolAuthority: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user])
+        .rpc();
+
+    it("pays out part and relocks the rest", async () => {
+      const user = await newUser();
+      const { mint, userAta } = await fundedMint(user, 100);
+      const unlock = (await now()) + 2;
+      const { lockAccount, vaultAta } = await initSplLock(user, mint, userAta, 100, unlock);
+
+      await expectError(
+        relock(user, mint, userAta, lockAccount, vaultAta, 40, 60, unlock + 3_600),
+        "TimeLockNotExpired"
+      );
+      await waitUntil(unlock);
+      await expectError(
+        relock(user, mint, userAta, lockAccount, vaultAta, 50, 51, unlock + 3_600),
+        "InsufficientVaultBalance"
+      );
+
+      const newUnlock = (await now()) + 3_600;
+      await relock(user, mint, userAta, lockAccount, vaultAta, 40, 60, newUnlock);
+      assert.equal(Number((await getAccount(connection, userAta)).amount), 40);
+      assert.equal(Number((await getAccount(connection, vaultAta)).amount), 60);
+      const lock = await program.account.timeLockAccount.fetch(lockAccount);
+      assert.equal(lock.amount.toNumber(), 60);
+      assert.equal(lock.unlockTimestamp.toNumber(), newUnlock);
+      await expectError(
+        withdrawSpl(user, mint, userAta, lockAccount, vaultAta),
+        "TimeLockNotExpired"
+      );
+    });
+
+    it("pins later withdrawals to the first partial's destination", async () => {
+      const user = await newUser();
+      const { mint, userAta } = await fundedMint(user, 100);
+      const unlock = (await now()) + 2;
+      const { lockAccount, vaultAta } = await initSplLock(user, mint, userAta, 100, unlock);
+      // A second token account of the same owner and mint, as a relayer might substitute.
+      const otherAta = await createAccount(connection, payer, mint, user.publicKey, Keypair.generate());
+      await waitUntil(unlock);
+
+      const newUnlock = (await now()) + 2;
+      await relock(user, mint, userAta, lockAccount, vaultAta, 40, 60, newUnlock);
+      const lock = await program.account.timeLockAccount.fetch(lockAccount);
+      assert.isTrue(lock.withdrawDestination.equals(userAta));
+      await waitUntil(newUnlock);
+
+      // Withdrawals only pay the initializer's ATA, so the substitute is refused outright.
+      await expectError(
+        withdrawSpl(user, mint, otherAta, lockAccount, vaultAta),
+        "AccountNotAssociatedTokenAccount"
+      );
+      await withdrawSpl(user, mint, userAta, lockAccount, vaultAta);
+      assert.equal(Number((await getAccount(connection, userAta)).amount), 100);
+    });
+
+    it("holds partials to the withdraw step but lets the final withdrawal drain the rest", async () => {
+      const user = await newUser();
+      const { mint, userAta } = await fundedMint(user, 105);
+      const unlock = (await now()) + 2;
+      const { lockAccount, vaultAta } = await initSplLock(user, mint, userAta, 105, unlock);
+      const setStep = (step) =>
+        program.methods
+          .setWithdrawStep(new BN(step))
+          .accountsPartial({ initializer: user.publicKey, lockAccount })
+          .signers([user])
+          .rpc();
+      await expectError(setStep(106), "InvalidAmount");
+      await setStep(10);
+      await waitUntil(unlock);
+
+      const newUnlock = (await now()) + 2;
+      await expectError(
+        relock(user, mint, userAta, lockAccount, vaultAta, 15, 90, newUnlock),
+        "NotStepAligned"
+      );
+      await relock(user, mint, userAta, lockAccount, vaultAta, 10, 95, newUnlock);
+      await waitUntil(newUnlock);
+      // 95 isn't a multiple of 10, but a full withdrawal isn't a partial.
+      await withdrawSpl(user, mint, userAta, lockAccount, vaultAta);
+      assert.equal(Number((await getAccount(connection, userAta)).amount), 105);
+    });
+
+    it("stops partials at the cap, across relocks, but still allows the final drain", async () => {
+      const user = await newUser();
+      const { mint, userAta } = await fundedMint(user, 100);
+      const unlock = (await now()) + 2;
+      const { lockAccount, vaultAta } = await initSplLock(user, mint, userAta, 100, unlock);
+      const setMax = (max) =>
+        program.methods
+          .setMaxWithdrawals(max)
+          .accountsPartial({ initializer: user.publicKey, lockAccount })
+          .signers([user])
+          .rpc();
+      await setMax(3);
+      await setMax(2);
+      // Tightening only.
+      await expectError(setMax(3), "InvalidAmount");
+      await waitUntil(unlock);
+
+      let newUnlock = (await now()) + 2;
+      await relock(user, mint, userAta, lockAccount, vaultAta, 10, 90, newUnlock);
+      await waitUntil(newUnlock);
+      newUnlock = (await now()) + 2;
+      await relock(user, mint, userAta, lockAccount, vaultAta, 10, 80, newUnlock);
+      const lock = await program.account.timeLockAccount.fetch(lockAccount);
+      assert.equal(lock.withdrawalsMade, 2);
+      await waitUntil(newUnlock);
+
+      await expectError(
+        relock(user, mint, userAta, lockAccount, vaultAta, 10, 70, (await now()) + 2),
+        "MaxWithdrawalsReached"
+      );
+      await withdrawSpl(user, mint, userAta, lockAccount, vaultAta);
+      assert.equal(Number((await getAccount(connection, userAta)).amount), 100);
+    });
+  });
+
+  describe("auto-extend on deposit", () => {
+    it("moves the unlock past each top-up but never shortens it", async () => {
+      const user = await newUser();
+      const unlock = (await now()) + 60;
+      const { lockAccount } = await initSolLock(user, LAMPORTS_PER_SOL, unlock);
+      await expectError(
+        program.methods
+          .setAutoExtend(new BN(-1))
+          .accountsPartial({ initializer: user.publicKey, lockAccount })
+          .signers([user])
+          .rpc(),
+        "InvalidDuration"
+      );
+      await program.methods
+        .setAutoExtend(new BN(3_600))
+        .accountsPartial({ initializer: user.publicKey, lockAccount })
+        .signers([user])
+        .rpc();
+
+      const topup = (newUnlock) =>
+        program.methods
+          .topupAndExtendSol(new BN(LAMPORTS_PER_SOL / 10), new BN(newUnlock))
+          .accountsPartial({
+            initializer: user.publicKey,
+            lockAccount,
+            config: configPda,
+            stats: statsPda,
+            lockSummary: null,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([user])
+          .rpc();
+
+      const before = await now();
+      const events = await eventsOf(await topup(unlock));
+      const extended = events.find((e) => e.name === "unlockAutoExtended");
+      assert.ok(extended);
+      const lock = await program.account.timeLockAccount.fetch(lockAccount);
+      assert.isAtLeast(lock.unlockTimestamp.toNumber(), before + 3_600);
+      assert.equal(extended.data.unlockTimestamp.toNumber(), lock.unlockTimestamp.toNumber());
+
+      // A later explicit unlock beyond the auto-extend window wins.
+      const far = (await now()) + 7_200;
+      const farEvents = await eventsOf(await topup(far));
+      assert.isUndefined(farEvents.find((e) => e.name === "unlockAutoExtended"));
+      assert.equal(
+        (await program.account.timeLockAccount.fetch(lockAccount)).unlockTimestamp.toNumber(),
+        far
+      );
+    });
+  });
+
+  describe("lock summaries", () => {
+    async function assertInSync(lockAccount, isWithdrawn) {
+      const summary = await program.account.lockSummary.fetch(summaryPda(lockAccount));
+      const lock = await program.account.timeLockAccount.fetchNullable(lockAccount);
+      if (lock !== null) {
+        assert.equal(summary.unlockTimestamp.toNumber(), lock.unlockTimestamp.toNumber());
+      }
+      assert.equal(summary.isWithdrawn, isWithdrawn);
+    }
+
+    it("tracks a SOL lock through top-up and withdrawal", async () => {
+      const user = await newUser();
+      const unlock = (await now()) + 3;
+      const { lockAccount } = await initSolLock(user, LAMPORTS_PER_SOL, unlock);
+      const lockSummary = summaryPda(lockAccount);
+      await program.methods
+        .createLockSummary()
+        .accountsPartial({
+          initializer: user.publicKey,
+          lockAccount,
+          lockSummary,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+      await assertInSync(lockAccount, false);
+
+      const topup = (summary) =>
+        program.methods
+          .topupAndExtendSol(new BN(LAMPORTS_PER_SOL / 10), new BN(unlock + 2))
+          .accountsPartial({
+            initializer: user.publicKey,
+            lockAccount,
+            config: configPda,
+            stats: statsPda,
+            lockSummary: summary,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([user])
+          .rpc();
+      await expectError(topup(null), "SummaryMissing");
+      await topup(lockSummary);
+      await assertInSync(lockAccount, false);
+
+      await waitUntil(unlock + 2);
+      await program.methods
+        .withdrawSol()
+        .accountsPartial({
+          authority: user.publicKey,
+          initializer: user.publicKey,
+          lockAccount,
+          savingsLock: null,
+          config: configPda,
+          stats: statsPda,
+          lockSummary,
+          condition: null,
+          rentRecipient: null,
+          notifyProgram: null,
+          allowedNotifyProgram: null,
+          treasury: await treasury(),
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+      assert.isNull(await connection.getAccountInfo(lockAccount));
+      await assertInSync(lockAccount, true);
+    });
+  });
+
+  describe("vault authority", () => {
+    it("refuses to pay out of a vault the lock no longer controls", async () => {
+      const user = await newUser();
+      const { mint, userAta } = await fundedMint(user, 100);
+      const unlock = (await now()) + 2;
+      const { lockAccount } = await initSplLock(user, mint, userAta, 100, unlock);
+      // Same mint, but its authority is someone else, as if the vault's had been reassigned.
+      const outsider = await newUser();
+      const foreignVault = await getOrCreateAssociatedTokenAccount(
+        connection,
+        payer,
+        mint,
+        outsider.publicKey
+      );
+      await waitUntil(unlock);
+
+      await expectError(
+        withdrawSpl(user, mint, userAta, lockAccount, foreignVault.address),
+        "VaultAuthorityChanged"
+      );
+    });
+
+    it("lets the admin migrate a vault to a new authority PDA", async () => {
+      const user = await newUser();
+      const { mint, userAta } = await fundedMint(user, 100);
+      const unlock = (await now()) + 2;
+      const { lockAccount, vaultAta } = await initSplLock(user, mint, userAta, 100, unlock);
+      const newAuthority = vaultAuthorityPda(lockAccount);
+      const migrate = (admin) =>
+        program.methods
+          .migrateVaultAuthority()
+          .accountsPartial({
+            admin: admin.publicKey,
+            config: configPda,
+            lockAccount,
+            vaultAta,
+            newAuthority,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers(admin === payer ? [] : [admin])
+          .rpc();
+
+      await expectError(migrate(user), "Unauthorized");
+      await migrate(payer);
+      const vault = await getAccount(connection, vaultAta);
+      assert.isTrue(vault.owner.equals(newAuthority));
+      const lock = await program.account.timeLockAccount.fetch(lockAccount);
+      assert.isTrue(lock.vaultAuthority.equals(newAuthority));
+      await expectError(migrate(payer), "VaultAuthorityChanged");
+      await waitUntil(unlock);
+
+      await expectError(
+        withdrawSpl(user, mint, userAta, lockAccount, vaultAta),
+        "VaultAuthorityMissing"
+      );
+      await withdrawSpl(user, mint, userAta, lockAccount, vaultAta, null, null, newAuthority);
+      assert.equal(Number((await getAccount(connection, userAta)).amount), 100);
+      await closeSplLock(user, mint, lockAccount, vaultAta, newAuthority);
+      assert.isNull(await connection.getAccountInfo(lockAccount));
+    });
+  });
+
+  describe("SPL baskets", () => {
+    it("locks several mints together and releases them in one call", async () => {
+      const user = await newUser();
+      const basket = basketPda(user.publicKey);
+      const unlock = (await now()) + 4;
+      const signature = await program.methods
+        .initializeBasket(new BN(unlock), 3)
+        .accountsPartial({
+          initializer: user.publicKey,
+          basket,
+          config: configPda,
+          stats: statsPda,
+          invite: null,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+      const created = (await eventsOf(signature)).find((e) => e.name === "lockCreated");
+      assert.isTrue(created.data.lockAccount.equals(basket));
+      assert.deepEqual(created.data.category, { escrow: {} });
+      assert.isNull(created.data.mint);
+      assert.deepEqual((await program.account.basket.fetch(basket)).category, { escrow: {} });
+
+      const addMint = (mint, userAta, amount) =>
+        program.methods
+          .addBasketMint(new BN(amount))
+          .accountsPartial({
+            initializer: user.publicKey,
+            basket,
+            mint,
+            userAta,
+            vaultAta: getAssociatedTokenAddressSync(mint, basket, true),
+            config: configPda,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([user])
+          .rpc();
+
+      const first = await fundedMint(user, 100);
+      const second = await fundedMint(user, 50);
+      await addMint(first.mint, first.userAta, 60);
+      await addMint(second.mint, second.userAta, 50);
+      await expectError(addMint(first.mint, first.userAta, 10), "BasketMintExists");
+
+      const stored = await program.account.basket.fetch(basket);
+      assert.deepEqual(
+        stored.mints.map((m) => m.toBase58()),
+        [first.mint.toBase58(), second.mint.toBase58()]
+      );
+
+      const remaining = [first, second].flatMap(({ mint, userAta }) => [
+        {
+          pubkey: getAssociatedTokenAddressSync(mint, basket, true),
+          isSigner: false,
+          isWritable: true,
+        },
+        { pubkey: userAta, isSigner: false, isWritable: true },
+        // No fee is configured, so the treasury slot is never read.
+        { pubkey: userAta, isSigner: false, isWritable: true },
+      ]);
+      const withdraw = (accounts) =>
+        program.methods
+          .withdrawBasket()
+          .accountsPartial({
+            initializer: user.publicKey,
+            basket,
+            config: configPda,
+            stats: statsPda,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .remainingAccounts(accounts)
+          .signers([user])
+          .rpc();
+
+      await expectError(withdraw(remaining), "TimeLockNotExpired");
+      await waitUntil(unlock);
+      await expectError(withdraw(remaining.slice(0, 3)), "BasketAccountsMismatch");
+      await withdraw(remaining);
+
+      assert.equal(Number((await getAccount(connection, first.userAta)).amount), 100);
+      assert.equal(Number((await getAccount(connection, second.userAta)).amount), 50);
+      assert.isNull(await connection.getAccountInfo(basket));
+    });
+  });
+
+  describe("rent recipients", () => {
+    const assign = (signer, lockAccount, newRecipient) =>
+      program.methods
+        .assignRentRecipient(newRecipient)
+        .accountsPartial({ rentRecipient: signer.publicKey, lockAccount })
+        .signers([signer])
+        .rpc();
+
+    it("sends the rent reserve to the assigned recipient on close", async () => {
+      const user = await newUser();
+      const custodian = await newUser(1);
+      const unlock = (await now()) + 3;
+      const { lockAccount } = await initSolLock(user, LAMPORTS_PER_SOL, unlock);
+      const reserve = await connection.getMinimumBalanceForRentExemption(
+        (await connection.getAccountInfo(lockAccount)).data.length
+      );
+
+      const [event] = await eventsOf(await assign(user, lockAccount, custodian.publicKey));
+      assert.equal(event.name, "rentRecipientChanged");
+      assert.equal(event.data.oldRecipient.toBase58(), user.publicKey.toBase58());
+      // The initializer gave the right away, so only the custodian can move it now.
+      await expectError(assign(user, lockAccount, user.publicKey), "Unauthorized");
+
+      const withdraw = async (rentRecipient) =>
+        program.methods
+          .withdrawSol()
+          .accountsPartial({
+            authority: user.publicKey,
+            initializer: user.publicKey,
+            lockAccount,
+            savingsLock: null,
+            config: configPda,
+            stats: statsPda,
+            lockSummary: null,
+            condition: null,
+            rentRecipient,
+            notifyProgram: null,
+            allowedNotifyProgram: null,
+            treasury: await treasury(),
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([user])
+          .rpc();
+
+      await waitUntil(unlock);
+      await expectError(withdraw(null), "RentRecipientMissing");
+      const before = await connection.getBalance(custodian.publicKey);
+      await withdraw(custodian.publicKey);
+      assert.equal(await connection.getBalance(custodian.publicKey), before + reserve);
+    });
+  });
+
+  describe("capabilities", () => {
+    const CAP_BASKETS = 1 << 2;
+    const CAP_CREATION_BOND = 1 << 19;
+
+    async function capabilities() {
+      const signature = await program.methods
+        .getCapabilities()
+        .accountsPartial({ config: configPda })
+        .rpc();
+      const [event] = await eventsOf(signature);
+      assert.equal(event.name, "capabilities");
+      return event.data;
+    }
+    const setBond = (lamports) =>
+      program.methods
+        .setBond(new BN(lamports))
+        .accountsPartial({ admin: payer.publicKey, config: configPda })
+        .rpc();
+
+    it("reports the version, built-in features and config-gated ones", async () => {
+      const caps = await capabilities();
+      assert.equal(caps.version, "0.1.0");
+      assert.ok(caps.features.toNumber() & CAP_BASKETS);
+      assert.notOk(caps.features.toNumber() & CAP_CREATION_BOND);
+
+      await setBond(1_000);
+      try {
+        assert.ok((await capabilities()).features.toNumber() & CAP_CREATION_BOND);
+      } finally {
+        await setBond(0);
+      }
+    });
+
+    it("reports the effective config and the program's hard limits", async () => {
+      const signature = await program.methods
+        .getConfig()
+        .accountsPartial({ config: configPda })
+        .rpc();
+      const [event] = await eventsOf(signature);
+      assert.equal(event.name, "effectiveConfig");
+      const config = await program.account.config.fetch(configPda);
+      assert.ok(event.data.admin.equals(config.admin));
+      assert.equal(event.data.maxTvlLamports.toString(), config.maxTvlLamports.toString());
+      assert.equal(event.data.feeTiers.length, config.feeTiers.length);
+      assert.equal(event.data.maxFeeBps, 500);
+      assert.equal(event.data.maxKeeperTipLamports.toNumber(), 100_000);
+      assert.isFalse(event.data.withdrawalsFrozen);
+    });
+
+    it("estimates creation cost from cluster rent, the vault and the bond", async () => {
+      const estimate = async (kind) => {
+        const signature = await program.methods
+          .estimateCreationCost(kind)
+          .accountsPartial({ config: configPda })
+          .rpc();
+        return (await eventsOf(signature))[0].data;
+      };
+      const user = await newUser();
+      const { lockAccount } = await initSolLock(user, LAMPORTS_PER_SOL, (await now()) + 60);
+      const lockInfo = await connection.getAccountInfo(lockAccount);
+      const lockRent = await connection.getMinimumBalanceForRentExemption(lockInfo.data.length);
+      const vaultRent = await connection.getMinimumBalanceForRentExemption(165);
+
+      const sol = await estimate({ sol: {} });
+      assert.equal(sol.lockRent.toNumber(), lockRent);
+      assert.equal(sol.vaultRent.toNumber(), 0);
+      assert.equal(sol.total.toNumber(), lockRent);
+
+      await setBond(1_000);
+      try {
+        const spl = await estimate({ spl: {} });
+        assert.equal(spl.vaultRent.toNumber(), vaultRent);
+        assert.equal(spl.bond.toNumber(), 1_000);
+        assert.equal(spl.total.toNumber(), lockRent + vaultRent + 1_000);
+        // Safes have a vault but pay no bond.
+        assert.equal((await estimate({ solAndSpl: {} })).bond.toNumber(), 0);
+      } finally {
+        await setBond(0);
+      }
+    });
+  });
+
+  describe("slot-based unlock", () => {
+    it("withdraws from the unlock slot onward and ignores the clock", async () => {
+      const user = await newUser();
+      const unlockSlot = (await connection.getSlot()) + 20;
+      const { lockAccount } = await initSolLock(user, LAMPORTS_PER_SOL, 0, { unlockSlot });
+      const lock = await program.account.timeLockAccount.fetch(lockAccount);
+      assert.equal(lock.unlockSlot.toNumber(), unlockSlot);
+      assert.equal(lock.unlockTimestamp.toNumber(), 0);
+
+      // A 0 timestamp would already have passed; only the slot counts.
+      await expectError(withdrawSol(user, lockAccount), "TimeLockNotExpired");
+      while ((await connection.getSlot()) < unlockSlot) {
+        await sleep(100);
+      }
+      await withdrawSol(user, lockAccount);
+      assert.isNull(await connection.getAccountInfo(lockAccount));
+    });
+
+    it("requires exactly one unlock mode", async () => {
+      const user = await newUser();
+      const slot = await connection.getSlot();
+      await expectError(
+        initSolLock(user, LAMPORTS_PER_SOL, (await now()) + 60, { unlockSlot: slot + 100 }),
+        "InvalidUnlockMode"
+      );
+      await expectError(
+        initSolLock(user, LAMPORTS_PER_SOL, 0, {
+          unlockSlot: slot + 100,
+          streamPeriods: 2,
+          streamPeriodSecs: 1,
+        }),
+        "InvalidUnlockMode"
+      );
+      await expectError(
+        initSolLock(user, LAMPORTS_PER_SOL, 0, { unlockSlot: slot }),
+        "UnlockInPast"
+      );
+    });
+  });
+
+  describe("quorum release", () => {
+    const quorumRelease = async (user, lockAccount, signers) =>
+      program.methods
+        .quorumRelease()
+        .accountsPartial({
+          initializer: user.publicKey,
+          lockAccount,
+          config: configPda,
+          stats: statsPda,
+          lockSummary: null,
+          condition: null,
+          rentRecipient: null,
+          treasury: await treasury(),
+        })
+        .remainingAccounts(
+          signers.map((signer) => ({ pubkey: signer.publicKey, isWritable: false, isSigner: true }))
+        )
+        .signers([...new Set(signers)])
+        .rpc();
+
+    it("releases early once the threshold of distinct signers approves", async () => {
+      const user = await newUser();
+      const [a, b, c] = [Keypair.generate(), Keypair.generate(), Keypair.generate()];
+      const { lockAccount } = await initSolLock(user, LAMPORTS_PER_SOL, (await now()) + 3_600, {
+        quorumSigners: [a.publicKey, b.publicKey, c.publicKey],
+        quorumThreshold: 2,
+      });
+
+      await expectError(quorumRelease(user, lockAccount, [a]), "ThresholdNotMet");
+      // The same signer passed twice still counts once.
+      await expectError(quorumRelease(user, lockAccount, [a, a]), "ThresholdNotMet");
+      // Signers outside the quorum don't count at all.
+      await expectError(quorumRelease(user, lockAccount, [a, user]), "ThresholdNotMet");
+
+      const before = await connection.getBalance(user.publicKey);
+      const [event] = await eventsOf(await quorumRelease(user, lockAccount, [a, c]));
+      assert.equal(event.name, "quorumReleased");
+      assert.equal(event.data.approvals, 2);
+      assert.equal(event.data.principal.toNumber(), LAMPORTS_PER_SOL);
+      assert.isNull(await connection.getAccountInfo(lockAccount));
+      assert.isAbove(await connection.getBalance(user.publicKey), before);
+    });
+
+    it("rejects malformed quorums and locks without one", async () => {
+      const user = await newUser();
+      const a = Keypair.generate();
+      const unlock = (await now()) + 3_600;
+      await expectError(
+        initSolLock(user, LAMPORTS_PER_SOL, unlock, { quorumSigners: [a.publicKey], quorumThreshold: 2 }),
+        "InvalidQuorum"
+      );
+      await expectError(
+        initSolLock(user, LAMPORTS_PER_SOL, unlock, {
+          quorumSigners: [a.publicKey, a.publicKey],
+          quorumThreshold: 1,
+        }),
+        "InvalidQuorum"
+      );
+      const { lockAccount } = await initSolLock(user, LAMPORTS_PER_SOL, unlock);
+      await expectError(quorumRelease(user, lockAccount, [a]), "InvalidQuorum");
+    });
+
+    it("stops a renounced quorum from releasing early, for good", async () => {
+      const user = await newUser();
+      const a = Keypair.generate();
+      const { lockAccount } = await initSolLock(user, LAMPORTS_PER_SOL, (await now()) + 3_600, {
+        quorumSigners: [a.publicKey],
+        quorumThreshold: 1,
+      });
+      const renounce = () =>
+        program.methods
+          .renounceAuthorities()
+          .accountsPartial({ initializer: user.publicKey, lockAccount, stats: statsPda })
+          .signers([user])
+          .rpc();
+
+      const [event] = await eventsOf(await renounce());
+      assert.equal(event.name, "authoritiesRenounced");
+      assert.ok(event.data.quorumSigners[0].equals(a.publicKey));
+      await expectError(quorumRelease(user, lockAccount, [a]), "InvalidQuorum");
+      await expectError(renounce(), "AuthorityRenounced");
+      await expectError(
+        program.methods
+          .setWithdrawDelegate(a.publicKey)
+          .accountsPartial({ initializer: user.publicKey, lockAccount })
+          .signers([user])
+          .rpc(),
+        "AuthorityRenounced"
+      );
+      const lock = await program.account.timeLockAccount.fetch(lockAccount);
+      assert.isTrue(lock.authoritiesRenounced);
+      assert.equal(lock.quorumThreshold, 0);
+    });
+  });
+
+  describe("SOL reconciliation", () => {
+    const reconcile = (user, lockAccount, repair) =>
+      program.methods
+        .reconcileSol(repair)
+        .accountsPartial({
+          initializer: user.publicKey,
+          lockAccount,
+          config: configPda,
+          stats: statsPda,
+        })
+        .signers([user])
+        .rpc();
+
+    it("reports drift and repairs the amount only when asked", async () => {
+      const user = await newUser();
+      const { lockAccount } = await initSolLock(user, LAMPORTS_PER_SOL, (await now()) + 3_600);
+      const extra = 5_000_000;
+      await provider.sendAndConfirm(
+        new anchor.web3.Transaction().add(
+          SystemProgram.transfer({ fromPubkey: user.publicKey, toPubkey: lockAccount, lamports: extra })
+        ),
+        [user]
+      );
+
+      let [event] = await eventsOf(await reconcile(user, lockAccount, false));
+      assert.equal(event.name, "reconciled");
+      assert.equal(event.data.oldAmount.toNumber(), LAMPORTS_PER_SOL);
+      assert.equal(event.data.newAmount.toNumber(), LAMPORTS_PER_SOL + extra);
+      assert.isFalse(event.data.repaired);
+      let lock = await program.account.timeLockAccount.fetch(lockAccount);
+      assert.equal(lock.amount.toNumber(), LAMPORTS_PER_SOL);
+
+      const statsBefore = await program.account.stats.fetch(statsPda);
+      [event] = await eventsOf(await reconcile(user, lockAccount, true));
+      assert.isTrue(event.data.repaired);
+      lock = await program.account.timeLockAccount.fetch(lockAccount);
+      assert.equal(lock.amount.toNumber(), LAMPORTS_PER_SOL + extra);
+      const statsAfter = await program.account.stats.fetch(statsPda);
+      assert.equal(statsAfter.totalSolLocked.sub(statsBefore.totalSolLocked).toNumber(), extra);
+    });
+  });
+
+  describe("withdrawal notifications", () => {
+    const notifyPda = (programId) => pda(Buffer.from("notify-program"), programId.toBuffer());
+    const setNotify = (user, lockAccount, notifyProgram) =>
+      program.methods
+        .setNotifyProgram(notifyProgram)
+        .accountsPartial({ initializer: user.publicKey, lockAccount })
+        .signers([user])
+        .rpc();
+    const withdraw = async (user, lockAccount, notifyProgram, allowedNotifyProgram) =>
+      program.methods
+        .withdrawSol()
+        .accountsPartial({
+          authority: user.publicKey,
+          initializer: user.publicKey,
+          lockAccount,
+          savingsLock: null,
+          config: configPda,
+          stats: statsPda,
+          lockSummary: null,
+          condition: null,
+          rentRecipient: null,
+          notifyProgram,
+          allowedNotifyProgram,
+          treasury: await treasury(),
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+
+    it("requires an allow-listed program and reverts when the notify fails", async () => {
+      // This program has no on_lock_withdrawn instruction, so notifying it always fails.
+      const target = program.programId;
+      const allowed = notifyPda(target);
+      const allow = (admin) =>
+        program.methods
+          .allowNotifyProgram(target)
+          .accountsPartial({ admin: admin.publicKey, config: configPda, notifyProgram: allowed })
+          .signers(admin === payer ? [] : [admin])
+          .rpc();
+
+      const user = await newUser();
+      const unlock = (await now()) + 2;
+      const { lockAccount } = await initSolLock(user, LAMPORTS_PER_SOL, unlock);
+      await setNotify(user, lockAccount, target);
+      await waitUntil(unlock);
+
+      await expectError(withdraw(user, lockAccount, null, null), "NotifyProgramMissing");
+      await expectError(allow(user), "Unauthorized");
+      if ((await connection.getAccountInfo(allowed)) === null) {
+        await allow(payer);
+      }
+      await expectError(withdraw(user, lockAccount, target, allowed), "NotifyFailed");
+
+      // Clearing the hook is always possible, so a failing program can't trap the funds.
+      await setNotify(user, lockAccount, null);
+      await withdraw(user, lockAccount, null, null);
+      assert.isNull(await connection.getAccountInfo(lockAccount));
+
+      await program.methods
+        .disallowNotifyProgram()
+        .accountsPartial({ admin: payer.publicKey, config: configPda, notifyProgram: allowed })
+        .rpc();
+      assert.isNull(await connection.getAccountInfo(allowed));
+    });
+  });
+
+  describe("lock entire balance", () => {
+    const lockAll = (user, mint, userAta, unlock) => {
+      const lockAccount = splLockPda(user.publicKey);
+      return program.methods
+        .lockAllSpl(new BN(unlock), 0)
+        .accountsPartial({
+          initializer: user.publicKey,
+          lockAccount,
+          mint,
+          userAta,
+          vaultAta: getAssociatedTokenAddressSync(mint, lockAccount, true),
+          config: configPda,
+          stats: statsPda,
+          lockSummary: null,
+          invite: null,
+          rewardPoolAta: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+    };
+
+    it("locks whatever the token account holds at execution", async () => {
+      const user = await newUser();
+      const { mint, userAta } = await fundedMint(user, 250);
+      await lockAll(user, mint, userAta, (await now()) + 60);
+
+      const lockAccount = splLockPda(user.publicKey);
+      const lock = await program.account.timeLockAccount.fetch(lockAccount);
+      assert.equal(lock.amount.toNumber(), 250);
+      const vaultAta = getAssociatedTokenAddressSync(mint, lockAccount, true);
+      assert.equal(Number((await getAccount(connection, vaultAta)).amount), 250);
+      assert.equal(Number((await getAccount(connection, userAta)).amount), 0);
+    });
+
+    it("rejects an empty token account", async () => {
+      const user = await newUser();
+      const mint = await createMint(connection, payer, payer.publicKey, null, 6);
+      const empty = await getOrCreateAssociatedTokenAccount(connection, payer, mint, user.publicKey);
+      await expectError(lockAll(user, mint, empty.address, (await now()) + 60), "InvalidAmount");
+    });
+  });
+
+  describe("lock categories", () => {
+    it("stores the category and reports it when the lock is created", async () => {
+      const user = await newUser();
+      const unlock = (await now()) + 60;
+      const { lockAccount, signature } = await initSolLock(user, LAMPORTS_PER_SOL, unlock, {
+        category: 4,
+      });
+      const lock = await program.account.timeLockAccount.fetch(lockAccount);
+      assert.deepEqual(lock.category, { gift: {} });
+      const created = (await eventsOf(signature)).find((e) => e.name === "lockCreated");
+      assert.deepEqual(created.data.category, { gift: {} });
+      assert.equal(created.data.amount.toNumber(), LAMPORTS_PER_SOL);
+    });
+
+    it("rejects an unknown category", async () => {
+      const user = await newUser();
+      await expectError(
+        initSolLock(user, LAMPORTS_PER_SOL, (await now()) + 60, { category: 5 }),
+        "InvalidCategory"
+      );
+    });
+  });
+
+  describe("condition gates", () => {
+    const conditionPda = (authority, id) =>
+      pda(Buffer.from("condition"), authority.toBuffer(), new BN(id).toArrayLike(Buffer, "le", 8));
+
+    it("holds withdrawal until the condition's authority releases it", async () => {
+      const authority = await newUser();
+      const condition = conditionPda(authority.publicKey, 1);
+      await program.methods
+        .createCondition(new BN(1))
+        .accountsPartial({ authority: authority.publicKey, condition })
+        .signers([authority])
+        .rpc();
+      const release = (signer) =>
+        program.methods
+          .releaseCondition()
+          .accountsPartial({ authority: signer.publicKey, condition })
+          .signers([signer])
+          .rpc();
+
+      const user = await newUser();
+      const { mint, userAta } = await fundedMint(user, 100);
+      const unlock = (await now()) + 2;
+      const { lockAccount, vaultAta } = await initSplLock(user, mint, userAta, 100, unlock);
+      const setCondition = () =>
+        program.methods
+          .setConditionAccount()
+          .accountsPartial({ initializer: user.publicKey, lockAccount, condition })
+          .signers([user])
+          .rpc();
+      await setCondition();
+      await expectError(setCondition(), "ConditionAlreadySet");
+      await waitUntil(unlock);
+
+      const withdraw = (conditionAccount) =>
+        program.methods
+          .withdrawSpl()
+          .accountsPartial({
+            authority: user.publicKey,
+            initializer: user.publicKey,
+            lockAccount,
+            mint,
+            userAta,
+            vaultAta,
+            vaultAuthority: null,
+            priceFeed: null,
+            config: configPda,
+            treasuryAta: null,
+            stats: statsPda,
+            lockSummary: null,
+            condition: conditionAccount,
+            notifyProgram: null,
+            allowedNotifyProgram: null,
+            rewardPoolAta: null,
+            rewardPoolAuthority: null,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([user])
+          .rpc();
+
+      await expectError(withdraw(null), "ConditionNotMet");
+      await expectError(withdraw(condition), "ConditionNotMet");
+      await expectError(release(user), "Unauthorized");
+      await release(authority);
+      await withdraw(condition);
+      assert.equal(Number((await getAccount(connection, userAta)).amount), 100);
+    });
+  });
+
+  describe("explain unlock", () => {
+    const explain = async (lockAccount, condition = null) => {
+      const signature = await program.methods
+        .explainUnlock()
+        .accountsPartial({ lockAccount, priceFeed: null, condition })
+        .rpc();
+      return (await eventsOf(signature))[0].data;
+    };
+
+    it("lists only the configured conditions and flips once they are met", async () => {
+      const user = await newUser();
+      const unlock = (await now()) + 2;
+      const { lockAccount } = await initSolLock(user, LAMPORTS_PER_SOL, unlock);
+
+      const early = await explain(lockAccount);
+      assert.deepEqual(
+        early.conditions.map((c) => [Object.keys(c.kind)[0], c.met, c.gate]),
+        [["timestamp", false, false]]
+      );
+      assert.isFalse(early.withdrawable);
+
+      await waitUntil(unlock);
+      const late = await explain(lockAccount);
+      assert.isTrue(late.conditions[0].met);
+      assert.isTrue(late.withdrawable);
+    });
+
+    it("predicts the soonest boundary at which anything changes", async () => {
+      const nextEventAt = async (lockAccount) => {
+        const signature = await program.methods
+          .nextEventAt()
+          .accountsPartial({ lockAccount, config: configPda })
+          .rpc();
+        return (await eventsOf(signature))[0].data.nextEventAt.toString();
+      };
+      const user = await newUser();
+      const unlock = (await now()) + 30;
+      // The 2-second trial ends long before the unlock.
+      const { lockAccount } = await initSolLock(user, LAMPORTS_PER_SOL, unlock, { trialSeconds: 2 });
+      const lock = await program.account.timeLockAccount.fetch(lockAccount);
+      assert.equal(await nextEventAt(lockAccount), String(lock.fundedAt.toNumber() + 3));
+
+      await waitUntil(lock.fundedAt.toNumber() + 3);
+      assert.equal(await nextEventAt(lockAccount), String(unlock));
+
+      const other = await newUser();
+      const soon = (await now()) + 2;
+      const { lockAccount: plain } = await initSolLock(other, LAMPORTS_PER_SOL, soon);
+      await waitUntil(soon);
+      assert.equal(await nextEventAt(plain), "9223372036854775807");
+    });
+
+    it("reports an unreleased condition flag as a failing gate", async () => {
+      const authority = await newUser();
+      const condition = pda(
+        Buffer.from("condition"),
+        authority.publicKey.toBuffer(),
+        new BN(7).toArrayLike(Buffer, "le", 8)
+      );
+      await program.methods
+        .createCondition(new BN(7))
+        .accountsPartial({ authority: authority.publicKey, condition })
+        .signers([authority])
+        .rpc();
+
+      const user = await newUser();
+      const { mint, userAta } = await fundedMint(user, 100);
+      const unlock = (await now()) + 2;
+      const { lockAccount } = await initSplLock(user, mint, userAta, 100, unlock);
+      await program.methods
+        .setConditionAccount()
+        .accountsPartial({ initializer: user.publicKey, lockAccount, condition })
+        .signers([user])
+        .rpc();
+      await waitUntil(unlock);
+
+      const report = await explain(lockAccount, condition);
+      const gate = report.conditions.find((c) => c.gate);
+      assert.deepEqual(Object.keys(gate.kind), ["conditionFlag"]);
+      assert.isFalse(gate.met);
+      assert.isFalse(report.withdrawable);
+    });
+  });
+
+  describe("SPL beneficiaries", () => {
+    it("pays a beneficiary with no SOL, creating their ATA from the lock's reserve", async () => {
+      const user = await newUser();
+      const beneficiary = Keypair.generate();
+      const { mint, userAta } = await fundedMint(user, 100);
+      const unlock = (await now()) + 2;
+      const { lockAccount, vaultAta } = await initSplLock(user, mint, userAta, 100, unlock);
+      await program.methods
+        .setSplBeneficiary(beneficiary.publicKey)
+        .accountsPartial({ initializer: user.publicKey, lockAccount, mint })
+        .signers([user])
+        .rpc();
+      const lock = await program.account.timeLockAccount.fetch(lockAccount);
+      assert.isAbove(lock.ataRentReserve.toNumber(), 0);
+      await waitUntil(unlock);
+
+      // The initializer's own withdrawal is pinned out.
+      await expectError(
+        withdrawSpl(user, mint, userAta, lockAccount, vaultAta),
+        "DestinationLocked"
+      );
+
+      const beneficiaryAta = getAssociatedTokenAddressSync(mint, beneficiary.publicKey);
+      const payerBefore = await connection.getBalance(payer.publicKey);
+      await program.methods
+        .withdrawSplToBeneficiary()
+        .accountsPartial({
+          payer: payer.publicKey,
+          lockAccount,
+          beneficiary: beneficiary.publicKey,
+          mint,
+          beneficiaryAta,
+          vaultAta,
+          vaultAuthority: null,
+          config: configPda,
+          treasuryAta: null,
+          stats: statsPda,
+          lockSummary: null,
+          condition: null,
+          notifyProgram: null,
+          allowedNotifyProgram: null,
+          rewardPoolAta: null,
+          rewardPoolAuthority: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+      assert.equal(Number((await getAccount(connection, beneficiaryAta)).amount), 100);
+      assert.equal(await connection.getBalance(beneficiary.publicKey), 0);
+      // The payer only covered the transaction fee; the ATA's rent came from the lock.
+      assert.isAbove(
+        await connection.getBalance(payer.publicKey),
+        payerBefore - 10_000
+      );
+      const after = await program.account.timeLockAccount.fetch(lockAccount);
+      assert.equal(after.ataRentReserve.toNumber(), 0);
+    });
+
+    it("corrects a beneficiary only with the arbiter's signature", async () => {
+      const user = await newUser();
+      const arbiter = await newUser();
+      const wrong = Keypair.generate().publicKey;
+      const right = Keypair.generate().publicKey;
+      const { mint, userAta } = await fundedMint(user, 100);
+      const { lockAccount } = await initSplLock(user, mint, userAta, 100, (await now()) + 3_600);
+      await program.methods
+        .setArbiter(arbiter.publicKey)
+        .accountsPartial({ initializer: user.publicKey, lockAccount })
+        .signers([user])
+        .rpc();
+      await program.methods
+        .setSplBeneficiary(wrong)
+        .accountsPartial({ initializer: user.publicKey, lockAccount, mint })
+        .signers([user])
+        .rpc();
+      await expectError(
+        program.methods
+          .setArbiter(user.publicKey)
+          .accountsPartial({ initializer: user.publicKey, lockAccount })
+          .signers([user])
+          .rpc(),
+        "DestinationLocked"
+      );
+
+      const forceUpdate = (arbiterKey, signers) =>
+        program.methods
+          .forceUpdateBeneficiary(right)
+          .accountsPartial({ initializer: user.publicKey, arbiter: arbiterKey, lockAccount, mint })
+          .signers(signers)
+          .rpc();
+      await expectError(forceUpdate(user.publicKey, [user]), "NotArbiter");
+      await expectError(forceUpdate(arbiter.publicKey, [user]), "MissingArbiterSignature");
+      const signature = await forceUpdate(arbiter.publicKey, [user, arbiter]);
+
+      const [event] = await eventsOf(signature);
+      assert.equal(event.name, "beneficiaryForceUpdated");
+      assert.isTrue(event.data.oldBeneficiary.equals(wrong));
+      const lock = await program.account.timeLockAccount.fetch(lockAccount);
+      assert.isTrue(lock.beneficiary.equals(right));
+      assert.isTrue(
+        lock.withdrawDestination.equals(getAssociatedTokenAddressSync(mint, right))
+      );
+    });
+
+    it("keeps paying the old beneficiary while a change waits out the delay", async () => {
+      const setDelay = (secs) =>
+        program.methods
+          .setBeneficiaryChangeDelay(new BN(secs))
+          .accountsPartial({ admin: payer.publicKey, config: configPda })
+          .rpc();
+      const user = await newUser();
+      const arbiter = await newUser();
+      const oldBeneficiary = Keypair.generate().publicKey;
+      const newBeneficiary = Keypair.generate().publicKey;
+      const { mint, userAta } = await fundedMint(user, 100);
+      const unlock = (await now()) + 3;
+      const { lockAccount, vaultAta } = await initSplLock(user, mint, userAta, 100, unlock);
+      await program.methods
+        .setArbiter(arbiter.publicKey)
+        .accountsPartial({ initializer: user.publicKey, lockAccount })
+        .signers([user])
+        .rpc();
+      await program.methods
+        .setSplBeneficiary(oldBeneficiary)
+        .accountsPartial({ initializer: user.publicKey, lockAccount, mint })
+        .signers([user])
+        .rpc();
+
+      await setDelay(3_600);
+      try {
+        const [event] = await eventsOf(
+          await program.methods
+            .forceUpdateBeneficiary(newBeneficiary)
+            .accountsPartial({ initializer: user.publicKey, arbiter: arbiter.publicKey, lockAccount, mint })
+            .signers([user, arbiter])
+            .rpc()
+        );
+        assert.isAbove(event.data.effectiveAt.toNumber(), await now());
+      } finally {
+        await setDelay(0);
+      }
+      const lock = await program.account.timeLockAccount.fetch(lockAccount);
+      assert.isTrue(lock.beneficiary.equals(oldBeneficiary));
+      assert.isTrue(lock.pendingBeneficiary.equals(newBeneficiary));
+      await expectError(
+        program.methods
+          .applyPendingBeneficiary()
+          .accountsPartial({ lockAccount, mint })
+          .rpc(),
+        "BeneficiaryChangeNotDue"
+      );
+
+      await waitUntil(unlock);
+      const payTo = (beneficiary) =>
+        program.methods
+          .withdrawSplToBeneficiary()
+          .accountsPartial({
+            payer: payer.publicKey,
+            lockAccount,
+            beneficiary,
+            mint,
+            beneficiaryAta: getAssociatedTokenAddressSync(mint, beneficiary),
+            vaultAta,
+            vaultAuthority: null,
+            config: configPda,
+            treasuryAta: null,
+            stats: statsPda,
+            lockSummary: null,
+            condition: null,
+            notifyProgram: null,
+            allowedNotifyProgram: null,
+            rewardPoolAta: null,
+            rewardPoolAuthority: null,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          })
+          .rpc();
+      await expectError(payTo(newBeneficiary), "BeneficiaryMismatch");
+      await payTo(oldBeneficiary);
+      const paid = await getAccount(connection, getAssociatedTokenAddressSync(mint, oldBeneficiary));
+      assert.equal(Number(paid.amount), 100);
+    });
+  });
+
+  describe("charity fallback", () => {
+    it("donates a lock left unclaimed past its grace period", async () => {
+      const charity = Keypair.generate().publicKey;
+      const allowedCharity = pda(Buffer.from("charity"), charity.toBuffer());
+      await program.methods
+        .allowCharity(charity)
+        .accountsPartial({ admin: payer.publicKey, config: configPda, charity: allowedCharity })
+        .rpc();
+
+      const user = await newUser();
+      const unlock = (await now()) + 2;
+      const { lockAccount } = await initSolLock(user, LAMPORTS_PER_SOL, unlock);
+      await program.methods
+        .setCharityFallback(new BN(2))
+        .accountsPartial({ initializer: user.publicKey, lockAccount, allowedCharity })
+        .signers([user])
+        .rpc();
+
+      const donate = () =>
+        program.methods
+          .donateAbandonedSol()
+          .accountsPartial({
+            initializer: user.publicKey,
+            lockAccount,
+            charity,
+            allowedCharity,
+            stats: statsPda,
+            lockSummary: null,
+            rentRecipient: null,
+          })
+          .rpc();
+
+      await waitUntil(unlock);
+      // Unlocked, but the owner still has the grace period to claim.
+      await expectError(donate(), "NotAbandoned");
+      await waitUntil(unlock + 2);
+      const signature = await donate();
+
+      const event = (await eventsOf(signature)).find((e) => e.name === "donatedFromAbandoned");
+      assert.equal(event.data.amount.toNumber(), LAMPORTS_PER_SOL);
+      assert.equal(await connection.getBalance(charity), LAMPORTS_PER_SOL);
+      assert.isNull(await connection.getAccountInfo(lockAccount));
+    });
+
+    it("counts a stream's grace from its final vest and refuses quorum locks", async () => {
+      const charity = Keypair.generate().publicKey;
+      const allowedCharity = pda(Buffer.from("charity"), charity.toBuffer());
+      await program.methods
+        .allowCharity(charity)
+        .accountsPartial({ admin: payer.publicKey, config: configPda, charity: allowedCharity })
+        .rpc();
+      const setFallback = (user, lockAccount) =>
+        program.methods
+          .setCharityFallback(new BN(2))
+          .accountsPartial({ initializer: user.publicKey, lockAccount, allowedCharity })
+          .signers([user])
+          .rpc();
+
+      const quorumUser = await newUser();
+      const { lockAccount: quorumLock } = await initSolLock(
+        quorumUser,
+        LAMPORTS_PER_SOL,
+        (await now()) + 3_600,
+        { quorumSigners: [Keypair.generate().publicKey], quorumThreshold: 1 }
+      );
+      await expectError(setFallback(quorumUser, quorumLock), "ReleaseModeConflict");
+
+      const user = await newUser();
+      const unlock = (await now()) + 2;
+      const { lockAccount } = await initSolLock(user, 200_000_000, unlock, {
+        streamPeriods: 2,
+        streamPeriodSecs: 8,
+      });
+      await setFallback(user, lockAccount);
+      await waitUntil(unlock);
+      await program.methods
+        .claimSolStream()
+        .accountsPartial({
+          initializer: user.publicKey,
+          lockAccount,
+          config: configPda,
+          stats: statsPda,
+          lockSummary: null,
+          condition: null,
+          treasury: await treasury(),
+        })
+        .signers([user])
+        .rpc();
+
+      // Past the grace counted from unlock, but the second period hasn't even vested yet.
+      await waitUntil(unlock + 3);
+      await expectError(
+        program.methods
+          .donateAbandonedSol()
+          .accountsPartial({
+            initializer: user.publicKey,
+            lockAccount,
+            charity,
+            allowedCharity,
+            stats: statsPda,
+            lockSummary: null,
+            rentRecipient: null,
+          })
+          .rpc(),
+        "NotAbandoned"
+      );
+      assert.equal(await connection.getBalance(charity), 0);
+    });
+  });
+
+  describe("withdrawal freeze", () => {
+    const setFrozen = (frozen, seconds) =>
+      program.methods
+        .setWithdrawalsFrozen(frozen, new BN(seconds))
+        .accountsPartial({ admin: payer.publicKey, config: configPda })
+        .rpc();
+
+    it("blocks withdrawals but not creations, and lifts itself", async () => {
+      const user = await newUser();
+      const unlock = (await now()) + 2;
+      await initSolLock(user, LAMPORTS_PER_SOL, unlock);
+      await waitUntil(unlock);
+
+      await expectError(setFrozen(true, 3 * 86_400), "InvalidDuration");
+      await setFrozen(true, 4);
+      try {
+        const frozenUntil = (await program.account.config.fetch(configPda)).frozenUntil.toNumber();
+        await expectError(withdrawSol(user), "WithdrawalsFrozen");
+        // Locks can still be created while frozen.
+        const other = await newUser();
+        await initSolLock(other, LAMPORTS_PER_SOL, (await now()) + 60);
+        // Back-to-back freezes would amount to an indefinite one.
+        await expectError(setFrozen(true, 4), "FreezeCooldown");
+
+        await waitUntil(frozenUntil);
+        await withdrawSol(user);
+      } finally {
+        await setFrozen(false, 0);
+      }
+    });
+  });
+
+  describe("reward multiplier snapshot", () => {
+    const setMultiplier = (bps) =>
+      program.methods
+        .setRewardMultiplier(bps)
+        .accountsPartial({ admin: payer.publicKey, config: configPda })
+        .rpc();
+
+    it("keeps the rate a lock was created with when the config changes", async () => {
+      await setMultiplier(15_000);
+      try {
+        const user = await newUser();
+        const { lockAccount } = await initSolLock(user, LAMPORTS_PER_SOL, (await now()) + 60);
+        await setMultiplier(20_000);
+        const lock = await program.account.timeLockAccount.fetch(lockAccount);
+        assert.equal(lock.rewardMultiplierBps, 15_000);
+
+        const later = await newUser();
+        const { lockAccount: laterLock } = await initSolLock(later, LAMPORTS_PER_SOL, (await now()) + 60);
+        assert.equal((await program.account.timeLockAccount.fetch(laterLock)).rewardMultiplierBps, 20_000);
+      } finally {
+        await setMultiplier(10_000);
+      }
+    });
+  });
+
+  describe("withdrawal accounting", () => {
+    it("reports principal, rent and fee separately", async () => {
+      const user = await newUser();
+      const unlock = (await now()) + 2;
+      const { lockAccount } = await initSolLock(user, LAMPORTS_PER_SOL, unlock);
+      const lockInfo = await connection.getAccountInfo(lockAccount);
+      const rent = await connection.getMinimumBalanceForRentExemption(lockInfo.data.length);
+      await waitUntil(unlock);
+
+      const signature = await withdrawSol(user);
+      const event = (await eventsOf(signature)).find((e) => e.name === "solWithdrawn");
+      assert.equal(
+        event.data.principalReturned.toNumber() + event.data.feeTaken.toNumber(),
+        LAMPORTS_PER_SOL
+      );
+      assert.equal(event.data.rentRefunded.toNumber(), rent);
+      assert.isTrue(event.data.rentRecipient.equals(user.publicKey));
+      assert.equal(event.data.saved.toNumber(), 0);
+    });
+  });
+
+  describe("withdraw delegates", () => {
+    it("lets the delegate trigger a withdrawal that still pays the owner", async () => {
+      const user = await newUser();
+      const delegate = await newUser();
+      const stranger = await newUser();
+      const { mint, userAta } = await fundedMint(user, 100);
+      const unlock = (await now()) + 2;
+      const { lockAccount, vaultAta } = await initSplLock(user, mint, userAta, 100, unlock);
+      await program.methods
+        .setWithdrawDelegate(delegate.publicKey)
+        .accountsPartial({ initializer: user.publicKey, lockAccount })
+        .signers([user])
+        .rpc();
+      await waitUntil(unlock);
+
+      const withdrawAs = (signer) =>
+        program.methods
+          .withdrawSpl()
+          .accountsPartial({
+            authority: signer.publicKey,
+            initializer: user.publicKey,
+            lockAccount,
+            mint,
+            userAta,
+            vaultAta,
+            vaultAuthority: null,
+            priceFeed: null,
+            config: configPda,
+            treasuryAta: null,
+            stats: statsPda,
+            lockSummary: null,
+            condition: null,
+            notifyProgram: null,
+            allowedNotifyProgram: null,
+            rewardPoolAta: null,
+            rewardPoolAuthority: null,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([signer])
+          .rpc();
+
+      await expectError(withdrawAs(stranger), "NotAuthorized");
+      await withdrawAs(delegate);
+      assert.equal(Number((await getAccount(connection, userAta)).amount), 100);
+    });
+  });
+
+  describe("auto-release", () => {
+    const setKeeperTip = (lamports) =>
+      program.methods
+        .setKeeperTip(new BN(lamports))
+        .accountsPartial({ admin: payer.publicKey, config: configPda })
+        .rpc();
+
+    const autoRelease = async (keeper, user, lockAccount) =>
+      program.methods
+        .autoRelease()
+        .accountsPartial({
+          keeper: keeper.publicKey,
+          initializer: user.publicKey,
+          lockAccount,
+          config: configPda,
+          stats: statsPda,
+          lockSummary: null,
+          condition: null,
+          rentRecipient: null,
+          notifyProgram: null,
+          allowedNotifyProgram: null,
+          treasury: await treasury(),
+        })
+        .signers([keeper])
+        .rpc();
+
+    it("lets any keeper release an opted-in lock to its owner for a capped tip", async () => {
+      await expectError(setKeeperTip(100_001), "InvalidAmount");
+      await setKeeperTip(5_000);
+      try {
+        const keeper = await newUser();
+        const user = await newUser();
+        const unlock = (await now()) + 2;
+        const { lockAccount } = await initSolLock(user, LAMPORTS_PER_SOL, unlock);
+
+        await expectError(autoRelease(keeper, user, lockAccount), "AutoReleaseNotEnabled");
+        await program.methods
+          .setAutoRelease(true)
+          .accountsPartial({ initializer: user.publicKey, lockAccount })
+          .signers([user])
+          .rpc();
+        await expectError(autoRelease(keeper, user, lockAccount), "TimeLockNotExpired");
+        await waitUntil(unlock);
+
+        const keeperBefore = await connection.getBalance(keeper.publicKey);
+        const userBefore = await connection.getBalance(user.publicKey);
+        const lockBalance = await connection.getBalance(lockAccount);
+        await autoRelease(keeper, user, lockAccount);
+
+        assert.equal(await connection.getBalance(keeper.publicKey), keeperBefore + 5_000);
+        // Principal less the tip, plus the lock's rent back.
+        assert.equal(
+          await connection.getBalance(user.publicKey),
+          userBefore + lockBalance - 5_000
+        );
+        assert.isNull(await connection.getAccountInfo(lockAccount));
+      } finally {
+        await setKeeperTip(0);
+      }
+    });
+  });
+
+  describe("deposit fees", () => {
+    const setDepositFee = (bps) =>
+      program.methods
+        .setDepositFee(bps)
+        .accountsPartial({ admin: payer.publicKey, config: configPda })
+        .rpc();
+    const rewardPoolAuthority = pda(Buffer.from("reward-pool"));
+
+    after(() => setDepositFee(0));
+
+    it("pays the deposit fee into the pool and refunds it after the full term", async () => {
+      await setDepositFee(100);
+      const user = await newUser();
+      const { mint, userAta } = await fundedMint(user, 1_000);
+      const rewardPoolAta = (
+        await getOrCreateAssociatedTokenAccount(connection, payer, mint, rewardPoolAuthority, true)
+      ).address;
+      const unlock = (await now()) + 2;
+
+      await expectError(
+        initSplLock(user, mint, userAta, 1_000, unlock),
+        "RewardPoolMissing"
+      );
+      const { lockAccount, vaultAta } = await initSplLock(user, mint, userAta, 1_000, unlock, {
+        rewardPoolAta,
+      });
+      assert.equal(Number((await getAccount(connection, vaultAta)).amount), 990);
+      assert.equal(Number((await getAccount(connection, rewardPoolAta)).amount), 10);
+      const lock = await program.account.timeLockAccount.fetch(lockAccount);
+      assert.equal(lock.amount.toNumber(), 990);
+      assert.equal(lock.depositFeeTaken.toNumber(), 10);
+
+      await waitUntil(unlock);
+      const signature = await withdrawSpl(
+        user,
+        mint,
+        userAta,
+        lockAccount,
+        vaultAta,
+        null,
+        null,
+        null,
+        rewardPoolAta,
+        rewardPoolAuthority
+      );
+      const event = (await eventsOf(signature)).find((e) => e.name === "depositFeeRefunded");
+      assert.equal(event.data.feeTaken.toNumber(), 10);
+      assert.equal(event.data.refunded.toNumber(), 10);
+      assert.equal(Number((await getAccount(connection, userAta)).amount), 1_000);
+      assert.equal(Number((await getAccount(connection, rewardPoolAta)).amount), 0);
+    });
+
+    it("refunds the fee on the claim that drains a vesting lock", async () => {
+      await setDepositFee(100);
+      const user = await newUser();
+      const { mint, userAta } = await fundedMint(user, 1_000);
+      const rewardPoolAta = (
+        await getOrCreateAssociatedTokenAccount(connection, payer, mint, rewardPoolAuthority, true)
+      ).address;
+      const unlock = (await now()) + 2;
+      const { lockAccount, vaultAta } = await initSplLock(user, mint, userAta, 1_000, unlock, {
+        rewardPoolAta,
+        vestingPeriods: 2,
+        vestingPeriodSecs: 3,
+      });
+      const claim = (pool) =>
+        program.methods
+          .claimVestedSpl()
+          .accountsPartial({
+            authority: user.publicKey,
+            initializer: user.publicKey,
+            lockAccount,
+            mint,
+            userAta,
+            vaultAta,
+            vaultAuthority: null,
+            priceFeed: null,
+            config: configPda,
+            treasuryAta: null,
+            stats: statsPda,
+            lockSummary: null,
+            condition: null,
+            notifyProgram: null,
+            allowedNotifyProgram: null,
+            rewardPoolAta: pool ? rewardPoolAta : null,
+            rewardPoolAuthority: pool ? rewardPoolAuthority : null,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([user])
+          .rpc();
+      await waitUntil(unlock);
+
+      // Only the last claim settles the fee, so an earlier one needs no pool.
+      let events = await eventsOf(await claim(false));
+      assert.isUndefined(events.find((e) => e.name === "depositFeeRefunded"));
+      await waitUntil(unlock + 3);
+      await expectError(claim(false), "RewardPoolMissing");
+      events = await eventsOf(await claim(true));
+      assert.equal(events.find((e) => e.name === "depositFeeRefunded").data.refunded.toNumber(), 10);
+      assert.equal(Number((await getAccount(connection, userAta)).amount), 1_000);
+      const lock = await program.account.timeLockAccount.fetch(lockAccount);
+      assert.equal(lock.depositFeeTaken.toNumber(), 0);
+    });
+
+    it("refunds the fee to the beneficiary along with the tokens", async () => {
+      await setDepositFee(100);
+      const user = await newUser();
+      const beneficiary = Keypair.generate();
+      const { mint, userAta } = await fundedMint(user, 1_000);
+      const rewardPoolAta = (
+        await getOrCreateAssociatedTokenAccount(connection, payer, mint, rewardPoolAuthority, true)
+      ).address;
+      const unlock = (await now()) + 2;
+      const { lockAccount, vaultAta } = await initSplLock(user, mint, userAta, 1_000, unlock, {
+        rewardPoolAta,
+      });
+      await program.methods
+        .setSplBeneficiary(beneficiary.publicKey)
+        .accountsPartial({ initializer: user.publicKey, lockAccount, mint })
+        .signers([user])
+        .rpc();
+      const beneficiaryAta = getAssociatedTokenAddressSync(mint, beneficiary.publicKey);
+      const payTo = (pool) =>
+        program.methods
+          .withdrawSplToBeneficiary()
+          .accountsPartial({
+            payer: payer.publicKey,
+            lockAccount,
+            beneficiary: beneficiary.publicKey,
+            mint,
+            beneficiaryAta,
+            vaultAta,
+            vaultAuthority: null,
+            config: configPda,
+            treasuryAta: null,
+            stats: statsPda,
+            lockSummary: null,
+            condition: null,
+            notifyProgram: null,
+            allowedNotifyProgram: null,
+            rewardPoolAta: pool ? rewardPoolAta : null,
+            rewardPoolAuthority: pool ? rewardPoolAuthority : null,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          })
+          .rpc();
+      await waitUntil(unlock);
+
+      await expectError(payTo(false), "RewardPoolMissing");
+      await payTo(true);
+      assert.equal(Number((await getAccount(connection, beneficiaryAta)).amount), 1_000);
+      assert.equal(Number((await getAccount(connection, rewardPoolAta)).amount), 0);
+    });
+
+    it("checks the minimum against what is left after the fee", async () => {
+      const setMinLockAmounts = (tokens) =>
+        program.methods
+          .setMinLockAmounts(new BN(0), new BN(tokens))
+          .accountsPartial({ admin: payer.publicKey, config: configPda })
+          .rpc();
+      await setDepositFee(100);
+      await setMinLockAmounts(100);
+      try {
+        const user = await newUser();
+        const { mint, userAta } = await fundedMint(user, 1_000);
+        const rewardPoolAta = (
+          await getOrCreateAssociatedTokenAccount(connection, payer, mint, rewardPoolAuthority, true)
+        ).address;
+        const unlock = (await now()) + 3_600;
+
+        // 100 sent, 1 to the fee: the lock would hold 99.
+        await expectError(
+          initSplLock(user, mint, userAta, 100, unlock, { rewardPoolAta }),
+          "BelowMinimumAmount"
+        );
+        const { lockAccount } = await initSplLock(user, mint, userAta, 102, unlock, { rewardPoolAta });
+        assert.equal((await program.account.timeLockAccount.fetch(lockAccount)).amount.toNumber(), 101);
+      } finally {
+        await setMinLockAmounts(0);
+      }
+    });
+  });
+
+  describe("delegated locks", () => {
+    const delegatedPda = (user) =>
+      pda(Buffer.from("time-lock-delegated"), user.toBuffer());
+    const initDelegatedLock = (user, mint, userAta, amount, unlock) =>
+      program.methods
+        .initializeLockDelegated(new BN(amount), new BN(unlock), 0)
+        .accountsPartial({
+          initializer: user.publicKey,
+          lockAccount: delegatedPda(user.publicKey),
+          mint,
+          userAta,
+          config: configPda,
+          stats: statsPda,
+          invite: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+    const withdrawDelegated = (user, mint, sourceAta, destinationAta) =>
+      program.methods
+        .withdrawDelegated()
+        .accountsPartial({
+          initializer: user.publicKey,
+          lockAccount: delegatedPda(user.publicKey),
+          mint,
+          sourceAta,
+          destinationAta,
+          config: configPda,
+          treasuryAta: null,
+          stats: statsPda,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user])
+        .rpc();
+
+    it("keeps the tokens in the owner's account and moves them only after unlock", async () => {
+      const user = await newUser();
+      const { mint, userAta } = await fundedMint(user, 100);
+      const unlock = (await now()) + 2;
+      await initDelegatedLock(user, mint, userAta, 60, unlock);
+      const lockAccount = delegatedPda(user.publicKey);
+
+      let source = await getAccount(connection, userAta);
+      assert.equal(Number(source.amount), 100);
+      assert.isTrue(source.delegate.equals(lockAccount));
+      assert.equal(Number(source.delegatedAmount), 60);
+
+      const destination = await createAccount(connection, payer, mint, user.publicKey, Keypair.generate());
+      await expectError(withdrawDelegated(user, mint, userAta, destination), "TimeLockNotExpired");
+      await waitUntil(unlock);
+      await withdrawDelegated(user, mint, userAta, destination);
+
+      assert.equal(Number((await getAccount(connection, userAta)).amount), 40);
+      assert.equal(Number((await getAccount(connection, destination)).amount), 60);
+      assert.isNull(await connection.getAccountInfo(lockAccount));
+    });
+
+    it("fails with DelegationRevoked after an external revoke, and can still be closed", async () => {
+      const user = await newUser();
+      const { mint, userAta } = await fundedMint(user, 100);
+      const unlock = (await now()) + 2;
+      await initDelegatedLock(user, mint, userAta, 60, unlock);
+      const lockAccount = delegatedPda(user.publicKey);
+      await revoke(connection, payer, userAta, user);
+
+      const destination = await createAccount(connection, payer, mint, user.publicKey, Keypair.generate());
+      await waitUntil(unlock);
+      await expectError(withdrawDelegated(user, mint, userAta, destination), "DelegationRevoked");
+
+      await program.methods
+        .closeDelegatedLock()
+        .accountsPartial({
+          initializer: user.publicKey,
+          lockAccount,
+          sourceAta: userAta,
+          stats: statsPda,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user])
+        .rpc();
+      assert.isNull(await connection.getAccountInfo(lockAccount));
+      assert.equal(Number((await getAccount(connection, userAta)).amount), 100);
+    });
+  });
+
+  describe("active locks", () => {
+    const activeLocks = async (owner) => {
+      const signature = await program.methods
+        .hasActiveLocks()
+        .accountsPartial({ owner: owner.publicKey })
+        .rpc();
+      return (await eventsOf(signature))[0].data;
+    };
+
+    it("counts an owner's open locks and drops to zero once they are closed", async () => {
+      const user = await newUser();
+      let report = await activeLocks(user);
+      assert.isFalse(report.hasActiveLocks);
+      assert.equal(report.count, 0);
+
+      const unlock = (await now()) + 2;
+      const { lockAccount: solLock } = await initSolLock(user, LAMPORTS_PER_SOL, unlock);
+      const { mint, userAta } = await fundedMint(user, 100);
+      const { lockAccount: splLock, vaultAta } = await initSplLock(user, mint, userAta, 100, unlock);
+      report = await activeLocks(user);
+      assert.isTrue(report.hasActiveLocks);
+      assert.equal(report.count, 2);
+
+      await waitUntil(unlock);
+      await withdrawSol(user, solLock);
+      await withdrawSpl(user, mint, userAta, splLock, vaultAta);
+      await closeSplLock(user, mint, splLock, vaultAta);
+      report = await activeLocks(user);
+      assert.isFalse(report.hasActiveLocks);
+      assert.equal(report.count, 0);
+    });
+  });
+
+  describe("unlock attestation", () => {
+    const consumer = anchor.workspace.lockConsumer;
+    const requireMatured = (lockAccount) =>
+      consumer.methods
+        .requireMatured()
+        .accountsPartial({ lockAccount, timelockProgram: program.programId })
+        .rpc();
+
+    it("lets a consuming program branch on a lock's maturity", async () => {
+      const user = await newUser();
+      const unlock = (await now()) + 2;
+      const { lockAccount } = await initSolLock(user, LAMPORTS_PER_SOL, unlock);
+
+      await expectError(requireMatured(lockAccount), "LockNotMatured");
+      await waitUntil(unlock);
+      await requireMatured(lockAccount);
+    });
+  });
+
+  describe("swap withdrawals", () => {
+    const swapProgram = anchor.workspace.mockSwap;
+    const reserveAuthority = PublicKey.findProgramAddressSync(
+      [Buffer.from("reserve")],
+      swapProgram.programId
+    )[0];
+    const allowedSwapProgram = pda(Buffer.from("swap-program"), swapProgram.programId.toBuffer());
+    const noTier = { maxDurationSecs: new BN(0), feeBps: 0 };
+    const setFeeSchedule = (tiers) =>
+      program.methods
+        .setFeeSchedule(payer.publicKey, tiers)
+        .accountsPartial({ admin: payer.publicKey, config: configPda })
+        .rpc();
+    let outMint;
+    let reserve;
+
+    before(async () => {
+      await program.methods
+        .allowSwapProgram(swapProgram.programId)
+        .accountsPartial({ admin: payer.publicKey, config: configPda, swapProgram: allowedSwapProgram })
+        .rpc();
+      // 1% on every lock these tests create.
+      await setFeeSchedule([{ maxDurationSecs: new BN(3_600), feeBps: 100 }, noTier, noTier, noTier]);
+      outMint = await createMint(connection, payer, payer.publicKey, null, 6);
+      reserve = (
+        await getOrCreateAssociatedTokenAccount(connection, payer, outMint, reserveAuthority, true)
+      ).address;
+      await mintTo(connection, payer, outMint, reserve, payer, 1_000_000);
+    });
+
+    after(async () => {
+      await setFeeSchedule([noTier, noTier, noTier, noTier]);
+      await program.methods
+        .disallowSwapProgram()
+        .accountsPartial({ admin: payer.publicKey, config: configPda, swapProgram: allowedSwapProgram })
+        .rpc();
+    });
+
+    // A locked mint, the user's output ATA and the treasury's fee ATA for a fresh user.
+    async function swapSetup(user, amount) {
+      const { mint, userAta } = await fundedMint(user, amount);
+      const ata = async (owner, ataMint = mint) =>
+        (await getOrCreateAssociatedTokenAccount(connection, payer, ataMint, owner, true)).address;
+      return {
+        mint,
+        userAta,
+        userOutAta: await ata(user.publicKey, outMint),
+        treasuryAta: await ata(payer.publicKey),
+        sink: await ata(reserveAuthority),
+      };
+    }
+
+    // What a client forwards: the mock's swap instruction as data plus remaining accounts. The
+    // vault's authority (the lock PDA unless migrated) is its `owner`; timelock-wallet signs for
+    // it inside the CPI, not the transaction.
+    async function route(lockAccount, vaultAta, { sink, userOutAta, vaultAuthority }, amountIn, amountOut) {
+      const ix = await swapProgram.methods
+        .swap(new BN(amountIn), new BN(amountOut))
+        .accountsPartial({
+          owner: vaultAuthority ?? lockAccount,
+          source: vaultAta,
+          sink,
+          reserve,
+          destination: userOutAta,
+          reserveAuthority,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .instruction();
+      return { data: ix.data, accounts: ix.keys.map((key) => ({ ...key, isSigner: false })) };
+    }
+
+    const swapWithdraw = (method, user, lockAccount, vaultAta, setup, minOut, swap) =>
+      program.methods[method](new BN(minOut), swap.data)
+        .accountsPartial({
+          initializer: user.publicKey,
+          lockAccount,
+          mint: setup.mint,
+          vaultAta,
+          vaultAuthority: setup.vaultAuthority ?? null,
+          outMint,
+          userOutAta: setup.userOutAta,
+          treasuryAta: setup.treasuryAta,
+          allowedSwapProgram,
+          swapProgram: swapProgram.programId,
+          config: configPda,
+          stats: statsPda,
+          lockSummary: null,
+          condition: null,
+          rewardPoolAta: null,
+          rewardPoolAuthority: null,
+          userAta: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts(swap.accounts)
+        .signers([user])
+        .rpc();
+
+    it("swaps a matured lock's tokens, net of the fee, into the user's output ATA", async () => {
+      const user = await newUser();
+      const setup = await swapSetup(user, 1_000);
+      const unlock = (await now()) + 2;
+      const { lockAccount, vaultAta } = await initSplLock(user, setup.mint, setup.userAta, 1_000, unlock);
+      const withdraw = async (amountIn, amountOut, minOut) =>
+        swapWithdraw(
+          "withdrawSplWithSwap",
+          user,
+          lockAccount,
+          vaultAta,
+          setup,
+          minOut,
+          await route(lockAccount, vaultAta, setup, amountIn, amountOut)
+        );
+
+      await expectError(withdraw(990, 500, 500), "TimeLockNotExpired");
+      await waitUntil(unlock);
+      await expectError(withdraw(990, 499, 500), "SlippageExceeded");
+      // The route has to spend everything left after the fee.
+      await expectError(withdraw(900, 500, 500), "SwapFailed");
+
+      const treasuryBefore = Number((await getAccount(connection, setup.treasuryAta)).amount);
+      await withdraw(990, 500, 500);
+      assert.equal(Number((await getAccount(connection, vaultAta)).amount), 0);
+      assert.equal(Number((await getAccount(connection, setup.userOutAta)).amount), 500);
+      assert.equal(
+        Number((await getAccount(connection, setup.treasuryAta)).amount) - treasuryBefore,
+        10
+      );
+    });
+
+    it("signs the swap with a migrated vault authority", async () => {
+      const user = await newUser();
+      const setup = await swapSetup(user, 1_000);
+      const unlock = (await now()) + 2;
+      const { lockAccount, vaultAta } = await initSplLock(user, setup.mint, setup.userAta, 1_000, unlock);
+      const vaultAuthority = vaultAuthorityPda(lockAccount);
+      await program.methods
+        .migrateVaultAuthority()
+        .accountsPartial({
+          admin: payer.publicKey,
+          config: configPda,
+          lockAccount,
+          vaultAta,
+          newAuthority: vaultAuthority,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+      await waitUntil(unlock);
+
+      const migrated = { ...setup, vaultAuthority };
+      await expectError(
+        swapWithdraw(
+          "withdrawSplWithSwap",
+          user,
+          lockAccount,
+          vaultAta,
+          setup,
+          500,
+          await route(lockAccount, vaultAta, migrated, 990, 500)
+        ),
+        "VaultAuthorityMissing"
+      );
+      await swapWithdraw(
+        "withdrawSplWithSwap",
+        user,
+        lockAccount,
+        vaultAta,
+        migrated,
+        500,
+        await route(lockAccount, vaultAta, migrated, 990, 500)
+      );
+      assert.equal(Number((await getAccount(connection, vaultAta)).amount), 0);
+      assert.equal(Number((await getAccount(connection, setup.userOutAta)).amount), 500);
+    });
+
+    it("leaves vesting locks to the per-tranche swap", async () => {
+      const user = await newUser();
+      const setup = await swapSetup(user, 1_000);
+      const unlock = (await now()) + 2;
+      const { lockAccount, vaultAta } = await initSplLock(
+        user,
+        setup.mint,
+        setup.userAta,
+        1_000,
+        unlock,
+        { vestingPeriods: 2, vestingPeriodSecs: 3_600 }
+      );
+      await waitUntil(unlock);
+
+      await expectError(
+        swapWithdraw(
+          "withdrawSplWithSwap",
+          user,
+          lockAccount,
+          vaultAta,
+          setup,
+          1,
+          await route(lockAccount, vaultAta, setup, 990, 500)
+        ),
+        "StreamNotDrained"
+      );
+    });
+
+    it("swaps each vested tranche, net of its fee", async () => {
+      const user = await newUser();
+      const setup = await swapSetup(user, 1_000);
+      const unlock = (await now()) + 2;
+      const { lockAccount, vaultAta } = await initSplLock(
+        user,
+        setup.mint,
+        setup.userAta,
+        1_000,
+        unlock,
+        { vestingPeriods: 2, vestingPeriodSecs: 4 }
+      );
+      const claim = async (amountIn, amountOut) =>
+        swapWithdraw(
+          "claimVestedSplWithSwap",
+          user,
+          lockAccount,
+          vaultAta,
+          setup,
+          amountOut,
+          await route(lockAccount, vaultAta, setup, amountIn, amountOut)
+        );
+      await waitUntil(unlock);
+
+      // 500 vested: 5 to the fee, and the route must spend exactly the other 495. Reaching for
+      // the fee too fails in the token program itself, with its InsufficientFunds.
+      await expectError(claim(500, 250), "custom program error: 0x1");
+      await expectError(claim(400, 250), "SwapFailed");
+      let [event] = (await eventsOf(await claim(495, 250))).filter(
+        (event) => event.name === "splVestingClaimed"
+      );
+      assert.equal(event.data.claimedNow.toNumber(), 500);
+      assert.equal(event.data.fee.toNumber(), 5);
+      assert.equal(Number((await getAccount(connection, vaultAta)).amount), 500);
+      await expectError(claim(495, 250), "NothingToClaim");
+
+      await waitUntil(unlock + 4);
+      [event] = (await eventsOf(await claim(495, 250))).filter(
+        (event) => event.name === "splVestingClaimed"
+      );
+      assert.equal(event.data.totalClaimed.toNumber(), 1_000);
+      assert.equal(event.data.remainingLocked.toNumber(), 0);
+      assert.equal(Number((await getAccount(connection, vaultAta)).amount), 0);
+      assert.equal(Number((await getAccount(connection, setup.userOutAta)).amount), 500);
+    });
+  });
+
+  describe("frozen vaults", () => {
+    const acknowledgeFrozenLock = (lockAccount, vaultAta) =>
+      program.methods
+        .acknowledgeFrozenLock()
+        .accountsPartial({
+          admin: payer.publicKey,
+          config: configPda,
+          lockAccount,
+          vaultAta,
+          stats: statsPda,
+        })
+        .rpc();
+
+    it("fails withdrawal with VaultFrozen and lets the admin flag the lock", async () => {
+      const user = await newUser();
+      // A mint with a freeze authority, unlike fundedMint's.
+      const mint = await createMint(connection, payer, payer.publicKey, payer.publicKey, 6);
+      const userAta = (
+        await getOrCreateAssociatedTokenAccount(connection, payer, mint, user.publicKey)
+      ).address;
+      await mintTo(connection, payer, mint, userAta, payer, 100);
+      const { lockAccount, vaultAta } = await initSplLock(
+        user,
+        mint,
+        userAta,
+        100,
+        (await now()) + 2
+      );
+      await freezeAccount(connection, payer, vaultAta, mint, payer);
+      await sleep(3_000);
+
+      await expectError(
+        withdrawSpl(user, mint, userAta, lockAccount, vaultAta),
+        "VaultFrozen"
+      );
+      await acknowledgeFrozenLock(lockAccount, vaultAta);
+      assert.isTrue((await program.account.timeLockAccount.fetch(lockAccount)).vaultFrozen);
+
+      await thawAccount(connection, payer, vaultAta, mint, payer);
+      await acknowledgeFrozenLock(lockAccount, vaultAta);
+      assert.isFalse((await program.account.timeLockAccount.fetch(lockAccount)).vaultFrozen);
+      await withdrawSpl(user, mint, userAta, lockAccount, vaultAta);
+      assert.equal(Number((await getAccount(connection, userAta)).amount), 100);
+    });
+  });
+
+  describe("lock config", () => {
+    const consumer = anchor.workspace.lockConsumer;
+    const requireUnencumbered = (lockAccount, minAmount) =>
+      consumer.methods
+        .requireUnencumbered(new BN(minAmount))
+        .accountsPartial({ lockAccount, timelockProgram: program.programId })
+        .rpc();
+
+    it("lets a consuming program check a lock's amount and authorities", async () => {
+      const user = await newUser();
+      const { lockAccount } = await initSolLock(
+        user,
+        LAMPORTS_PER_SOL,
+        (await now()) + 3_600
+      );
+
+      await requireUnencumbered(lockAccount, LAMPORTS_PER_SOL);
+      await expectError(
+        requireUnencumbered(lockAccount, 2 * LAMPORTS_PER_SOL),
+        "LockTooSmall"
+      );
+      await program.methods
+        .setWithdrawDelegate(Keypair.generate().publicKey)
+        .accountsPartial({ initializer: user.publicKey, lockAccount })
+        .signers([user])
+        .rpc();
+      await expectError(requireUnencumbered(lockAccount, LAMPORTS_PER_SOL), "LockEncumbered");
+    });
+
+    // Each on a lock of its own, so nothing else could be what the consumer objects to.
+    it("treats a receipt, a password, a quorum or a charity fallback as encumbering the lock", async () => {
+      const encumbered = async (encumber, options = {}) => {
+        const user = await newUser();
+        const { lockAccount } = await initSolLock(
+          user,
+          LAMPORTS_PER_SOL,
+          (await now()) + 3_600,
+          options
+        );
+        if (encumber) {
+          await encumber(user, lockAccount).signers([user]).rpc();
+        }
+        await expectError(requireUnencumbered(lockAccount, LAMPORTS_PER_SOL), "LockEncumbered");
+      };
+
+      await encumbered((user, lockAccount) => {
+        const receiptMint = pda(Buffer.from("receipt"), lockAccount.toBuffer());
+        return program.methods.mintLockReceipt().accountsPartial({
+          initializer: user.publicKey,
+          lockAccount,
+          receiptMint,
+          receiptAta: getAssociatedTokenAddressSync(receiptMint, user.publicKey),
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        });
+      });
+      await encumbered((user, lockAccount) =>
+        program.methods
+          .setPassword([...createHash("sha256").update("hunter2").digest()])
+          .accountsPartial({ initializer: user.publicKey, lockAccount })
+      );
+      await encumbered(null, {
+        quorumSigners: [Keypair.generate().publicKey, Keypair.generate().publicKey],
+        quorumThreshold: 1,
+      });
+      const charity = Keypair.generate().publicKey;
+      const allowedCharity = pda(Buffer.from("charity"), charity.toBuffer());
+      await program.methods
+        .allowCharity(charity)
+        .accountsPartial({ admin: payer.publicKey, config: configPda, charity: allowedCharity })
+        .rpc();
+      await encumbered((user, lockAccount) =>
+        program.methods
+          .setCharityFallback(new BN(60))
+          .accountsPartial({ initializer: user.publicKey, lockAccount, allowedCharity })
+      );
+    });
+  });
+
+  (STRICT_INIT ? describe : describe.skip)("strict init", () => {
+    // The system program refuses to create an account that already exists: custom error 0x0.
+    it("refuses to initialize an existing SOL lock", async () => {
+      const user = await newUser();
+      const unlock = (await now()) + 3_600;
+      const { lockAccount } = await initSolLock(user, LAMPORTS_PER_SOL, unlock);
+      await sleep(1_000);
+
+      await expectError(initSolLock(user, 2 * LAMPORTS_PER_SOL, unlock), "0x0");
+      const lock = await program.account.timeLockAccount.fetch(lockAccount);
+      assert.equal(lock.amount.toNumber(), LAMPORTS_PER_SOL);
+    });
+
+    it("refuses to initialize an existing SPL lock", async () => {
+      const user = await newUser();
+      const { mint, userAta } = await fundedMint(user, 200);
+      const unlock = (await now()) + 3_600;
+      const { vaultAta } = await initSplLock(user, mint, userAta, 100, unlock);
+      await sleep(1_000);
+
+      await expectError(initSplLock(user, mint, userAta, 100, unlock), "0x0");
+      assert.equal(Number((await getAccount(connection, vaultAta)).amount), 100);
+    });
+  });
+
+  describe("settlement gap", () => {
+    const setMinSettlementSlots = (slots) =>
+      program.methods
+        .setMinSettlementSlots(new BN(slots))
+        .accountsPartial({ admin: payer.publicKey, config: configPda })
+        .rpc();
+
+    before(() => setMinSettlementSlots(30));
+    after(() => setMinSettlementSlots(0));
+
+    it("refuses withdrawal until the deposit has aged enough slots", async () => {
+      const user = await newUser();
+      const unlockSlot = (await connection.getSlot()) + 2;
+      const { lockAccount } = await initSolLock(user, LAMPORTS_PER_SOL, 0, { unlockSlot });
+      const lock = await program.account.timeLockAccount.fetch(lockAccount);
+      const settledSlot = lock.fundingSlot.toNumber() + 30;
+      assert.isAbove(lock.fundingSlot.toNumber(), 0);
+
+      while ((await connection.getSlot()) < unlockSlot) {
+        await sleep(100);
+      }
+      await expectError(withdrawSol(user, lockAccount), "NotYetSettled");
+      while ((await connection.getSlot()) < settledSlot) {
+        await sleep(100);
+      }
+      await withdrawSol(user, lockAccount);
+      assert.isNull(await connection.getAccountInfo(lockAccount));
+    });
+  });
+
+  describe("contribution schedules", () => {
+    const contribute = (user, lamports) =>
+      program.methods
+        .fundSolLock(new BN(lamports))
+        .accountsPartial({
+          initializer: user.publicKey,
+          lockAccount: solLockPda(user.publicKey),
+          config: configPda,
+          stats: statsPda,
+          lockSummary: null,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+
+    it("matches deposits to the schedule and marks skipped contributions missed", async () => {
+      const user = await newUser();
+      const start = await now();
+      const { lockAccount } = await initSolLock(user, LAMPORTS_PER_SOL, start + 60);
+      const firstAt = start + 3;
+      const contribution = LAMPORTS_PER_SOL / 10;
+      await program.methods
+        .setContributionSchedule(new BN(contribution), new BN(4), 3, new BN(firstAt))
+        .accountsPartial({ initializer: user.publicKey, lockAccount })
+        .signers([user])
+        .rpc();
+
+      await expectError(contribute(user, contribution), "ContributionNotDue");
+      await waitUntil(firstAt);
+      await expectError(contribute(user, contribution * 2), "FundingMismatch");
+      const first = await contribute(user, contribution);
+      const [received] = (await eventsOf(first)).filter((e) => e.name === "contributionReceived");
+      assert.equal(received.data.index, 0);
+      assert.equal(received.data.remaining, 2);
+
+      // Skipping the second interval leaves only the last contribution, paid late.
+      await waitUntil(firstAt + 8);
+      await contribute(user, contribution);
+      const lock = await program.account.timeLockAccount.fetch(lockAccount);
+      assert.equal(lock.contributionsMade, 2);
+      assert.equal(lock.contributionsMissed, 1);
+      assert.equal(lock.amount.toNumber(), LAMPORTS_PER_SOL + 2 * contribution);
+      await expectError(contribute(user, contribution), "ContributionScheduleComplete");
+    });
+
+    it("rejects schedules that run past the unlock", async () => {
+      const user = await newUser();
+      const start = await now();
+      const { lockAccount } = await initSolLock(user, LAMPORTS_PER_SOL, start + 60);
+      await expectError(
+        program.methods
+          .setContributionSchedule(new BN(1_000), new BN(30), 3, new BN(start + 10))
+          .accountsPartial({ initializer: user.publicKey, lockAccount })
+          .signers([user])
+          .rpc(),
+        "InvalidContributionSchedule"
+      );
+    });
+  });
+
+  describe("lock groups", () => {
+    const grant = Array.from(Buffer.alloc(16, 7));
+    const otherGrant = Array.from(Buffer.alloc(16, 9));
+
+    const joinGroup = (user, lockAccount, groupId) =>
+      program.methods
+        .setGroup(groupId)
+        .accountsPartial({ initializer: user.publicKey, lockAccount })
+        .signers([user])
+        .rpc();
+
+    it("releases only the matured locks of the requested group", async () => {
+      const matured = await newUser();
+      const pending = await newUser();
+      const outsider = await newUser();
+      const unlock = (await now()) + 2;
+      const { lockAccount: maturedLock } = await initSolLock(matured, LAMPORTS_PER_SOL, unlock);
+      const { lockAccount: pendingLock } = await initSolLock(pending, LAMPORTS_PER_SOL, unlock + 3_600);
+      const { lockAccount: outsiderLock } = await initSolLock(outsider, LAMPORTS_PER_SOL, unlock);
+      await joinGroup(matured, maturedLock, grant);
+      await joinGroup(pending, pendingLock, grant);
+      await joinGroup(outsider, outsiderLock, otherGrant);
+      await waitUntil(unlock);
 
-  it("Is initialized!", async () => {
-    // Add your test here.
-    const program = anchor.workspace.timelockWallet;
-    const tx = await program.methods.initialize().rpc();
-    console.log("Your transaction signature", tx);
+      const before = await connection.getBalance(matured.publicKey);
+      const signature = await program.methods
+        .releaseGroup(grant)
+        .accountsPartial({ config: configPda, stats: statsPda, treasury: await treasury() })
+        .remainingAccounts(
+          [
+            [maturedLock, matured.publicKey],
+            [pendingLock, pending.publicKey],
+            [outsiderLock, outsider.publicKey],
+          ]
+            .flat()
+            .map((pubkey) => ({ pubkey, isWritable: true, isSigner: false }))
+        )
+        .rpc();
+      const event = (await eventsOf(signature)).find((e) => e.name === "groupReleased");
+      assert.equal(event.data.released, 1);
+      assert.equal(event.data.skipped, 2);
+      assert.isNull(await connection.getAccountInfo(maturedLock));
+      assert.isNotNull(await connection.getAccountInfo(pendingLock));
+      assert.isNotNull(await connection.getAccountInfo(outsiderLock));
+      assert.isAbove(await connection.getBalance(matured.publicKey), before + LAMPORTS_PER_SOL / 2);
+    });
   });
 });